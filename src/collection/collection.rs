@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use super::item::Item;
 
@@ -26,6 +28,21 @@ impl CollectionType {
     }
 }
 
+/// Resolve a path relative to one of `directories`, see `Collection::resolve`.
+pub fn resolve_path(directories: &[String], relative: &Path) -> PathBuf {
+    if directories.len() > 1 {
+        if let Some(path) = directories
+            .iter()
+            .map(|d| Path::new(d).join(relative))
+            .find(|p| p.exists())
+        {
+            return path;
+        }
+    }
+    let root = directories.first().map(|d| d.as_str()).unwrap_or_default();
+    Path::new(root).join(relative)
+}
+
 /// Collection represents a media collection (movies or TV shows)
 #[derive(Debug, Clone)]
 pub struct Collection {
@@ -37,10 +54,13 @@ pub struct Collection {
     pub collection_type: CollectionType,
     /// Items in the collection, could be type movies or shows
     pub items: Vec<Item>,
-    /// Directory where the collection is stored
-    pub directory: String,
+    /// Directories where the collection is stored. Item paths are relative to
+    /// one of them; if several contain the same path, the first one wins.
+    pub directories: Vec<String>,
     /// HLS server URL for streaming content
     pub hls_server: String,
+    /// Start time of the last completed scan, None if never scanned.
+    pub last_scan: Option<SystemTime>,
 }
 
 impl Collection {
@@ -48,7 +68,7 @@ impl Collection {
         id: String,
         name: String,
         collection_type: CollectionType,
-        directory: String,
+        directories: Vec<String>,
         hls_server: String,
     ) -> Self {
         Self {
@@ -56,11 +76,26 @@ impl Collection {
             name,
             collection_type,
             items: Vec::new(),
-            directory,
+            directories,
             hls_server,
+            last_scan: None,
         }
     }
 
+    /// Root returns the collection directory that contains `path`, if any.
+    pub fn root(&self, path: &Path) -> Option<&str> {
+        self.directories
+            .iter()
+            .map(|d| d.as_str())
+            .find(|d| !d.is_empty() && path.starts_with(d))
+    }
+
+    /// Resolve a path relative to the collection to a full path. The first
+    /// directory that contains it is used, or the first directory if none does.
+    pub fn resolve(&self, relative: impl AsRef<Path>) -> PathBuf {
+        resolve_path(&self.directories, relative.as_ref())
+    }
+
     /// Details returns collection details such as genres, tags, ratings, etc.
     pub fn details(&self) -> CollectionDetails {
         let mut movie_count = 0;
@@ -68,6 +103,7 @@ impl Collection {
         let mut episode_count = 0;
         let mut genres = HashSet::new();
         let mut studios = HashSet::new();
+        let mut tags = HashSet::new();
         let mut official_ratings = HashSet::new();
         let mut years = HashSet::new();
 
@@ -85,6 +121,11 @@ impl Collection {
                             studios.insert(studio.clone());
                         }
                     }
+                    for tag in &movie.metadata.tags {
+                        if !tag.is_empty() {
+                            tags.insert(tag.clone());
+                        }
+                    }
                     if let Some(rating) = &movie.metadata.official_rating {
                         if !rating.is_empty() {
                             official_ratings.insert(rating.clone());
@@ -111,6 +152,11 @@ impl Collection {
                             studios.insert(studio.clone());
                         }
                     }
+                    for tag in &show.metadata.tags {
+                        if !tag.is_empty() {
+                            tags.insert(tag.clone());
+                        }
+                    }
                     if let Some(rating) = &show.metadata.official_rating {
                         if !rating.is_empty() {
                             official_ratings.insert(rating.clone());
@@ -132,7 +178,7 @@ impl Collection {
             episode_count,
             genres: genres.into_iter().collect(),
             studios: studios.into_iter().collect(),
-            tags: Vec::new(),
+            tags: tags.into_iter().collect(),
             official_ratings: official_ratings.into_iter().collect(),
             years: years.into_iter().collect(),
         }
@@ -182,6 +228,42 @@ pub struct CollectionDetails {
     pub years: Vec<i32>,
 }
 
+impl CollectionDetails {
+    /// Combine merges the details of several collections.
+    pub fn combine<I: IntoIterator<Item = CollectionDetails>>(details: I) -> CollectionDetails {
+        let mut movie_count = 0;
+        let mut show_count = 0;
+        let mut episode_count = 0;
+        let mut genres = HashSet::new();
+        let mut studios = HashSet::new();
+        let mut tags = HashSet::new();
+        let mut official_ratings = HashSet::new();
+        let mut years = HashSet::new();
+
+        for d in details {
+            movie_count += d.movie_count;
+            show_count += d.show_count;
+            episode_count += d.episode_count;
+            genres.extend(d.genres);
+            studios.extend(d.studios);
+            tags.extend(d.tags);
+            official_ratings.extend(d.official_ratings);
+            years.extend(d.years);
+        }
+
+        CollectionDetails {
+            movie_count,
+            show_count,
+            episode_count,
+            genres: genres.into_iter().collect(),
+            studios: studios.into_iter().collect(),
+            tags: tags.into_iter().collect(),
+            official_ratings: official_ratings.into_iter().collect(),
+            years: years.into_iter().collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -208,7 +290,7 @@ mod tests {
             "test-id".to_string(),
             "Test Collection".to_string(),
             CollectionType::Movies,
-            "/media/movies".to_string(),
+            vec!["/media/movies".to_string()],
             "".to_string(),
         );
 
@@ -224,7 +306,7 @@ mod tests {
             "test".to_string(),
             "Test".to_string(),
             CollectionType::Movies,
-            "/test".to_string(),
+            vec!["/test".to_string()],
             "".to_string(),
         );
 
@@ -1,38 +1,80 @@
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
 use arc_swap::ArcSwap;
-use rand::seq::SliceRandom;
-use tracing::info;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
 
-use super::collection::{Collection, CollectionType};
+use super::collection::{resolve_path, Collection, CollectionType};
 use super::item::Item;
 use crate::idhash::*;
+use super::search::{Search, SearchDocument};
+use super::watcher::DirectoryWatcher;
+use crate::mediaprobe::MediaProber;
+
+/// LibraryChange lists the items a rescan added to or removed from a collection.
+#[derive(Debug, Clone, Default)]
+pub struct LibraryChange {
+    pub collection_id: String,
+    pub items_added: Vec<String>,
+    pub items_removed: Vec<String>,
+}
 
 /// CollectionRepo is a repository holding content collections.
 pub struct CollectionRepo {
     collections: Arc<ArcSwap<Vec<Collection>>>,
+    changes: broadcast::Sender<LibraryChange>,
+    prober: Arc<OnceLock<Arc<MediaProber>>>,
+    search: Arc<OnceLock<Arc<Search>>>,
+    watcher: OnceLock<Arc<DirectoryWatcher>>,
 }
 
 impl CollectionRepo {
     /// Create a new CollectionRepo
     pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(64);
         Self {
             collections: Arc::new(ArcSwap::from_pointee(Vec::new())),
+            changes,
+            prober: Arc::new(OnceLock::new()),
+            search: Arc::new(OnceLock::new()),
+            watcher: OnceLock::new(),
+        }
+    }
+
+    /// Probe the video files of all collections with `prober` when they are
+    /// scanned. Must be called before `init`.
+    pub fn set_prober(&self, prober: Arc<MediaProber>) {
+        if self.prober.set(prober).is_err() {
+            warn!("Media prober already set");
+        }
+    }
+
+    /// Keep `search` up to date with the collections after every scan. Must
+    /// be called before `init`.
+    pub fn set_search(&self, search: Arc<Search>) {
+        if self.search.set(search).is_err() {
+            warn!("Search index already set");
         }
     }
 
-    /// Add a new content collection to the repository
+    /// Subscribe to the items added or removed by collection rescans.
+    pub fn subscribe(&self) -> broadcast::Receiver<LibraryChange> {
+        self.changes.subscribe()
+    }
+
+    /// Add a new content collection to the repository. Returns the collection ID.
     pub fn add_collection(
         &self,
         name: String,
         id: Option<String>,
         collection_type: &str,
-        directory: String,
+        directories: Vec<String>,
         hls_server: String,
-    ) -> Result<(), String> {
+    ) -> Result<String, String> {
         let ct = CollectionType::from_str(collection_type)
             .ok_or_else(|| format!("Unknown collection type: {}", collection_type))?;
 
@@ -44,47 +86,276 @@ impl CollectionRepo {
         };
 
         info!(
-            "Adding collection {}, id: {}, type: {}, directory: {}",
-            name, collection_id, collection_type, directory
+            "Adding collection {}, id: {}, type: {}, directories: {}",
+            name,
+            collection_id,
+            collection_type,
+            directories.join(", ")
         );
 
-        let collection = Collection::new(collection_id, name, ct, directory, hls_server);
+        if self.get_collection(&collection_id).is_some() {
+            return Err(format!("Collection {} already exists", collection_id));
+        }
+
+        self.watch_directories(&directories);
+        let collection = Collection::new(collection_id.clone(), name, ct, directories, hls_server);
 
         // Add to collections
-        let mut collections = (**self.collections.load()).clone();
-        collections.push(collection);
-        self.collections.store(Arc::new(collections));
+        self.collections.rcu(|current| {
+            let mut collections = (**current).clone();
+            collections.push(collection.clone());
+            collections
+        });
 
-        Ok(())
+        Ok(collection_id)
+    }
+
+    /// Remove a collection from the repository. Returns false if it does not exist.
+    pub fn remove_collection(&self, collection_id: &str) -> bool {
+        let Some(collection) = self.get_collection(collection_id) else {
+            return false;
+        };
+        info!("Removing collection {}, id: {}", collection.name, collection_id);
+
+        self.collections.rcu(|current| {
+            current
+                .iter()
+                .filter(|c| c.id != collection_id)
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        self.unwatch_directories(&collection.directories);
+
+        if let Some(search) = self.search.get() {
+            if let Err(e) = search.remove_collection(collection_id) {
+                warn!("Updating search index failed: {}", e);
+            }
+        }
+
+        let mut removed: Vec<String> = item_ids(&collection).into_iter().collect();
+        removed.sort();
+        if !removed.is_empty() {
+            let _ = self.changes.send(LibraryChange {
+                collection_id: collection_id.to_string(),
+                items_added: Vec::new(),
+                items_removed: removed,
+            });
+        }
+        true
+    }
+
+    /// Rename a collection. Returns false if it does not exist.
+    pub fn rename_collection(&self, collection_id: &str, name: &str) -> bool {
+        self.modify_collection(collection_id, |c| c.name = name.to_string())
+    }
+
+    /// Change the directories of a collection. Its items are dropped until
+    /// the next scan. Returns false if the collection does not exist.
+    pub fn set_collection_directories(&self, collection_id: &str, directories: &[String]) -> bool {
+        let Some(previous) = self.get_collection(collection_id) else {
+            return false;
+        };
+        self.watch_directories(directories);
+        self.modify_collection(collection_id, |c| {
+            if c.directories != directories {
+                c.directories = directories.to_vec();
+                c.items.clear();
+                c.last_scan = None;
+            }
+        });
+        let removed: Vec<String> = previous
+            .directories
+            .into_iter()
+            .filter(|d| !directories.contains(d))
+            .collect();
+        self.unwatch_directories(&removed);
+        true
+    }
+
+    /// Add directories to the watcher, if collections are watched.
+    fn watch_directories(&self, directories: &[String]) {
+        if let Some(watcher) = self.watcher.get() {
+            for dir in directories {
+                watcher.watch(Path::new(dir));
+            }
+        }
+    }
+
+    /// Remove directories from the watcher, unless another collection still
+    /// has them.
+    fn unwatch_directories(&self, directories: &[String]) {
+        let Some(watcher) = self.watcher.get() else {
+            return;
+        };
+        let collections = self.collections.load();
+        for dir in directories {
+            if !collections.iter().any(|c| c.directories.contains(dir)) {
+                watcher.unwatch(Path::new(dir));
+            }
+        }
+    }
+
+    fn modify_collection(&self, collection_id: &str, f: impl Fn(&mut Collection)) -> bool {
+        if self.get_collection(collection_id).is_none() {
+            return false;
+        }
+        self.collections.rcu(|current| {
+            let mut collections = (**current).clone();
+            for c in collections.iter_mut().filter(|c| c.id == collection_id) {
+                f(c);
+            }
+            collections
+        });
+        true
     }
 
     /// Initialize collections by scanning directories
     pub fn init(&self) {
         info!("Initializing collections...");
-        self.update_collections(Duration::from_millis(0));
+        Self::update_collections(
+            &self.collections,
+            &self.changes,
+            &self.prober,
+            &self.search,
+            Duration::ZERO,
+            None,
+            &ScanHooks::NONE,
+        );
     }
 
-    /// Background task that continuously scans for content changes
-    pub fn background(&self) {
-        let _collections = Arc::clone(&self.collections);
+    /// Scan a single collection in the background, e.g. after it was added.
+    pub fn scan_collection(&self, collection_id: &str) {
+        let collections = Arc::clone(&self.collections);
+        let changes = self.changes.clone();
+        let prober = Arc::clone(&self.prober);
+        let search = Arc::clone(&self.search);
+        let collection_id = collection_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            info!("Scanning collection {}", collection_id);
+            Self::update_collections(
+                &collections,
+                &changes,
+                &prober,
+                &search,
+                Duration::ZERO,
+                Some(&collection_id),
+                &ScanHooks::NONE,
+            );
+        });
+    }
 
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(300)).await; // 5 minutes
-                info!("Background scan starting...");
+    /// Rescan all collections and wait for the scan to finish. A non-zero
+    /// `scan_interval` only reads directories changed since the last scan.
+    /// `progress` is called with the percentage done after each collection.
+    /// The scan stops before the next collection once `cancelled` returns
+    /// true, without changing anything.
+    pub async fn refresh(
+        &self,
+        scan_interval: Duration,
+        progress: impl Fn(f64) + Send + 'static,
+        cancelled: impl Fn() -> bool + Send + 'static,
+    ) -> Result<(), String> {
+        let collections = Arc::clone(&self.collections);
+        let changes = self.changes.clone();
+        let prober = Arc::clone(&self.prober);
+        let search = Arc::clone(&self.search);
+        let completed = tokio::task::spawn_blocking(move || {
+            Self::update_collections(
+                &collections,
+                &changes,
+                &prober,
+                &search,
+                scan_interval,
+                None,
+                &ScanHooks {
+                    progress: &progress,
+                    cancelled: &cancelled,
+                },
+            )
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        match completed {
+            true => Ok(()),
+            false => Err("scan cancelled".to_string()),
+        }
+    }
 
-                // TODO: Implement update_collections
-                // For now, just log
-                info!("Background scan complete");
-            }
-        });
+    /// Rescan a single item and wait for it to finish. Movies and shows are
+    /// read again from their directory, including NFO files and images. For
+    /// seasons and episodes the whole show is rescanned, for a collection
+    /// folder the collection. Returns false if the item does not exist.
+    pub async fn refresh_item(&self, item_id: &str) -> Result<bool, String> {
+        let collections = Arc::clone(&self.collections);
+        let changes = self.changes.clone();
+        let prober = Arc::clone(&self.prober);
+        let search = Arc::clone(&self.search);
+
+        if self.get_collection(item_id).is_some() {
+            let collection_id = item_id.to_string();
+            return tokio::task::spawn_blocking(move || {
+                Self::update_collections(
+                    &collections,
+                    &changes,
+                    &prober,
+                    &search,
+                    Duration::ZERO,
+                    Some(&collection_id),
+                    &ScanHooks::NONE,
+                )
+            })
+            .await
+            .map_err(|e| e.to_string());
+        }
+
+        let Some((collection, item)) = self.get_item_by_id(item_id) else {
+            return Ok(false);
+        };
+        let path = match &item {
+            Item::Movie(m) => &m.path,
+            Item::Show(s) => &s.path,
+            Item::Season(s) => &s.path,
+            Item::Episode(e) => &e.path,
+            _ => return Ok(false),
+        };
+        let dir = collection.resolve(path);
+        tokio::task::spawn_blocking(move || {
+            Self::rescan_collections_path(&collections, &changes, &prober, &search, &dir)
+        })
+        .await
+        .map_err(|e| e.to_string())
     }
 
-    /// Update collections with latest content from filesystem
-    fn update_collections(&self, scan_interval: Duration) {
-        let mut updated_collections = (**self.collections.load()).clone();
+    /// Update collections with latest content from filesystem.
+    ///
+    /// The scan runs on a copy of the collections. The result is merged back by
+    /// collection ID, so collections added or removed while scanning are not lost
+    /// and renames made while scanning are kept.
+    /// If `only` is set, just that collection is scanned. Returns false if the
+    /// scan was cancelled, in which case nothing is changed.
+    fn update_collections(
+        collections: &Arc<ArcSwap<Vec<Collection>>>,
+        changes: &broadcast::Sender<LibraryChange>,
+        prober: &OnceLock<Arc<MediaProber>>,
+        search: &OnceLock<Arc<Search>>,
+        scan_interval: Duration,
+        only: Option<&str>,
+        hooks: &ScanHooks,
+    ) -> bool {
+        let mut updated_collections = (**collections.load()).clone();
+        updated_collections.retain(|c| only.is_none_or(|id| c.id == id));
+        let mut library_changes = Vec::new();
+        let mut unprobed = Vec::new();
+        let mut removed = Vec::new();
 
-        for collection in &mut updated_collections {
+        let total = updated_collections.len();
+        for (done, collection) in updated_collections.iter_mut().enumerate() {
+            if (hooks.cancelled)() {
+                info!("Scan cancelled, collections are left as they were");
+                return false;
+            }
+            let before = item_ids(collection);
+            let before_paths = video_paths(collection);
             match collection.collection_type {
                 CollectionType::Movies => {
                     super::kodifs::build_movies(collection, scan_interval);
@@ -93,9 +364,188 @@ impl CollectionRepo {
                     super::kodifs::build_shows(collection, scan_interval);
                 }
             }
+            library_changes.extend(diff_items(&collection.id, &before, &item_ids(collection)));
+            if let Some(prober) = prober.get() {
+                unprobed.extend(apply_media_info(collection, prober));
+                removed.extend(before_paths.difference(&video_paths(collection)).cloned());
+            }
+            (hooks.progress)((done + 1) as f64 * 100.0 / total as f64);
+        }
+
+        collections.rcu(|current| {
+            current
+                .iter()
+                .map(|c| match updated_collections.iter().find(|u| u.id == c.id) {
+                    Some(u) => merge_scan(c, u),
+                    None => c.clone(),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        if let Some(search) = search.get() {
+            let result = match only {
+                // A full scan also drops collections that no longer exist.
+                None => search.index_collections(&collections.load()),
+                Some(_) => updated_collections.iter().try_for_each(|c| search.index_collection(c)),
+            };
+            if let Err(e) = result {
+                warn!("Updating search index failed: {}", e);
+            }
+        }
+
+        for change in library_changes {
+            // Nobody listening is not an error.
+            let _ = changes.send(change);
+        }
+
+        if let Some(prober) = prober.get() {
+            Self::probe_in_background(collections, prober, unprobed, removed);
+        }
+        true
+    }
+
+    /// Drop the probe results of video files that are gone, probe video files
+    /// without a cached probe result, then apply the results to the collections.
+    fn probe_in_background(
+        collections: &Arc<ArcSwap<Vec<Collection>>>,
+        prober: &Arc<MediaProber>,
+        paths: Vec<PathBuf>,
+        removed: Vec<PathBuf>,
+    ) {
+        if paths.is_empty() && removed.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let collections = Arc::clone(collections);
+        let prober = Arc::clone(prober);
+        runtime.spawn(async move {
+            prober.remove(&removed).await;
+            let probed = prober.probe_files(paths).await;
+            if probed == 0 {
+                return;
+            }
+            info!("Probed {} video files", probed);
+            let res = tokio::task::spawn_blocking(move || {
+                collections.rcu(|current| {
+                    let mut updated = (**current).clone();
+                    for collection in &mut updated {
+                        apply_media_info(collection, &prober);
+                    }
+                    updated
+                });
+            })
+            .await;
+            if let Err(e) = res {
+                warn!("Applying probe results failed: {}", e);
+            }
+        });
+    }
+
+    /// Rescan the movie, show or season directory that contains `path`.
+    /// Returns false if `path` is not inside any collection.
+    pub fn rescan_path(&self, path: &Path) -> bool {
+        Self::rescan_collections_path(&self.collections, &self.changes, &self.prober, &self.search, path)
+    }
+
+    fn rescan_collections_path(
+        collections: &Arc<ArcSwap<Vec<Collection>>>,
+        changes: &broadcast::Sender<LibraryChange>,
+        prober: &OnceLock<Arc<MediaProber>>,
+        search: &OnceLock<Arc<Search>>,
+        path: &Path,
+    ) -> bool {
+        let mut collection = match collections
+            .load()
+            .iter()
+            .find(|c| c.root(path).is_some())
+            .cloned()
+        {
+            Some(c) => c,
+            None => return false,
+        };
+
+        let before = item_ids(&collection);
+        let before_paths = video_paths(&collection);
+        if !super::kodifs::rescan_path(&mut collection, path) {
+            return false;
+        }
+        let change = diff_items(&collection.id, &before, &item_ids(&collection));
+        let (unprobed, removed) = match prober.get() {
+            Some(prober) => {
+                let after_paths = video_paths(&collection);
+                let removed = before_paths.difference(&after_paths).cloned().collect();
+                (apply_media_info(&mut collection, prober), removed)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        collections.rcu(|current| {
+            current
+                .iter()
+                .map(|c| match c.id == collection.id {
+                    true => merge_scan(c, &collection),
+                    false => c.clone(),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        if let Some(search) = search.get() {
+            if let Err(e) = search.index_collection(&collection) {
+                warn!("Updating search index failed: {}", e);
+            }
+        }
+
+        if let Some(change) = change {
+            let _ = changes.send(change);
+        }
+        if let Some(prober) = prober.get() {
+            Self::probe_in_background(collections, prober, unprobed, removed);
+        }
+        true
+    }
+
+    /// Watch the collection directories for changes and rescan the affected
+    /// directories once they have been quiet for `delay`. Directories of
+    /// collections added or changed later are watched as well.
+    pub fn watch(&self, delay: Duration) -> Result<(), String> {
+        let directories: Vec<PathBuf> = self
+            .collections
+            .load()
+            .iter()
+            .flat_map(|c| c.directories.iter().map(PathBuf::from))
+            .collect();
+        let (watcher, mut events) = super::watcher::watch_directories(&directories, delay)?;
+        if self.watcher.set(watcher).is_err() {
+            return Err("collections are already watched".to_string());
         }
 
-        self.collections.store(Arc::new(updated_collections));
+        let collections = Arc::clone(&self.collections);
+        let changes = self.changes.clone();
+        let prober = Arc::clone(&self.prober);
+        let search = Arc::clone(&self.search);
+        tokio::spawn(async move {
+            while let Some(dirs) = events.recv().await {
+                let collections = Arc::clone(&collections);
+                let changes = changes.clone();
+                let prober = Arc::clone(&prober);
+                let search = Arc::clone(&search);
+                let res = tokio::task::spawn_blocking(move || {
+                    for dir in dirs {
+                        info!("Rescanning {}", dir.display());
+                        Self::rescan_collections_path(&collections, &changes, &prober, &search, &dir);
+                    }
+                })
+                .await;
+                if let Err(e) = res {
+                    warn!("Rescan failed: {}", e);
+                }
+            }
+        });
+
+        Ok(())
     }
 
     /// Get all collections
@@ -294,168 +744,218 @@ impl CollectionRepo {
         next_up_ids
     }
 
-    pub async fn similar(&self, collection_id: &str, item_id: &str, limit: usize) -> Vec<String> {
-        let collection = match self.get_collection(collection_id) {
-            Some(c) => c,
-            None => return Vec::new(),
-        };
-        let item = match self.get_item(collection_id, item_id) {
-            Some(i) => i,
-            None => return Vec::new(),
-        };
-
-        let source_meta = match &item {
-            Item::Movie(m) => &m.metadata,
-            Item::Show(s) => &s.metadata,
-            _ => return Vec::new(),
-        };
-
-        let source_genres: HashSet<&str> = source_meta.genres.iter().map(|s| s.as_str()).collect();
-        let source_people: HashSet<&str> = source_meta
-            .actors
-            .iter()
-            .chain(source_meta.directors.iter())
-            .map(|s| s.as_str())
-            .collect();
-
-        if source_genres.is_empty() && source_people.is_empty() {
+    /// Similar returns the IDs of the movies or shows most similar to a
+    /// movie or show, most similar first. Returns nothing if no search index
+    /// is set.
+    pub fn similar(&self, item_id: &str, limit: usize) -> Vec<String> {
+        let Some(search) = self.search.get() else {
             return Vec::new();
+        };
+        match search.similar(item_id, limit) {
+            Ok(docs) => docs.into_iter().map(|doc| doc.id).collect(),
+            Err(e) => {
+                warn!("Finding items similar to {} failed: {}", item_id, e);
+                Vec::new()
+            }
         }
+    }
 
-        let source_id = item.id();
-        let match_shows = matches!(item, Item::Show(_));
-
-        // Score candidates by genre + people overlap
-        let mut scored: Vec<(String, usize)> = collection
-            .items
+    /// Name of a person credited in one of the movies or shows, as it is
+    /// spelled in the library, found by a name in any case.
+    pub fn person_name(&self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        let collections = self.collections.load();
+        collections
             .iter()
-            .filter_map(|candidate| {
-                let (cid, meta) = match candidate {
-                    Item::Movie(m) if !match_shows => (m.id.as_str(), &m.metadata),
-                    Item::Show(s) if match_shows => (s.id.as_str(), &s.metadata),
-                    _ => return None,
-                };
-                if cid == source_id {
-                    return None;
-                }
-                let genre_score = meta
-                    .genres
-                    .iter()
-                    .filter(|g| source_genres.contains(g.as_str()))
-                    .count();
-                let people_score = meta
-                    .actors
-                    .iter()
-                    .chain(meta.directors.iter())
-                    .filter(|p| source_people.contains(p.as_str()))
-                    .count();
-                let score = genre_score + people_score;
-                if score > 0 {
-                    Some((cid.to_string(), score))
-                } else {
-                    None
-                }
+            .flat_map(|c| c.items.iter())
+            .filter(|item| matches!(item, Item::Movie(_) | Item::Show(_)))
+            .find_map(|item| {
+                item.metadata()
+                    .credits()
+                    .find(|credit| credit.name.to_lowercase() == name)
+                    .map(|credit| credit.name.to_string())
             })
-            .collect();
-
-        // Sort by score descending, take top 3*limit, shuffle, return limit
-        scored.sort_by(|a, b| b.1.cmp(&a.1));
-        scored.truncate(3 * limit);
-        scored.shuffle(&mut rand::thread_rng());
-        scored.into_iter().take(limit).map(|(id, _)| id).collect()
     }
 
-    /// Search performs a item search in collection repository and returns matching items.
-    pub fn search(&self, term: &str) -> Vec<String> {
-        println!("XXX search {}", term);
-        let mut results = Vec::new();
-        let term_lower = term.to_lowercase();
+    /// Image of a person from the `.actors` directory of one of the movies
+    /// or shows they are credited for.
+    pub fn person_image(&self, person_id: &str) -> Option<PathBuf> {
         let collections = self.collections.load();
         for collection in collections.iter() {
             for item in &collection.items {
-                match item {
-                    Item::Movie(m) => {
-                        if m.name.to_lowercase().contains(&term_lower) {
-                            results.push(m.id.clone());
-                        }
-                    }
-                    Item::Show(s) => {
-                        if s.name.to_lowercase().contains(&term_lower) {
-                            results.push(s.id.clone());
-                        }
-                        for season in &s.seasons {
-                            for episode in &season.episodes {
-                                if episode.name.to_lowercase().contains(&term_lower) {
-                                    results.push(episode.id.clone());
-                                }
-                            }
-                        }
-                    }
-                    Item::Season(s) => {
-                        if s.name.to_lowercase().contains(&term_lower) {
-                            results.push(s.id.clone());
-                        }
-                    }
-                    Item::Episode(e) => {
-                        if e.name.to_lowercase().contains(&term_lower) {
-                            results.push(e.id.clone());
+                let (path, metadata) = match item {
+                    Item::Movie(m) => (&m.path, &m.metadata),
+                    Item::Show(s) => (&s.path, &s.metadata),
+                    _ => continue,
+                };
+                for credit in metadata.credits() {
+                    let Some(file) = credit.thumb_file() else {
+                        continue;
+                    };
+                    if id_hash_prefix(ITEM_PREFIX_PERSON, credit.name) == person_id {
+                        let dir = collection.resolve(path);
+                        let image = dir.join(file);
+                        // A symlink must not lead out of the item directory.
+                        let inside = match (image.canonicalize(), dir.canonicalize()) {
+                            (Ok(image), Ok(dir)) => image.starts_with(dir),
+                            _ => false,
+                        };
+                        if inside {
+                            return Some(image);
                         }
                     }
-                    _ => {}
                 }
             }
         }
-        println!("XXX search results: {}", results.len());
-        results
+        None
+    }
+
+    /// Search the collections for movies, shows, seasons and episodes, best
+    /// matches first. Returns nothing if no search index is set.
+    pub fn search(&self, term: &str, limit: usize) -> Vec<SearchDocument> {
+        let Some(search) = self.search.get() else {
+            return Vec::new();
+        };
+        search.search(term, limit).unwrap_or_else(|e| {
+            warn!("Search for {} failed: {}", term, e);
+            Vec::new()
+        })
+    }
+
+    /// Search the people, studios and genres of the collections by name,
+    /// best matches first. Returns nothing if no search index is set.
+    pub fn search_names(&self, term: &str, limit: usize) -> Vec<SearchDocument> {
+        let Some(search) = self.search.get() else {
+            return Vec::new();
+        };
+        search.search_names(term, limit).unwrap_or_else(|e| {
+            warn!("Search for {} failed: {}", term, e);
+            Vec::new()
+        })
     }
 
     /// Details returns repository details
     pub fn details(&self) -> super::collection::CollectionDetails {
         let collections = self.collections.load();
-        let mut movie_count = 0;
-        let mut show_count = 0;
-        let mut episode_count = 0;
-        let mut genres = HashSet::new();
-        let mut studios = HashSet::new();
-        let mut official_ratings = HashSet::new();
-        let mut years = HashSet::new();
-
-        for c in collections.iter() {
-            let details = c.details();
-            movie_count += details.movie_count;
-            show_count += details.show_count;
-            episode_count += details.episode_count;
-            for g in details.genres {
-                genres.insert(g);
-            }
-            for s in details.studios {
-                studios.insert(s);
+        super::collection::CollectionDetails::combine(collections.iter().map(|c| c.details()))
+    }
+}
+
+impl Default for CollectionRepo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// IDs of all movies, shows, seasons and episodes in a collection.
+fn item_ids(collection: &Collection) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    for item in &collection.items {
+        ids.insert(item.id());
+        if let Item::Show(show) = item {
+            for season in &show.seasons {
+                ids.insert(season.id.clone());
+                ids.extend(season.episodes.iter().map(|e| e.id.clone()));
             }
-            for r in details.official_ratings {
-                official_ratings.insert(r);
+        }
+    }
+    ids
+}
+
+/// Apply cached probe results to the movies and episodes of a collection.
+/// Returns the video files that have no valid cached result yet.
+fn apply_media_info(collection: &mut Collection, prober: &MediaProber) -> Vec<PathBuf> {
+    let directories = &collection.directories;
+    let mut unprobed = Vec::new();
+    let mut apply = |path: PathBuf, metadata: &mut super::Metadata| match prober.cached(&path) {
+        Some(Some(info)) => info.apply(metadata),
+        Some(None) => {}
+        None => unprobed.push(path),
+    };
+
+    for item in &mut collection.items {
+        match item {
+            Item::Movie(movie) if !movie.file_name.is_empty() => {
+                let path = Path::new(&movie.path).join(&movie.file_name);
+                apply(resolve_path(directories, &path), &mut movie.metadata);
             }
-            for y in details.years {
-                years.insert(y);
+            Item::Show(show) => {
+                for season in &mut show.seasons {
+                    for episode in &mut season.episodes {
+                        let path = Path::new(&episode.path).join(&episode.file_name);
+                        apply(resolve_path(directories, &path), &mut episode.metadata);
+                    }
+                }
             }
+            _ => {}
         }
+    }
+    unprobed
+}
 
-        super::collection::CollectionDetails {
-            movie_count,
-            show_count,
-            episode_count,
-            genres: genres.into_iter().collect(),
-            studios: studios.into_iter().collect(),
-            tags: Vec::new(),
-            official_ratings: official_ratings.into_iter().collect(),
-            years: years.into_iter().collect(),
+/// Video files of the movies and episodes of a collection.
+fn video_paths(collection: &Collection) -> HashSet<PathBuf> {
+    let mut paths = HashSet::new();
+    for item in &collection.items {
+        match item {
+            Item::Movie(movie) if !movie.file_name.is_empty() => {
+                let path = Path::new(&movie.path).join(&movie.file_name);
+                paths.insert(collection.resolve(path));
+            }
+            Item::Show(show) => {
+                for episode in show.seasons.iter().flat_map(|s| &s.episodes) {
+                    let path = Path::new(&episode.path).join(&episode.file_name);
+                    paths.insert(collection.resolve(path));
+                }
+            }
+            _ => {}
         }
     }
+    paths
 }
 
-impl Default for CollectionRepo {
-    fn default() -> Self {
-        Self::new()
+/// Compare item IDs before and after a scan, None if nothing was added or removed.
+fn diff_items(collection_id: &str, before: &HashSet<String>, after: &HashSet<String>) -> Option<LibraryChange> {
+    let mut items_added: Vec<String> = after.difference(before).cloned().collect();
+    let mut items_removed: Vec<String> = before.difference(after).cloned().collect();
+    if items_added.is_empty() && items_removed.is_empty() {
+        return None;
     }
+    items_added.sort();
+    items_removed.sort();
+    Some(LibraryChange {
+        collection_id: collection_id.to_string(),
+        items_added,
+        items_removed,
+    })
+}
+
+/// Merge the result of a scan into the current state of its collection.
+/// Only the items are taken from the scan, as the collection may have been
+/// renamed or changed meanwhile. If its directories changed the scan result
+/// is outdated and dropped.
+fn merge_scan(current: &Collection, scanned: &Collection) -> Collection {
+    let mut merged = current.clone();
+    if current.directories == scanned.directories {
+        merged.items = scanned.items.clone();
+        merged.last_scan = scanned.last_scan;
+    }
+    merged
+}
+
+/// ScanHooks reports the progress of a scan and tells it when to stop.
+struct ScanHooks<'a> {
+    /// Called with the percentage done after each collection.
+    progress: &'a dyn Fn(f64),
+    /// Checked before each collection.
+    cancelled: &'a dyn Fn() -> bool,
+}
+
+impl ScanHooks<'static> {
+    const NONE: ScanHooks<'static> = ScanHooks {
+        progress: &|_| {},
+        cancelled: &|| false,
+    };
 }
 
 #[cfg(test)]
@@ -476,7 +976,7 @@ mod tests {
             "Test Movies".to_string(),
             None,
             "movies",
-            "/test/movies".to_string(),
+            vec!["/test/movies".to_string()],
             "".to_string(),
         );
 
@@ -492,7 +992,7 @@ mod tests {
             "Test".to_string(),
             None,
             "invalid",
-            "/test".to_string(),
+            vec!["/test".to_string()],
             "".to_string(),
         );
 
@@ -507,7 +1007,7 @@ mod tests {
             "Test Movies".to_string(),
             Some("test-id".to_string()),
             "movies",
-            "/test/movies".to_string(),
+            vec!["/test/movies".to_string()],
             "".to_string(),
         )
         .unwrap();
@@ -516,4 +1016,157 @@ mod tests {
         assert!(collection.is_some());
         assert_eq!(collection.unwrap().name, "Test Movies");
     }
+
+    #[test]
+    fn test_diff_items() {
+        let before: HashSet<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+        let after: HashSet<String> = ["b", "c"].iter().map(|s| s.to_string()).collect();
+
+        let change = diff_items("coll", &before, &after).unwrap();
+        assert_eq!(change.collection_id, "coll");
+        assert_eq!(change.items_added, vec!["c".to_string()]);
+        assert_eq!(change.items_removed, vec!["a".to_string()]);
+
+        assert!(diff_items("coll", &after, &after).is_none());
+    }
+    #[test]
+    fn test_merge_scan() {
+        let dirs = vec!["/movies".to_string()];
+        let mut scanned = Collection::new(
+            "c1".into(),
+            "Movies".into(),
+            CollectionType::Movies,
+            dirs,
+            "".into(),
+        );
+        scanned.last_scan = Some(std::time::SystemTime::now());
+
+        // Renamed while scanning: the new name is kept, the scan result used.
+        let mut current = scanned.clone();
+        current.name = "Films".to_string();
+        current.last_scan = None;
+        let merged = merge_scan(&current, &scanned);
+        assert_eq!(merged.name, "Films");
+        assert_eq!(merged.last_scan, scanned.last_scan);
+
+        // Directories changed while scanning: the scan result is outdated.
+        current.directories = vec!["/films".to_string()];
+        let merged = merge_scan(&current, &scanned);
+        assert_eq!(merged.directories, vec!["/films".to_string()]);
+        assert_eq!(merged.last_scan, None);
+    }
+
+    #[test]
+    fn test_person_mixed_case() {
+        let root = std::env::temp_dir().join("test_person_mixed_case");
+        let _ = std::fs::remove_dir_all(&root);
+        let dir = root.join("Alien (1979)");
+        std::fs::create_dir_all(dir.join(".actors")).unwrap();
+        std::fs::write(dir.join("alien.mkv"), b"a").unwrap();
+        std::fs::write(dir.join(".actors/Sigourney_Weaver.jpg"), b"jpg").unwrap();
+        std::fs::write(
+            dir.join("movie.nfo"),
+            "<movie><actor><name>Sigourney Weaver</name></actor></movie>",
+        )
+        .unwrap();
+
+        let repo = CollectionRepo::new();
+        repo.add_collection(
+            "Movies".to_string(),
+            Some("movies".to_string()),
+            "movies",
+            vec![root.to_string_lossy().to_string()],
+            "".to_string(),
+        )
+        .unwrap();
+        repo.init();
+
+        // Paths are lowercased before they reach the handlers.
+        let name = repo.person_name("sigourney weaver").unwrap();
+        assert_eq!(name, "Sigourney Weaver");
+        let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, &name);
+        assert_eq!(
+            repo.person_image(&person_id),
+            Some(dir.join(".actors/Sigourney_Weaver.jpg"))
+        );
+        assert!(repo.person_name("john hurt").is_none());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_person_image_outside_item() {
+        let root = std::env::temp_dir().join("test_person_image_outside_item");
+        let _ = std::fs::remove_dir_all(&root);
+        let dir = root.join("Alien (1979)");
+        std::fs::create_dir_all(dir.join(".actors")).unwrap();
+        std::fs::write(dir.join("alien.mkv"), b"a").unwrap();
+        std::fs::write(root.join("secret.jpg"), b"jpg").unwrap();
+        std::fs::write(
+            dir.join("movie.nfo"),
+            format!(
+                "<movie>\
+                 <actor><name>John Hurt</name><thumb>../secret.jpg</thumb></actor>\
+                 <actor><name>Ian Holm</name><thumb>{}</thumb></actor>\
+                 <actor><name>Tom Skerritt</name><thumb>.actors/../../secret.jpg</thumb></actor>\
+                 <actor><name>Yaphet Kotto</name><thumb>.actors/Yaphet_Kotto.jpg</thumb></actor>\
+                 </movie>",
+                root.join("secret.jpg").display()
+            ),
+        )
+        .unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("secret.jpg"), dir.join(".actors/Yaphet_Kotto.jpg")).unwrap();
+
+        let repo = CollectionRepo::new();
+        repo.add_collection(
+            "Movies".to_string(),
+            Some("movies".to_string()),
+            "movies",
+            vec![root.to_string_lossy().to_string()],
+            "".to_string(),
+        )
+        .unwrap();
+        repo.init();
+
+        for name in ["John Hurt", "Ian Holm", "Tom Skerritt", "Yaphet Kotto"] {
+            let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, name);
+            assert_eq!(repo.person_image(&person_id), None, "{}", name);
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_video_paths() {
+        let root = std::env::temp_dir().join("test_video_paths");
+        let _ = std::fs::remove_dir_all(&root);
+        let movie = root.join("Alien (1979)");
+        std::fs::create_dir_all(&movie).unwrap();
+        std::fs::write(movie.join("alien.mkv"), b"a").unwrap();
+
+        let repo = CollectionRepo::new();
+        repo.add_collection(
+            "Movies".to_string(),
+            Some("movies".to_string()),
+            "movies",
+            vec![root.to_string_lossy().to_string()],
+            "".to_string(),
+        )
+        .unwrap();
+        repo.init();
+
+        let collection = repo.get_collections().remove(0);
+        let paths = video_paths(&collection);
+        assert_eq!(paths, HashSet::from([movie.join("alien.mkv")]));
+
+        // A rescan after the movie is deleted reports its file as removed.
+        std::fs::remove_dir_all(&movie).unwrap();
+        assert!(repo.rescan_path(&movie));
+        let after = video_paths(&repo.get_collections().remove(0));
+        assert!(after.is_empty());
+        assert_eq!(paths.difference(&after).count(), 1);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
@@ -447,8 +447,10 @@ impl Item {
     /// Returns whether the item has subtitles.
     pub fn has_subtitles(&self) -> bool {
         match self {
-            Item::Movie(m) => !m.srt_subs.is_empty() || !m.vtt_subs.is_empty(),
-            Item::Episode(e) => !e.srt_subs.is_empty() || !e.vtt_subs.is_empty(),
+            Item::Movie(m) => !m.srt_subs.is_empty() || !m.vtt_subs.is_empty() || !m.metadata.subtitle_tracks.is_empty(),
+            Item::Episode(e) => {
+                !e.srt_subs.is_empty() || !e.vtt_subs.is_empty() || !e.metadata.subtitle_tracks.is_empty()
+            }
             _ => false,
         }
     }
@@ -1,79 +1,418 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tracing::info;
 use walkdir::WalkDir;
 
-use super::collection::Collection;
+use super::collection::{Collection, CollectionType};
 use super::item::{Episode, Item, Movie, Season, Show};
-use super::metadata::Metadata;
+use super::metadata::{Actor, Metadata};
 use crate::idhash::*;
 
-/// Build movies collection by scanning directory
-pub fn build_movies(collection: &mut Collection, _scan_interval: Duration) {
-    info!("Scanning movies in: {}", collection.directory);
+/// Directory of a movie or show with images of the actors.
+pub(super) const ACTORS_DIR: &str = ".actors";
+
+/// Build movies collection by scanning its directories.
+///
+/// A zero `scan_interval`, or a collection that was never scanned, results in
+/// a full scan. Otherwise only movie directories that were modified since the
+/// last scan are rescanned, and all other movies are carried over as-is.
+///
+/// A movie that is found in more than one directory is only added once, from
+/// the first directory that has it.
+pub fn build_movies(collection: &mut Collection, scan_interval: Duration) {
+    info!("Scanning movies in: {}", collection.directories.join(", "));
+
+    let scan_start = SystemTime::now();
+    let last_scan = collection.last_scan.filter(|_| !scan_interval.is_zero());
+
+    // Previous movies by relative path, so unchanged ones can be reused.
+    let mut previous: HashMap<String, Movie> = HashMap::new();
+    if last_scan.is_some() {
+        for item in collection.items.drain(..) {
+            if let Item::Movie(movie) = item {
+                previous.insert(movie.path.clone(), movie);
+            }
+        }
+    }
 
     let mut movies = Vec::new();
+    let mut seen = HashSet::new();
+    let mut rescanned = 0;
+
+    // Walk the directories looking for movie folders
+    for root in &collection.directories {
+        for entry in WalkDir::new(root)
+            .follow_links(true)
+            .max_depth(2)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if !entry.file_type().is_dir() {
+                continue;
+            }
 
-    // Walk the directory looking for movie folders
-    for entry in WalkDir::new(&collection.directory)
-        .follow_links(true)
-        .max_depth(2)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        if !entry.file_type().is_dir() {
-            continue;
-        }
+            let path = entry.path();
+            let relative_path = relative_path(path, root);
 
-        let path = entry.path();
-        if let Some(mut movie) = scan_movie_directory(path, &collection.directory) {
-            movie.base_url = format!("/data/{}", collection.id);
-            movie.collection_id = collection.id.clone();
-            movies.push(Item::Movie(movie));
+            // The id only depends on the directory name, so a movie that was
+            // already found in an earlier directory is skipped unscanned.
+            if seen.contains(&dir_id(ITEM_PREFIX_MOVIE, path)) {
+                info!("Skipping duplicate movie {} in {}", relative_path, root);
+                continue;
+            }
+
+            let prev = previous.remove(&relative_path);
+            if let (Some(prev), Some(since)) = (&prev, last_scan) {
+                if !dir_modified_since(path, since) {
+                    seen.insert(prev.id.clone());
+                    movies.push(Item::Movie(prev.clone()));
+                    continue;
+                }
+            }
+
+            if let Some(mut movie) = scan_movie_directory(path, root) {
+                seen.insert(movie.id.clone());
+                movie.base_url = format!("/data/{}", collection.id);
+                movie.collection_id = collection.id.clone();
+                if let Some(prev) = prev {
+                    movie.created = prev.created;
+                }
+                rescanned += 1;
+                movies.push(Item::Movie(movie));
+            }
         }
     }
 
-    info!("Found {} movies in {}", movies.len(), collection.name);
+    info!(
+        "Found {} movies in {} ({} scanned)",
+        movies.len(),
+        collection.name,
+        rescanned
+    );
     collection.items = movies;
+    collection.last_scan = Some(scan_start);
 }
 
-/// Build shows collection by scanning directory
-pub fn build_shows(collection: &mut Collection, _scan_interval: Duration) {
-    info!("Scanning shows in: {}", collection.directory);
+/// Build shows collection by scanning its directories.
+///
+/// Like `build_movies`, a non-zero `scan_interval` on a previously scanned
+/// collection only rescans show directories (or their season directories)
+/// that were modified since the last scan, and a show that is found in more
+/// than one directory is taken from the first one.
+pub fn build_shows(collection: &mut Collection, scan_interval: Duration) {
+    info!("Scanning shows in: {}", collection.directories.join(", "));
+
+    let scan_start = SystemTime::now();
+    let last_scan = collection.last_scan.filter(|_| !scan_interval.is_zero());
+
+    // Previous shows by relative path, so unchanged ones can be reused.
+    let mut previous: HashMap<String, Show> = HashMap::new();
+    if last_scan.is_some() {
+        for item in collection.items.drain(..) {
+            if let Item::Show(show) = item {
+                previous.insert(show.path.clone(), show);
+            }
+        }
+    }
 
     let mut shows = Vec::new();
+    let mut seen = HashSet::new();
+    let mut rescanned = 0;
+
+    // Walk the directories looking for show folders
+    for root in &collection.directories {
+        for entry in WalkDir::new(root)
+            .follow_links(true)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if !entry.file_type().is_dir() {
+                continue;
+            }
 
-    // Walk the directory looking for show folders
-    for entry in WalkDir::new(&collection.directory)
-        .follow_links(true)
-        .max_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
+            let path = entry.path();
+            if path == Path::new(root) {
+                continue; // Skip root directory
+            }
+
+            let relative_path = relative_path(path, root);
+            if seen.contains(&dir_id(ITEM_PREFIX_SHOW, path)) {
+                info!("Skipping duplicate show {} in {}", relative_path, root);
+                continue;
+            }
+
+            let prev = previous.remove(&relative_path);
+            if let (Some(prev), Some(since)) = (&prev, last_scan) {
+                if !dir_modified_since(path, since) {
+                    seen.insert(prev.id.clone());
+                    shows.push(Item::Show(prev.clone()));
+                    continue;
+                }
+            }
+
+            if let Some(mut show) = scan_show_directory(path, root) {
+                seen.insert(show.id.clone());
+                show.base_url = format!("/data/{}", collection.id);
+                if let Some(prev) = prev {
+                    carry_over_show_timestamps(&mut show, &prev);
+                }
+                let mut item = Item::Show(show);
+                item.set_collection_id(collection.id.clone());
+                item.populate_hierarchy_ids();
+                rescanned += 1;
+                shows.push(item);
+            }
+        }
+    }
+
+    info!(
+        "Found {} shows in {} ({} scanned)",
+        shows.len(),
+        collection.name,
+        rescanned
+    );
+    collection.items = shows;
+    collection.last_scan = Some(scan_start);
+}
+
+/// Rescan only the movie or show directory that contains `path`, e.g. after a
+/// filesystem event. Inside a show, a change below a season directory only
+/// rescans that season. Returns true if the path belonged to the collection.
+///
+/// The directory is looked up by its path relative to the collection, so a
+/// movie or show that also exists in another directory of the collection
+/// comes back from there when it is removed here.
+pub fn rescan_path(collection: &mut Collection, path: &Path) -> bool {
+    let components: Vec<_> = match collection
+        .root(path)
+        .and_then(|root| path.strip_prefix(root).ok())
     {
-        if !entry.file_type().is_dir() {
-            continue;
+        Some(rel) => rel.components().map(|c| c.as_os_str().to_owned()).collect(),
+        None => return false,
+    };
+    if components.is_empty() {
+        return false;
+    }
+
+    match collection.collection_type {
+        CollectionType::Movies => {
+            // Movies live at depth 1 or 2 below the collection root.
+            let mut dir = PathBuf::new();
+            for component in components.iter().take(2) {
+                dir.push(component);
+                rescan_movie(collection, &dir);
+            }
+        }
+        CollectionType::Shows => {
+            let show_dir = PathBuf::from(&components[0]);
+            let season_dir = components
+                .get(1)
+                .map(|c| show_dir.join(c))
+                .filter(|d| collection.resolve(d).is_dir());
+            match season_dir {
+                Some(season_dir) if rescan_season(collection, &show_dir, &season_dir) => {}
+                _ => rescan_show(collection, &show_dir),
+            }
         }
+    }
+    true
+}
 
-        let path = entry.path();
-        if path == Path::new(&collection.directory) {
-            continue; // Skip root directory
+/// Rescan a single movie directory, adding, replacing or removing the movie.
+/// `dir` is relative to the collection.
+fn rescan_movie(collection: &mut Collection, dir: &Path) {
+    let relative_path = dir.to_str().unwrap_or_default().to_string();
+    let pos = collection
+        .items
+        .iter()
+        .position(|i| matches!(i, Item::Movie(m) if m.path == relative_path));
+
+    let movie = find_directory(collection, dir).and_then(|(dir, root)| scan_movie_directory(&dir, &root));
+
+    match (movie, pos) {
+        (Some(mut movie), pos) => {
+            movie.base_url = format!("/data/{}", collection.id);
+            movie.collection_id = collection.id.clone();
+            match pos {
+                Some(pos) => {
+                    if let Item::Movie(prev) = &collection.items[pos] {
+                        movie.created = prev.created;
+                    }
+                    collection.items[pos] = Item::Movie(movie);
+                }
+                None => {
+                    info!("Added movie {} to {}", relative_path, collection.name);
+                    collection.items.push(Item::Movie(movie));
+                }
+            }
+        }
+        (None, Some(pos)) => {
+            info!("Removed movie {} from {}", relative_path, collection.name);
+            collection.items.remove(pos);
         }
+        (None, None) => {}
+    }
+}
+
+/// Rescan a complete show directory, adding, replacing or removing the show.
+/// `dir` is relative to the collection.
+fn rescan_show(collection: &mut Collection, dir: &Path) {
+    let relative_path = dir.to_str().unwrap_or_default().to_string();
+    let pos = collection
+        .items
+        .iter()
+        .position(|i| matches!(i, Item::Show(s) if s.path == relative_path));
+
+    let show = find_directory(collection, dir).and_then(|(dir, root)| scan_show_directory(&dir, &root));
 
-        if let Some(mut show) = scan_show_directory(path, &collection.directory) {
+    match (show, pos) {
+        (Some(mut show), pos) => {
             show.base_url = format!("/data/{}", collection.id);
+            if let Some(Item::Show(prev)) = pos.map(|p| &collection.items[p]) {
+                carry_over_show_timestamps(&mut show, prev);
+            }
             let mut item = Item::Show(show);
             item.set_collection_id(collection.id.clone());
             item.populate_hierarchy_ids();
-            shows.push(item);
+            match pos {
+                Some(pos) => collection.items[pos] = item,
+                None => {
+                    info!("Added show {} to {}", relative_path, collection.name);
+                    collection.items.push(item);
+                }
+            }
         }
+        (None, Some(pos)) => {
+            info!("Removed show {} from {}", relative_path, collection.name);
+            collection.items.remove(pos);
+        }
+        (None, None) => {}
     }
+}
 
-    info!("Found {} shows in {}", shows.len(), collection.name);
-    collection.items = shows;
+/// Rescan one season directory of a show that is already known. Both
+/// directories are relative to the collection. Returns false if the show is
+/// unknown or the directory is not a season.
+fn rescan_season(collection: &mut Collection, show_dir: &Path, season_dir: &Path) -> bool {
+    let season_no = match season_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(parse_season_number)
+    {
+        Some(n) => n,
+        None => return false,
+    };
+    let relative_path = show_dir.to_str().unwrap_or_default().to_string();
+    let pos = match collection
+        .items
+        .iter()
+        .position(|i| matches!(i, Item::Show(s) if s.path == relative_path))
+    {
+        Some(pos) => pos,
+        None => return false,
+    };
+
+    let prev = match &collection.items[pos] {
+        Item::Show(show) => show.clone(),
+        _ => return false,
+    };
+    let mut show = prev.clone();
+    show.seasons.retain(|s| s.season_no != season_no);
+    if let Some(season) = scan_season_directory(&collection.resolve(season_dir), &relative_path, season_no) {
+        show.seasons.push(season);
+        show.seasons.sort_by_key(|s| s.season_no);
+    }
+    carry_over_show_timestamps(&mut show, &prev);
+
+    let mut item = Item::Show(show);
+    item.set_collection_id(collection.id.clone());
+    item.populate_hierarchy_ids();
+    collection.items[pos] = item;
+    true
+}
+
+/// Keep the timestamps of a rescanned show stable. Episodes that were already
+/// known keep their creation time; last_video only moves if episodes were added.
+fn carry_over_show_timestamps(show: &mut Show, prev: &Show) {
+    let mut created = HashMap::new();
+    for season in &prev.seasons {
+        for episode in &season.episodes {
+            created.insert(episode.id.as_str(), episode.created);
+        }
+    }
+
+    let mut added = false;
+    for season in &mut show.seasons {
+        for episode in &mut season.episodes {
+            match created.get(episode.id.as_str()) {
+                Some(c) => episode.created = *c,
+                None => added = true,
+            }
+        }
+    }
+
+    show.first_video = prev.first_video;
+    if !added {
+        show.last_video = prev.last_video;
+    }
+}
+
+/// Find a directory, relative to the collection, in the first collection
+/// directory that has it. Returns the full path and the collection directory.
+fn find_directory(collection: &Collection, relative: &Path) -> Option<(PathBuf, String)> {
+    let dir = collection.resolve(relative);
+    if !dir.is_dir() {
+        return None;
+    }
+    let root = collection.root(&dir)?.to_string();
+    Some((dir, root))
+}
+
+/// Relative path of a directory below the collection root.
+fn relative_path(path: &Path, collection_root: &str) -> String {
+    path.strip_prefix(collection_root)
+        .ok()
+        .and_then(|p| p.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Id of the movie or show in directory `path`, which is derived from the
+/// directory name only (see `scan_movie_directory` and `scan_show_directory`).
+fn dir_id(prefix: &str, path: &Path) -> String {
+    let dir_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    id_hash_prefix(prefix, dir_name)
+}
+
+/// Check if a directory, or one of its direct subdirectories, was modified
+/// at or after `since`. Unreadable directories count as modified.
+fn dir_modified_since(path: &Path, since: SystemTime) -> bool {
+    let modified = |p: &Path| {
+        std::fs::metadata(p)
+            .and_then(|m| m.modified())
+            .map(|t| t >= since)
+            .unwrap_or(true)
+    };
+
+    if modified(path) {
+        return true;
+    }
+
+    let entries = match std::fs::read_dir(path) {
+        Ok(e) => e,
+        Err(_) => return true,
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .any(|p| modified(&p))
 }
 
 /// Scan a movie directory for video files and metadata
-fn scan_movie_directory(path: &Path, collection_root: &str) -> Option<Movie> {
+pub fn scan_movie_directory(path: &Path, collection_root: &str) -> Option<Movie> {
     let dir_name = path.file_name()?.to_str()?;
 
     // Find video file
@@ -108,11 +447,13 @@ fn scan_movie_directory(path: &Path, collection_root: &str) -> Option<Movie> {
                 .and_then(|s| s.to_str())
                 .unwrap_or(dir_name);
             let nfo_path = find_nfo(path, dir_name, video_stem);
-            if !nfo_path.as_os_str().is_empty() {
+            let mut metadata = if !nfo_path.as_os_str().is_empty() {
                 super::nfo::parse_movie_nfo(&nfo_path).unwrap_or_default()
             } else {
                 Metadata::default()
-            }
+            };
+            find_actor_thumbs(path, &mut metadata.actors);
+            metadata
         },
         srt_subs: Vec::new(), // TODO: Find subtitles
         vtt_subs: Vec::new(),
@@ -122,7 +463,7 @@ fn scan_movie_directory(path: &Path, collection_root: &str) -> Option<Movie> {
 }
 
 /// Scan a show directory for seasons and episodes
-fn scan_show_directory(path: &Path, collection_root: &str) -> Option<Show> {
+pub fn scan_show_directory(path: &Path, collection_root: &str) -> Option<Show> {
     let dir_name = path.file_name()?.to_str()?;
 
     // Generate ID from directory name
@@ -186,11 +527,13 @@ fn scan_show_directory(path: &Path, collection_root: &str) -> Option<Show> {
         file_size: 0,
         metadata: {
             let nfo_path = path.join("tvshow.nfo");
-            if nfo_path.exists() {
+            let mut metadata = if nfo_path.exists() {
                 super::nfo::parse_show_nfo(&nfo_path).unwrap_or_default()
             } else {
                 Metadata::default()
-            }
+            };
+            find_actor_thumbs(path, &mut metadata.actors);
+            metadata
         },
         srt_subs: Vec::new(),
         vtt_subs: Vec::new(),
@@ -340,7 +683,7 @@ fn is_video_file(path: &Path) -> bool {
 }
 
 /// Find an image file with a specific name pattern
-fn find_image(path: &Path, name: &str) -> String {
+pub(super) fn find_image(path: &Path, name: &str) -> String {
     let extensions = ["jpg", "jpeg", "png", "webp"];
 
     for ext in &extensions {
@@ -355,6 +698,22 @@ fn find_image(path: &Path, name: &str) -> String {
     String::new()
 }
 
+/// Use the images in the `.actors` directory of a movie or show as actor
+/// thumbnails, in favour of the ones from the NFO. Kodi names them after the
+/// actor with spaces replaced by underscores, e.g. `.actors/John_Hurt.jpg`.
+fn find_actor_thumbs(path: &Path, actors: &mut [Actor]) {
+    let dir = path.join(ACTORS_DIR);
+    if actors.is_empty() || !dir.is_dir() {
+        return;
+    }
+    for actor in actors {
+        let image = find_image(&dir, &actor.name.replace(' ', "_"));
+        if !image.is_empty() {
+            actor.thumb = Some(format!("{}/{}", ACTORS_DIR, image));
+        }
+    }
+}
+
 /// Find NFO file for a movie
 fn find_nfo(path: &Path, dir_name: &str, video_stem: &str) -> PathBuf {
     // 1. Check video_filename.nfo
@@ -417,4 +776,157 @@ mod tests {
         assert_eq!(parse_season_number("S10"), Some(10));
         assert_eq!(parse_season_number("Invalid"), None);
     }
+
+    #[test]
+    fn test_actor_thumbs() {
+        let root = std::env::temp_dir().join("test_actor_thumbs");
+        let _ = std::fs::remove_dir_all(&root);
+        let dir = root.join("Alien (1979)");
+        std::fs::create_dir_all(dir.join(".actors")).unwrap();
+        std::fs::write(dir.join("alien.mkv"), b"a").unwrap();
+        std::fs::write(dir.join(".actors/Sigourney_Weaver.jpg"), b"jpg").unwrap();
+        std::fs::write(
+            dir.join("movie.nfo"),
+            r#"<movie>
+                <actor><name>Sigourney Weaver</name><thumb>https://example.com/sw.jpg</thumb></actor>
+                <actor><name>John Hurt</name><thumb>https://example.com/jh.jpg</thumb></actor>
+                <actor><name>Tom Skerritt</name></actor>
+            </movie>"#,
+        )
+        .unwrap();
+
+        let movie = scan_movie_directory(&dir, root.to_str().unwrap()).unwrap();
+        let thumbs: Vec<Option<&str>> = movie.metadata.actors.iter().map(|a| a.thumb.as_deref()).collect();
+        assert_eq!(
+            thumbs,
+            vec![
+                Some(".actors/Sigourney_Weaver.jpg"),
+                Some("https://example.com/jh.jpg"),
+                None
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_build_movies_incremental() {
+        let root = std::env::temp_dir().join("test_build_movies_incremental");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("Movie A (2001)")).unwrap();
+        std::fs::write(root.join("Movie A (2001)/movie-a.mkv"), b"a").unwrap();
+
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec![root.to_str().unwrap().to_string()],
+            String::new(),
+        );
+        build_movies(&mut collection, Duration::ZERO);
+        assert_eq!(collection.items.len(), 1);
+        let id_a = collection.items[0].id();
+
+        // Mark the existing movie, so we can see whether it was carried over.
+        if let Item::Movie(m) = &mut collection.items[0] {
+            m.name = "unchanged".to_string();
+        }
+
+        std::fs::create_dir_all(root.join("Movie B (2002)")).unwrap();
+        std::fs::write(root.join("Movie B (2002)/movie-b.mkv"), b"b").unwrap();
+        build_movies(&mut collection, Duration::from_secs(300));
+
+        assert_eq!(collection.items.len(), 2);
+        let a = collection.items.iter().find(|i| i.id() == id_a).unwrap();
+        assert_eq!(a.name(), "unchanged");
+        assert!(collection.items.iter().any(|i| i.name() == "Movie B (2002)"));
+
+        // A zero interval forces a full rescan.
+        build_movies(&mut collection, Duration::ZERO);
+        let a = collection.items.iter().find(|i| i.id() == id_a).unwrap();
+        assert_eq!(a.name(), "Movie A (2001)");
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_build_movies_multiple_directories() {
+        let root = std::env::temp_dir().join("test_build_movies_multiple_directories");
+        let _ = std::fs::remove_dir_all(&root);
+        let (disk1, disk2) = (root.join("disk1"), root.join("disk2"));
+        for (disk, name) in [
+            (&disk1, "Movie A (2001)"),
+            (&disk2, "Movie A (2001)"),
+            (&disk2, "Movie B (2002)"),
+        ] {
+            std::fs::create_dir_all(disk.join(name)).unwrap();
+            std::fs::write(disk.join(name).join("movie.mkv"), b"m").unwrap();
+        }
+
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec![
+                disk1.to_str().unwrap().to_string(),
+                disk2.to_str().unwrap().to_string(),
+            ],
+            String::new(),
+        );
+        build_movies(&mut collection, Duration::ZERO);
+        assert_eq!(collection.items.len(), 2);
+        assert_eq!(
+            collection.resolve("Movie A (2001)/movie.mkv"),
+            disk1.join("Movie A (2001)/movie.mkv")
+        );
+        assert_eq!(
+            collection.resolve("Movie B (2002)/movie.mkv"),
+            disk2.join("Movie B (2002)/movie.mkv")
+        );
+
+        // Once removed from the first disk, the movie is found on the second.
+        std::fs::remove_dir_all(disk1.join("Movie A (2001)")).unwrap();
+        assert!(rescan_path(&mut collection, &disk1.join("Movie A (2001)")));
+        assert_eq!(collection.items.len(), 2);
+        assert_eq!(
+            collection.resolve("Movie A (2001)/movie.mkv"),
+            disk2.join("Movie A (2001)/movie.mkv")
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_build_shows_multiple_directories() {
+        let root = std::env::temp_dir().join("test_build_shows_multiple_directories");
+        let _ = std::fs::remove_dir_all(&root);
+        let (disk1, disk2) = (root.join("disk1"), root.join("disk2"));
+        for (disk, season) in [(&disk1, "Season 1"), (&disk2, "Season 2")] {
+            let dir = disk.join("Show A").join(season);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("Show A S01E01.mkv"), b"e").unwrap();
+        }
+
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Shows".to_string(),
+            CollectionType::Shows,
+            vec![
+                disk1.to_str().unwrap().to_string(),
+                disk2.to_str().unwrap().to_string(),
+            ],
+            String::new(),
+        );
+        build_shows(&mut collection, Duration::ZERO);
+        assert_eq!(collection.items.len(), 1);
+        match &collection.items[0] {
+            Item::Show(show) => {
+                let seasons: Vec<_> = show.seasons.iter().map(|s| s.season_no).collect();
+                assert_eq!(seasons, vec![1]);
+            }
+            _ => panic!("expected a show"),
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
@@ -1,16 +1,25 @@
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
 use std::time::Duration;
 
+use super::kodifs::ACTORS_DIR;
+
 /// Metadata holds metadata information for media items
 #[derive(Debug, Clone, Default)]
 pub struct Metadata {
     pub title: Option<String>,
+    pub original_title: Option<String>,
     pub plot: Option<String>,
     pub taglines: Vec<String>,
     pub genres: Vec<String>,
     pub studios: Vec<String>,
-    pub actors: Vec<String>,
+    pub tags: Vec<String>,
+    /// Cast, in billing order.
+    pub actors: Vec<Actor>,
     pub directors: Vec<String>,
+    pub writers: Vec<String>,
+    pub producers: Vec<String>,
     pub year: Option<i32>,
     pub rating: Option<f32>,
     pub official_rating: Option<String>,
@@ -21,10 +30,114 @@ pub struct Metadata {
     pub video_frame_rate: Option<f64>,
     pub video_height: Option<i32>,
     pub video_width: Option<i32>,
+    pub video_profile: Option<String>,
+    pub video_bit_depth: Option<i32>,
+    /// video_range_type is "SDR", "HDR10", "HLG", "DOVI" or "DOVIWithHDR10".
+    pub video_range_type: Option<String>,
     pub audio_codec: Option<String>,
     pub audio_bitrate: Option<i32>,
     pub audio_channels: Option<i32>,
     pub audio_language: Option<String>,
+    /// All audio tracks of the video file, in file order.
+    pub audio_tracks: Vec<AudioTrack>,
+    /// Subtitle tracks embedded in the video file, in file order.
+    pub subtitle_tracks: Vec<SubtitleTrack>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// Actor is a member of the cast of a movie or show.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Actor {
+    pub name: String,
+    pub role: Option<String>,
+    pub order: Option<i32>,
+    /// Image of the actor, a path relative to the item directory or a URL.
+    pub thumb: Option<String>,
+}
+
+/// PersonKind is the part a person had in the making of a movie or show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PersonKind {
+    Actor,
+    Director,
+    Writer,
+    Producer,
+}
+
+impl PersonKind {
+    /// Jellyfin name of the person type.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PersonKind::Actor => "Actor",
+            PersonKind::Director => "Director",
+            PersonKind::Writer => "Writer",
+            PersonKind::Producer => "Producer",
+        }
+    }
+}
+
+/// Credit is a person credited for a movie or show.
+#[derive(Debug, Clone, Copy)]
+pub struct Credit<'a> {
+    pub name: &'a str,
+    pub kind: PersonKind,
+    pub role: Option<&'a str>,
+    pub thumb: Option<&'a str>,
+}
+
+impl Credit<'_> {
+    /// Image of the person if it is on the web.
+    pub fn thumb_url(&self) -> Option<&str> {
+        self.thumb.filter(|t| is_url(t))
+    }
+
+    /// Image of the person if it is a file in the `.actors` directory,
+    /// relative to the item directory. Other paths in an NFO could point
+    /// anywhere on the server and are ignored.
+    pub fn thumb_file(&self) -> Option<&str> {
+        self.thumb.filter(|t| {
+            let mut parts = Path::new(t).components();
+            matches!(
+                (parts.next(), parts.next(), parts.next()),
+                (Some(Component::Normal(dir)), Some(Component::Normal(_)), None) if dir == ACTORS_DIR
+            )
+        })
+    }
+}
+
+pub(crate) fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+/// AudioTrack describes one audio stream of a video file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: Option<i32>,
+    pub bitrate: Option<i32>,
+    pub sample_rate: Option<i32>,
+    pub profile: Option<String>,
+    pub default: bool,
+}
+
+/// SubtitleTrack describes one subtitle stream of a video file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleTrack {
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+    pub hearing_impaired: bool,
+}
+
+/// Chapter marks the start of a chapter in a video file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start: Duration,
+    pub name: Option<String>,
 }
 
 impl Metadata {
@@ -35,4 +148,29 @@ impl Metadata {
     pub fn duration(&self) -> Option<Duration> {
         self.duration
     }
+
+    /// All people credited: the cast in billing order, then directors,
+    /// writers and producers.
+    pub fn credits(&self) -> impl Iterator<Item = Credit<'_>> {
+        fn crew(names: &[String], kind: PersonKind) -> impl Iterator<Item = Credit<'_>> {
+            names.iter().map(move |name| Credit {
+                name: name.as_str(),
+                kind,
+                role: None,
+                thumb: None,
+            })
+        }
+        self.actors
+            .iter()
+            .map(|a| Credit {
+                name: a.name.as_str(),
+                kind: PersonKind::Actor,
+                role: a.role.as_deref(),
+                thumb: a.thumb.as_deref(),
+            })
+            .chain(crew(&self.directors, PersonKind::Director))
+            .chain(crew(&self.writers, PersonKind::Writer))
+            .chain(crew(&self.producers, PersonKind::Producer))
+            .filter(|c| !c.name.is_empty())
+    }
 }
@@ -3,16 +3,20 @@ pub mod collectionrepo;
 pub mod item;
 pub mod kodifs;
 pub mod metadata;
+pub mod parentalrating;
 pub mod parsefilename;
+pub mod people;
 pub mod search;
+pub mod watcher;
 
 pub use collection::{Collection, CollectionDetails, CollectionType};
-pub use collectionrepo::CollectionRepo;
+pub use collectionrepo::{CollectionRepo, LibraryChange};
 pub use item::{
     make_sort_name, CollectionFolder, Episode, Item, ItemRef, Movie, PlaylistItem, Season, Show, Subs,
     Subtitles, UserView,
 };
-pub use metadata::Metadata;
+pub use metadata::{Actor, AudioTrack, Chapter, Credit, Metadata, PersonKind, SubtitleTrack};
+pub use parentalrating::{parental_rating_score, PARENTAL_RATINGS};
 pub use parsefilename::parse_episode_name;
 pub use search::{Search, SearchDocument};
 pub mod nfo;
@@ -6,7 +6,8 @@ use chrono::Datelike;
 use quick_xml::de::from_str;
 use tracing::warn;
 
-use super::metadata::Metadata;
+use super::metadata::{is_url, Actor, AudioTrack, Metadata, SubtitleTrack};
+use super::people::PersonInfo;
 use crate::jellyfin::parse_iso8601_date;
 
 /// Parse movie NFO file
@@ -51,13 +52,26 @@ pub fn parse_episode_nfo(path: &Path) -> Option<Metadata> {
     Some(nfo.into())
 }
 
+/// Parse person NFO file
+pub fn parse_person_nfo(path: &Path) -> Option<PersonInfo> {
+    let content = fs::read_to_string(path).ok()?;
+    let nfo: PersonNfo = match from_str(&content) {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Failed to parse person NFO {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    Some(nfo.into())
+}
+
 // --- NFO Structures ---
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(default, rename_all = "lowercase")]
 struct MovieNfo {
     title: Option<String>,
-    #[allow(dead_code)]
     originaltitle: Option<String>,
     #[allow(dead_code)]
     sorttitle: Option<String>,
@@ -68,8 +82,11 @@ struct MovieNfo {
     mpaa: Option<String>,
     genre: Vec<String>,
     studio: Vec<String>,
+    tag: Vec<String>,
     actor: Vec<NfoActor>,
     director: Vec<String>,
+    credits: Vec<String>,
+    producer: Vec<NfoProducer>,
     premiered: Option<String>,
     fileinfo: Option<FileInfo>,
 }
@@ -78,6 +95,7 @@ struct MovieNfo {
 #[serde(default, rename_all = "lowercase")]
 struct ShowNfo {
     title: Option<String>,
+    originaltitle: Option<String>,
     rating: Option<f32>,
     year: Option<i32>,
     plot: Option<String>,
@@ -85,8 +103,11 @@ struct ShowNfo {
     mpaa: Option<String>,
     genre: Vec<String>,
     studio: Vec<String>,
+    tag: Vec<String>,
     actor: Vec<NfoActor>,
     director: Vec<String>,
+    credits: Vec<String>,
+    producer: Vec<NfoProducer>,
     premiered: Option<String>,
 }
 
@@ -101,6 +122,21 @@ struct EpisodeNfo {
     fileinfo: Option<FileInfo>,
 }
 
+/// Jellyfin writes `<plot>`, `<premiered>` and `<placeofbirth>`, other
+/// tools `<biography>`, `<birthdate>` and `<birthplace>`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "lowercase")]
+struct PersonNfo {
+    name: Option<String>,
+    #[serde(alias = "plot", alias = "overview")]
+    biography: Option<String>,
+    #[serde(alias = "premiered")]
+    birthdate: Option<String>,
+    #[serde(alias = "placeofbirth")]
+    birthplace: Option<String>,
+    thumb: Vec<String>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(default, rename_all = "lowercase")]
 struct FileInfo {
@@ -111,7 +147,8 @@ struct FileInfo {
 #[serde(default, rename_all = "lowercase")]
 struct StreamDetails {
     video: Option<VideoDetails>,
-    audio: Option<AudioDetails>,
+    audio: Vec<AudioDetails>,
+    subtitle: Vec<SubtitleDetails>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -134,12 +171,30 @@ struct AudioDetails {
     channels: Option<i32>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "lowercase")]
+struct SubtitleDetails {
+    codec: Option<String>,
+    language: Option<String>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(default, rename_all = "lowercase")]
 struct NfoActor {
     name: String,
-    #[allow(dead_code)]
     role: Option<String>,
+    order: Option<i32>,
+    thumb: Option<String>,
+}
+
+/// Producers are either `<producer>Name</producer>` or, like actors,
+/// `<producer><name>Name</name></producer>`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NfoProducer {
+    #[serde(rename = "$text")]
+    text: Option<String>,
+    name: Option<String>,
 }
 
 // --- Conversions ---
@@ -151,6 +206,7 @@ impl From<MovieNfo> for Metadata {
 
         let mut m = Metadata {
             title: nfo.title,
+            original_title: nfo.originaltitle,
             plot: nfo.plot,
             rating: nfo.rating,
             year: year,
@@ -158,8 +214,11 @@ impl From<MovieNfo> for Metadata {
             official_rating: nfo.mpaa,
             genres: nfo.genre,
             studios: nfo.studio,
-            actors: nfo.actor.into_iter().map(|a| a.name).collect(),
+            tags: nfo.tag,
+            actors: make_actors(nfo.actor),
             directors: nfo.director,
+            writers: nfo.credits,
+            producers: make_producers(nfo.producer),
             taglines: nfo.tagline,
             ..Default::default()
         };
@@ -179,6 +238,7 @@ impl From<ShowNfo> for Metadata {
 
         Metadata {
             title: nfo.title,
+            original_title: nfo.originaltitle,
             plot: nfo.plot,
             rating: nfo.rating,
             premiered: premiered,
@@ -186,8 +246,11 @@ impl From<ShowNfo> for Metadata {
             official_rating: nfo.mpaa,
             genres: nfo.genre,
             studios: nfo.studio,
-            actors: nfo.actor.into_iter().map(|a| a.name).collect(),
+            tags: nfo.tag,
+            actors: make_actors(nfo.actor),
             directors: nfo.director,
+            writers: nfo.credits,
+            producers: make_producers(nfo.producer),
             taglines: nfo.tagline,
             ..Default::default()
         }
@@ -211,6 +274,52 @@ impl From<EpisodeNfo> for Metadata {
     }
 }
 
+impl From<PersonNfo> for PersonInfo {
+    fn from(nfo: PersonNfo) -> Self {
+        let trimmed = |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        PersonInfo {
+            name: trimmed(nfo.name).unwrap_or_default(),
+            biography: trimmed(nfo.biography),
+            birth_date: nfo
+                .birthdate
+                .as_deref()
+                .and_then(|d| parse_iso8601_date(d.trim())),
+            birth_place: trimmed(nfo.birthplace),
+            image: nfo
+                .thumb
+                .into_iter()
+                .map(|t| t.trim().to_string())
+                .find(|t| is_url(t)),
+        }
+    }
+}
+
+/// Convert NFO actors to the cast in billing order. Actors without an order
+/// keep their place after the ones that have one.
+fn make_actors(actors: Vec<NfoActor>) -> Vec<Actor> {
+    let mut actors: Vec<Actor> = actors
+        .into_iter()
+        .map(|a| Actor {
+            name: a.name.trim().to_string(),
+            role: a.role.map(|r| r.trim().to_string()).filter(|r| !r.is_empty()),
+            order: a.order,
+            thumb: a.thumb.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+        })
+        .filter(|a| !a.name.is_empty())
+        .collect();
+    actors.sort_by_key(|a| a.order.unwrap_or(i32::MAX));
+    actors
+}
+
+fn make_producers(producers: Vec<NfoProducer>) -> Vec<String> {
+    producers
+        .into_iter()
+        .filter_map(|p| p.name.or(p.text))
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
 fn calc_duration(secs: Option<i32>, mins: Option<f32>) -> Option<std::time::Duration> {
     if let Some(s) = secs {
         Some(std::time::Duration::from_secs(s as u64))
@@ -231,12 +340,107 @@ fn apply_file_info(m: &mut Metadata, fi: FileInfo) {
             m.video_bitrate = v.bitrate.map(|b| if b < 250_000 { b * 1000 } else { b });
             m.duration = calc_duration(v.durationinseconds, v.duration);
         }
-        if let Some(a) = sd.audio {
-            m.audio_codec = a.codec;
-            m.audio_language = a.language;
+        // Every <audio> entry is a track, the first one is the default.
+        m.audio_tracks = sd
+            .audio
+            .into_iter()
+            .enumerate()
+            .map(|(i, a)| AudioTrack {
+                codec: a.codec,
+                language: a.language,
+                channels: a.channels,
+                // If it's smaller than 25_000, it's in kbps, otherwise it's in bps
+                bitrate: a.bitrate.map(|b| if b < 25_000 { b * 1000 } else { b }),
+                default: i == 0,
+                ..Default::default()
+            })
+            .collect();
+        if let Some(a) = m.audio_tracks.first() {
+            m.audio_codec = a.codec.clone();
+            m.audio_language = a.language.clone();
             m.audio_channels = a.channels;
-            // If it's smaller than 25_000, it's in kbps, otherwise it's in bps
-            m.audio_bitrate = a.bitrate.map(|b| if b < 25_000 { b * 1000 } else { b });
+            m.audio_bitrate = a.bitrate;
         }
+        m.subtitle_tracks = sd
+            .subtitle
+            .into_iter()
+            .map(|s| SubtitleTrack {
+                codec: s.codec,
+                language: s.language,
+                ..Default::default()
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cast_and_crew() {
+        let xml = r#"<movie>
+            <title>Alien</title>
+            <actor><name>John Hurt</name><role>Kane</role><order>2</order></actor>
+            <actor><name>Sigourney Weaver</name><role>Ripley</role><order>0</order>
+                <thumb>https://example.com/weaver.jpg</thumb></actor>
+            <actor><name>Tom Skerritt</name><role>Dallas</role><order>1</order></actor>
+            <director>Ridley Scott</director>
+            <credits>Dan O'Bannon</credits>
+            <producer>Gordon Carroll</producer>
+            <producer><name>Walter Hill</name><role>Producer</role></producer>
+        </movie>"#;
+        let m: Metadata = from_str::<MovieNfo>(xml).unwrap().into();
+        let names: Vec<&str> = m.actors.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Sigourney Weaver", "Tom Skerritt", "John Hurt"]);
+        assert_eq!(m.actors[0].role.as_deref(), Some("Ripley"));
+        assert_eq!(
+            m.actors[0].thumb.as_deref(),
+            Some("https://example.com/weaver.jpg")
+        );
+        assert_eq!(m.directors, vec!["Ridley Scott"]);
+        assert_eq!(m.writers, vec!["Dan O'Bannon"]);
+        assert_eq!(m.producers, vec!["Gordon Carroll", "Walter Hill"]);
+    }
+
+    #[test]
+    fn test_person_nfo() {
+        let xml = r#"<person>
+            <name> John Hurt </name>
+            <plot>English actor.</plot>
+            <premiered>1940-01-22T00:00:00Z</premiered>
+            <placeofbirth>Chesterfield, England</placeofbirth>
+            <thumb aspect="poster">https://example.com/hurt.jpg</thumb>
+        </person>"#;
+        let p: PersonInfo = from_str::<PersonNfo>(xml).unwrap().into();
+        assert_eq!(p.name, "John Hurt");
+        assert_eq!(p.biography.as_deref(), Some("English actor."));
+        assert_eq!(p.birth_place.as_deref(), Some("Chesterfield, England"));
+        assert_eq!(p.birth_date.map(|d| d.year()), Some(1940));
+        assert_eq!(p.image.as_deref(), Some("https://example.com/hurt.jpg"));
+    }
+
+    #[test]
+    fn test_multiple_audio_and_subtitle_streams() {
+        let nfo = r#"<episodedetails>
+            <title>Pilot</title>
+            <fileinfo><streamdetails>
+                <video><codec>h264</codec><width>1920</width><height>1080</height></video>
+                <audio><codec>aac</codec><language>jpn</language><channels>2</channels></audio>
+                <audio><codec>ac3</codec><language>eng</language><channels>6</channels><bitrate>384</bitrate></audio>
+                <subtitle><language>eng</language></subtitle>
+                <subtitle><language>nld</language></subtitle>
+            </streamdetails></fileinfo>
+        </episodedetails>"#;
+        let m: Metadata = from_str::<EpisodeNfo>(nfo).unwrap().into();
+
+        assert_eq!(m.audio_tracks.len(), 2);
+        assert!(m.audio_tracks[0].default);
+        assert_eq!(m.audio_tracks[1].language.as_deref(), Some("eng"));
+        assert_eq!(m.audio_tracks[1].bitrate, Some(384_000));
+        assert_eq!(m.audio_language.as_deref(), Some("jpn"));
+
+        assert_eq!(m.subtitle_tracks.len(), 2);
+        assert_eq!(m.subtitle_tracks[1].language.as_deref(), Some("nld"));
     }
 }
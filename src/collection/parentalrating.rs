@@ -0,0 +1,108 @@
+/// Well-known ratings and their scores. The score is the minimum age of the
+/// audience, like Jellyfin uses since 10.9.
+pub const PARENTAL_RATINGS: &[(&str, i32)] = &[
+    ("G", 0),
+    ("TV-Y", 0),
+    ("TV-G", 0),
+    ("U", 0),
+    ("AL", 0),
+    ("TV-Y7", 7),
+    ("TV-Y7-FV", 7),
+    ("PG", 10),
+    ("TV-PG", 10),
+    ("12A", 12),
+    ("PG-13", 13),
+    ("TV-14", 14),
+    ("R", 17),
+    ("TV-MA", 17),
+    ("NC-17", 18),
+    ("R18", 18),
+    ("X", 18),
+];
+
+/// Prefixes of country specific ratings that are followed by an age,
+/// e.g. "FSK 16", "NL-12" or "DE/16".
+const AGE_RATING_PREFIXES: &[&str] = &[
+    "FSK",
+    "NL",
+    "DE",
+    "GB",
+    "UK",
+    "FR",
+    "BE",
+    "AT",
+    "CH",
+    "KIJKWIJZER",
+];
+
+/// parental_rating_score maps an official rating, as found in the mpaa
+/// field of NFO files, to a score. Returns None for unknown ratings and
+/// unrated items ("NR", "Unrated", "Not Rated").
+pub fn parental_rating_score(rating: &str) -> Option<i32> {
+    let mut rating = rating.trim().to_uppercase();
+    if let Some(r) = rating.strip_prefix("RATED ") {
+        rating = r.trim().to_string();
+    }
+    // Country prefixed ratings like "US:PG-13" or "DE/16".
+    if let Some((country, value)) = rating.split_once([':', '/']) {
+        if let Some(score) = parental_rating_score(value) {
+            return Some(score);
+        }
+        rating = format!("{} {}", country.trim(), value.trim());
+    }
+    if rating.is_empty() {
+        return None;
+    }
+
+    if let Some((_, score)) = PARENTAL_RATINGS.iter().find(|(name, _)| *name == rating) {
+        return Some(*score);
+    }
+
+    // Plain age, e.g. "12" or "16+".
+    if let Ok(age) = rating.trim_end_matches('+').parse::<i32>() {
+        return Some(age);
+    }
+
+    // Country prefix followed by an age or a rating, e.g. "FSK 16", "NL-12", "NL-AL".
+    for prefix in AGE_RATING_PREFIXES {
+        if let Some(rest) = rating.strip_prefix(prefix) {
+            let rest = rest.trim_start_matches([' ', '-', '_']);
+            if !rest.is_empty() {
+                return parental_rating_score(rest);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parental_rating_score() {
+        assert_eq!(parental_rating_score("G"), Some(0));
+        assert_eq!(parental_rating_score("PG-13"), Some(13));
+        assert_eq!(parental_rating_score("Rated PG-13"), Some(13));
+        assert_eq!(parental_rating_score("US:PG-13"), Some(13));
+        assert_eq!(parental_rating_score("TV-MA"), Some(17));
+        assert_eq!(parental_rating_score("tv-14"), Some(14));
+        assert_eq!(parental_rating_score("NL-12"), Some(12));
+        assert_eq!(parental_rating_score("NL-AL"), Some(0));
+        assert_eq!(parental_rating_score("FSK 16"), Some(16));
+        assert_eq!(parental_rating_score("FSK-0"), Some(0));
+        assert_eq!(parental_rating_score("FSK16"), Some(16));
+        assert_eq!(parental_rating_score("DE/12"), Some(12));
+        assert_eq!(parental_rating_score("16+"), Some(16));
+        assert_eq!(parental_rating_score("12A"), Some(12));
+    }
+
+    #[test]
+    fn test_parental_rating_unrated() {
+        assert_eq!(parental_rating_score(""), None);
+        assert_eq!(parental_rating_score("NR"), None);
+        assert_eq!(parental_rating_score("Not Rated"), None);
+        assert_eq!(parental_rating_score("Unrated"), None);
+    }
+}
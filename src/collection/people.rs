@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use super::kodifs::{find_image, ACTORS_DIR};
+use super::metadata::is_url;
+use super::nfo::parse_person_nfo;
+use crate::database::model::Person;
+use crate::database::Repository;
+use crate::idhash::*;
+
+const PERSON_NFO: &str = "person.nfo";
+const PERSON_IMAGES: &[&str] = &["folder", "poster", "person", "thumb"];
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+/// Person directories are at most this deep, e.g. `people/A/Name/person.nfo`.
+const MAX_DEPTH: usize = 3;
+
+/// PersonInfo is the metadata of a person from the people directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PersonInfo {
+    pub name: String,
+    pub biography: Option<String>,
+    pub birth_date: Option<DateTime<Utc>>,
+    pub birth_place: Option<String>,
+    /// Path of a local image, or an URL.
+    pub image: Option<String>,
+}
+
+/// Scan a people directory for person NFOs and images.
+///
+/// A person is a directory with a `person.nfo` and optionally a `folder.jpg`
+/// or `poster.jpg`, anywhere up to a few levels deep, so both `<Name>/` and
+/// the letter buckets of `people/A/<Name>/` work. Images in `.actors` named
+/// after a person, like Kodi's `.actors/John_Hurt.jpg`, are used for people
+/// without one.
+pub fn scan_people_directory(dir: &Path) -> Vec<PersonInfo> {
+    let mut people = BTreeMap::new();
+    scan_person_dirs(dir, 0, &mut people);
+
+    if let Ok(entries) = fs::read_dir(dir.join(ACTORS_DIR)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let ext = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            if !path.is_file() || !IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let name = stem.replace('_', " ");
+            let person = people.entry(name.clone()).or_insert_with(|| PersonInfo {
+                name,
+                ..Default::default()
+            });
+            if person.image.as_deref().is_none_or(is_url) {
+                person.image = Some(path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    people.into_values().collect()
+}
+
+fn scan_person_dirs(dir: &Path, depth: usize, people: &mut BTreeMap<String, PersonInfo>) {
+    let nfo = dir.join(PERSON_NFO);
+    if depth > 0 && nfo.is_file() {
+        if let Some(person) = scan_person_dir(dir, &nfo) {
+            people.insert(person.name.clone(), person);
+        }
+        return;
+    }
+    if depth == MAX_DEPTH {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && path.is_dir() {
+            scan_person_dirs(&path, depth + 1, people);
+        }
+    }
+}
+
+fn scan_person_dir(dir: &Path, nfo: &Path) -> Option<PersonInfo> {
+    let mut person = parse_person_nfo(nfo)?;
+    if person.name.is_empty() {
+        person.name = dir.file_name()?.to_string_lossy().to_string();
+    }
+    let image = PERSON_IMAGES
+        .iter()
+        .map(|name| find_image(dir, name))
+        .find(|image| !image.is_empty());
+    if let Some(image) = image {
+        person.image = Some(dir.join(image).to_string_lossy().to_string());
+    }
+    Some(person)
+}
+
+/// Import the people directory into the persons table, and remove the
+/// people that are no longer in it. Returns the number of people that were
+/// imported.
+pub async fn import_people(dir: &Path, repo: &dyn Repository) -> Result<usize, String> {
+    if !dir.is_dir() {
+        return Err(format!("people directory {} not found", dir.display()));
+    }
+    info!("Importing people from: {}", dir.display());
+
+    let scan_dir = PathBuf::from(dir);
+    let people = tokio::task::spawn_blocking(move || scan_people_directory(&scan_dir))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let now = Utc::now();
+    let mut imported = 0;
+    for info in people {
+        let person = Person {
+            id: id_hash_prefix(ITEM_PREFIX_PERSON, &info.name),
+            name: info.name,
+            date_of_birth: info.birth_date.unwrap_or_default(),
+            place_of_birth: info.birth_place.unwrap_or_default(),
+            poster_url: info.image.unwrap_or_default(),
+            bio: info.biography.unwrap_or_default(),
+            created: now,
+            last_updated: now,
+        };
+        match repo.upsert_person(&person).await {
+            Ok(()) => imported += 1,
+            Err(e) => warn!("Failed to import person {}: {}", person.name, e),
+        }
+    }
+    info!("Imported {} people", imported);
+
+    // Everyone still in the directory was just updated.
+    match repo.delete_persons_updated_before(now).await {
+        Ok(0) => {}
+        Ok(removed) => info!("Removed {} people no longer in the people directory", removed),
+        Err(e) => warn!("Failed to remove old people: {}", e),
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::PersonRepo;
+
+    #[test]
+    fn test_scan_people_directory() {
+        let root = std::env::temp_dir().join("test_scan_people_directory");
+        let _ = fs::remove_dir_all(&root);
+        let weaver = root.join("people/S/Sigourney Weaver");
+        fs::create_dir_all(&weaver).unwrap();
+        fs::create_dir_all(root.join(".actors")).unwrap();
+        fs::write(
+            weaver.join("person.nfo"),
+            r#"<person>
+                <name>Sigourney Weaver</name>
+                <biography>American actress.</biography>
+                <birthdate>1949-10-08</birthdate>
+                <birthplace>New York City</birthplace>
+                <thumb>https://example.com/weaver.jpg</thumb>
+            </person>"#,
+        )
+        .unwrap();
+        fs::write(weaver.join("folder.jpg"), b"jpg").unwrap();
+        fs::write(root.join(".actors/John_Hurt.jpg"), b"jpg").unwrap();
+        fs::write(root.join(".actors/Sigourney_Weaver.jpg"), b"jpg").unwrap();
+        // Only images in .actors are people.
+        fs::write(root.join("README.jpg"), b"jpg").unwrap();
+
+        let people = scan_people_directory(&root);
+        assert_eq!(people.len(), 2);
+
+        assert_eq!(people[0].name, "John Hurt");
+        let image = root.join(".actors/John_Hurt.jpg");
+        assert_eq!(people[0].image.as_deref(), image.to_str());
+
+        let sw = &people[1];
+        assert_eq!(sw.name, "Sigourney Weaver");
+        assert_eq!(sw.biography.as_deref(), Some("American actress."));
+        assert_eq!(sw.birth_place.as_deref(), Some("New York City"));
+        assert_eq!(
+            sw.birth_date.map(|d| d.format("%Y-%m-%d").to_string()).as_deref(),
+            Some("1949-10-08")
+        );
+        let image = weaver.join("folder.jpg");
+        assert_eq!(sw.image.as_deref(), image.to_str());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_import_people() {
+        let root = std::env::temp_dir().join("test_import_people");
+        let _ = fs::remove_dir_all(&root);
+        let weaver = root.join("Sigourney Weaver");
+        fs::create_dir_all(&weaver).unwrap();
+        fs::create_dir_all(root.join(".actors")).unwrap();
+        fs::write(
+            weaver.join("person.nfo"),
+            "<person><name>Sigourney Weaver</name></person>",
+        )
+        .unwrap();
+        fs::write(root.join(".actors/John_Hurt.jpg"), b"jpg").unwrap();
+
+        let repo = crate::database::SqliteRepository::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let weaver_id = id_hash_prefix(ITEM_PREFIX_PERSON, "Sigourney Weaver");
+        let hurt_id = id_hash_prefix(ITEM_PREFIX_PERSON, "John Hurt");
+        assert_eq!(import_people(&root, &repo).await.unwrap(), 2);
+        assert!(repo.get_person_by_id(&weaver_id).await.is_ok());
+        assert!(repo.get_person_by_id(&hurt_id).await.is_ok());
+
+        // People removed from the directory are removed on the next import.
+        fs::remove_file(root.join(".actors/John_Hurt.jpg")).unwrap();
+        let mut hurt = repo.get_person_by_id(&hurt_id).await.unwrap();
+        hurt.last_updated -= chrono::Duration::days(1);
+        repo.upsert_person(&hurt).await.unwrap();
+        assert_eq!(import_people(&root, &repo).await.unwrap(), 1);
+        assert!(repo.get_person_by_id(&weaver_id).await.is_ok());
+        assert!(repo.get_person_by_id(&hurt_id).await.is_err());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -1,11 +1,69 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
+use std::sync::Mutex;
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, FuzzyTermQuery, MoreLikeThisQuery, Occur, Query,
+    TermQuery,
+};
 use tantivy::schema::*;
-use tantivy::{doc, Index};
+use tantivy::tokenizer::{AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy};
+use tracing::warn;
 
 use super::collection::Collection;
 use super::item::Item;
+use super::metadata::Metadata;
+use crate::idhash::id_hash;
+
+/// Tokenizer of the text fields. Folds accents, so "amelie" finds "Amélie".
+const TOKENIZER: &str = "search";
+
+/// Memory used by the index writer.
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Boosts of the ways a search term can match. Matches in the name count
+/// more than matches in the people, genres or overview, exact matches more
+/// than prefix and fuzzy ones.
+const BOOST_NAME: f32 = 4.0;
+const BOOST_NAME_PREFIX: f32 = 3.0;
+const BOOST_NAME_FUZZY: f32 = 2.0;
+const BOOST_ORIGINAL_TITLE: f32 = 3.0;
+const BOOST_PEOPLE: f32 = 2.0;
+const BOOST_YEAR: f32 = 2.0;
+const BOOST_GENRES: f32 = 1.5;
+const BOOST_STUDIOS: f32 = 1.5;
+const BOOST_TAGLINES: f32 = 1.0;
+const BOOST_OVERVIEW: f32 = 1.0;
+
+/// Weights of what similar items have in common. Shared people and studios
+/// are scored by how rare they are, like the words of the plot and taglines.
+/// Years within `SIMILAR_YEAR_WINDOW` of each other count for less the
+/// further apart they are.
+const SIMILAR_TEXT: f32 = 1.0;
+const SIMILAR_GENRE: f32 = 1.5;
+const SIMILAR_PEOPLE: f32 = 1.0;
+const SIMILAR_STUDIO: f32 = 0.5;
+const SIMILAR_YEAR: f32 = 1.0;
+const SIMILAR_YEAR_WINDOW: i64 = 5;
+
+/// Words of the plot and taglines used to find similar items.
+const SIMILAR_MAX_TERMS: usize = 25;
+
+/// Search terms need at least this many characters to match fuzzily.
+const FUZZY_MIN_LENGTH: usize = 4;
+
+/// Document types of the items in a collection.
+const MEDIA_TYPES: &[&str] = &["movie", "show", "season", "episode"];
+
+/// Document types of the people, studios and genres of a collection. Their
+/// id is their name.
+const NAME_TYPES: &[&str] = &["person", "studio", "genre"];
+
+/// A document is identified within its collection by its type and id.
+type DocKey = (String, String);
 
 /// Search document structure
 #[derive(Debug, Clone)]
@@ -15,6 +73,7 @@ pub struct SearchDocument {
     pub name: String,
     pub overview: String,
     pub genres: Vec<String>,
+    pub year: Option<i32>,
     pub item_type: String,
 }
 
@@ -22,99 +81,314 @@ pub struct SearchDocument {
 pub struct Search {
     index: Index,
     schema: Schema,
+    reader: IndexReader,
+    /// Tantivy allows a single writer per index.
+    writer: Mutex<IndexWriter>,
+    /// Checksums of the indexed documents per collection, read from the
+    /// index the first time a collection is indexed. Lock after `writer`.
+    checksums: Mutex<HashMap<String, HashMap<DocKey, String>>>,
 }
 
 impl Search {
     /// Create a new search index in memory
     pub fn new_in_memory() -> Result<Self, String> {
+        Self::with_index(Index::create_in_ram(Self::schema()))
+    }
+
+    /// Open the search index in `path`, or create it if it does not exist
+    /// or was made with another schema.
+    pub fn new_on_disk(path: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(path).map_err(|e| e.to_string())?;
+        let dir = MmapDirectory::open(path).map_err(|e| e.to_string())?;
+        let index = match Index::open_or_create(dir, Self::schema()) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Recreating search index {}: {}", path.display(), e);
+                std::fs::remove_dir_all(path).map_err(|e| e.to_string())?;
+                std::fs::create_dir_all(path).map_err(|e| e.to_string())?;
+                Index::create_in_dir(path, Self::schema()).map_err(|e| e.to_string())?
+            }
+        };
+        Self::with_index(index)
+    }
+
+    fn schema() -> Schema {
         let mut schema_builder = Schema::builder();
 
+        let text = TextOptions::default().set_stored().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
         schema_builder.add_text_field("id", STRING | STORED);
         schema_builder.add_text_field("collection_id", STRING | STORED);
-        schema_builder.add_text_field("name", TEXT | STORED);
-        schema_builder.add_text_field("overview", TEXT);
-        schema_builder.add_text_field("genres", TEXT);
+        schema_builder.add_text_field("name", text.clone());
+        schema_builder.add_text_field("original_title", text.clone());
+        schema_builder.add_text_field("overview", text.clone());
+        schema_builder.add_text_field("taglines", text.clone());
+        schema_builder.add_text_field("genres", text.clone());
+        schema_builder.add_text_field("people", text.clone());
+        schema_builder.add_text_field("studios", text);
+        schema_builder.add_i64_field("year", INDEXED | STORED);
+        // Exact, lowercased names for similarity.
+        schema_builder.add_text_field("genre_keys", STRING | STORED);
+        schema_builder.add_text_field("people_keys", STRING | STORED);
+        schema_builder.add_text_field("studio_keys", STRING | STORED);
         schema_builder.add_text_field("item_type", STRING | STORED);
+        // Hash of the other fields, to skip documents that did not change.
+        schema_builder.add_text_field("checksum", STORED);
 
-        let schema = schema_builder.build();
-        let index = Index::create_in_ram(schema.clone());
+        schema_builder.build()
+    }
 
-        Ok(Self { index, schema })
+    fn with_index(index: Index) -> Result<Self, String> {
+        // Tokenizers are not stored in the index, they are registered on every open.
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter)
+            .build();
+        index.tokenizers().register(TOKENIZER, analyzer);
+
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(|e: tantivy::TantivyError| e.to_string())?;
+        let writer = index.writer(WRITER_MEMORY).map_err(|e| e.to_string())?;
+        let schema = index.schema();
+
+        Ok(Self {
+            index,
+            schema,
+            reader,
+            writer: Mutex::new(writer),
+            checksums: Mutex::new(HashMap::new()),
+        })
     }
 
-    /// Create a new search index on disk
-    pub fn new_on_disk(path: &Path) -> Result<Self, String> {
-        let mut schema_builder = Schema::builder();
+    fn field(&self, name: &str) -> Field {
+        self.schema.get_field(name).unwrap()
+    }
 
-        schema_builder.add_text_field("id", STRING | STORED);
-        schema_builder.add_text_field("collection_id", STRING | STORED);
-        schema_builder.add_text_field("name", TEXT | STORED);
-        schema_builder.add_text_field("overview", TEXT);
-        schema_builder.add_text_field("genres", TEXT);
-        schema_builder.add_text_field("item_type", STRING | STORED);
+    /// Index a collection. Only the documents that were added, changed or
+    /// removed since the collection was indexed last are written.
+    pub fn index_collection(&self, collection: &Collection) -> Result<(), String> {
+        let mut writer = self.writer.lock().unwrap();
+        let result = self.update_collection(&writer, collection);
+        self.finish(&mut writer, result)
+    }
 
-        let schema = schema_builder.build();
+    /// Index `collections` and remove all other collections from the index.
+    pub fn index_collections(&self, collections: &[Collection]) -> Result<(), String> {
+        let mut writer = self.writer.lock().unwrap();
+        let result = self
+            .remove_other_collections(&writer, collections)
+            .and_then(|removed| {
+                collections.iter().try_fold(removed, |changed, collection| {
+                    Ok(self.update_collection(&writer, collection)? || changed)
+                })
+            });
+        self.finish(&mut writer, result)
+    }
 
-        std::fs::create_dir_all(path).map_err(|e| e.to_string())?;
-        let index = Index::create_in_dir(path, schema.clone()).map_err(|e| e.to_string())?;
+    /// Remove the documents of a collection from the index.
+    pub fn remove_collection(&self, collection_id: &str) -> Result<(), String> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.field("collection_id"), collection_id));
+        self.checksums.lock().unwrap().remove(collection_id);
+        self.commit(&mut writer)
+    }
 
-        Ok(Self { index, schema })
+    fn commit(&self, writer: &mut IndexWriter) -> Result<(), String> {
+        writer.commit().map_err(|e| e.to_string())?;
+        self.reader.reload().map_err(|e| e.to_string())
     }
 
-    /// Index a collection
-    pub fn index_collection(&self, collection: &Collection) -> Result<(), String> {
-        let mut index_writer = self.index.writer(50_000_000).map_err(|e| e.to_string())?;
+    /// Commit if anything changed. After an error the pending changes are
+    /// dropped and the checksums are read from the index again.
+    fn finish(&self, writer: &mut IndexWriter, changed: Result<bool, String>) -> Result<(), String> {
+        let result = match changed {
+            Ok(true) => self.commit(writer),
+            Ok(false) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.checksums.lock().unwrap().clear();
+            let _ = writer.rollback();
+        }
+        result
+    }
 
-        let id_field = self.schema.get_field("id").unwrap();
-        let collection_id_field = self.schema.get_field("collection_id").unwrap();
-        let name_field = self.schema.get_field("name").unwrap();
-        let overview_field = self.schema.get_field("overview").unwrap();
-        let genres_field = self.schema.get_field("genres").unwrap();
-        let item_type_field = self.schema.get_field("item_type").unwrap();
+    /// Write the documents of a collection that differ from the index.
+    /// Returns true if anything was written.
+    fn update_collection(&self, writer: &IndexWriter, collection: &Collection) -> Result<bool, String> {
+        let docs = self.collection_docs(collection);
+        let mut checksums = self.checksums.lock().unwrap();
+        let indexed = match checksums.entry(collection.id.clone()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(self.indexed_checksums(&collection.id)?),
+        };
+
+        let mut changed = false;
+        let mut keys = HashSet::new();
+        for (key, mut doc) in docs {
+            let checksum = id_hash(&doc.to_json(&self.schema));
+            keys.insert(key.clone());
+            match indexed.get(&key) {
+                Some(c) if *c == checksum => continue,
+                Some(_) => self.delete_doc(writer, &collection.id, &key)?,
+                None => {}
+            }
+            doc.add_text(self.field("checksum"), &checksum);
+            writer.add_document(doc).map_err(|e| e.to_string())?;
+            indexed.insert(key, checksum);
+            changed = true;
+        }
+
+        let removed: Vec<DocKey> = indexed.keys().filter(|k| !keys.contains(*k)).cloned().collect();
+        for key in removed {
+            self.delete_doc(writer, &collection.id, &key)?;
+            indexed.remove(&key);
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Read the keys and checksums of the documents of a collection.
+    fn indexed_checksums(&self, collection_id: &str) -> Result<HashMap<DocKey, String>, String> {
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(self.field("collection_id"), collection_id),
+            IndexRecordOption::Basic,
+        );
+        let addresses = searcher
+            .search(&query, &DocSetCollector)
+            .map_err(|e| e.to_string())?;
+
+        let mut checksums = HashMap::new();
+        for address in addresses {
+            let doc: TantivyDocument = searcher.doc(address).map_err(|e| e.to_string())?;
+            let get = |field: &str| {
+                doc.get_first(self.field(field))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string()
+            };
+            checksums.insert((get("item_type"), get("id")), get("checksum"));
+        }
+        Ok(checksums)
+    }
+
+    fn delete_doc(&self, writer: &IndexWriter, collection_id: &str, key: &DocKey) -> Result<(), String> {
+        let (item_type, id) = key;
+        let query = BooleanQuery::intersection(vec![
+            self.term_query("collection_id", collection_id),
+            self.term_query("item_type", item_type),
+            self.term_query("id", id),
+        ]);
+        writer
+            .delete_query(Box::new(query))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Remove the documents of collections that are not in `collections`.
+    /// Returns true if there were any.
+    fn remove_other_collections(
+        &self,
+        writer: &IndexWriter,
+        collections: &[Collection],
+    ) -> Result<bool, String> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, Box::new(AllQuery))];
+        for collection in collections {
+            clauses.push((Occur::MustNot, self.term_query("collection_id", &collection.id)));
+        }
+        let query = BooleanQuery::new(clauses);
+        let count = self
+            .reader
+            .searcher()
+            .search(&query, &Count)
+            .map_err(|e| e.to_string())?;
+        if count == 0 {
+            return Ok(false);
+        }
+        writer.delete_query(Box::new(query)).map_err(|e| e.to_string())?;
+        self.checksums
+            .lock()
+            .unwrap()
+            .retain(|id, _| collections.iter().any(|c| c.id == *id));
+        Ok(true)
+    }
+
+    fn term_query(&self, field: &str, value: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_field_text(self.field(field), value),
+            IndexRecordOption::Basic,
+        ))
+    }
+
+    /// Build the documents of the items, people, studios and genres of a collection.
+    fn collection_docs(&self, collection: &Collection) -> Vec<(DocKey, TantivyDocument)> {
+        let mut docs = Vec::new();
+        let mut add = |id: &str, name: &str, metadata: Option<&Metadata>, item_type: &str| {
+            let mut doc = doc!(
+                self.field("id") => id,
+                self.field("collection_id") => collection.id.as_str(),
+                self.field("name") => name,
+                self.field("item_type") => item_type,
+            );
+            if let Some(m) = metadata {
+                let mut people: Vec<&str> = m.credits().map(|c| c.name).collect();
+                people.sort_unstable();
+                people.dedup();
+                doc.add_text(
+                    self.field("original_title"),
+                    m.original_title.as_deref().unwrap_or_default(),
+                );
+                doc.add_text(self.field("overview"), m.plot.as_deref().unwrap_or_default());
+                doc.add_text(self.field("taglines"), m.taglines.join(", "));
+                doc.add_text(self.field("genres"), m.genres.join(", "));
+                doc.add_text(self.field("people"), people.join(", "));
+                doc.add_text(self.field("studios"), m.studios.join(", "));
+                if let Some(year) = m.year {
+                    doc.add_i64(self.field("year"), year as i64);
+                }
+                for (field, names) in [("genre_keys", &m.genres), ("studio_keys", &m.studios)] {
+                    for name in names.iter().filter(|n| !n.is_empty()) {
+                        doc.add_text(self.field(field), name.to_lowercase());
+                    }
+                }
+                for name in people.iter().filter(|n| !n.is_empty()) {
+                    doc.add_text(self.field("people_keys"), name.to_lowercase());
+                }
+            }
+            docs.push(((item_type.to_string(), id.to_string()), doc));
+        };
+
+        let mut people = HashSet::new();
+        let mut studios = HashSet::new();
+        let mut genres = HashSet::new();
+        let mut add_names = |m: &Metadata| {
+            people.extend(m.credits().map(|c| c.name.to_string()));
+            studios.extend(m.studios.iter().cloned());
+            genres.extend(m.genres.iter().cloned());
+        };
 
         for item in &collection.items {
             match item {
                 Item::Movie(movie) => {
-                    let genres_text = movie.metadata.genres.join(" ");
-
-                    index_writer
-                        .add_document(doc!(
-                            id_field => movie.id.clone(),
-                            collection_id_field => collection.id.clone(),
-                            name_field => movie.name.clone(),
-                            overview_field => movie.metadata.plot.clone().unwrap_or_default(),
-                            genres_field => genres_text,
-                            item_type_field => "movie".to_string(),
-                        ))
-                        .map_err(|e| e.to_string())?;
+                    add(&movie.id, &movie.name, Some(&movie.metadata), "movie");
+                    add_names(&movie.metadata);
                 }
                 Item::Show(show) => {
-                    let genres_text = show.metadata.genres.join(" ");
-
-                    index_writer
-                        .add_document(doc!(
-                            id_field => show.id.clone(),
-                            collection_id_field => collection.id.clone(),
-                            name_field => show.name.clone(),
-                            overview_field => show.metadata.plot.clone().unwrap_or_default(),
-                            genres_field => genres_text,
-                            item_type_field => "show".to_string(),
-                        ))
-                        .map_err(|e| e.to_string())?;
-
-                    // Index episodes
+                    add(&show.id, &show.name, Some(&show.metadata), "show");
+                    add_names(&show.metadata);
+
                     for season in &show.seasons {
+                        add(&season.id, &season.name, None, "season");
                         for episode in &season.episodes {
-                            index_writer
-                                .add_document(doc!(
-                                    id_field => episode.id.clone(),
-                                    collection_id_field => collection.id.clone(),
-                                    name_field => episode.name.clone(),
-                                    overview_field => episode.metadata.plot.clone().unwrap_or_default(),
-                                    genres_field => String::new(),
-                                    item_type_field => "episode".to_string(),
-                                ))
-                                .map_err(|e| e.to_string())?;
+                            add(&episode.id, &episode.name, Some(&episode.metadata), "episode");
                         }
                     }
                 }
@@ -122,107 +396,339 @@ impl Search {
             }
         }
 
-        index_writer.commit().map_err(|e| e.to_string())?;
-
-        Ok(())
+        for (names, item_type) in [(people, "person"), (studios, "studio"), (genres, "genre")] {
+            for name in names.iter().filter(|n| !n.is_empty()) {
+                add(name, name, None, item_type);
+            }
+        }
+        docs
     }
 
-    /// Search for items
+    /// Search for movies, shows, seasons and episodes, best matches first.
+    /// Every word of the query must match the name, original title,
+    /// overview, taglines, genres, people, studios or year, either exactly,
+    /// as prefix or with a typo.
     pub fn search(&self, query_str: &str, limit: usize) -> Result<Vec<SearchDocument>, String> {
-        let reader = self
+        self.search_types(query_str, MEDIA_TYPES, limit)
+    }
+
+    /// Search for people, studios and genres by name, best matches first.
+    /// The same name occurs once for every collection it appears in.
+    pub fn search_names(&self, query_str: &str, limit: usize) -> Result<Vec<SearchDocument>, String> {
+        self.search_types(query_str, NAME_TYPES, limit)
+    }
+
+    fn search_types(
+        &self,
+        query_str: &str,
+        item_types: &[&str],
+        limit: usize,
+    ) -> Result<Vec<SearchDocument>, String> {
+        let name_field = self.field("name");
+        let item_type_field = self.field("item_type");
+        let year_field = self.field("year");
+
+        let mut analyzer = self
             .index
-            .reader()
-            .map_err(|e: tantivy::TantivyError| e.to_string())?;
+            .tokenizer_for_field(name_field)
+            .map_err(|e| e.to_string())?;
+        let mut words = Vec::new();
+        let mut stream = analyzer.token_stream(query_str);
+        while stream.advance() {
+            words.push(stream.token().text.clone());
+        }
+        if words.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
 
-        let searcher = reader.searcher();
+        let boost = |query: Box<dyn Query>, boost: f32| -> (Occur, Box<dyn Query>) {
+            (Occur::Should, Box::new(BoostQuery::new(query, boost)))
+        };
+        let term_query = |field: &str, word: &str| -> Box<dyn Query> {
+            let term = Term::from_field_text(self.field(field), word);
+            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+        };
+
+        let mut clauses = Vec::new();
+        for word in &words {
+            let name_term = Term::from_field_text(name_field, word);
+            let mut should = vec![
+                boost(term_query("name", word), BOOST_NAME),
+                boost(
+                    Box::new(FuzzyTermQuery::new_prefix(name_term.clone(), 0, true)),
+                    BOOST_NAME_PREFIX,
+                ),
+                boost(term_query("original_title", word), BOOST_ORIGINAL_TITLE),
+                boost(term_query("overview", word), BOOST_OVERVIEW),
+                boost(term_query("taglines", word), BOOST_TAGLINES),
+                boost(term_query("genres", word), BOOST_GENRES),
+                boost(term_query("people", word), BOOST_PEOPLE),
+                boost(term_query("studios", word), BOOST_STUDIOS),
+            ];
+            if word.chars().count() >= FUZZY_MIN_LENGTH {
+                should.push(boost(
+                    Box::new(FuzzyTermQuery::new(name_term, 1, true)),
+                    BOOST_NAME_FUZZY,
+                ));
+            }
+            if let Ok(year) = word.parse::<i64>() {
+                let term = Term::from_field_i64(year_field, year);
+                should.push(boost(
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                    BOOST_YEAR,
+                ));
+            }
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(should)) as Box<dyn Query>));
+        }
+        let types = item_types
+            .iter()
+            .map(|t| {
+                let term = Term::from_field_text(item_type_field, t);
+                let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, query)
+            })
+            .collect();
+        clauses.push((Occur::Must, Box::new(BooleanQuery::new(types))));
+        let query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        self.collect(&searcher, &query, limit)
+    }
 
-        let name_field = self.schema.get_field("name").unwrap();
-        let overview_field = self.schema.get_field("overview").unwrap();
-        let genres_field = self.schema.get_field("genres").unwrap();
+    /// Find the movies or shows most similar to a movie or show, most
+    /// similar first. Items are similar if they have words of their plot and
+    /// taglines, genres, people or studios in common, or were made around
+    /// the same year.
+    pub fn similar(&self, item_id: &str, limit: usize) -> Result<Vec<SearchDocument>, String> {
+        let searcher = self.reader.searcher();
+        let id_term = Term::from_field_text(self.field("id"), item_id);
+        let id_query = TermQuery::new(id_term.clone(), IndexRecordOption::Basic);
+        let source = searcher
+            .search(&id_query, &TopDocs::with_limit(MEDIA_TYPES.len()))
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|(_, address)| searcher.doc::<TantivyDocument>(address))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|doc| {
+                let item_type = doc.get_first(self.field("item_type")).and_then(|v| v.as_str());
+                matches!(item_type, Some("movie") | Some("show"))
+            });
+        let Some(source) = source else {
+            return Ok(Vec::new());
+        };
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
 
-        let query_parser =
-            QueryParser::for_index(&self.index, vec![name_field, overview_field, genres_field]);
+        let mut should: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for field in ["overview", "taglines"] {
+            let field = self.field(field);
+            let values: Vec<OwnedValue> = source.get_all(field).cloned().collect();
+            let query = MoreLikeThisQuery::builder()
+                .with_min_doc_frequency(2)
+                .with_min_term_frequency(1)
+                .with_min_word_length(3)
+                .with_max_query_terms(SIMILAR_MAX_TERMS)
+                .with_document_fields(vec![(field, values)]);
+            should.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(query), SIMILAR_TEXT)),
+            ));
+        }
+        for (field, boost) in [
+            ("genre_keys", SIMILAR_GENRE),
+            ("people_keys", SIMILAR_PEOPLE),
+            ("studio_keys", SIMILAR_STUDIO),
+        ] {
+            let field = self.field(field);
+            for value in source.get_all(field).filter_map(|v| v.as_str()) {
+                let term = Term::from_field_text(field, value);
+                let query = TermQuery::new(term, IndexRecordOption::Basic);
+                should.push((Occur::Should, Box::new(BoostQuery::new(Box::new(query), boost))));
+            }
+        }
+        if let Some(year) = source.get_first(self.field("year")).and_then(|v| v.as_i64()) {
+            for distance in -SIMILAR_YEAR_WINDOW..=SIMILAR_YEAR_WINDOW {
+                let term = Term::from_field_i64(self.field("year"), year + distance);
+                let score = SIMILAR_YEAR * (1.0 - distance.abs() as f32 / (SIMILAR_YEAR_WINDOW + 1) as f32);
+                let query = TermQuery::new(term, IndexRecordOption::Basic);
+                should.push((
+                    Occur::Should,
+                    Box::new(ConstScoreQuery::new(Box::new(query), score)),
+                ));
+            }
+        }
 
-        let query = query_parser.parse_query(query_str).map_err(|e| e.to_string())?;
+        let item_type = source
+            .get_first(self.field("item_type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let item_type_term = Term::from_field_text(self.field("item_type"), item_type);
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(BooleanQuery::new(should))),
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(item_type_term, IndexRecordOption::Basic)),
+            ),
+            (Occur::MustNot, Box::new(id_query)),
+        ]);
+
+        self.collect(&searcher, &query, limit)
+    }
 
-        let top_docs = searcher
-            .search(&query, &TopDocs::with_limit(limit))
-            .map_err(|e| e.to_string())?;
+    /// Run `query` and return the best `limit` documents. Documents with the
+    /// same score are ordered by id, so the order does not depend on where
+    /// the documents are stored in the index.
+    fn collect(
+        &self,
+        searcher: &tantivy::Searcher,
+        query: &dyn Query,
+        limit: usize,
+    ) -> Result<Vec<SearchDocument>, String> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        // Fetch until all documents with the same score as the last one
+        // returned are in.
+        let num_docs = (searcher.num_docs() as usize).max(1);
+        let mut fetch = limit.saturating_add(1);
+        let top_docs = loop {
+            let top_docs = searcher
+                .search(query, &TopDocs::with_limit(fetch.min(num_docs)))
+                .map_err(|e| e.to_string())?;
+            let complete = top_docs.len() < fetch
+                || top_docs[limit - 1].0 > top_docs[top_docs.len() - 1].0
+                || fetch >= num_docs;
+            if complete {
+                break top_docs;
+            }
+            fetch = fetch.saturating_mul(2);
+        };
 
         let mut results = Vec::new();
-
-        let id_field = self.schema.get_field("id").unwrap();
-        let collection_id_field = self.schema.get_field("collection_id").unwrap();
-        let item_type_field = self.schema.get_field("item_type").unwrap();
-
-        for (_score, doc_address) in top_docs {
-            let retrieved_doc: tantivy::TantivyDocument =
-                searcher.doc(doc_address).map_err(|e| e.to_string())?;
-
-            let id = retrieved_doc
-                .get_first(id_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            let collection_id = retrieved_doc
-                .get_first(collection_id_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            let name = retrieved_doc
-                .get_first(name_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            let overview = retrieved_doc
-                .get_first(overview_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            let genres_text = retrieved_doc
-                .get_first(genres_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-
-            let genres = if genres_text.is_empty() {
-                Vec::new()
-            } else {
-                genres_text.split_whitespace().map(|s| s.to_string()).collect()
+        for (score, doc_address) in top_docs {
+            let retrieved_doc: TantivyDocument = searcher.doc(doc_address).map_err(|e| e.to_string())?;
+            let get = |field: &str| {
+                retrieved_doc
+                    .get_first(self.field(field))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string()
             };
 
-            let item_type = retrieved_doc
-                .get_first(item_type_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            results.push(SearchDocument {
-                id,
-                collection_id,
-                name,
-                overview,
-                genres,
-                item_type,
-            });
+            let genres = get("genres")
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            results.push((
+                score,
+                SearchDocument {
+                    id: get("id"),
+                    collection_id: get("collection_id"),
+                    name: get("name"),
+                    overview: get("overview"),
+                    genres,
+                    year: retrieved_doc
+                        .get_first(self.field("year"))
+                        .and_then(|v| v.as_i64())
+                        .map(|y| y as i32),
+                    item_type: get("item_type"),
+                },
+            ));
         }
 
-        Ok(results)
-    }
-
-    /// Find similar items (stub for now)
-    pub fn similar(&self, _item_id: &str, _limit: usize) -> Result<Vec<SearchDocument>, String> {
-        // TODO: Implement more-like-this query
-        Ok(Vec::new())
+        results
+            .sort_by(|(a_score, a), (b_score, b)| b_score.total_cmp(a_score).then_with(|| a.id.cmp(&b.id)));
+        results.truncate(limit);
+        Ok(results.into_iter().map(|(_, doc)| doc).collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collection::{Actor, CollectionType, Metadata, Movie};
+
+    fn movie(id: &str, name: &str, plot: &str) -> Item {
+        movie_with(
+            id,
+            name,
+            Metadata {
+                plot: Some(plot.to_string()),
+                ..Metadata::default()
+            },
+        )
+    }
+
+    fn movie_with(id: &str, name: &str, metadata: Metadata) -> Item {
+        Item::Movie(Movie {
+            id: id.to_string(),
+            collection_id: "c1".to_string(),
+            user_data: None,
+            name: name.to_string(),
+            sort_name: name.to_lowercase(),
+            path: name.to_string(),
+            base_url: "".to_string(),
+            created: chrono::Utc::now(),
+            banner: "".to_string(),
+            fanart: "".to_string(),
+            folder: "".to_string(),
+            poster: "".to_string(),
+            file_name: "movie.mkv".to_string(),
+            file_size: 0,
+            metadata,
+            srt_subs: Vec::new(),
+            vtt_subs: Vec::new(),
+        })
+    }
+
+    fn search() -> Search {
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec!["/movies".to_string()],
+            "".to_string(),
+        );
+        collection.items = vec![
+            movie("m1", "Alien (1979)", "The crew of a spaceship meets a creature."),
+            movie("m2", "Star Wars (1977)", "A farm boy fights an empire."),
+            movie(
+                "m3",
+                "Amélie (2001)",
+                "A waitress in Paris helps the people around her.",
+            ),
+            movie("m4", "Paul (2011)", "Two geeks meet an alien on a road trip."),
+            movie_with(
+                "m5",
+                "Le Fabuleux Destin (2001)",
+                Metadata {
+                    original_title: Some("Le Fabuleux Destin d'Amélie Poulain".to_string()),
+                    taglines: vec!["She'll change your life".to_string()],
+                    actors: vec![Actor {
+                        name: "Audrey Tautou".to_string(),
+                        ..Actor::default()
+                    }],
+                    directors: vec!["Jean-Pierre Jeunet".to_string()],
+                    studios: vec!["Claudie Ossard Productions".to_string()],
+                    genres: vec!["Comedy".to_string()],
+                    year: Some(2001),
+                    ..Metadata::default()
+                },
+            ),
+        ];
+        let search = Search::new_in_memory().unwrap();
+        search.index_collection(&collection).unwrap();
+        search
+    }
+
+    fn ids(results: Vec<SearchDocument>) -> Vec<String> {
+        results.into_iter().map(|d| d.id).collect()
+    }
 
     #[test]
     fn test_create_in_memory_search() {
@@ -237,4 +743,237 @@ mod tests {
         assert!(results.is_ok());
         assert_eq!(results.unwrap().len(), 0);
     }
+
+    #[test]
+    fn test_search_ranking() {
+        let search = search();
+        // Name matches rank above overview matches.
+        assert_eq!(ids(search.search("alien", 10).unwrap()), vec!["m1", "m4"]);
+        // Prefix, typo and accent folding.
+        assert_eq!(ids(search.search("sta", 10).unwrap()), vec!["m2"]);
+        assert_eq!(ids(search.search("star wras", 10).unwrap()), vec!["m2"]);
+        assert_eq!(ids(search.search("amelie", 10).unwrap()), vec!["m3", "m5"]);
+        // All words must match.
+        assert!(search.search("alien paris", 10).unwrap().is_empty());
+        assert!(search.search("  ", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_metadata() {
+        let search = search();
+        assert_eq!(ids(search.search("tautou", 10).unwrap()), vec!["m5"]);
+        assert_eq!(ids(search.search("jeunet comedy", 10).unwrap()), vec!["m5"]);
+        assert_eq!(ids(search.search("poulain", 10).unwrap()), vec!["m5"]);
+        assert_eq!(ids(search.search("ossard", 10).unwrap()), vec!["m5"]);
+        assert_eq!(ids(search.search("change your life", 10).unwrap()), vec!["m5"]);
+        assert_eq!(ids(search.search("fabuleux 2001", 10).unwrap()), vec!["m5"]);
+    }
+
+    #[test]
+    fn test_search_names() {
+        let search = search();
+        let names = search.search_names("audrey", 10).unwrap();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].item_type, "person");
+        assert_eq!(names[0].name, "Audrey Tautou");
+        let names = search.search_names("comedy", 10).unwrap();
+        assert_eq!(names[0].item_type, "genre");
+        // Names are not returned as items.
+        assert_eq!(ids(search.search("audrey", 10).unwrap()), vec!["m5"]);
+    }
+
+    #[test]
+    fn test_similar() {
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec!["/movies".to_string()],
+            "".to_string(),
+        );
+        let meta = |plot: &str, genres: &[&str], actors: &[&str], year: i32| Metadata {
+            plot: Some(plot.to_string()),
+            genres: genres.iter().map(|s| s.to_string()).collect(),
+            actors: actors
+                .iter()
+                .map(|s| Actor {
+                    name: s.to_string(),
+                    ..Actor::default()
+                })
+                .collect(),
+            year: Some(year),
+            ..Metadata::default()
+        };
+        collection.items = vec![
+            movie_with(
+                "m1",
+                "Alien (1979)",
+                meta(
+                    "A spaceship crew meets a deadly creature.",
+                    &["Horror", "Science Fiction"],
+                    &["Sigourney Weaver"],
+                    1979,
+                ),
+            ),
+            movie_with(
+                "m2",
+                "Aliens (1986)",
+                meta(
+                    "Marines fight a deadly creature on a colony.",
+                    &["Action", "Science Fiction"],
+                    &["Sigourney Weaver"],
+                    1986,
+                ),
+            ),
+            movie_with(
+                "m3",
+                "The Thing (1982)",
+                meta(
+                    "A research crew meets a shapeshifting creature.",
+                    &["Horror", "Science Fiction"],
+                    &["Kurt Russell"],
+                    1982,
+                ),
+            ),
+            movie_with(
+                "m4",
+                "Notting Hill (1999)",
+                meta(
+                    "A bookseller meets a film star.",
+                    &["Romance"],
+                    &["Julia Roberts"],
+                    1999,
+                ),
+            ),
+        ];
+        let search = Search::new_in_memory().unwrap();
+        search.index_collection(&collection).unwrap();
+
+        let similar = ids(search.similar("m1", 10).unwrap());
+        assert_eq!(&similar[..2], &["m3", "m2"]);
+        assert!(!similar.contains(&"m1".to_string()));
+        // Deterministic.
+        assert_eq!(ids(search.similar("m1", 10).unwrap()), similar);
+        assert!(search.similar("unknown", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_similar_ties() {
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec!["/movies".to_string()],
+            "".to_string(),
+        );
+        let meta = || Metadata {
+            genres: vec!["Horror".to_string()],
+            actors: vec![Actor {
+                name: "Sigourney Weaver".to_string(),
+                ..Actor::default()
+            }],
+            ..Metadata::default()
+        };
+        collection.items = vec![
+            movie_with("m1", "Alien (1979)", meta()),
+            movie_with("m2", "Aliens (1986)", meta()),
+            movie_with("m3", "Alien 3 (1992)", meta()),
+        ];
+        let search = Search::new_in_memory().unwrap();
+        search.index_collection(&collection).unwrap();
+        assert_eq!(ids(search.similar("m1", 10).unwrap()), vec!["m2", "m3"]);
+        assert_eq!(ids(search.similar("m1", 1).unwrap()), vec!["m2"]);
+
+        // Re-indexing m2 moves its document, not its place in the results.
+        collection.items[1] = movie_with("m2", "Aliens: Special Edition (1986)", meta());
+        search.index_collection(&collection).unwrap();
+        assert_eq!(ids(search.similar("m1", 10).unwrap()), vec!["m2", "m3"]);
+        assert_eq!(ids(search.similar("m1", 1).unwrap()), vec!["m2"]);
+    }
+
+    #[test]
+    fn test_search_remove_collection() {
+        let search = search();
+        search.remove_collection("c1").unwrap();
+        assert!(search.search("alien", 10).unwrap().is_empty());
+    }
+
+    fn opstamp(search: &Search) -> u64 {
+        search.index.load_metas().unwrap().opstamp
+    }
+
+    #[test]
+    fn test_index_incremental() {
+        let search = search();
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec!["/movies".to_string()],
+            "".to_string(),
+        );
+        collection.items = vec![
+            movie("m1", "Alien (1979)", "The crew of a spaceship meets a creature."),
+            movie("m2", "Star Wars (1977)", "A farm boy fights an empire."),
+            movie(
+                "m3",
+                "Amélie (2001)",
+                "A waitress in Paris helps the people around her.",
+            ),
+            movie("m4", "Paul (2011)", "Two geeks meet an alien on a road trip."),
+        ];
+        // m5 and the names only it had are removed.
+        search.index_collection(&collection).unwrap();
+        assert_eq!(ids(search.search("amelie", 10).unwrap()), vec!["m3"]);
+        assert!(search.search_names("audrey", 10).unwrap().is_empty());
+
+        // Nothing is written if nothing changed.
+        let before = opstamp(&search);
+        search.index_collection(&collection).unwrap();
+        search
+            .index_collections(std::slice::from_ref(&collection))
+            .unwrap();
+        assert_eq!(opstamp(&search), before);
+
+        // A changed item replaces its old document.
+        collection.items[1] = movie("m2", "Star Trek (1979)", "A starship meets a cloud.");
+        search.index_collection(&collection).unwrap();
+        assert!(search.search("wars", 10).unwrap().is_empty());
+        assert_eq!(ids(search.search("trek", 10).unwrap()), vec!["m2"]);
+        assert_eq!(search.search("star", 10).unwrap().len(), 1);
+
+        // A full index drops collections that are gone.
+        search.index_collections(&[]).unwrap();
+        assert!(search.search("alien", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_index_reopen() {
+        let dir = std::env::temp_dir().join("test_search_index_reopen");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut collection = Collection::new(
+            "c1".to_string(),
+            "Movies".to_string(),
+            CollectionType::Movies,
+            vec!["/movies".to_string()],
+            "".to_string(),
+        );
+        collection.items = vec![movie("m1", "Alien (1979)", "A crew meets a creature.")];
+
+        let search = Search::new_on_disk(&dir).unwrap();
+        search.index_collection(&collection).unwrap();
+        drop(search);
+
+        // The documents in the index are kept when they are still current.
+        let search = Search::new_on_disk(&dir).unwrap();
+        let before = opstamp(&search);
+        search
+            .index_collections(std::slice::from_ref(&collection))
+            .unwrap();
+        assert_eq!(opstamp(&search), before);
+        assert_eq!(ids(search.search("alien", 10).unwrap()), vec!["m1"]);
+        drop(search);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// DirectoryWatcher is a running watcher, directories can be added to it
+/// while it runs. It stops once dropped.
+pub struct DirectoryWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+}
+
+impl DirectoryWatcher {
+    /// Watch another directory recursively.
+    pub fn watch(&self, dir: &Path) {
+        match self.watcher.lock().unwrap().watch(dir, RecursiveMode::Recursive) {
+            Ok(_) => info!("Watching {} for changes", dir.display()),
+            Err(e) => warn!("Failed to watch {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Stop watching a directory.
+    pub fn unwatch(&self, dir: &Path) {
+        match self.watcher.lock().unwrap().unwatch(dir) {
+            Ok(_) => info!("Stopped watching {}", dir.display()),
+            Err(e) => warn!("Failed to unwatch {}: {}", dir.display(), e),
+        }
+    }
+}
+
+/// Watch directories recursively for filesystem changes (inotify on Linux).
+///
+/// Events are debounced per path: a path is only reported once no events
+/// arrived for it during `delay`, so files that are still being copied are
+/// not picked up half-written. The returned channel yields batches of
+/// changed paths for as long as the returned watcher is kept.
+pub fn watch_directories(
+    directories: &[PathBuf],
+    delay: Duration,
+) -> Result<(Arc<DirectoryWatcher>, mpsc::UnboundedReceiver<Vec<PathBuf>>), String> {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) => {
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) | EventKind::Any
+            ) {
+                for path in event.paths {
+                    let _ = event_tx.send(path);
+                }
+            }
+        }
+        Err(e) => warn!("Filesystem watcher error: {}", e),
+    })
+    .map_err(|e| e.to_string())?;
+
+    let watcher = Arc::new(DirectoryWatcher {
+        watcher: Mutex::new(watcher),
+    });
+    for dir in directories {
+        watcher.watch(dir);
+    }
+
+    let (changed_tx, changed_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            tokio::select! {
+                path = event_rx.recv() => {
+                    match path {
+                        Some(path) => {
+                            pending.insert(debounce_key(&path), Instant::now());
+                        }
+                        None => break,
+                    }
+                }
+                _ = tick.tick() => {
+                    let ready: Vec<PathBuf> = pending
+                        .iter()
+                        .filter(|(_, last)| last.elapsed() >= delay)
+                        .map(|(path, _)| path.clone())
+                        .collect();
+                    if ready.is_empty() {
+                        continue;
+                    }
+                    for path in &ready {
+                        pending.remove(path);
+                    }
+                    if changed_tx.send(ready).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok((watcher, changed_rx))
+}
+
+/// Events for files are grouped by the directory they are in, so copying a
+/// movie with its images and NFO results in a single rescan.
+fn debounce_key(path: &Path) -> PathBuf {
+    if path.is_file() {
+        if let Some(parent) = path.parent() {
+            return parent.to_path_buf();
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce_key() {
+        let dir = std::env::temp_dir().join("test_debounce_key");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("movie.mkv"), b"x").unwrap();
+
+        assert_eq!(debounce_key(&dir.join("movie.mkv")), dir);
+        assert_eq!(debounce_key(&dir), dir);
+        // Removed files cannot be stat'ed, they are reported as-is.
+        assert_eq!(debounce_key(&dir.join("gone.mkv")), dir.join("gone.mkv"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_unwatch() {
+        let dir = std::env::temp_dir().join("test_unwatch");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (watcher, mut events) = watch_directories(&[dir.clone()], Duration::ZERO).unwrap();
+        std::fs::write(dir.join("a.mkv"), b"x").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(5), events.recv()).await;
+        assert_eq!(changed.unwrap().unwrap(), vec![dir.clone()]);
+
+        watcher.unwatch(&dir);
+        std::fs::write(dir.join("b.mkv"), b"x").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(2), events.recv()).await;
+        assert!(changed.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
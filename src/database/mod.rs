@@ -2,18 +2,28 @@ pub mod model;
 pub mod sqlite;
 
 pub use model::{
-    AccessToken, DatabaseError, ImageMetadata, Item, Playlist, QuickConnectCode, Result, User, UserData,
-    UserProperties,
+    AccessSchedule, AccessToken, ApiKey, DatabaseError, ImageMetadata, Item, Library, LoginAttempts,
+    MediaProbe, Playlist, QuickConnectCode, Result, User, UserData, UserProperties,
 };
 pub use sqlite::SqliteRepository;
 
 use async_trait::async_trait;
+use std::collections::HashMap;
+
+use crate::tasks::TaskTrigger;
 
 /// PersonRepo defines person DB operations
 #[async_trait]
 pub trait PersonRepo {
-    /// GetPerson retrieves a person by name.
+    /// GetPerson retrieves a person by name, ignoring case.
     async fn get_person(&self, name: &str, user_id: &str) -> Result<model::Person>;
+    /// GetPersonById retrieves a person by id.
+    async fn get_person_by_id(&self, id: &str) -> Result<model::Person>;
+    /// UpsertPerson creates or updates a person by name, keeping its creation time.
+    async fn upsert_person(&self, person: &model::Person) -> Result<()>;
+    /// Delete persons last updated before `before`. Returns the number of
+    /// persons deleted.
+    async fn delete_persons_updated_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<u64>;
 }
 
 /// Database repo aggregates the repo interfaces.
@@ -27,11 +37,16 @@ pub trait Repository:
     + PersonRepo
     + QuickConnectRepo
     + ImageRepo
+    + MediaProbeRepo
+    + LoginAttemptRepo
+    + ApiKeyRepo
+    + LibraryRepo
+    + TaskTriggerRepo
     + Send
     + Sync
 {
-    /// Start background jobs for the repository.
-    fn start_background_jobs(&self);
+    /// Compact the database file.
+    async fn vacuum(&self) -> Result<()>;
 }
 
 /// UserRepo defines the interface for user database operations
@@ -62,6 +77,26 @@ pub trait AccessTokenRepo {
     async fn upsert_access_token(&self, token: &AccessToken) -> Result<()>;
     /// DeleteAccessToken deletes an access token.
     async fn delete_access_token(&self, token: &str) -> Result<()>;
+    /// Delete access tokens last used before `idle_before` or created before
+    /// `created_before`. Returns the number of deleted tokens.
+    async fn delete_expired_access_tokens(
+        &self,
+        idle_before: Option<chrono::DateTime<chrono::Utc>>,
+        created_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u64>;
+}
+
+/// ApiKeyRepo defines API key operations
+#[async_trait]
+pub trait ApiKeyRepo {
+    /// Get an API key by its token.
+    async fn get_api_key(&self, token: &str) -> Result<ApiKey>;
+    /// Get all API keys.
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>>;
+    /// UpsertApiKey upserts an API key.
+    async fn upsert_api_key(&self, key: &ApiKey) -> Result<()>;
+    /// DeleteApiKey deletes an API key.
+    async fn delete_api_key(&self, token: &str) -> Result<()>;
 }
 
 /// QuickConnectRepo defines quick connect code operations
@@ -88,6 +123,47 @@ pub trait ImageRepo {
     async fn delete_image(&self, item_id: &str, image_type: &str) -> Result<()>;
 }
 
+/// MediaProbeRepo defines media probe cache operations
+#[async_trait]
+pub trait MediaProbeRepo {
+    /// Get all cached probe results.
+    async fn get_media_probes(&self) -> Result<Vec<MediaProbe>>;
+    /// Store the probe result of a file, replacing older results for the same path.
+    async fn upsert_media_probe(&self, probe: &MediaProbe) -> Result<()>;
+    /// Delete the probe result of a file.
+    async fn delete_media_probe(&self, path: &str) -> Result<()>;
+}
+
+/// LoginAttemptRepo defines failed login tracking per remote address
+#[async_trait]
+pub trait LoginAttemptRepo {
+    /// Get the failed logins from a remote address.
+    async fn get_login_attempts(&self, remote_address: &str) -> Result<LoginAttempts>;
+    /// Store the failed logins from a remote address.
+    async fn upsert_login_attempts(&self, attempts: &LoginAttempts) -> Result<()>;
+    /// Forget the failed logins from a remote address.
+    async fn delete_login_attempts(&self, remote_address: &str) -> Result<()>;
+}
+
+/// LibraryRepo defines operations on collections managed through the API
+#[async_trait]
+pub trait LibraryRepo {
+    /// Get all libraries.
+    async fn get_libraries(&self) -> Result<Vec<Library>>;
+    /// UpsertLibrary upserts a library.
+    async fn upsert_library(&self, library: &Library) -> Result<()>;
+}
+
+/// TaskTriggerRepo defines operations on the triggers of scheduled tasks
+/// that were changed through the API
+#[async_trait]
+pub trait TaskTriggerRepo {
+    /// Get the triggers of all tasks, by task ID.
+    async fn get_task_triggers(&self) -> Result<HashMap<String, Vec<TaskTrigger>>>;
+    /// UpsertTaskTriggers replaces the triggers of a task.
+    async fn upsert_task_triggers(&self, task_id: &str, triggers: &[TaskTrigger]) -> Result<()>;
+}
+
 /// ItemRepo defines item operations
 #[async_trait]
 pub trait ItemRepo {
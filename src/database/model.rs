@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDateTime, Timelike, Utc, Weekday};
 use serde::{Deserialize, Serialize};
 
 /// User represents a user in the system.
@@ -43,6 +43,23 @@ pub struct AccessToken {
     pub last_used: DateTime<Utc>,
 }
 
+/// ApiKey is a long-lived access token for scripts and other tools that
+/// are not a device. Requests made with it act on behalf of the admin that
+/// created it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// Token is the API key string.
+    pub token: String,
+    /// UserID is the ID of the admin that created the key.
+    pub user_id: String,
+    /// Name is the name of the application the key is for.
+    pub name: String,
+    /// Created is the time the key was created.
+    pub created: DateTime<Utc>,
+    /// LastUsed is the last time the key was used.
+    pub last_used: DateTime<Utc>,
+}
+
 /// Item represents a media item in the database.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
@@ -87,6 +104,18 @@ pub struct UserProperties {
     pub my_media_excludes: Vec<String>,
     pub allow_tags: Vec<String>,
     pub block_tags: Vec<String>,
+    /// Highest parental rating score the user may see, None for no limit.
+    pub max_parental_rating: Option<i32>,
+    /// Item types ("Movie", "Series") that are hidden when they have no rating.
+    pub block_unrated_items: Vec<String>,
+    /// Weekly windows in which the user may log in and play media.
+    /// If empty, access is always allowed.
+    pub access_schedules: Vec<AccessSchedule>,
+    /// Number of failed logins since the last successful one.
+    pub invalid_login_attempts: i32,
+    /// Failed logins before the account is disabled. 0 uses the server
+    /// default, -1 never locks the account.
+    pub login_attempts_before_lockout: i32,
 }
 
 impl Default for UserProperties {
@@ -102,8 +131,56 @@ impl Default for UserProperties {
             my_media_excludes: Vec::new(),
             allow_tags: Vec::new(),
             block_tags: Vec::new(),
+            max_parental_rating: None,
+            block_unrated_items: Vec::new(),
+            access_schedules: Vec::new(),
+            invalid_login_attempts: 0,
+            login_attempts_before_lockout: 0,
+        }
+    }
+}
+
+impl UserProperties {
+    /// Check if the access schedules allow access at a (local) time.
+    pub fn access_allowed_at(&self, now: NaiveDateTime) -> bool {
+        self.access_schedules.is_empty() || self.access_schedules.iter().any(|s| s.allows(now))
+    }
+}
+
+/// AccessSchedule is a weekly window in which a user has access.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessSchedule {
+    /// DayOfWeek is "Sunday" to "Saturday", "Everyday", "Weekday" or "Weekend".
+    pub day_of_week: String,
+    /// StartHour is the start of the window, e.g. 7.5 for 07:30.
+    pub start_hour: f64,
+    /// EndHour is the end of the window, up to 24.
+    pub end_hour: f64,
+}
+
+impl AccessSchedule {
+    /// Check if the schedule applies to a day of the week.
+    pub fn includes_day(&self, day: Weekday) -> bool {
+        let weekend = matches!(day, Weekday::Sat | Weekday::Sun);
+        match self.day_of_week.to_lowercase().as_str() {
+            "everyday" => true,
+            "weekday" => !weekend,
+            "weekend" => weekend,
+            d => d.parse::<Weekday>().is_ok_and(|d| d == day),
         }
     }
+    /// Check if the schedule allows access at a (local) time. A window that
+    /// ends at or before its start hour runs past midnight into the next day,
+    /// e.g. Friday 20 to 2 ends Saturday at 02:00.
+    pub fn allows(&self, now: NaiveDateTime) -> bool {
+        let hour = now.hour() as f64 + now.minute() as f64 / 60.0;
+        let today = self.includes_day(now.weekday());
+        if self.end_hour > self.start_hour {
+            return today && hour >= self.start_hour && hour < self.end_hour;
+        }
+        let yesterday = self.includes_day(now.weekday().pred());
+        (today && hour >= self.start_hour) || (yesterday && hour < self.end_hour)
+    }
 }
 
 /// Playlist represents a user playlist with item IDs.
@@ -174,4 +251,92 @@ pub struct ImageMetadata {
     pub updated: DateTime<Utc>,
 }
 
+/// MediaProbe is a cached ffprobe result of a video file. It is valid as
+/// long as the size and modification time of the file are unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaProbe {
+    pub path: String,
+    pub size: i64,
+    /// mtime is the modification time of the file in seconds since the epoch.
+    pub mtime: i64,
+    /// data is the probe result as JSON.
+    pub data: String,
+}
+
+/// Library is a collection added, changed or removed through the API. It
+/// overrides the collection with the same ID from the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Library {
+    pub id: String,
+    pub name: String,
+    /// CollectionType is "movies" or "shows".
+    pub collection_type: String,
+    pub directories: Vec<String>,
+    pub hls_server: String,
+    /// Removed is set if the collection was removed.
+    pub removed: bool,
+}
+
+/// LoginAttempts counts the failed logins from a remote address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginAttempts {
+    pub remote_address: String,
+    pub failed: i32,
+    pub last_failed: DateTime<Utc>,
+}
+
 pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // January 2024 starts on a Monday.
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn schedule(day_of_week: &str, start_hour: f64, end_hour: f64) -> AccessSchedule {
+        AccessSchedule {
+            day_of_week: day_of_week.to_string(),
+            start_hour,
+            end_hour,
+        }
+    }
+
+    #[test]
+    fn test_includes_day() {
+        assert!(schedule("Everyday", 0.0, 24.0).includes_day(Weekday::Sun));
+        assert!(schedule("Weekday", 0.0, 24.0).includes_day(Weekday::Fri));
+        assert!(!schedule("Weekday", 0.0, 24.0).includes_day(Weekday::Sat));
+        assert!(schedule("Weekend", 0.0, 24.0).includes_day(Weekday::Sun));
+        assert!(schedule("tuesday", 0.0, 24.0).includes_day(Weekday::Tue));
+        assert!(!schedule("Tuesday", 0.0, 24.0).includes_day(Weekday::Wed));
+        assert!(!schedule("Someday", 0.0, 24.0).includes_day(Weekday::Wed));
+    }
+
+    #[test]
+    fn test_access_allowed_at() {
+        let mut props = UserProperties::default();
+        assert!(props.access_allowed_at(at(1, 3, 0)));
+
+        props.access_schedules = vec![schedule("Weekday", 7.5, 21.0)];
+        assert!(!props.access_allowed_at(at(1, 7, 0)));
+        assert!(props.access_allowed_at(at(1, 7, 30)));
+        assert!(props.access_allowed_at(at(5, 20, 59)));
+        assert!(!props.access_allowed_at(at(5, 21, 0)));
+        assert!(!props.access_allowed_at(at(6, 12, 0)));
+
+        // Overnight: Friday 20:00 until Saturday 02:00.
+        props.access_schedules = vec![schedule("Friday", 20.0, 2.0)];
+        assert!(!props.access_allowed_at(at(5, 19, 59)));
+        assert!(props.access_allowed_at(at(5, 23, 0)));
+        assert!(props.access_allowed_at(at(6, 1, 30)));
+        assert!(!props.access_allowed_at(at(6, 2, 0)));
+        assert!(!props.access_allowed_at(at(5, 1, 0)));
+    }
+}
@@ -7,14 +7,15 @@ use async_trait::async_trait;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 
 use super::model::{
-    AccessToken, DatabaseError, ImageMetadata, Item, Person, Playlist, QuickConnectCode, Result, User,
-    UserData, UserProperties,
+    AccessSchedule, AccessToken, ApiKey, DatabaseError, ImageMetadata, Item, Library, LoginAttempts,
+    MediaProbe, Person, Playlist, QuickConnectCode, Result, User, UserData, UserProperties,
 };
 use super::{
-    AccessTokenRepo, ImageRepo, ItemRepo, PersonRepo, PlaylistRepo, QuickConnectRepo, Repository,
-    UserDataRepo, UserRepo,
+    AccessTokenRepo, ApiKeyRepo, ImageRepo, ItemRepo, LibraryRepo, LoginAttemptRepo, MediaProbeRepo,
+    PersonRepo, PlaylistRepo, QuickConnectRepo, Repository, TaskTriggerRepo, UserDataRepo, UserRepo,
 };
 use crate::idhash::*;
+use crate::tasks::TaskTrigger;
 
 /// SQLite database repository implementation
 pub struct SqliteRepository {
@@ -208,6 +209,72 @@ impl SqliteRepository {
         .execute(pool)
         .await?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS media_probe (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                mtime INTEGER NOT NULL,
+                data TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS login_attempts (
+                remote_address TEXT PRIMARY KEY,
+                failed INTEGER NOT NULL DEFAULT 0,
+                last_failed INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_keys (
+                token TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                created INTEGER NOT NULL,
+                last_used INTEGER NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS libraries (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                type TEXT NOT NULL,
+                directories TEXT NOT NULL,
+                hls_server TEXT NOT NULL DEFAULT '',
+                removed INTEGER NOT NULL DEFAULT 0
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS task_triggers (
+                task_id TEXT PRIMARY KEY,
+                triggers TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
         Ok(())
     }
 
@@ -243,9 +310,36 @@ impl SqliteRepository {
 
 #[async_trait]
 impl Repository for SqliteRepository {
-    fn start_background_jobs(&self) {
-        // No-op: all writes are now write-through (DB first, then cache).
+    async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Delete access tokens last used before `idle_before` or created before
+/// `created_before`, from both the database and the cache.
+async fn delete_expired_tokens(
+    pool: &SqlitePool,
+    cache: &Mutex<HashMap<String, AccessToken>>,
+    idle_before: Option<chrono::DateTime<chrono::Utc>>,
+    created_before: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<u64> {
+    if idle_before.is_none() && created_before.is_none() {
+        return Ok(0);
     }
+    let idle_before = idle_before.map(|t| t.timestamp()).unwrap_or(i64::MIN);
+    let created_before = created_before.map(|t| t.timestamp()).unwrap_or(i64::MIN);
+
+    // Hold the cache lock so a token cannot be refreshed while it is being removed.
+    let mut cache = cache.lock().await;
+    let result = sqlx::query("DELETE FROM access_tokens WHERE last_used < ? OR created < ?")
+        .bind(idle_before)
+        .bind(created_before)
+        .execute(pool)
+        .await?;
+    cache.retain(|_, t| t.last_used.timestamp() >= idle_before && t.created.timestamp() >= created_before);
+
+    Ok(result.rows_affected())
 }
 
 impl SqliteRepository {
@@ -270,6 +364,13 @@ impl SqliteRepository {
                 "my_media_excludes" => props.my_media_excludes = split_comma(&value),
                 "allow_tags" => props.allow_tags = split_comma(&value),
                 "block_tags" => props.block_tags = split_comma(&value),
+                "max_parental_rating" => props.max_parental_rating = value.parse().ok(),
+                "block_unrated_items" => props.block_unrated_items = split_comma(&value),
+                "access_schedules" => props.access_schedules = parse_access_schedules(&value),
+                "invalid_login_attempts" => props.invalid_login_attempts = value.parse().unwrap_or(0),
+                "login_attempts_before_lockout" => {
+                    props.login_attempts_before_lockout = value.parse().unwrap_or(0)
+                }
                 _ => {}
             }
         }
@@ -289,6 +390,17 @@ impl SqliteRepository {
             ("my_media_excludes", props.my_media_excludes.join(",")),
             ("allow_tags", props.allow_tags.join(",")),
             ("block_tags", props.block_tags.join(",")),
+            (
+                "max_parental_rating",
+                props.max_parental_rating.map(|r| r.to_string()).unwrap_or_default(),
+            ),
+            ("block_unrated_items", props.block_unrated_items.join(",")),
+            ("access_schedules", format_access_schedules(&props.access_schedules)),
+            ("invalid_login_attempts", props.invalid_login_attempts.to_string()),
+            (
+                "login_attempts_before_lockout",
+                props.login_attempts_before_lockout.to_string(),
+            ),
         ];
         for (key, value) in kvs {
             sqlx::query("INSERT OR REPLACE INTO user_properties (userid, key, value) VALUES (?, ?, ?)")
@@ -310,6 +422,31 @@ fn split_comma(s: &str) -> Vec<String> {
     }
 }
 
+/// Access schedules are stored as a comma separated list of "Day@start-end",
+/// e.g. "Weekday@7-20.5,Weekend@9-22".
+fn parse_access_schedules(s: &str) -> Vec<AccessSchedule> {
+    split_comma(s)
+        .iter()
+        .filter_map(|entry| {
+            let (day, hours) = entry.split_once('@')?;
+            let (start, end) = hours.split_once('-')?;
+            Some(AccessSchedule {
+                day_of_week: day.to_string(),
+                start_hour: start.parse().ok()?,
+                end_hour: end.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn format_access_schedules(schedules: &[AccessSchedule]) -> String {
+    schedules
+        .iter()
+        .map(|s| format!("{}@{}-{}", s.day_of_week, s.start_hour, s.end_hour))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn bool_to_string(b: bool) -> String {
     if b { "1" } else { "0" }.to_string()
 }
@@ -523,6 +660,76 @@ impl AccessTokenRepo for SqliteRepository {
 
         Ok(())
     }
+
+    async fn delete_expired_access_tokens(
+        &self,
+        idle_before: Option<chrono::DateTime<chrono::Utc>>,
+        created_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u64> {
+        delete_expired_tokens(&self.pool, &self.access_token_cache, idle_before, created_before).await
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepo for SqliteRepository {
+    async fn get_api_key(&self, token: &str) -> Result<ApiKey> {
+        let row = sqlx::query_as::<_, (String, String, String, i64, i64)>(
+            "SELECT token, user_id, name, created, last_used FROM api_keys WHERE token = ?",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DatabaseError::NotFound)?;
+
+        Ok(ApiKey {
+            token: row.0,
+            user_id: row.1,
+            name: row.2,
+            created: chrono::DateTime::from_timestamp(row.3, 0).unwrap_or_default(),
+            last_used: chrono::DateTime::from_timestamp(row.4, 0).unwrap_or_default(),
+        })
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query_as::<_, (String, String, String, i64, i64)>(
+            "SELECT token, user_id, name, created, last_used FROM api_keys ORDER BY created",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ApiKey {
+                token: row.0,
+                user_id: row.1,
+                name: row.2,
+                created: chrono::DateTime::from_timestamp(row.3, 0).unwrap_or_default(),
+                last_used: chrono::DateTime::from_timestamp(row.4, 0).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn upsert_api_key(&self, key: &ApiKey) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO api_keys (token, user_id, name, created, last_used) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&key.token)
+        .bind(&key.user_id)
+        .bind(&key.name)
+        .bind(key.created.timestamp())
+        .bind(key.last_used.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_api_key(&self, token: &str) -> Result<()> {
+        sqlx::query("DELETE FROM api_keys WHERE token = ?")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -818,24 +1025,77 @@ impl PlaylistRepo for SqliteRepository {
 #[async_trait]
 impl PersonRepo for SqliteRepository {
     async fn get_person(&self, name: &str, _user_id: &str) -> Result<Person> {
-        let row = sqlx::query_as::<_, (String, String, i64, String, String, String, i64, i64)>(
-            "SELECT id, name, date_of_birth, place_of_birth, poster_url, bio, created, last_updated FROM persons WHERE name = ?"
+        let row = sqlx::query_as::<_, PersonRow>(
+            "SELECT id, name, date_of_birth, place_of_birth, poster_url, bio, created, last_updated FROM persons WHERE name = ? COLLATE NOCASE"
         )
         .bind(name)
         .fetch_optional(&self.pool)
         .await?
         .ok_or(DatabaseError::NotFound)?;
 
-        Ok(Person {
-            id: row.0,
-            name: row.1,
-            date_of_birth: chrono::DateTime::from_timestamp(row.2, 0).unwrap_or_default(),
-            place_of_birth: row.3,
-            poster_url: row.4,
-            bio: row.5,
-            created: chrono::DateTime::from_timestamp(row.6, 0).unwrap_or_default(),
-            last_updated: chrono::DateTime::from_timestamp(row.7, 0).unwrap_or_default(),
-        })
+        Ok(person_from_row(row))
+    }
+
+    async fn get_person_by_id(&self, id: &str) -> Result<Person> {
+        let row = sqlx::query_as::<_, PersonRow>(
+            "SELECT id, name, date_of_birth, place_of_birth, poster_url, bio, created, last_updated FROM persons WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DatabaseError::NotFound)?;
+
+        Ok(person_from_row(row))
+    }
+
+    async fn upsert_person(&self, person: &Person) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO persons (id, name, date_of_birth, place_of_birth, poster_url, bio, created, last_updated)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                id = excluded.id,
+                date_of_birth = excluded.date_of_birth,
+                place_of_birth = excluded.place_of_birth,
+                poster_url = excluded.poster_url,
+                bio = excluded.bio,
+                last_updated = excluded.last_updated
+            "#,
+        )
+        .bind(&person.id)
+        .bind(&person.name)
+        .bind(person.date_of_birth.timestamp())
+        .bind(&person.place_of_birth)
+        .bind(&person.poster_url)
+        .bind(&person.bio)
+        .bind(person.created.timestamp())
+        .bind(person.last_updated.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_persons_updated_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM persons WHERE last_updated < ?")
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+type PersonRow = (String, String, i64, String, String, String, i64, i64);
+
+fn person_from_row(row: PersonRow) -> Person {
+    Person {
+        id: row.0,
+        name: row.1,
+        date_of_birth: chrono::DateTime::from_timestamp(row.2, 0).unwrap_or_default(),
+        place_of_birth: row.3,
+        poster_url: row.4,
+        bio: row.5,
+        created: chrono::DateTime::from_timestamp(row.6, 0).unwrap_or_default(),
+        last_updated: chrono::DateTime::from_timestamp(row.7, 0).unwrap_or_default(),
     }
 }
 
@@ -972,3 +1232,250 @@ impl ImageRepo for SqliteRepository {
         Ok(())
     }
 }
+
+#[async_trait]
+impl MediaProbeRepo for SqliteRepository {
+    async fn get_media_probes(&self) -> Result<Vec<MediaProbe>> {
+        let rows = sqlx::query_as::<_, (String, i64, i64, String)>("SELECT path, size, mtime, data FROM media_probe")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(path, size, mtime, data)| MediaProbe {
+                path,
+                size,
+                mtime,
+                data,
+            })
+            .collect())
+    }
+
+    async fn upsert_media_probe(&self, probe: &MediaProbe) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO media_probe (path, size, mtime, data) VALUES (?, ?, ?, ?)")
+            .bind(&probe.path)
+            .bind(probe.size)
+            .bind(probe.mtime)
+            .bind(&probe.data)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_media_probe(&self, path: &str) -> Result<()> {
+        sqlx::query("DELETE FROM media_probe WHERE path = ?")
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepo for SqliteRepository {
+    async fn get_login_attempts(&self, remote_address: &str) -> Result<LoginAttempts> {
+        let row = sqlx::query_as::<_, (i32, i64)>(
+            "SELECT failed, last_failed FROM login_attempts WHERE remote_address = ?",
+        )
+        .bind(remote_address)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DatabaseError::NotFound)?;
+
+        Ok(LoginAttempts {
+            remote_address: remote_address.to_string(),
+            failed: row.0,
+            last_failed: chrono::DateTime::from_timestamp(row.1, 0).unwrap_or_default(),
+        })
+    }
+
+    async fn upsert_login_attempts(&self, attempts: &LoginAttempts) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO login_attempts (remote_address, failed, last_failed) VALUES (?, ?, ?)",
+        )
+        .bind(&attempts.remote_address)
+        .bind(attempts.failed)
+        .bind(attempts.last_failed.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_login_attempts(&self, remote_address: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE remote_address = ?")
+            .bind(remote_address)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LibraryRepo for SqliteRepository {
+    async fn get_libraries(&self) -> Result<Vec<Library>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, bool)>(
+            "SELECT id, name, type, directories, hls_server, removed FROM libraries ORDER BY rowid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut libraries = Vec::new();
+        for row in rows {
+            libraries.push(Library {
+                id: row.0,
+                name: row.1,
+                collection_type: row.2,
+                directories: serde_json::from_str(&row.3)?,
+                hls_server: row.4,
+                removed: row.5,
+            });
+        }
+        Ok(libraries)
+    }
+
+    async fn upsert_library(&self, library: &Library) -> Result<()> {
+        let directories_json = serde_json::to_string(&library.directories)?;
+        sqlx::query(
+            r#"
+            INSERT INTO libraries (id, name, type, directories, hls_server, removed) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                type = excluded.type,
+                directories = excluded.directories,
+                hls_server = excluded.hls_server,
+                removed = excluded.removed
+            "#,
+        )
+        .bind(&library.id)
+        .bind(&library.name)
+        .bind(&library.collection_type)
+        .bind(&directories_json)
+        .bind(&library.hls_server)
+        .bind(library.removed)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskTriggerRepo for SqliteRepository {
+    async fn get_task_triggers(&self) -> Result<HashMap<String, Vec<TaskTrigger>>> {
+        let rows = sqlx::query_as::<_, (String, String)>("SELECT task_id, triggers FROM task_triggers")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut triggers = HashMap::new();
+        for (task_id, json) in rows {
+            triggers.insert(task_id, serde_json::from_str(&json)?);
+        }
+        Ok(triggers)
+    }
+
+    async fn upsert_task_triggers(&self, task_id: &str, triggers: &[TaskTrigger]) -> Result<()> {
+        let triggers_json = serde_json::to_string(triggers)?;
+        sqlx::query("INSERT OR REPLACE INTO task_triggers (task_id, triggers) VALUES (?, ?)")
+            .bind(task_id)
+            .bind(&triggers_json)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_schedules_roundtrip() {
+        let schedules = parse_access_schedules("Weekday@7.5-21,Friday@20-2,bogus,Sunday@x-3");
+        assert_eq!(
+            schedules,
+            vec![
+                AccessSchedule {
+                    day_of_week: "Weekday".to_string(),
+                    start_hour: 7.5,
+                    end_hour: 21.0,
+                },
+                AccessSchedule {
+                    day_of_week: "Friday".to_string(),
+                    start_hour: 20.0,
+                    end_hour: 2.0,
+                },
+            ]
+        );
+        assert_eq!(parse_access_schedules(&format_access_schedules(&schedules)), schedules);
+        assert!(parse_access_schedules("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_expired_tokens() {
+        let repo = SqliteRepository::new("sqlite::memory:").await.unwrap();
+        let now = chrono::Utc::now();
+        let days = |n: i64| now - chrono::Duration::days(n);
+        repo.upsert_user(&User {
+            id: "alice".to_string(),
+            username: "alice".to_string(),
+            password: String::new(),
+            created: now,
+            last_login: now,
+            last_used: now,
+            properties: Default::default(),
+        })
+        .await
+        .unwrap();
+        for (name, created, last_used) in [
+            ("fresh", days(1), days(0)),
+            ("idle", days(10), days(5)),
+            ("old", days(100), days(0)),
+        ] {
+            repo.upsert_access_token(&AccessToken {
+                user_id: "alice".to_string(),
+                token: name.to_string(),
+                device_id: name.to_string(),
+                device_name: "Phone".to_string(),
+                application_name: "App".to_string(),
+                application_version: "1.0".to_string(),
+                remote_address: "10.0.0.1".to_string(),
+                created,
+                last_used,
+            })
+            .await
+            .unwrap();
+        }
+        let tokens = || async {
+            let mut tokens: Vec<String> = repo
+                .get_access_tokens("alice")
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token)
+                .collect();
+            tokens.sort();
+            tokens
+        };
+
+        // Without limits nothing is removed.
+        assert_eq!(repo.delete_expired_access_tokens(None, None).await.unwrap(), 0);
+        assert_eq!(tokens().await.len(), 3);
+
+        let removed = repo
+            .delete_expired_access_tokens(Some(days(3)), None)
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(tokens().await, vec!["fresh", "old"]);
+        assert!(repo.get_access_token("idle").await.is_err());
+
+        let removed = repo
+            .delete_expired_access_tokens(None, Some(days(30)))
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(tokens().await, vec!["fresh"]);
+        // The cache no longer has the removed token either.
+        assert!(repo.get_access_token("old").await.is_err());
+        assert!(repo.get_access_token("fresh").await.is_ok());
+    }
+}
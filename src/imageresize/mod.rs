@@ -210,6 +210,33 @@ impl ImageResizer {
         Ok(())
     }
 
+    /// Remove cached images created `max_age` or longer ago. Only the shard
+    /// directories are searched, other directories in the cache directory,
+    /// like the transcoder's, are left alone. Returns the number of removed files.
+    pub fn clean_cache(&self, max_age: std::time::Duration) -> Result<u64> {
+        let mut removed = 0;
+        for shard in fs::read_dir(&self.cache_dir)? {
+            let shard = shard?;
+            let is_shard = shard
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()));
+            if !is_shard || !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let age = metadata.modified()?.elapsed().unwrap_or_default();
+                if metadata.is_file() && age >= max_age {
+                    fs::remove_file(entry.path())?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
     /// Get cache directory path
     pub fn cache_dir(&self) -> &Path {
         &self.cache_dir
@@ -270,4 +297,24 @@ mod tests {
 
         let _ = fs::remove_dir_all(temp_dir);
     }
+
+    #[test]
+    fn test_clean_cache() {
+        let temp_dir = env::temp_dir().join("test_clean_cache");
+        let resizer = ImageResizer::new(temp_dir.clone()).unwrap();
+        fs::create_dir_all(temp_dir.join("ab")).unwrap();
+        fs::create_dir_all(temp_dir.join("transcode")).unwrap();
+        fs::write(temp_dir.join("ab").join("image.jpg"), b"x").unwrap();
+        fs::write(temp_dir.join("transcode").join("segment.ts"), b"x").unwrap();
+
+        assert_eq!(
+            resizer.clean_cache(std::time::Duration::from_secs(3600)).unwrap(),
+            0
+        );
+        assert_eq!(resizer.clean_cache(std::time::Duration::ZERO).unwrap(), 1);
+        assert!(!temp_dir.join("ab").join("image.jpg").exists());
+        assert!(temp_dir.join("transcode").join("segment.ts").exists());
+
+        let _ = fs::remove_dir_all(temp_dir);
+    }
 }
@@ -0,0 +1,193 @@
+use super::auth::{generate_random_token, is_admin};
+use super::error::apierror;
+use super::jellyfin::JellyfinState;
+use super::types::*;
+use crate::database::model::{AccessToken, ApiKey};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ApiKeyCreateQuery {
+    pub app: Option<String>,
+}
+
+/// GET /Auth/Keys
+pub async fn api_keys_get(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let keys = match state.repo.get_api_keys().await {
+        Ok(keys) => keys,
+        Err(_) => {
+            return apierror(StatusCode::INTERNAL_SERVER_ERROR, "Error retrieving API keys").into_response()
+        }
+    };
+
+    let items: Vec<AuthenticationInfo> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| make_authentication_info(i as i64 + 1, key))
+        .collect();
+    let count = items.len();
+    axum::Json(QueryResult {
+        items,
+        start_index: 0,
+        total_record_count: count as i32,
+    })
+    .into_response()
+}
+
+/// POST /Auth/Keys?app=<name>
+pub async fn api_keys_post(
+    Extension(token): Extension<AccessToken>,
+    Query(query): Query<ApiKeyCreateQuery>,
+    State(state): State<JellyfinState>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let name = match query.app.filter(|a| !a.trim().is_empty()) {
+        Some(name) => name,
+        None => return apierror(StatusCode::BAD_REQUEST, "App name missing").into_response(),
+    };
+
+    let now = chrono::Utc::now();
+    let key = ApiKey {
+        token: generate_random_token(),
+        user_id: token.user_id.clone(),
+        name,
+        created: now,
+        last_used: now,
+    };
+    match state.repo.upsert_api_key(&key).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => apierror(StatusCode::INTERNAL_SERVER_ERROR, "Error creating API key").into_response(),
+    }
+}
+
+/// DELETE /Auth/Keys/{key}
+pub async fn api_keys_delete(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    if state.repo.get_api_key(&key).await.is_err() {
+        return apierror(StatusCode::NOT_FOUND, "API key not found").into_response();
+    }
+    match state.repo.delete_api_key(&key).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => apierror(StatusCode::INTERNAL_SERVER_ERROR, "Error deleting API key").into_response(),
+    }
+}
+
+fn make_authentication_info(id: i64, key: &ApiKey) -> AuthenticationInfo {
+    AuthenticationInfo {
+        id,
+        access_token: key.token.clone(),
+        app_name: key.name.clone(),
+        is_active: true,
+        date_created: key.created,
+        date_last_activity: key.last_used,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::CollectionRepo;
+    use crate::database::model::User;
+    use std::sync::Arc;
+
+    fn token(user_id: &str) -> AccessToken {
+        let now = chrono::Utc::now();
+        AccessToken {
+            user_id: user_id.to_string(),
+            token: format!("{}-token", user_id),
+            device_id: "d".to_string(),
+            device_name: "Phone".to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created: now,
+            last_used: now,
+        }
+    }
+
+    async fn add_user(state: &JellyfinState, name: &str, admin: bool) {
+        let now = chrono::Utc::now();
+        let mut user = User {
+            id: name.to_string(),
+            username: name.to_string(),
+            password: String::new(),
+            created: now,
+            last_login: now,
+            last_used: now,
+            properties: Default::default(),
+        };
+        user.properties.admin = admin;
+        state.repo.upsert_user(&user).await.unwrap();
+    }
+
+    async fn get(state: &JellyfinState, user_id: &str) -> StatusCode {
+        api_keys_get(Extension(token(user_id)), State(state.clone()))
+            .await
+            .into_response()
+            .status()
+    }
+
+    async fn post(state: &JellyfinState, user_id: &str) -> StatusCode {
+        let query = ApiKeyCreateQuery {
+            app: Some("script".to_string()),
+        };
+        api_keys_post(Extension(token(user_id)), Query(query), State(state.clone()))
+            .await
+            .into_response()
+            .status()
+    }
+
+    async fn delete(state: &JellyfinState, user_id: &str, key: &str) -> StatusCode {
+        api_keys_delete(
+            Extension(token(user_id)),
+            State(state.clone()),
+            Path(key.to_string()),
+        )
+        .await
+        .into_response()
+        .status()
+    }
+
+    #[tokio::test]
+    async fn test_admin_only() {
+        let state =
+            JellyfinState::for_test("test_api_keys_admin_only", Arc::new(CollectionRepo::new())).await;
+        add_user(&state, "admin", true).await;
+        add_user(&state, "alice", false).await;
+
+        assert_eq!(post(&state, "alice").await, StatusCode::FORBIDDEN);
+        assert_eq!(post(&state, "admin").await, StatusCode::NO_CONTENT);
+        let key = state.repo.get_api_keys().await.unwrap().remove(0).token;
+
+        assert_eq!(get(&state, "alice").await, StatusCode::FORBIDDEN);
+        assert_eq!(get(&state, "admin").await, StatusCode::OK);
+        // Unknown users are not admins either.
+        assert_eq!(get(&state, "nobody").await, StatusCode::FORBIDDEN);
+
+        assert_eq!(delete(&state, "alice", &key).await, StatusCode::FORBIDDEN);
+        assert!(state.repo.get_api_key(&key).await.is_ok());
+        assert_eq!(delete(&state, "admin", &key).await, StatusCode::NO_CONTENT);
+        assert!(state.repo.get_api_key(&key).await.is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use axum::{extract::State, response::Json, Extension};
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::types::*;
 use crate::database::model::AccessToken;
@@ -7,10 +8,10 @@ use crate::idhash::*;
 
 /// GET /Users/{userId}/Items/Filters - Get item filters
 pub async fn item_filters(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Json<ItemFilterResponse> {
-    let details = state.collections.details();
+    let details = AccessFilter::for_user(&state, &token.user_id).await.details();
     Json(ItemFilterResponse {
         genres: details.genres,
         tags: details.tags,
@@ -21,10 +22,10 @@ pub async fn item_filters(
 
 /// GET /Users/{userId}/Items/Filters2 - Get item filters version 2
 pub async fn item_filters2(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Json<ItemFilter2Response> {
-    let details = state.collections.details();
+    let details = AccessFilter::for_user(&state, &token.user_id).await.details();
     let genres = details
         .genres
         .into_iter()
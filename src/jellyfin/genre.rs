@@ -8,6 +8,7 @@ use std::collections::HashMap;
 
 use chrono::Utc;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::types::*;
 use crate::database::model::AccessToken;
@@ -15,25 +16,26 @@ use crate::idhash::*;
 
 /// GET /Genres
 pub async fn genres_all(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Json<UserItemsResponse> {
     let parent_id = query_params.get("parentId").cloned();
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
 
     let genres = if let Some(pid) = parent_id {
-        if let Some(collection) = state.collections.get_collection(&pid) {
+        if let Some(collection) = access.get_collection(&pid) {
             collection.details().genres.clone()
         } else {
             Vec::new()
         }
     } else {
-        state.collections.details().genres.clone()
+        access.details().genres.clone()
     };
 
     let items: Vec<BaseItemDto> = genres
         .into_iter()
-        .map(|g| make_jfitem_genre(&state, &g))
+        .map(|g| make_jfitem_genre(&state, &access, &g))
         .collect();
 
     let total_count = items.len() as i32;
@@ -47,25 +49,26 @@ pub async fn genres_all(
 
 /// GET /Genres/{name}
 pub async fn genre_details(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
     Path(name): Path<String>,
 ) -> Result<Json<BaseItemDto>, StatusCode> {
-    let genres = state.collections.details().genres;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let genres = access.details().genres;
     if genres.contains(&name) {
-        Ok(Json(make_jfitem_genre(&state, &name)))
+        Ok(Json(make_jfitem_genre(&state, &access, &name)))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
 /// make_jfitem_genre creates a genre item.
-fn make_jfitem_genre(state: &JellyfinState, genre: &str) -> BaseItemDto {
+pub fn make_jfitem_genre(state: &JellyfinState, access: &AccessFilter, genre: &str) -> BaseItemDto {
     let genre_id = id_hash_prefix(ITEM_PREFIX_GENRE, genre);
 
     // Try to get actual genre item count from collections
     let mut child_count = 1;
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         let counts = c.genre_count();
         if let Some(&count) = counts.get(&genre_id) {
             child_count = count as i32;
@@ -12,8 +12,11 @@ use tower_http::services::ServeFile;
 
 use tracing::warn;
 
+use super::access::AccessFilter;
+use super::auth::extract_token;
 use super::jellyfin::JellyfinState;
 use crate::collection::item::Item;
+use crate::collection::metadata::is_url;
 use crate::collection::CollectionRepo;
 use crate::database::model::AccessToken;
 use crate::database::ImageMetadata;
@@ -95,6 +98,24 @@ async fn get_image_common(
         return Err(StatusCode::NOT_FOUND);
     }
 
+    // Library images are subject to the access policy of the requesting
+    // user. Requests without a valid token cannot see any library item.
+    let user_id = match extract_token(req.headers(), req.uri()) {
+        Some(token) => state.repo.get_access_token(&token).await.ok().map(|t| t.user_id),
+        None => None,
+    };
+    let access = match user_id {
+        Some(user_id) => AccessFilter::for_user(&state, &user_id).await,
+        None => AccessFilter::deny_all(state.collections.clone()),
+    };
+    let hidden = match state.collections.get_item_by_id(&item_id) {
+        Some((_, item)) => !access.item_allowed(&item),
+        None => state.collections.get_collection(&item_id).is_some() && !access.collection_allowed(&item_id),
+    };
+    if hidden {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
     // Check DB first
     if let Ok(Some(meta)) = state.repo.has_image(&item_id, &image_type).await {
         if let Ok((_, data)) = state.repo.get_image(&item_id, &image_type).await {
@@ -109,7 +130,16 @@ async fn get_image_common(
         }
     }
 
-    let image_path = find_image_path(&state.collections, &item_id, &image_type).ok_or_else(|| {
+    let image_path = if is_jf_person_id(&item_id) {
+        match find_person_image(&state, &item_id, &image_type).await {
+            Some(PersonImage::Url(url)) => return Ok(Redirect::to(&url).into_response()),
+            Some(PersonImage::File(path)) => Some(path),
+            None => None,
+        }
+    } else {
+        find_image_path(&state.collections, &item_id, &image_type)
+    };
+    let image_path = image_path.ok_or_else(|| {
         warn!("Image not found: item_id={}, image_type={}", item_id, image_type);
         StatusCode::NOT_FOUND
     })?;
@@ -203,11 +233,7 @@ fn find_image_path(collections: &CollectionRepo, item_id: &str, image_type: &str
         _ => "",
     };
 
-    let mut path = PathBuf::from(&collection.directory);
-    if !item_path.is_empty() {
-        path.push(item_path);
-    }
-    path.push(image_filename);
+    let path = collection.resolve(PathBuf::from(item_path).join(image_filename));
 
     if path.exists() {
         Some(path)
@@ -217,6 +243,29 @@ fn find_image_path(collections: &CollectionRepo, item_id: &str, image_type: &str
     }
 }
 
+enum PersonImage {
+    File(PathBuf),
+    Url(String),
+}
+
+/// Image of a person: the one imported from the people directory, or else
+/// a thumb from the `.actors` directory of a movie or show.
+async fn find_person_image(state: &JellyfinState, person_id: &str, image_type: &str) -> Option<PersonImage> {
+    if !matches!(image_type.to_lowercase().as_str(), "primary" | "thumb") {
+        return None;
+    }
+    if let Ok(person) = state.repo.get_person_by_id(person_id).await {
+        if is_url(&person.poster_url) {
+            return Some(PersonImage::Url(person.poster_url));
+        }
+        let path = PathBuf::from(&person.poster_url);
+        if !person.poster_url.is_empty() && path.exists() {
+            return Some(PersonImage::File(path));
+        }
+    }
+    state.collections.person_image(person_id).map(PersonImage::File)
+}
+
 /// POST /Items/{item}/Images/{type} — upload image to DB
 pub async fn post_item_image(
     Extension(_token): Extension<AccessToken>,
@@ -377,13 +426,15 @@ pub async fn post_studio_image(
     store_db_image(&state, &studio_id, &image_type, &headers, &body).await
 }
 
-/// GET /Persons/{name}/Images/{type} — serve person image from DB
+/// GET /Persons/{name}/Images/{type} — serve uploaded, people directory or library person image
 pub async fn get_person_image(
     State(state): State<JellyfinState>,
     AxumPath((name, image_type)): AxumPath<(String, String)>,
+    Query(params): Query<ImageParams>,
+    req: http::Request<Body>,
 ) -> Result<Response, StatusCode> {
-    let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, &name);
-    get_db_image(&state, &person_id, &image_type).await
+    let person_id = person_id(&state, &name).await;
+    get_image_common(state, person_id, image_type, 0, params, req).await
 }
 
 /// POST /Persons/{name}/Images/{type} — upload person image
@@ -394,10 +445,20 @@ pub async fn post_person_image(
     headers: HeaderMap,
     body: axum::body::Bytes,
 ) -> StatusCode {
-    let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, &name);
+    let person_id = person_id(&state, &name).await;
     store_db_image(&state, &person_id, &image_type, &headers, &body).await
 }
 
+/// Id of a person by name. The path is lowercased by normalize_uri, so the
+/// name is first resolved to the person as spelled in the database or library.
+async fn person_id(state: &JellyfinState, name: &str) -> String {
+    if let Ok(person) = state.repo.get_person(name, "").await {
+        return person.id;
+    }
+    let name = state.collections.person_name(name).unwrap_or_else(|| name.to_string());
+    id_hash_prefix(ITEM_PREFIX_PERSON, &name)
+}
+
 async fn get_db_image(
     state: &JellyfinState,
     item_id: &str,
@@ -7,6 +7,7 @@ use axum::{
 use std::collections::{HashMap, HashSet};
 use tracing::warn;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
@@ -30,37 +31,42 @@ pub async fn items_query(
         .map(|v| v == "true")
         .unwrap_or(false);
     let items_ids = query_params.get("ids").map(|v| v.split(',').collect::<Vec<_>>());
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
 
     // Get native Items based on the request type
     let mut qitems = match parent_id {
         _ if items_ids.is_some() => {
-            get_items_by_ids(&state, items_ids.unwrap()).map_err(|_| StatusCode::NOT_FOUND)?
+            get_items_by_ids(&access, items_ids.unwrap()).map_err(|_| StatusCode::NOT_FOUND)?
         }
-        None if recursive => get_items_all(&state),
+        None if recursive => get_items_all(&access),
         None => {
             // No parentId, not recursive → root overview
-            get_root_overview_items(&state, &token.user_id).await
+            get_root_overview_items(&state, &access, &token.user_id).await
+        }
+        Some(pid) if is_jf_collection_favorites_id(pid) => {
+            get_favorites_items(&state, &access, &token.user_id).await
         }
-        Some(pid) if is_jf_collection_favorites_id(pid) => get_favorites_items(&state, &token.user_id).await,
         Some(pid) if is_jf_collection_playlist_id(pid) => {
             get_playlist_overview_items(&state, &token.user_id).await
         }
-        Some(pid) if is_jf_playlist_id(pid) => get_playlist_items_native(&state, &token.user_id, pid)
-            .await
-            .map_err(|_| StatusCode::NOT_FOUND)?,
+        Some(pid) if is_jf_playlist_id(pid) => {
+            get_playlist_items_native(&state, &access, &token.user_id, pid)
+                .await
+                .map_err(|_| StatusCode::NOT_FOUND)?
+        }
         Some(pid) if is_jf_collection_id(pid) && !is_jf_root_id(pid) => {
-            get_items_by_collection(&state, pid, recursive).map_err(|_| StatusCode::NOT_FOUND)?
+            get_items_by_collection(&access, pid, recursive).map_err(|_| StatusCode::NOT_FOUND)?
         }
-        Some(pid) if is_jf_genre_id(pid) => get_items_by_genre(&state, pid),
-        Some(pid) if is_jf_studio_id(pid) => get_items_by_studio(&state, pid),
+        Some(pid) if is_jf_genre_id(pid) => get_items_by_genre(&access, pid),
+        Some(pid) if is_jf_studio_id(pid) => get_items_by_studio(&access, pid),
         Some(pid) => {
             // Check if parent_id is a show (→ seasons) or season (→ episodes)
-            match state.collections.get_item_by_id(pid) {
+            match access.get_item_by_id(pid) {
                 Some((_, Item::Show(_))) => {
-                    get_seasons_items(&state, pid).map_err(|_| StatusCode::NOT_FOUND)?
+                    get_seasons_items(&access, pid).map_err(|_| StatusCode::NOT_FOUND)?
                 }
                 Some((_, Item::Season(_))) => {
-                    get_episodes_items(&state, pid).map_err(|_| StatusCode::NOT_FOUND)?
+                    get_episodes_items(&access, pid).map_err(|_| StatusCode::NOT_FOUND)?
                 }
                 _ => {
                     warn!("items_query: unsupported parent_id {}", pid);
@@ -75,7 +81,7 @@ pub async fn items_query(
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_item_count = qitems.len() as i32;
     let mut qitems = qitems;
     apply_query_item_sorting(&mut qitems, &query_params);
@@ -111,9 +117,10 @@ pub async fn items_resume(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let mut qitems: Vec<Item> = Vec::new();
     for id in resume_ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(&id) {
+        if let Some((_, item)) = access.get_item_by_id(&id) {
             qitems.push(item);
         }
     }
@@ -125,7 +132,7 @@ pub async fn items_resume(
         user_data_loaded = true;
     }
 
-    let mut qitems = apply_query_items_filter(qitems, &query_params);
+    let mut qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_count = qitems.len() as i32;
     apply_query_item_sorting(&mut qitems, &query_params);
     let (mut qitems, start_index) = apply_query_item_pagination(qitems, &query_params);
@@ -277,9 +284,9 @@ fn apply_fields_filter(items: &mut Vec<BaseItemDto>, query_params: &HashMap<Stri
 }
 
 /// Collect items matching a genre ID across all collections.
-fn get_items_by_genre(state: &JellyfinState, genre_id: &str) -> Vec<Item> {
+fn get_items_by_genre(access: &AccessFilter, genre_id: &str) -> Vec<Item> {
     let mut items = Vec::new();
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         for item in c.items {
             let matches = item
                 .genres()
@@ -294,9 +301,9 @@ fn get_items_by_genre(state: &JellyfinState, genre_id: &str) -> Vec<Item> {
 }
 
 /// Collect items matching a studio ID across all collections.
-fn get_items_by_studio(state: &JellyfinState, studio_id: &str) -> Vec<Item> {
+fn get_items_by_studio(access: &AccessFilter, studio_id: &str) -> Vec<Item> {
     let mut items = Vec::new();
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         for item in c.items {
             let matches = item
                 .studios()
@@ -1,25 +1,36 @@
 use axum::{
     extract::{Path as AxumPath, Query, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json},
     Extension,
 };
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
+use super::access::AccessFilter;
+use super::auth::is_admin;
+use super::error::apierror;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
 use super::util::item::{apply_query_item_sorting, apply_query_items_filter};
+use crate::collection::CollectionRepo;
 use crate::database::model::AccessToken;
+use crate::tasks::{TASK_REFRESH_ITEMS, TASK_REFRESH_LIBRARY};
+
+/// Most similar items returned, whatever limit the client asks for.
+const SIMILAR_MAX_LIMIT: usize = 100;
 
 /// GET /Library/MediaFolders - Returns collections as media folders (same as VirtualFolders)
 pub async fn library_media_folders(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Json<UserItemsResponse> {
     // Re-use user_views logic: return collections as items
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let mut items = Vec::new();
-    for collection in state.collections.get_collections() {
+    for collection in access.get_collections() {
         items.push(BaseItemDto {
             id: collection.id.clone(),
             name: collection.name.clone(),
@@ -35,17 +46,62 @@ pub async fn library_media_folders(
     })
 }
 
-/// POST /Library/Refresh - Trigger library refresh (not implemented)
-pub async fn library_refresh(Extension(_token): Extension<AccessToken>) -> StatusCode {
-    StatusCode::NO_CONTENT
+/// POST /Library/Refresh - Rescan all collections in the background
+pub async fn library_refresh(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    // A scan that is already running is not started again.
+    let collections = state.collections.clone();
+    let _ = state.tasks.spawn(TASK_REFRESH_LIBRARY, move |ctx| async move {
+        collections
+            .refresh(Duration::ZERO, ctx.progress_fn(), ctx.cancelled_fn())
+            .await
+    });
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// POST /Items/{item}/Refresh - Rescan an item in the background
+pub async fn items_refresh(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    AxumPath(item_id): AxumPath<String>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    if state.collections.get_collection(&item_id).is_none()
+        && state.collections.get_item_by_id(&item_id).is_none()
+    {
+        return apierror(StatusCode::NOT_FOUND, "Item not found").into_response();
+    }
+
+    // Refreshes of other items can run at the same time, only one of them
+    // shows up as running task.
+    let refresh = |collections: Arc<CollectionRepo>, item_id: String| async move {
+        collections.refresh_item(&item_id).await.map(|_| ())
+    };
+    let (collections, id) = (state.collections.clone(), item_id.clone());
+    if state
+        .tasks
+        .spawn(TASK_REFRESH_ITEMS, move |_| refresh(collections, id))
+        .is_err()
+    {
+        tokio::spawn(refresh(state.collections.clone(), item_id));
+    }
+    StatusCode::NO_CONTENT.into_response()
 }
 
 /// GET /Items/Counts - Get item counts
 pub async fn items_counts(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Json<ItemCountResponse> {
-    let details = state.collections.details();
+    let details = AccessFilter::for_user(&state, &token.user_id).await.details();
 
     Json(ItemCountResponse {
         movie_count: details.movie_count as i32,
@@ -84,10 +140,11 @@ pub async fn item_ancestors(
 ) -> Result<Json<Vec<BaseItemDto>>, StatusCode> {
     use crate::collection::{CollectionFolder, Item};
 
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let mut ancestors: Vec<Item> = Vec::new();
 
     // Try episode first (most specific) → season → show → collection
-    if let Some((collection, show, season, _episode)) = state.collections.get_episode_by_id(&item_id) {
+    if let Some((collection, show, season, _episode)) = access.get_episode_by_id(&item_id) {
         ancestors.push(Item::Season(season));
         ancestors.push(Item::Show(show));
         ancestors.push(Item::CollectionFolder(CollectionFolder {
@@ -99,7 +156,7 @@ pub async fn item_ancestors(
         }));
     }
     // Try season → show → collection
-    else if let Some((collection, show, _season)) = state.collections.get_season_by_id(&item_id) {
+    else if let Some((collection, show, _season)) = access.get_season_by_id(&item_id) {
         ancestors.push(Item::Show(show));
         ancestors.push(Item::CollectionFolder(CollectionFolder {
             id: collection.id.clone(),
@@ -110,7 +167,7 @@ pub async fn item_ancestors(
         }));
     }
     // Movie or show → collection
-    else if let Some((collection, _item)) = state.collections.get_item_by_id(&item_id) {
+    else if let Some((collection, _item)) = access.get_item_by_id(&item_id) {
         ancestors.push(Item::CollectionFolder(CollectionFolder {
             id: collection.id.clone(),
             name: collection.name.clone(),
@@ -124,7 +181,7 @@ pub async fn item_ancestors(
 
     let mut result = convert_items_to_dtos(&ancestors, &state, &token.user_id).await;
 
-    let root_item = make_jfitem_root(&state, &token.user_id)
+    let root_item = make_jfitem_root(&state, &access, &token.user_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     result.push(root_item);
@@ -156,20 +213,19 @@ pub async fn items_similar(
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Result<Json<UsersItemsSimilarResponse>, StatusCode> {
     let item_id = path.last().ok_or(StatusCode::BAD_REQUEST)?;
-    let (collection, item) = state
-        .collections
-        .get_item_by_id(&item_id)
-        .ok_or(StatusCode::NOT_FOUND)?;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let (_, item) = access.get_item_by_id(&item_id).ok_or(StatusCode::NOT_FOUND)?;
 
     let limit = query_params
         .get("limit")
         .and_then(|v| v.parse::<usize>().ok())
-        .unwrap_or(10);
-    let similar_ids = state.collections.similar(&collection.id, &item.id(), limit).await;
+        .unwrap_or(10)
+        .min(SIMILAR_MAX_LIMIT);
+    let similar_ids = access.similar(&item.id(), limit);
 
     let mut qitems: Vec<crate::collection::Item> = Vec::new();
     for id in similar_ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(&id) {
+        if let Some((_, item)) = access.get_item_by_id(&id) {
             qitems.push(item);
         }
     }
@@ -178,7 +234,7 @@ pub async fn items_similar(
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_count = qitems.len() as i32;
     let mut qitems = qitems;
     apply_query_item_sorting(&mut qitems, &query_params);
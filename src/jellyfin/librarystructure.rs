@@ -1,27 +1,360 @@
-use axum::{extract::State, response::Json, Extension};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    Extension,
+};
 
+use super::access::AccessFilter;
+use super::auth::is_admin;
+use super::error::apierror;
 use super::jellyfin::JellyfinState;
 use super::types::*;
-use crate::database::model::AccessToken;
+use crate::collection::Collection;
+use crate::database::model::{AccessToken, Library};
+
+#[derive(serde::Deserialize)]
+pub struct VirtualFolderQuery {
+    pub name: Option<String>,
+    #[serde(rename = "collectionType")]
+    pub collection_type: Option<String>,
+    #[serde(rename = "newName")]
+    pub new_name: Option<String>,
+    pub path: Option<String>,
+}
 
 /// GET /Library/VirtualFolders
-/// Returns the available collections as virtual folders.
+/// Returns the available collections as virtual folders. Only admins get to
+/// see the directories of a collection.
 pub async fn library_virtual_folders(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Json<Vec<MediaLibrary>> {
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let admin = is_admin(&state.repo, &token.user_id).await;
     let mut response = Vec::new();
 
-    for collection in state.collections.get_collections() {
+    for collection in access.get_collections() {
+        let locations = match admin {
+            true => collection.directories.clone(),
+            false => vec!["/".to_string()],
+        };
         response.push(MediaLibrary {
             name: collection.name.clone(),
             item_id: Some(collection.id.clone()),
             primary_image_item_id: Some(collection.id.clone()),
             collection_type: Some(collection.collection_type.as_str().to_string()),
-            locations: Some(vec!["/".to_string()]),
+            locations: Some(locations),
             ..MediaLibrary::default()
         });
     }
 
     Json(response)
 }
+
+/// POST /Library/VirtualFolders
+/// Adds a collection and starts scanning it.
+pub async fn library_virtual_folders_add(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query): Query<VirtualFolderQuery>,
+    Query(params): Query<Vec<(String, String)>>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let Some(name) = query.name.filter(|n| !n.trim().is_empty()) else {
+        return apierror(StatusCode::BAD_REQUEST, "Name missing").into_response();
+    };
+    let collection_type = match query.collection_type.as_deref().map(|t| t.to_lowercase()) {
+        Some(t) if t == "movies" => "movies",
+        Some(t) if t == "tvshows" || t == "shows" => "shows",
+        _ => return apierror(StatusCode::BAD_REQUEST, "Unsupported collection type").into_response(),
+    };
+
+    // The paths are passed as query parameter or in the library options.
+    // Body is optional, some clients send none.
+    let dto = serde_json::from_slice::<AddVirtualFolderDto>(&body).unwrap_or_default();
+    let mut directories = query_paths(&params);
+    if directories.is_empty() {
+        directories = dto
+            .library_options
+            .and_then(|o| o.path_infos)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|p| p.path)
+            .collect();
+    }
+
+    let collection_id = match state.collections.add_collection(
+        name.trim().to_string(),
+        None,
+        collection_type,
+        directories,
+        String::new(),
+    ) {
+        Ok(id) => id,
+        Err(e) => return apierror(StatusCode::BAD_REQUEST, &e).into_response(),
+    };
+
+    if let Err(status) = save_library(&state, &collection_id, false).await {
+        return status.into_response();
+    }
+    state.collections.scan_collection(&collection_id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// DELETE /Library/VirtualFolders
+/// Removes a collection.
+pub async fn library_virtual_folders_delete(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query): Query<VirtualFolderQuery>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(collection) = collection_by_name(&state, query.name.as_deref()) else {
+        return apierror(StatusCode::NOT_FOUND, "Library not found").into_response();
+    };
+
+    // Store the removal first, the row needs the details of the collection.
+    if let Err(status) = save_library(&state, &collection.id, true).await {
+        return status.into_response();
+    }
+    state.collections.remove_collection(&collection.id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// POST /Library/VirtualFolders/Name
+/// Renames a collection. The ID stays the same.
+pub async fn library_virtual_folders_rename(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query): Query<VirtualFolderQuery>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(collection) = collection_by_name(&state, query.name.as_deref()) else {
+        return apierror(StatusCode::NOT_FOUND, "Library not found").into_response();
+    };
+    let Some(new_name) = query.new_name.filter(|n| !n.trim().is_empty()) else {
+        return apierror(StatusCode::BAD_REQUEST, "New name missing").into_response();
+    };
+    if collection_by_name(&state, Some(&new_name)).is_some_and(|c| c.id != collection.id) {
+        return apierror(StatusCode::CONFLICT, "A library with that name already exists").into_response();
+    }
+
+    state
+        .collections
+        .rename_collection(&collection.id, new_name.trim());
+    match save_library(&state, &collection.id, false).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// POST /Library/VirtualFolders/Paths
+/// Adds a directory to a collection and rescans it.
+pub async fn library_virtual_folders_add_path(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(dto): Json<MediaPathDto>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(collection) = collection_by_name(&state, Some(&dto.name)) else {
+        return apierror(StatusCode::NOT_FOUND, "Library not found").into_response();
+    };
+    let Some(path) = dto
+        .path
+        .or(dto.path_info.and_then(|p| p.path))
+        .filter(|p| !p.trim().is_empty())
+    else {
+        return apierror(StatusCode::BAD_REQUEST, "Path missing").into_response();
+    };
+
+    if collection.directories.contains(&path) {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    let mut directories = collection.directories.clone();
+    directories.push(path);
+    state
+        .collections
+        .set_collection_directories(&collection.id, &directories);
+    if let Err(status) = save_library(&state, &collection.id, false).await {
+        return status.into_response();
+    }
+    state.collections.scan_collection(&collection.id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// DELETE /Library/VirtualFolders/Paths
+/// Removes a directory from a collection and rescans it.
+pub async fn library_virtual_folders_delete_path(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query): Query<VirtualFolderQuery>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(collection) = collection_by_name(&state, query.name.as_deref()) else {
+        return apierror(StatusCode::NOT_FOUND, "Library not found").into_response();
+    };
+    let Some(path) = query.path else {
+        return apierror(StatusCode::BAD_REQUEST, "Path missing").into_response();
+    };
+    if !collection.directories.contains(&path) {
+        return apierror(StatusCode::NOT_FOUND, "Path not found").into_response();
+    }
+
+    let directories: Vec<String> = collection
+        .directories
+        .iter()
+        .filter(|d| **d != path)
+        .cloned()
+        .collect();
+    state
+        .collections
+        .set_collection_directories(&collection.id, &directories);
+    if let Err(status) = save_library(&state, &collection.id, false).await {
+        return status.into_response();
+    }
+    state.collections.scan_collection(&collection.id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// The `paths` query parameters, which are either repeated as in
+/// `?paths=a&paths=b`, or a single comma separated list as in `?paths=a,b`.
+fn query_paths(params: &[(String, String)]) -> Vec<String> {
+    params
+        .iter()
+        .filter(|(key, _)| key == "paths")
+        .flat_map(|(_, value)| value.split(','))
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Find a collection by name, ignoring case.
+fn collection_by_name(state: &JellyfinState, name: Option<&str>) -> Option<Collection> {
+    let name = name?.trim();
+    state
+        .collections
+        .get_collections()
+        .into_iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+}
+
+/// Persist the current state of a collection, so it survives a restart.
+async fn save_library(
+    state: &JellyfinState,
+    collection_id: &str,
+    removed: bool,
+) -> Result<(), impl IntoResponse> {
+    let Some(collection) = state.collections.get_collection(collection_id) else {
+        return Err(apierror(StatusCode::NOT_FOUND, "Library not found"));
+    };
+    let library = Library {
+        id: collection.id.clone(),
+        name: collection.name.clone(),
+        collection_type: collection.collection_type.as_str().to_string(),
+        directories: collection.directories.clone(),
+        hls_server: collection.hls_server.clone(),
+        removed,
+    };
+    state
+        .repo
+        .upsert_library(&library)
+        .await
+        .map_err(|_| apierror(StatusCode::INTERNAL_SERVER_ERROR, "Error saving library"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::CollectionRepo;
+    use crate::database::model::User;
+    use std::sync::Arc;
+
+    fn token(user_id: &str) -> AccessToken {
+        let now = chrono::Utc::now();
+        AccessToken {
+            user_id: user_id.to_string(),
+            token: format!("{}-token", user_id),
+            device_id: "d".to_string(),
+            device_name: "Phone".to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created: now,
+            last_used: now,
+        }
+    }
+
+    async fn add(state: &JellyfinState, uri: &str) -> StatusCode {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        library_virtual_folders_add(
+            Extension(token("admin")),
+            State(state.clone()),
+            Query::try_from_uri(&uri).unwrap(),
+            Query::try_from_uri(&uri).unwrap(),
+            Bytes::new(),
+        )
+        .await
+        .into_response()
+        .status()
+    }
+
+    #[test]
+    fn test_query_paths() {
+        let params = |q: &str| -> Vec<(String, String)> {
+            url::form_urlencoded::parse(q.as_bytes()).into_owned().collect()
+        };
+        assert_eq!(query_paths(&params("paths=/a,%20/b&name=x")), vec!["/a", "/b"]);
+        assert_eq!(query_paths(&params("paths=/a&paths=/b")), vec!["/a", "/b"]);
+        assert!(query_paths(&params("paths=&name=x")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_repeated_paths() {
+        let state = JellyfinState::for_test("test_add_repeated_paths", Arc::new(CollectionRepo::new())).await;
+        let now = chrono::Utc::now();
+        let mut admin = User {
+            id: "admin".to_string(),
+            username: "admin".to_string(),
+            password: String::new(),
+            created: now,
+            last_login: now,
+            last_used: now,
+            properties: Default::default(),
+        };
+        admin.properties.admin = true;
+        state.repo.upsert_user(&admin).await.unwrap();
+
+        let status = add(
+            &state,
+            "/Library/VirtualFolders?name=Movies&collectionType=movies&paths=/m1&paths=/m2",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let status = add(
+            &state,
+            "/Library/VirtualFolders?name=Shows&collectionType=tvshows&paths=/s1,/s2",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let movies = collection_by_name(&state, Some("Movies")).unwrap();
+        assert_eq!(movies.directories, vec!["/m1", "/m2"]);
+        let shows = collection_by_name(&state, Some("Shows")).unwrap();
+        assert_eq!(shows.directories, vec!["/s1", "/s2"]);
+    }
+}
@@ -1,6 +1,7 @@
 use axum::{http::header, http::HeaderMap, response::Json};
 
 use super::types::*;
+use crate::collection::PARENTAL_RATINGS;
 
 /// GET /Localization/Cultures
 pub async fn localization_cultures() -> (HeaderMap, Json<Vec<Language>>) {
@@ -36,10 +37,13 @@ pub async fn localization_parental_ratings() -> (HeaderMap, Json<Vec<ParentalRat
     let mut headers = HeaderMap::new();
     headers.insert(header::CACHE_CONTROL, "max-age=3600".parse().unwrap());
 
-    let ratings = vec![ParentalRating {
-        name: "Unrated".to_string(),
-        value: 0,
-    }];
+    let ratings = PARENTAL_RATINGS
+        .iter()
+        .map(|(name, value)| ParentalRating {
+            name: name.to_string(),
+            value: *value,
+        })
+        .collect::<Vec<_>>();
 
     (headers, Json(ratings))
 }
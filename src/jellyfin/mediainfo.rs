@@ -1,4 +1,5 @@
 use axum::{
+    body::Bytes,
     extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
@@ -6,24 +7,54 @@ use axum::{
 };
 use std::collections::HashMap;
 
+use super::deviceprofile::evaluate_device_profile;
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
 use crate::database::model;
 
+/// Query parameters of GET/POST /Items/{item}/PlaybackInfo
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PlaybackInfoQuery {
+    #[serde(rename = "maxStreamingBitrate")]
+    pub max_streaming_bitrate: Option<i64>,
+    #[serde(rename = "audioStreamIndex")]
+    pub audio_stream_index: Option<i32>,
+    #[serde(rename = "subtitleStreamIndex")]
+    pub subtitle_stream_index: Option<i32>,
+    #[serde(rename = "maxAudioChannels")]
+    pub max_audio_channels: Option<i32>,
+    #[serde(rename = "enableDirectPlay")]
+    pub enable_direct_play: Option<bool>,
+    #[serde(rename = "enableDirectStream")]
+    pub enable_direct_stream: Option<bool>,
+    #[serde(rename = "enableTranscoding")]
+    pub enable_transcoding: Option<bool>,
+}
+
+/// Audio bitrate requested when transcoding, in bits per second.
+const TRANSCODE_AUDIO_BITRATE: i64 = 192_000;
+
 /// GET /Items/{item}/PlaybackInfo - Returns playback info including media sources
+///
+/// With a DeviceProfile in the body, each media source is checked against the
+/// capabilities of the client. Sources it cannot play directly get a TranscodingUrl.
 pub async fn items_playback_info(
-    Extension(_token): Extension<model::AccessToken>,
+    Extension(token): Extension<model::AccessToken>,
     State(state): State<JellyfinState>,
     Path(item_id): Path<String>,
+    Query(query): Query<PlaybackInfoQuery>,
+    body: Bytes,
 ) -> Result<Json<PlaybackInfoResponse>, StatusCode> {
-    let (_, item) = state
-        .collections
+    let (_, item) = AccessFilter::for_user(&state, &token.user_id)
+        .await
         .get_item_by_id(&item_id)
         .ok_or(StatusCode::NOT_FOUND)?;
 
     use crate::collection::Item;
-    let media_sources = match &item {
+    let mut media_sources = match &item {
         Item::Movie(m) => make_media_source(&m.id, &m.file_name, m.file_size, &m.metadata),
         Item::Episode(e) => make_media_source(&e.id, &e.file_name, e.file_size, &e.metadata),
         _ => return Err(StatusCode::NOT_FOUND),
@@ -33,12 +64,103 @@ pub async fn items_playback_info(
         return Err(StatusCode::NOT_FOUND);
     }
 
+    // Body is optional, GET requests and some clients send none.
+    let dto = serde_json::from_slice::<PlaybackInfoDto>(&body).unwrap_or_default();
+    let max_bitrate = dto.max_streaming_bitrate.or(query.max_streaming_bitrate);
+    let audio_stream_index = dto.audio_stream_index.or(query.audio_stream_index);
+    let subtitle_stream_index = dto.subtitle_stream_index.or(query.subtitle_stream_index);
+    let max_audio_channels = dto.max_audio_channels.or(query.max_audio_channels);
+    let enable_direct_play = dto.enable_direct_play.or(query.enable_direct_play).unwrap_or(true);
+    let enable_direct_stream = dto.enable_direct_stream.or(query.enable_direct_stream).unwrap_or(true);
+    let enable_transcoding = dto.enable_transcoding.or(query.enable_transcoding).unwrap_or(true);
+
+    // Every playback gets its own session ID, clients echo it in their playstate reports.
+    let play_session_id = uuid::Uuid::new_v4().simple().to_string();
+
+    for source in media_sources.iter_mut() {
+        let Some(profile) = &dto.device_profile else {
+            continue;
+        };
+        let decision = evaluate_device_profile(profile, max_bitrate, source, audio_stream_index, subtitle_stream_index);
+
+        // We serve the file as is, so direct streaming is only possible
+        // when the client could direct play it as well.
+        source.supports_direct_play = enable_direct_play && decision.direct_play;
+        source.supports_direct_stream = enable_direct_stream && decision.direct_play;
+        source.supports_transcoding = enable_transcoding && decision.transcoding_profile.is_some();
+
+        if source.supports_direct_play || source.supports_direct_stream {
+            continue;
+        }
+        let mut reasons = decision.transcode_reasons;
+        if reasons.is_empty() {
+            reasons.push("DirectPlayError".to_string());
+        }
+        if let (true, Some(transcoding)) = (source.supports_transcoding, decision.transcoding_profile) {
+            let channels = max_audio_channels
+                .or_else(|| transcoding.max_audio_channels.as_deref().and_then(|c| c.parse().ok()));
+            source.transcoding_url = Some(make_transcoding_url(
+                source,
+                &token,
+                &play_session_id,
+                max_bitrate.or(profile.max_streaming_bitrate),
+                audio_stream_index,
+                channels,
+                &reasons,
+            ));
+            source.transcoding_sub_protocol = Some("hls".to_string());
+            source.transcoding_container = Some("ts".to_string());
+        }
+        source.transcode_reasons = Some(reasons);
+    }
+
     Ok(Json(PlaybackInfoResponse {
         media_sources,
-        play_session_id: super::session::SESSION_ID.to_string(),
+        play_session_id,
     }))
 }
 
+/// make_transcoding_url builds the URL of the HLS master playlist for a media source.
+fn make_transcoding_url(
+    source: &MediaSourceInfo,
+    token: &model::AccessToken,
+    play_session_id: &str,
+    max_bitrate: Option<i64>,
+    audio_stream_index: Option<i32>,
+    audio_channels: Option<i32>,
+    reasons: &[String],
+) -> String {
+    let mut params = url::form_urlencoded::Serializer::new(String::new());
+    params
+        .append_pair("DeviceId", &token.device_id)
+        .append_pair("MediaSourceId", &source.id)
+        .append_pair("PlaySessionId", play_session_id)
+        .append_pair("VideoCodec", "h264")
+        .append_pair("AudioCodec", "aac")
+        .append_pair("SegmentContainer", "ts")
+        .append_pair("AudioBitrate", &TRANSCODE_AUDIO_BITRATE.to_string());
+
+    // Stay within the bitrate limit, but don't exceed the source bitrate.
+    let mut video_bitrate = max_bitrate.map(|b| b - TRANSCODE_AUDIO_BITRATE);
+    if let Some(source_bitrate) = source.bitrate {
+        video_bitrate = Some(video_bitrate.map_or(source_bitrate as i64, |b| b.min(source_bitrate as i64)));
+    }
+    if let Some(bitrate) = video_bitrate.filter(|b| *b > 0) {
+        params.append_pair("VideoBitrate", &bitrate.to_string());
+    }
+    if let Some(index) = audio_stream_index {
+        params.append_pair("AudioStreamIndex", &index.to_string());
+    }
+    if let Some(channels) = audio_channels {
+        params.append_pair("TranscodingMaxAudioChannels", &channels.to_string());
+    }
+    params
+        .append_pair("TranscodeReasons", &reasons.join(","))
+        .append_pair("api_key", &token.token);
+
+    format!("/videos/{}/master.m3u8?{}", source.id, params.finish())
+}
+
 /// GET /Playback/BitrateTest
 pub async fn playback_bitrate_test(Query(params): Query<HashMap<String, String>>) -> Response {
     let size = params
@@ -5,6 +5,8 @@ pub use util::jellyfin;
 pub use util::jfitem;
 pub use util::*;
 
+pub mod apikey;
+pub use apikey::*;
 pub mod devices;
 pub use devices::*;
 pub mod displayprefs;
@@ -27,6 +29,8 @@ pub mod search;
 pub use search::*;
 pub mod session;
 pub use session::*;
+pub mod syncplay;
+pub use syncplay::*;
 pub mod system;
 pub use system::*;
 pub mod types;
@@ -39,6 +43,8 @@ pub mod userviews;
 pub use userviews::*;
 pub mod videos;
 pub use videos::*;
+pub mod websocket;
+pub use websocket::*;
 pub mod items;
 pub use items::*;
 pub mod show;
@@ -5,6 +5,7 @@ use axum::{
 };
 use std::collections::{HashMap, HashSet};
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::convert_items_to_dtos;
 use super::types::*;
@@ -35,9 +36,10 @@ pub async fn movies_recommendations(
         .unwrap_or_default();
     let favorite_ids = state.repo.get_favorites(&token.user_id).await.unwrap_or_default();
 
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let recent_movies: Vec<Movie> = recent_ids
         .iter()
-        .filter_map(|id| match state.collections.get_item_by_id(id) {
+        .filter_map(|id| match access.get_item_by_id(id) {
             Some((_, Item::Movie(m))) => Some(m),
             _ => None,
         })
@@ -45,7 +47,7 @@ pub async fn movies_recommendations(
 
     let favorite_movies: Vec<Movie> = favorite_ids
         .iter()
-        .filter_map(|id| match state.collections.get_item_by_id(id) {
+        .filter_map(|id| match access.get_item_by_id(id) {
             Some((_, Item::Movie(m))) => Some(m),
             _ => None,
         })
@@ -53,7 +55,7 @@ pub async fn movies_recommendations(
 
     // Build candidate pool: all movies from movie collections
     let mut all_movies: Vec<Movie> = Vec::new();
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         if c.collection_type != CollectionType::Movies {
             continue;
         }
@@ -81,10 +83,7 @@ pub async fn movies_recommendations(
         if groups.len() >= category_limit {
             break;
         }
-        let seed_genres: HashSet<&str> = seed.metadata.genres.iter().map(|s| s.as_str()).collect();
-        let seed_directors: HashSet<&str> = seed.metadata.directors.iter().map(|s| s.as_str()).collect();
-
-        let matches = find_similar_movies(&all_movies, &seed_genres, &seed_directors, &used_ids, item_limit);
+        let matches = find_similar_movies(&access, seed, &used_ids, item_limit);
         if matches.is_empty() {
             continue;
         }
@@ -109,10 +108,7 @@ pub async fn movies_recommendations(
         if groups.len() >= category_limit {
             break;
         }
-        let seed_genres: HashSet<&str> = seed.metadata.genres.iter().map(|s| s.as_str()).collect();
-        let seed_directors: HashSet<&str> = seed.metadata.directors.iter().map(|s| s.as_str()).collect();
-
-        let matches = find_similar_movies(&all_movies, &seed_genres, &seed_directors, &used_ids, item_limit);
+        let matches = find_similar_movies(&access, seed, &used_ids, item_limit);
         if matches.is_empty() {
             continue;
         }
@@ -171,13 +167,13 @@ pub async fn movies_recommendations(
         if groups.len() >= category_limit {
             break;
         }
-        for actor in seed.metadata.actors.iter().take(3) {
+        for actor in seed.metadata.actors.iter().take(3).map(|a| &a.name) {
             if groups.len() >= category_limit {
                 break;
             }
             let matches: Vec<Movie> = all_movies
                 .iter()
-                .filter(|m| !used_ids.contains(&m.id) && m.metadata.actors.contains(actor))
+                .filter(|m| !used_ids.contains(&m.id) && m.metadata.actors.iter().any(|a| &a.name == actor))
                 .take(item_limit)
                 .cloned()
                 .collect();
@@ -203,44 +199,22 @@ pub async fn movies_recommendations(
     Json(groups)
 }
 
-/// Find movies similar to a seed by genre and director overlap.
-/// Returns up to `limit` movies sorted by overlap score (descending).
+/// Find the movies most similar to a seed, most similar first, skipping
+/// the ones in `exclude`.
 fn find_similar_movies(
-    candidates: &[Movie],
-    seed_genres: &HashSet<&str>,
-    seed_directors: &HashSet<&str>,
+    access: &AccessFilter,
+    seed: &Movie,
     exclude: &HashSet<String>,
     limit: usize,
 ) -> Vec<Movie> {
-    if seed_genres.is_empty() && seed_directors.is_empty() {
-        return Vec::new();
-    }
-
-    let mut scored: Vec<(&Movie, usize)> = candidates
+    access
+        .similar(&seed.id, limit + exclude.len())
         .iter()
-        .filter(|m| !exclude.contains(&m.id))
-        .filter_map(|m| {
-            let genre_score = m
-                .metadata
-                .genres
-                .iter()
-                .filter(|g| seed_genres.contains(g.as_str()))
-                .count();
-            let director_score = m
-                .metadata
-                .directors
-                .iter()
-                .filter(|d| seed_directors.contains(d.as_str()))
-                .count();
-            let score = genre_score + director_score * 2;
-            if score > 0 {
-                Some((m, score))
-            } else {
-                None
-            }
+        .filter(|id| !exclude.contains(*id))
+        .filter_map(|id| match access.get_item_by_id(id) {
+            Some((_, Item::Movie(m))) => Some(m),
+            _ => None,
         })
-        .collect();
-
-    scored.sort_by(|a, b| b.1.cmp(&a.1));
-    scored.into_iter().take(limit).map(|(m, _)| m.clone()).collect()
+        .take(limit)
+        .collect()
 }
@@ -1,28 +1,80 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
     Extension,
 };
 use urlencoding::decode;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
+use super::jfitem::make_person_image_tag;
 use super::types::*;
+use super::util::item::apply_query_item_pagination;
+use crate::collection::metadata::is_url;
+use crate::collection::{Collection, Item, PersonKind};
 use crate::database::model::AccessToken;
 use crate::idhash::*;
 
+/// LibraryPerson is a person credited for movies or shows in the library.
+struct LibraryPerson {
+    name: String,
+    kinds: HashSet<PersonKind>,
+    image_tag: Option<String>,
+}
+
 /// GET /Persons
 pub async fn persons_all(
-    Extension(_token): Extension<AccessToken>,
-    State(_state): State<JellyfinState>,
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query_params): Query<HashMap<String, String>>,
 ) -> Json<UserItemsResponse> {
-    // Go implementation returns empty list
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let collections = match query_params.get("parentId") {
+        Some(pid) => access.get_collection(pid).into_iter().collect(),
+        None => access.get_collections(),
+    };
+
+    let types = |name: &str| -> Option<Vec<String>> {
+        query_params
+            .get(name)
+            .map(|t| t.split(',').map(|t| t.to_lowercase()).collect())
+    };
+    let person_types = types("personTypes");
+    let exclude_person_types = types("excludePersonTypes");
+    let has_type = |person: &LibraryPerson, types: &[String]| {
+        person
+            .kinds
+            .iter()
+            .any(|k| types.contains(&k.as_str().to_lowercase()))
+    };
+    let search_term = query_params.get("searchTerm").map(|t| t.to_lowercase());
+    let name_prefix = query_params.get("nameStartsWith").map(|t| t.to_lowercase());
+
+    let people: Vec<LibraryPerson> = library_people(&collections)
+        .into_iter()
+        .filter(|p| person_types.as_ref().is_none_or(|t| has_type(p, t)))
+        .filter(|p| exclude_person_types.as_ref().is_none_or(|t| !has_type(p, t)))
+        .filter(|p| {
+            let name = p.name.to_lowercase();
+            search_term.as_ref().is_none_or(|t| name.contains(t.as_str()))
+                && name_prefix.as_ref().is_none_or(|t| name.starts_with(t.as_str()))
+        })
+        .collect();
+
+    let total_count = people.len() as i32;
+    let (people, start_index) = apply_query_item_pagination(people, &query_params);
+    let items = people
+        .iter()
+        .map(|p| make_jfitem_library_person(&state, p))
+        .collect();
+
     Json(UserItemsResponse {
-        items: Vec::new(),
-        total_record_count: 0,
-        start_index: 0,
+        items,
+        total_record_count: total_count,
+        start_index,
     })
 }
 
@@ -34,17 +86,82 @@ pub async fn person_details(
 ) -> Result<Json<BaseItemDto>, StatusCode> {
     let decoded_name = decode(&name).map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    let db_person = state
-        .repo
-        .get_person(&decoded_name, &token.user_id)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    // The path is lowercased by normalize_uri, so names match in any case.
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let name = decoded_name.to_lowercase();
+    let library_person = library_people(&access.get_collections())
+        .into_iter()
+        .find(|p| p.name.to_lowercase() == name);
+
+    match state.repo.get_person(&decoded_name, &token.user_id).await {
+        Ok(db_person) => {
+            let mut dto = make_jf_item_person(&db_person, &state.server_id);
+            if let Some(tag) = library_person.and_then(|p| p.image_tag) {
+                dto.image_tags.entry("Primary".to_string()).or_insert(tag);
+            }
+            Ok(Json(dto))
+        }
+        Err(_) => {
+            let person = library_person.ok_or(StatusCode::NOT_FOUND)?;
+            Ok(Json(make_jfitem_library_person(&state, &person)))
+        }
+    }
+}
+
+/// All people credited in `collections`, sorted by name.
+fn library_people(collections: &[Collection]) -> Vec<LibraryPerson> {
+    let mut people: BTreeMap<&str, LibraryPerson> = BTreeMap::new();
+    for collection in collections {
+        for item in &collection.items {
+            let path = match item {
+                Item::Movie(m) => &m.path,
+                Item::Show(s) => &s.path,
+                _ => continue,
+            };
+            for credit in item.metadata().credits() {
+                let person = people.entry(credit.name).or_insert_with(|| LibraryPerson {
+                    name: credit.name.to_string(),
+                    kinds: HashSet::new(),
+                    image_tag: None,
+                });
+                person.kinds.insert(credit.kind);
+                if person.image_tag.is_none() {
+                    person.image_tag = make_person_image_tag(path, &credit);
+                }
+            }
+        }
+    }
+    people.into_values().collect()
+}
+
+fn make_jfitem_library_person(state: &JellyfinState, person: &LibraryPerson) -> BaseItemDto {
+    let mut dto = make_jfitem_person(state, &person.name);
+    if let Some(tag) = &person.image_tag {
+        dto.image_tags.insert("Primary".to_string(), tag.clone());
+    }
+    dto
+}
 
-    Ok(Json(make_jf_item_person(&db_person, &state.server_id)))
+/// make_jfitem_person creates a person item that only has a name.
+pub fn make_jfitem_person(state: &JellyfinState, name: &str) -> BaseItemDto {
+    let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, name);
+    BaseItemDto {
+        id: person_id.clone(),
+        server_id: state.server_id.clone(),
+        item_type: "Person".to_string(),
+        name: name.to_string(),
+        sort_name: Some(name.to_string()),
+        etag: Some(person_id),
+        location_type: Some("FileSystem".to_string()),
+        media_type: Some("Unknown".to_string()),
+        ..Default::default()
+    }
 }
 
 fn make_jf_item_person(person: &crate::database::model::Person, server_id: &str) -> BaseItemDto {
     let person_id = id_hash_prefix(ITEM_PREFIX_PERSON, &person.name);
+    // Imported people without a birth date have it set to the epoch.
+    let birth_date = Some(person.date_of_birth).filter(|d| d.timestamp() != 0);
     let mut dto = BaseItemDto {
         id: person_id.clone(),
         name: person.name.clone(),
@@ -52,8 +169,8 @@ fn make_jf_item_person(person: &crate::database::model::Person, server_id: &str)
         item_type: "Person".to_string(),
         etag: Some(person_id.clone()),
         overview: Some(person.bio.clone()),
-        date_created: Some(person.date_of_birth),
-        premiere_date: Some(person.date_of_birth),
+        date_created: birth_date,
+        premiere_date: birth_date,
         location_type: Some("FileSystem".to_string()),
         media_type: Some("Unknown".to_string()),
         play_access: Some("Full".to_string()),
@@ -66,7 +183,11 @@ fn make_jf_item_person(person: &crate::database::model::Person, server_id: &str)
 
     if !person.poster_url.is_empty() {
         let mut image_tags = HashMap::new();
-        image_tags.insert("Primary".to_string(), person.poster_url.clone());
+        let tag = match is_url(&person.poster_url) {
+            true => format!("redirect_{}", person.poster_url),
+            false => id_hash(&person.poster_url),
+        };
+        image_tags.insert("Primary".to_string(), tag);
         dto.image_tags = image_tags;
     }
 
@@ -78,3 +199,128 @@ fn make_jf_item_person(person: &crate::database::model::Person, server_id: &str)
 
     dto
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::CollectionRepo;
+    use crate::database::model::User;
+    use std::fs;
+    use std::sync::Arc;
+
+    /// A library with "Alien", with Sigourney Weaver and John Hurt, directed
+    /// by Ridley Scott, and "Aliens" with Sigourney Weaver and Bill Paxton,
+    /// directed by James Cameron.
+    async fn state(name: &str) -> (JellyfinState, std::path::PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let movies = root.join("movies");
+        for (dir, nfo) in [
+            (
+                "Alien (1979)",
+                "<movie><actor><name>Sigourney Weaver</name></actor>\
+                 <actor><name>John Hurt</name></actor><director>Ridley Scott</director></movie>",
+            ),
+            (
+                "Aliens (1986)",
+                "<movie><actor><name>Sigourney Weaver</name></actor>\
+                 <actor><name>Bill Paxton</name></actor><director>James Cameron</director></movie>",
+            ),
+        ] {
+            fs::create_dir_all(movies.join(dir)).unwrap();
+            fs::write(movies.join(dir).join("movie.mkv"), b"m").unwrap();
+            fs::write(movies.join(dir).join("movie.nfo"), nfo).unwrap();
+        }
+        let collections = CollectionRepo::new();
+        collections
+            .add_collection(
+                "Movies".to_string(),
+                Some("movies".to_string()),
+                "movies",
+                vec![movies.to_string_lossy().to_string()],
+                "".to_string(),
+            )
+            .unwrap();
+        collections.init();
+
+        let state = JellyfinState::for_test(name, Arc::new(collections)).await;
+        let now = chrono::Utc::now();
+        let user = User {
+            id: "alice".to_string(),
+            username: "alice".to_string(),
+            password: String::new(),
+            created: now,
+            last_login: now,
+            last_used: now,
+            properties: Default::default(),
+        };
+        state.repo.upsert_user(&user).await.unwrap();
+        (state, root)
+    }
+
+    async fn persons(state: &JellyfinState, params: &[(&str, &str)]) -> (Vec<String>, i32, i32) {
+        let now = chrono::Utc::now();
+        let token = AccessToken {
+            user_id: "alice".to_string(),
+            token: "t".to_string(),
+            device_id: "d".to_string(),
+            device_name: "Phone".to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created: now,
+            last_used: now,
+        };
+        let params = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let Json(resp) = persons_all(Extension(token), State(state.clone()), Query(params)).await;
+        let names = resp.items.into_iter().map(|i| i.name).collect();
+        (names, resp.total_record_count, resp.start_index)
+    }
+
+    #[tokio::test]
+    async fn test_persons_all() {
+        let (state, root) = state("test_persons_all").await;
+
+        let (names, total, _) = persons(&state, &[]).await;
+        assert_eq!(
+            names,
+            vec![
+                "Bill Paxton",
+                "James Cameron",
+                "John Hurt",
+                "Ridley Scott",
+                "Sigourney Weaver"
+            ]
+        );
+        assert_eq!(total, 5);
+
+        // The total is the number of matches before paging.
+        let (names, total, start) = persons(&state, &[("startIndex", "1"), ("limit", "2")]).await;
+        assert_eq!(names, vec!["James Cameron", "John Hurt"]);
+        assert_eq!((total, start), (5, 1));
+        let (names, total, _) = persons(&state, &[("startIndex", "5")]).await;
+        assert!(names.is_empty());
+        assert_eq!(total, 5);
+
+        // Types match in any case.
+        for types in ["Director", "director", "Director,Producer"] {
+            let (names, total, _) = persons(&state, &[("personTypes", types)]).await;
+            assert_eq!(names, vec!["James Cameron", "Ridley Scott"], "{}", types);
+            assert_eq!(total, 2);
+        }
+        let (names, _, _) = persons(&state, &[("excludePersonTypes", "ACTOR")]).await;
+        assert_eq!(names, vec!["James Cameron", "Ridley Scott"]);
+
+        let (names, _, _) = persons(&state, &[("nameStartsWith", "j")]).await;
+        assert_eq!(names, vec!["James Cameron", "John Hurt"]);
+        let params = [("nameStartsWith", "J"), ("personTypes", "Actor")];
+        let (names, total, _) = persons(&state, &params).await;
+        assert_eq!(names, vec!["John Hurt"]);
+        assert_eq!(total, 1);
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -1,4 +1,5 @@
 use super::error::apierror;
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::make_jfitem;
 use super::types::*;
@@ -124,10 +125,11 @@ pub async fn get_playlist_items(
         Err(_) => return apierror(StatusCode::NOT_FOUND, "Playlist not found").into_response(),
     };
 
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let mut items = Vec::new();
 
     for item_id in playlist.item_ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(&item_id) {
+        if let Some((_, item)) = access.get_item_by_id(&item_id) {
             if let Ok(jfitem) = make_jfitem(&state, &token.user_id, &item).await {
                 items.push(jfitem);
             }
@@ -80,6 +80,7 @@ pub async fn sessions_playing(
     State(state): State<JellyfinState>,
     Json(req): Json<UpdatePlayStateRequest>,
 ) -> StatusCode {
+    state.sessions.update_playing(&token, &req);
     match user_data_update(&state, &token.user_id, &req.item_id, req.position_ticks, false).await {
         Ok(_) => StatusCode::NO_CONTENT,
         Err(e) => {
@@ -95,6 +96,7 @@ pub async fn sessions_playing_progress(
     State(state): State<JellyfinState>,
     Json(req): Json<UpdatePlayStateRequest>,
 ) -> StatusCode {
+    state.sessions.update_playing(&token, &req);
     match user_data_update(&state, &token.user_id, &req.item_id, req.position_ticks, false).await {
         Ok(_) => StatusCode::NO_CONTENT,
         Err(e) => {
@@ -110,6 +112,17 @@ pub async fn sessions_playing_stopped(
     State(state): State<JellyfinState>,
     Json(req): Json<UpdatePlayStateRequest>,
 ) -> StatusCode {
+    let now_playing = state.sessions.stop_playing(&token);
+
+    // Stop the transcoding job of this playback, if any.
+    let play_session_id = req
+        .play_session_id
+        .clone()
+        .or_else(|| now_playing.and_then(|np| np.play_session_id));
+    if let Some(play_session_id) = play_session_id {
+        state.transcoder.stop(&play_session_id).await;
+    }
+
     match user_data_update(&state, &token.user_id, &req.item_id, req.position_ticks, false).await {
         Ok(_) => StatusCode::NO_CONTENT,
         Err(e) => {
@@ -119,6 +132,15 @@ pub async fn sessions_playing_stopped(
     }
 }
 
+/// POST /Sessions/Playing/Ping
+pub async fn sessions_playing_ping(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> StatusCode {
+    state.sessions.ping(&token);
+    StatusCode::NO_CONTENT
+}
+
 async fn user_data_update(
     state: &JellyfinState,
     user_id: &str,
@@ -181,5 +203,6 @@ async fn user_data_update(
     playstate.timestamp = Utc::now();
 
     state.repo.update_user_data(user_id, &item_id, &playstate).await?;
+    state.websocket.user_data_changed(user_id, item_id, &playstate);
     Ok(())
 }
@@ -4,21 +4,34 @@ use axum::{
     response::Json,
     Extension,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use super::access::AccessFilter;
+use super::genre::make_jfitem_genre;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
+use super::person::make_jfitem_person;
+use super::studio::make_jfitem_studio;
 use super::types::*;
 use super::util::item::{apply_query_item_pagination, apply_query_item_sorting, apply_query_items_filter};
 use crate::database::model;
 use crate::idhash::{is_jf_collection_id, is_jf_collection_playlist_id};
 
+/// Number of person, studio and genre hints returned if no limit is given.
+const NAME_HINTS_DEFAULT_LIMIT: usize = 20;
+
+/// Number of documents fetched from the search index for name hints. A
+/// name occurs once for every collection it appears in.
+const SEARCH_NAMES_MAX_RESULTS: usize = 200;
+
 /// GET /Search/Hints - Get search hints
 pub async fn search_hints(
     Extension(token): Extension<model::AccessToken>,
     State(state): State<JellyfinState>,
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Result<Json<SearchHintsResponse>, StatusCode> {
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+
     if let Some(parent_id) = query_params.get("parentId") {
         if is_jf_collection_playlist_id(parent_id) {
             let qitems = get_playlist_overview_items(&state, &token.user_id).await;
@@ -39,26 +52,91 @@ pub async fn search_hints(
         }
     });
 
-    let mut qitems = if let Some(ref scid) = search_collection_id {
-        get_items_by_collection(&state, scid, false).map_err(|_| StatusCode::NOT_FOUND)?
+    let mut qitems = if !flag(&query_params, "includeMedia") {
+        Vec::new()
+    } else if let Some(ref scid) = search_collection_id {
+        get_items_by_collection(&access, scid, false).map_err(|_| StatusCode::NOT_FOUND)?
     } else {
-        get_items_all(&state)
+        get_items_all(&access)
     };
 
     if needs_user_data(&query_params) {
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_count = qitems.len() as i32;
     let mut qitems = qitems;
     apply_query_item_sorting(&mut qitems, &query_params);
     let (qitems, _) = apply_query_item_pagination(qitems, &query_params);
 
-    let items = convert_items_to_dtos(&qitems, &state, &token.user_id).await;
+    let mut items = convert_items_to_dtos(&qitems, &state, &token.user_id).await;
+    let name_hints = name_hints(&state, &access, search_collection_id.as_deref(), &query_params);
+    let total_count = total_count + name_hints.len() as i32;
+    items.extend(name_hints);
 
     Ok(Json(SearchHintsResponse {
         search_hints: items,
         total_record_count: total_count,
     }))
 }
+
+/// Person, studio and genre hints whose name matches `searchTerm`, in the
+/// collections the user can access.
+fn name_hints(
+    state: &JellyfinState,
+    access: &AccessFilter,
+    collection_id: Option<&str>,
+    query_params: &HashMap<String, String>,
+) -> Vec<BaseItemDto> {
+    let Some(term) = query_params.get("searchTerm") else {
+        return Vec::new();
+    };
+    let wanted = |item_type: &str, flag_name: &str| {
+        let included = query_params
+            .get("includeItemTypes")
+            .is_none_or(|types| types.split(',').any(|t| t == item_type));
+        let excluded = query_params
+            .get("excludeItemTypes")
+            .is_some_and(|types| types.split(',').any(|t| t == item_type));
+        included && !excluded && flag(query_params, flag_name)
+    };
+    let want_person = wanted("Person", "includePeople");
+    let want_studio = wanted("Studio", "includeStudios");
+    let want_genre = wanted("Genre", "includeGenres");
+    let limit = query_params
+        .get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(NAME_HINTS_DEFAULT_LIMIT);
+
+    // Names only credited for items the user may not access are not shown.
+    let visible = access.visible_names();
+    let mut seen = HashSet::new();
+    state
+        .collections
+        .search_names(term, SEARCH_NAMES_MAX_RESULTS)
+        .into_iter()
+        .filter(|doc| collection_id.is_none_or(|id| doc.collection_id == id))
+        .filter(|doc| access.collection_allowed(&doc.collection_id))
+        .filter(|doc| {
+            visible.as_ref().is_none_or(|names| {
+                names.contains(&(doc.collection_id.clone(), doc.item_type.clone(), doc.name.clone()))
+            })
+        })
+        .filter(|doc| seen.insert((doc.item_type.clone(), doc.name.clone())))
+        .filter_map(|doc| match doc.item_type.as_str() {
+            "person" if want_person => Some(make_jfitem_person(state, &doc.name)),
+            "studio" if want_studio => Some(make_jfitem_studio(state, &doc.name)),
+            "genre" if want_genre => Some(make_jfitem_genre(state, access, &doc.name)),
+            _ => None,
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Boolean query parameter that defaults to true.
+fn flag(query_params: &HashMap<String, String>, name: &str) -> bool {
+    query_params
+        .get(name)
+        .is_none_or(|v| !v.eq_ignore_ascii_case("false"))
+}
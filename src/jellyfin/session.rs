@@ -1,94 +1,618 @@
 use super::error::apierror;
 use super::jellyfin::JellyfinState;
+use super::jfitem::make_jfitem;
+use super::sessions::Session;
 use super::types::*;
-use crate::database::model::AccessToken;
+use crate::database::model::{AccessToken, User};
 use axum::{
-    extract::State,
+    body::Bytes,
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json},
     Extension,
 };
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
 use std::collections::HashMap;
 
-pub(crate) const SESSION_ID: &str = "e3a869b7a901f8894de8ee65688db6c0";
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    #[serde(default, rename = "deviceId")]
+    pub device_id: Option<String>,
+    #[serde(default, rename = "activeWithinSeconds")]
+    pub active_within_seconds: Option<i64>,
+    #[serde(default, rename = "controllableByUserId")]
+    pub controllable_by_user_id: Option<String>,
+}
 
 /// GET /Sessions
 pub async fn sessions(
     Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
+    Query(query): Query<SessionsQuery>,
 ) -> impl IntoResponse {
     let user = match state.repo.get_user_by_id(&token.user_id).await {
         Ok(u) => u,
         Err(_) => return apierror(StatusCode::NOT_FOUND, "User not found").into_response(),
     };
-
-    let access_tokens = match state.repo.get_access_tokens(&token.user_id).await {
-        Ok(tokens) => tokens,
-        Err(_) => {
-            return apierror(StatusCode::INTERNAL_SERVER_ERROR, "error retrieving sessions").into_response()
-        }
+    let controller = match &query.controllable_by_user_id {
+        Some(id) if *id == user.id => Some(user.clone()),
+        Some(id) => match state.repo.get_user_by_id(id).await {
+            Ok(u) => Some(u),
+            Err(_) => return Json(Vec::<SessionInfo>::new()).into_response(),
+        },
+        None => None,
     };
 
-    // Keep most recent access token per device_id
-    let mut unique_tokens = HashMap::new();
-    for t in access_tokens {
-        let entry = unique_tokens.entry(t.device_id.clone()).or_insert(t.clone());
-        if t.last_used > entry.last_used {
-            *entry = t;
-        }
-    }
+    // Values too large to be a time are ignored.
+    let active_since = query
+        .active_within_seconds
+        .and_then(chrono::TimeDelta::try_seconds)
+        .and_then(|age| Utc::now().checked_sub_signed(age));
+
+    // Admins see all sessions, other users only their own.
+    let mut usernames: HashMap<String, String> = HashMap::new();
+    usernames.insert(user.id.clone(), user.username.clone());
 
     let mut sessions = Vec::new();
-    for t in unique_tokens.values() {
-        sessions.push(make_jf_session_info(&state, t, &user.username));
+    for session in state.sessions.list() {
+        if !user.properties.admin && session.user_id != user.id {
+            continue;
+        }
+        if query.device_id.as_ref().is_some_and(|d| *d != session.device_id) {
+            continue;
+        }
+        if active_since.is_some_and(|since| session.last_activity < since) {
+            continue;
+        }
+        if controller.as_ref().is_some_and(|controller| {
+            !can_control(controller, &session) || !state.websocket.is_connected(&session.device_id)
+        }) {
+            continue;
+        }
+        if !usernames.contains_key(&session.user_id) {
+            let username = match state.repo.get_user_by_id(&session.user_id).await {
+                Ok(u) => u.username,
+                Err(_) => continue,
+            };
+            usernames.insert(session.user_id.clone(), username);
+        }
+        let username = &usernames[&session.user_id];
+        sessions.push(make_jf_session_info(&state, &session, username).await);
     }
 
     Json(sessions).into_response()
 }
 
-fn make_jf_session_info(state: &JellyfinState, token: &AccessToken, username: &str) -> SessionInfo {
+async fn make_jf_session_info(state: &JellyfinState, session: &Session, username: &str) -> SessionInfo {
+    let mut play_state = PlayState {
+        can_seek: false,
+        ..PlayState::default()
+    };
+    let mut now_playing_item = None;
+    let mut now_playing_queue = Vec::new();
+
+    if let Some(np) = &session.now_playing {
+        play_state = PlayState {
+            can_seek: np.can_seek,
+            is_paused: np.is_paused,
+            is_muted: np.is_muted,
+            repeat_mode: np.repeat_mode.clone(),
+            playback_order: "Default".to_string(),
+            position_ticks: Some(np.position_ticks),
+            media_source_id: np.media_source_id.clone(),
+            play_method: Some(np.play_method.clone()),
+        };
+        if let Some((_, item)) = state.collections.get_item_by_id(&np.item_id) {
+            now_playing_item = make_jfitem(state, &session.user_id, &item).await.ok();
+        }
+        now_playing_queue = np.now_playing_queue.clone();
+    }
+
+    let supports_remote_control = state.websocket.is_connected(&session.device_id);
+    let supports_media_control = supports_remote_control && session.supports_media_control;
+
     SessionInfo {
-        id: SESSION_ID.to_string(),
-        user_id: token.user_id.clone(),
+        id: session.id.clone(),
+        user_id: session.user_id.clone(),
         user_name: username.to_string(),
-        last_activity_date: token.last_used,
-        remote_end_point: token.remote_address.clone(),
-        device_name: token.device_name.clone(),
-        device_id: token.device_id.clone(),
-        client: token.application_name.clone(),
-        application_version: token.application_version.clone(),
+        last_activity_date: session.last_activity,
+        remote_end_point: session.remote_address.clone(),
+        device_name: session.device_name.clone(),
+        device_id: session.device_id.clone(),
+        client: session.client.clone(),
+        application_version: session.application_version.clone(),
         is_active: true,
-        supports_media_control: false,
-        supports_remote_control: false,
+        supports_media_control,
+        supports_remote_control,
         has_custom_device_name: false,
         server_id: state.server_id.clone(),
         additional_users: Vec::new(),
-        play_state: PlayState {
-            repeat_mode: "RepeatNone".to_string(),
-            playback_order: "Default".to_string(),
-            can_seek: false,
-            is_paused: false,
-            is_muted: false,
-        },
+        play_state,
         capabilities: SessionResponseCapabilities {
-            playable_media_types: Vec::new(),
-            supported_commands: Vec::new(),
+            playable_media_types: session.playable_media_types.clone(),
+            supported_commands: session.supported_commands.clone(),
             supports_persistent_identifier: true,
-            supports_media_control: false,
+            supports_media_control: session.supports_media_control,
         },
-        now_playing_queue: Vec::new(),
+        now_playing_queue,
         now_playing_queue_full_items: Vec::new(),
-        supported_commands: Vec::new(),
-        playable_media_types: Vec::new(),
+        now_playing_item,
+        supported_commands: session.supported_commands.clone(),
+        playable_media_types: session.playable_media_types.clone(),
     }
 }
 
 /// POST /Sessions/Capabilities
-pub async fn sessions_capabilities() -> impl IntoResponse {
+pub async fn sessions_capabilities(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let split = |key: &str| -> Vec<String> {
+        params
+            .get(key)
+            .map(|v| v.split(',').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect())
+            .unwrap_or_default()
+    };
+    let capabilities = ClientCapabilitiesDto {
+        playable_media_types: split("playableMediaTypes"),
+        supported_commands: split("supportedCommands"),
+        supports_media_control: params
+            .get("supportsMediaControl")
+            .is_some_and(|v| v.eq_ignore_ascii_case("true")),
+    };
+    state.sessions.set_capabilities(&token, &capabilities);
     StatusCode::NO_CONTENT
 }
 
 /// POST /Sessions/Capabilities/Full
-pub async fn sessions_capabilities_full() -> impl IntoResponse {
+pub async fn sessions_capabilities_full(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Ok(capabilities) = serde_json::from_slice::<ClientCapabilitiesDto>(&body) {
+        state.sessions.set_capabilities(&token, &capabilities);
+    }
     StatusCode::NO_CONTENT
 }
+
+const PLAYSTATE_COMMANDS: &[&str] = &[
+    "Stop",
+    "Pause",
+    "Unpause",
+    "NextTrack",
+    "PreviousTrack",
+    "Seek",
+    "Rewind",
+    "FastForward",
+    "PlayPause",
+];
+
+const PLAY_COMMANDS: &[&str] = &["PlayNow", "PlayNext", "PlayLast", "PlayInstantMix", "PlayShuffle"];
+
+const GENERAL_COMMANDS: &[&str] = &[
+    "MoveUp",
+    "MoveDown",
+    "MoveLeft",
+    "MoveRight",
+    "PageUp",
+    "PageDown",
+    "PreviousLetter",
+    "NextLetter",
+    "ToggleOsd",
+    "ToggleContextMenu",
+    "Select",
+    "Back",
+    "TakeScreenshot",
+    "SendKey",
+    "SendString",
+    "GoHome",
+    "GoToSettings",
+    "VolumeUp",
+    "VolumeDown",
+    "Mute",
+    "Unmute",
+    "ToggleMute",
+    "SetVolume",
+    "SetAudioStreamIndex",
+    "SetSubtitleStreamIndex",
+    "ToggleFullscreen",
+    "DisplayContent",
+    "GoToSearch",
+    "DisplayMessage",
+    "SetRepeatMode",
+    "ChannelUp",
+    "ChannelDown",
+    "Guide",
+    "ToggleStats",
+    "PlayMediaSource",
+    "PlayTrailers",
+    "SetShuffleQueue",
+    "PlayState",
+    "ToggleOsdMenu",
+    "Play",
+    "SetMaxStreamingBitrate",
+    "SetPlaybackOrder",
+];
+
+/// Map a (lowercased by path normalization) command name to its canonical spelling.
+fn canonical_command(commands: &[&'static str], name: &str) -> Option<&'static str> {
+    commands.iter().find(|c| c.eq_ignore_ascii_case(name)).copied()
+}
+
+/// Check if a user may control a session: their own, or any if they are an admin.
+fn can_control(user: &User, session: &Session) -> bool {
+    user.properties.admin || session.user_id == user.id
+}
+
+/// Find the target session and check that the user is allowed to control it.
+async fn controlled_session(
+    state: &JellyfinState,
+    token: &AccessToken,
+    session_id: &str,
+) -> Result<Session, axum::response::Response> {
+    let session = state
+        .sessions
+        .get_by_id(session_id)
+        .ok_or_else(|| apierror(StatusCode::NOT_FOUND, "Session not found").into_response())?;
+
+    if session.user_id != token.user_id {
+        let user = state
+            .repo
+            .get_user_by_id(&token.user_id)
+            .await
+            .map_err(|_| apierror(StatusCode::UNAUTHORIZED, "User not found").into_response())?;
+        if !can_control(&user, &session) {
+            return Err(apierror(StatusCode::FORBIDDEN, "Not allowed to control this session").into_response());
+        }
+    }
+    Ok(session)
+}
+
+/// Deliver a message to the session's device over its WebSocket.
+fn send_to_session(
+    state: &JellyfinState,
+    session: &Session,
+    message_type: &str,
+    data: serde_json::Value,
+) -> axum::response::Response {
+    if state.websocket.send_to_device(&session.device_id, message_type, data) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        apierror(StatusCode::NOT_FOUND, "Session does not support remote control").into_response()
+    }
+}
+
+/// POST /Sessions/{id}/Playing/{command}
+pub async fn sessions_playstate_command(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path((session_id, command)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let session = match controlled_session(&state, &token, &session_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let Some(command) = canonical_command(PLAYSTATE_COMMANDS, &command) else {
+        return apierror(StatusCode::BAD_REQUEST, "Unknown playstate command").into_response();
+    };
+
+    let mut data = json!({
+        "Command": command,
+        "ControllingUserId": token.user_id,
+    });
+    if let Some(ticks) = params.get("seekPositionTicks").and_then(|v| v.parse::<i64>().ok()) {
+        data["SeekPositionTicks"] = json!(ticks);
+    }
+    send_to_session(&state, &session, "Playstate", data)
+}
+
+/// POST /Sessions/{id}/Command
+pub async fn sessions_general_command(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(session_id): Path<String>,
+    Json(mut req): Json<GeneralCommand>,
+) -> impl IntoResponse {
+    let session = match controlled_session(&state, &token, &session_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let Some(name) = canonical_command(GENERAL_COMMANDS, &req.name) else {
+        return apierror(StatusCode::BAD_REQUEST, "Unknown command").into_response();
+    };
+
+    req.name = name.to_string();
+    req.controlling_user_id = Some(token.user_id.clone());
+    send_to_session(&state, &session, "GeneralCommand", json!(req))
+}
+
+/// POST /Sessions/{id}/Command/{command}
+pub async fn sessions_general_command_named(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path((session_id, command)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let session = match controlled_session(&state, &token, &session_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let Some(name) = canonical_command(GENERAL_COMMANDS, &command) else {
+        return apierror(StatusCode::BAD_REQUEST, "Unknown command").into_response();
+    };
+
+    let req = GeneralCommand {
+        name: name.to_string(),
+        controlling_user_id: Some(token.user_id.clone()),
+        arguments: HashMap::new(),
+    };
+    send_to_session(&state, &session, "GeneralCommand", json!(req))
+}
+
+/// POST /Sessions/{id}/Message
+pub async fn sessions_message(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(session_id): Path<String>,
+    Json(req): Json<MessageCommand>,
+) -> impl IntoResponse {
+    let session = match controlled_session(&state, &token, &session_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let mut arguments = HashMap::new();
+    arguments.insert("Header".to_string(), req.header.unwrap_or_default());
+    arguments.insert("Text".to_string(), req.text);
+    if let Some(timeout) = req.timeout_ms {
+        arguments.insert("TimeoutMs".to_string(), timeout.to_string());
+    }
+    let cmd = GeneralCommand {
+        name: "DisplayMessage".to_string(),
+        controlling_user_id: Some(token.user_id.clone()),
+        arguments,
+    };
+    send_to_session(&state, &session, "GeneralCommand", json!(cmd))
+}
+
+/// POST /Sessions/{id}/Playing - Tell a client to play or queue items
+pub async fn sessions_play(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(session_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let session = match controlled_session(&state, &token, &session_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let play_command = params.get("playCommand").map(|s| s.as_str()).unwrap_or("PlayNow");
+    let Some(play_command) = canonical_command(PLAY_COMMANDS, play_command) else {
+        return apierror(StatusCode::BAD_REQUEST, "Unknown play command").into_response();
+    };
+    let item_ids: Vec<&str> = params
+        .get("itemIds")
+        .map(|v| v.split(',').filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    if item_ids.is_empty() {
+        return apierror(StatusCode::BAD_REQUEST, "No items to play").into_response();
+    }
+
+    let mut data = json!({
+        "ItemIds": item_ids,
+        "PlayCommand": play_command,
+        "ControllingUserId": token.user_id,
+    });
+    for key in ["startPositionTicks", "audioStreamIndex", "subtitleStreamIndex", "startIndex"] {
+        if let Some(value) = params.get(key).and_then(|v| v.parse::<i64>().ok()) {
+            data[pascal_case(key)] = json!(value);
+        }
+    }
+    if let Some(media_source_id) = params.get("mediaSourceId") {
+        data["MediaSourceId"] = json!(media_source_id);
+    }
+    send_to_session(&state, &session, "Play", data)
+}
+
+fn pascal_case(key: &str) -> String {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::CollectionRepo;
+    use crate::jellyfin::sessions::session_id;
+    use std::sync::Arc;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn token(user_id: &str, device_id: &str) -> AccessToken {
+        AccessToken {
+            user_id: user_id.to_string(),
+            token: format!("{}-token", device_id),
+            device_id: device_id.to_string(),
+            device_name: device_id.to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created: Utc::now(),
+            last_used: Utc::now(),
+        }
+    }
+
+    /// Users "alice", "bob" and "admin". Alice has a connected "tv" and a
+    /// "laptop" without WebSocket, Bob a connected "phone".
+    async fn state(name: &str) -> (JellyfinState, Vec<UnboundedReceiver<String>>) {
+        let state = JellyfinState::for_test(name, Arc::new(CollectionRepo::new())).await;
+        for (name, admin) in [("alice", false), ("bob", false), ("admin", true)] {
+            let mut user = User {
+                id: name.to_string(),
+                username: name.to_string(),
+                password: String::new(),
+                created: Utc::now(),
+                last_login: Utc::now(),
+                last_used: Utc::now(),
+                properties: Default::default(),
+            };
+            user.properties.admin = admin;
+            state.repo.upsert_user(&user).await.unwrap();
+        }
+        let mut receivers = Vec::new();
+        for (user_id, device_id) in [("alice", "tv"), ("alice", "laptop"), ("bob", "phone")] {
+            state.sessions.touch(&token(user_id, device_id));
+            if device_id != "laptop" {
+                receivers.push(state.websocket.register(user_id, device_id).1);
+            }
+        }
+        (state, receivers)
+    }
+
+    fn message(rx: &mut UnboundedReceiver<String>) -> serde_json::Value {
+        serde_json::from_str(&rx.try_recv().unwrap()).unwrap()
+    }
+
+    async fn named_command(
+        state: &JellyfinState,
+        user_id: &str,
+        device_id: &str,
+        command: &str,
+    ) -> StatusCode {
+        let path = Path((session_id(device_id), command.to_string()));
+        sessions_general_command_named(Extension(token(user_id, "remote")), State(state.clone()), path)
+            .await
+            .into_response()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        let (state, mut receivers) = state("test_session_commands").await;
+        let tv = &mut receivers[0];
+
+        assert_eq!(
+            named_command(&state, "alice", "tv", "displaymessage").await,
+            StatusCode::NO_CONTENT
+        );
+        let msg = message(tv);
+        assert_eq!(msg["MessageType"], "GeneralCommand");
+        assert_eq!(msg["Data"]["Name"], "DisplayMessage");
+        assert_eq!(msg["Data"]["ControllingUserId"], "alice");
+
+        // PlayNext is a play command, not a general one.
+        assert_eq!(
+            named_command(&state, "alice", "tv", "playnext").await,
+            StatusCode::BAD_REQUEST
+        );
+        let params = HashMap::from([
+            ("playCommand".to_string(), "playnext".to_string()),
+            ("itemIds".to_string(), "m1,m2".to_string()),
+            ("startPositionTicks".to_string(), "100".to_string()),
+        ]);
+        let path = Path(session_id("tv"));
+        let resp = sessions_play(
+            Extension(token("alice", "remote")),
+            State(state.clone()),
+            path,
+            Query(params),
+        )
+        .await
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let msg = message(tv);
+        assert_eq!(msg["MessageType"], "Play");
+        assert_eq!(msg["Data"]["PlayCommand"], "PlayNext");
+        assert_eq!(msg["Data"]["ItemIds"], json!(["m1", "m2"]));
+        assert_eq!(msg["Data"]["StartPositionTicks"], 100);
+
+        let params = HashMap::from([("seekPositionTicks".to_string(), "50".to_string())]);
+        let path = Path((session_id("tv"), "seek".to_string()));
+        let resp = sessions_playstate_command(
+            Extension(token("alice", "remote")),
+            State(state.clone()),
+            path,
+            Query(params),
+        )
+        .await
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let msg = message(tv);
+        assert_eq!(msg["MessageType"], "Playstate");
+        assert_eq!(msg["Data"]["Command"], "Seek");
+        assert_eq!(msg["Data"]["SeekPositionTicks"], 50);
+
+        // Only the owner and admins may control a session.
+        assert_eq!(
+            named_command(&state, "bob", "tv", "goHome").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            named_command(&state, "admin", "tv", "goHome").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(message(tv)["Data"]["ControllingUserId"], "admin");
+        assert!(tv.try_recv().is_err());
+
+        assert_eq!(
+            named_command(&state, "alice", "laptop", "goHome").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            named_command(&state, "alice", "radio", "goHome").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    async fn device_ids(state: &JellyfinState, user_id: &str, controllable_by: Option<&str>) -> Vec<String> {
+        let query = SessionsQuery {
+            device_id: None,
+            active_within_seconds: None,
+            controllable_by_user_id: controllable_by.map(|id| id.to_string()),
+        };
+        let resp = sessions(
+            Extension(token(user_id, "remote")),
+            State(state.clone()),
+            Query(query),
+        )
+        .await
+        .into_response();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let sessions: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        let mut ids: Vec<String> = sessions
+            .iter()
+            .map(|s| s["DeviceId"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_sessions_controllable() {
+        let (state, _receivers) = state("test_sessions_controllable").await;
+
+        assert_eq!(device_ids(&state, "alice", None).await, vec!["laptop", "tv"]);
+        assert_eq!(
+            device_ids(&state, "admin", None).await,
+            vec!["laptop", "phone", "tv"]
+        );
+
+        assert_eq!(device_ids(&state, "alice", Some("alice")).await, vec!["tv"]);
+        assert_eq!(
+            device_ids(&state, "admin", Some("admin")).await,
+            vec!["phone", "tv"]
+        );
+        assert_eq!(device_ids(&state, "admin", Some("bob")).await, vec!["phone"]);
+        // Users only see their own sessions, whoever may control them.
+        assert_eq!(device_ids(&state, "alice", Some("admin")).await, vec!["tv"]);
+        assert!(device_ids(&state, "bob", Some("alice")).await.is_empty());
+        assert!(device_ids(&state, "admin", Some("nobody")).await.is_empty());
+    }
+}
@@ -6,6 +6,7 @@ use axum::{
 };
 use std::collections::HashMap;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
@@ -21,14 +22,15 @@ pub async fn show_episodes(
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Result<Json<UserItemsResponse>, StatusCode> {
     // Get all episodes across all seasons as native Items
-    let mut qitems = get_show_all_episodes(&state, &show_id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let mut qitems = get_show_all_episodes(&access, &show_id).map_err(|_| StatusCode::NOT_FOUND)?;
 
     if needs_user_data(&query_params) {
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
     // Apply filtering (handles seasonId, includeItemTypes, etc.)
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_count = qitems.len() as i32;
     let mut qitems = qitems;
     apply_query_item_sorting(&mut qitems, &query_params);
@@ -49,13 +51,14 @@ pub async fn show_seasons(
     AxumPath(show_id): AxumPath<String>,
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Result<Json<UserItemsResponse>, StatusCode> {
-    let mut qitems = get_seasons_items(&state, &show_id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let mut qitems = get_seasons_items(&access, &show_id).map_err(|_| StatusCode::NOT_FOUND)?;
 
     if needs_user_data(&query_params) {
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
 
     // Sort seasons by index number (specials/season 0 → index 99, end up last)
     let mut qitems = qitems;
@@ -85,11 +88,12 @@ pub async fn shows_next_up(
         .get_recently_watched(&token.user_id, true, 500)
         .await
         .unwrap_or_default();
-    let next_up_ids = state.collections.next_up(&watched_episodes);
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let next_up_ids = access.next_up(&watched_episodes);
 
     let mut qitems: Vec<Item> = Vec::new();
     for id in next_up_ids {
-        if let Some((_, _show, _season, episode)) = state.collections.get_episode_by_id(&id) {
+        if let Some((_, _show, _season, episode)) = access.get_episode_by_id(&id) {
             qitems.push(Item::Episode(episode));
         }
     }
@@ -98,7 +102,7 @@ pub async fn shows_next_up(
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
     let total_count = qitems.len() as i32;
     let mut qitems = qitems;
     apply_query_item_sorting(&mut qitems, &query_params);
@@ -5,7 +5,6 @@
 /// For this reason, they are in this module instead of the module that they
 /// would otherwise be in.
 ///
-use axum::http::StatusCode;
 use axum::{extract::State, response::IntoResponse, Extension, Json};
 
 use super::types::*;
@@ -45,15 +44,6 @@ pub async fn media_segments_handler() -> impl IntoResponse {
     Json(response)
 }
 
-//
-// OpenApi tag: ItemRefresh.
-//
-
-/// POST /Items/{item}/Refresh - Queue item refresh (not implemented)
-pub async fn items_refresh() -> StatusCode {
-    StatusCode::NO_CONTENT
-}
-
 //
 // OpenApi tag: ItemLookup.
 //
@@ -67,20 +57,6 @@ pub async fn items_remote_images() -> Json<ItemRemoteImagesResponse> {
     })
 }
 
-//
-// OpenApi tag: SyncPlay.
-//
-
-/// GET /SyncPlay/List - List SyncPlay groups (stub)
-pub async fn sync_play_list() -> Json<Vec<serde_json::Value>> {
-    Json(Vec::new())
-}
-
-/// POST /SyncPlay/New - Create SyncPlay group (not implemented)
-pub async fn sync_play_new() -> StatusCode {
-    StatusCode::UNAUTHORIZED
-}
-
 //
 // OpenApi tag: Suggestions.
 //
@@ -7,6 +7,7 @@ use std::collections::HashMap;
 
 use chrono::Utc;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::types::*;
 use crate::database::model::AccessToken;
@@ -14,20 +15,21 @@ use crate::idhash::*;
 
 /// GET /Studios
 pub async fn studios_all(
-    Extension(_token): Extension<AccessToken>,
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
     Query(query_params): Query<HashMap<String, String>>,
 ) -> Json<UserItemsResponse> {
     let parent_id = query_params.get("parentId").cloned();
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
 
     let studios = if let Some(pid) = parent_id {
-        if let Some(collection) = state.collections.get_collection(&pid) {
+        if let Some(collection) = access.get_collection(&pid) {
             collection.details().studios.clone()
         } else {
             Vec::new()
         }
     } else {
-        state.collections.details().studios.clone()
+        access.details().studios.clone()
     };
 
     let items: Vec<BaseItemDto> = studios
@@ -0,0 +1,1063 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::info;
+
+use super::error::apierror;
+use super::jellyfin::JellyfinState;
+use super::types::*;
+use super::websocket::WebSocketHub;
+use crate::database::model::AccessToken;
+
+const TICKS_PER_MILLISECOND: i64 = 10_000;
+
+/// Commands are scheduled this far in the future at minimum, so that all
+/// participants receive them before they have to be executed.
+const MIN_COMMAND_DELAY_MS: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GroupState {
+    Idle,
+    Waiting,
+    Paused,
+    Playing,
+}
+
+impl GroupState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GroupState::Idle => "Idle",
+            GroupState::Waiting => "Waiting",
+            GroupState::Paused => "Paused",
+            GroupState::Playing => "Playing",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Participant {
+    device_id: String,
+    username: String,
+    /// Round trip time to the client in milliseconds, as reported by the client.
+    ping_ms: i64,
+    /// Client is loading or buffering and not ready to play.
+    buffering: bool,
+    /// Do not wait for this client when others are ready.
+    ignore_wait: bool,
+}
+
+#[derive(Debug, Clone)]
+struct QueueItem {
+    item_id: String,
+    playlist_item_id: String,
+}
+
+impl QueueItem {
+    fn new(item_id: &str) -> Self {
+        Self {
+            item_id: item_id.to_string(),
+            playlist_item_id: uuid::Uuid::new_v4().simple().to_string(),
+        }
+    }
+}
+
+/// Group is a set of sessions watching the same play queue in sync.
+#[derive(Debug)]
+struct Group {
+    id: String,
+    name: String,
+    state: GroupState,
+    participants: Vec<Participant>,
+    queue: Vec<QueueItem>,
+    /// Queue in its original order while shuffle is on.
+    unshuffled_queue: Option<Vec<QueueItem>>,
+    playing_index: i32,
+    /// Playback position at `position_time`.
+    position_ticks: i64,
+    position_time: DateTime<Utc>,
+    /// Resume playing once all participants are ready.
+    resume_playing: bool,
+    repeat_mode: String,
+    last_updated: DateTime<Utc>,
+}
+
+impl Group {
+    fn new(name: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            name: name.to_string(),
+            state: GroupState::Idle,
+            participants: Vec::new(),
+            queue: Vec::new(),
+            unshuffled_queue: None,
+            playing_index: -1,
+            position_ticks: 0,
+            position_time: Utc::now(),
+            resume_playing: false,
+            repeat_mode: "RepeatNone".to_string(),
+            last_updated: Utc::now(),
+        }
+    }
+
+    fn info(&self) -> Value {
+        json!({
+            "GroupId": self.id,
+            "GroupName": self.name,
+            "State": self.state.as_str(),
+            "Participants": self.participants.iter().map(|p| p.username.clone()).collect::<Vec<_>>(),
+            "LastUpdatedAt": self.last_updated,
+        })
+    }
+
+    fn current_item(&self) -> Option<&QueueItem> {
+        usize::try_from(self.playing_index).ok().and_then(|i| self.queue.get(i))
+    }
+
+    /// Playback position right now, extrapolated while playing.
+    fn current_position(&self, now: DateTime<Utc>) -> i64 {
+        if self.state == GroupState::Playing {
+            let elapsed = (now - self.position_time).num_milliseconds().max(0);
+            self.position_ticks + elapsed * TICKS_PER_MILLISECOND
+        } else {
+            self.position_ticks
+        }
+    }
+
+    fn participant_mut(&mut self, device_id: &str) -> Option<&mut Participant> {
+        self.participants.iter_mut().find(|p| p.device_id == device_id)
+    }
+
+    /// True if no connected participant is still loading or buffering.
+    fn all_ready(&self, hub: &WebSocketHub) -> bool {
+        self.participants
+            .iter()
+            .all(|p| !p.buffering || p.ignore_wait || !hub.is_connected(&p.device_id))
+    }
+
+    fn send_update_to(&self, hub: &WebSocketHub, device_id: &str, update_type: &str, data: Value) {
+        hub.send_to_device(
+            device_id,
+            "SyncPlayGroupUpdate",
+            json!({ "GroupId": self.id, "Type": update_type, "Data": data }),
+        );
+    }
+
+    fn send_update(&self, hub: &WebSocketHub, update_type: &str, data: Value) {
+        for p in &self.participants {
+            self.send_update_to(hub, &p.device_id, update_type, data.clone());
+        }
+    }
+
+    fn send_command(&self, hub: &WebSocketHub, command: &str, when: DateTime<Utc>, position_ticks: i64) {
+        let data = json!({
+            "GroupId": self.id,
+            "PlaylistItemId": self.current_item().map(|i| i.playlist_item_id.clone()).unwrap_or_default(),
+            "When": when,
+            "PositionTicks": position_ticks,
+            "Command": command,
+            "EmittedAt": Utc::now(),
+        });
+        for p in &self.participants {
+            hub.send_to_device(&p.device_id, "SyncPlayCommand", data.clone());
+        }
+    }
+
+    fn play_queue(&self, reason: &str) -> Value {
+        json!({
+            "Reason": reason,
+            "LastUpdate": self.last_updated,
+            "Playlist": self.queue.iter().map(|i| json!({
+                "ItemId": i.item_id,
+                "PlaylistItemId": i.playlist_item_id,
+            })).collect::<Vec<_>>(),
+            "PlayingItemIndex": self.playing_index,
+            "StartPositionTicks": self.position_ticks,
+            "IsPlaying": self.state == GroupState::Playing || self.resume_playing,
+            "ShuffleMode": if self.unshuffled_queue.is_some() { "Shuffle" } else { "Sorted" },
+            "RepeatMode": self.repeat_mode,
+        })
+    }
+
+    fn set_state(&mut self, hub: &WebSocketHub, state: GroupState, reason: &str) {
+        self.state = state;
+        self.last_updated = Utc::now();
+        self.send_update(
+            hub,
+            "StateUpdate",
+            json!({ "State": state.as_str(), "Reason": reason }),
+        );
+    }
+
+    /// Start playback for everyone at the same moment, or wait until all
+    /// participants are ready.
+    fn unpause(&mut self, hub: &WebSocketHub) {
+        if self.current_item().is_none() {
+            return;
+        }
+        if !self.all_ready(hub) {
+            self.resume_playing = true;
+            self.set_state(hub, GroupState::Waiting, "Unpause");
+            return;
+        }
+        let max_ping = self.participants.iter().map(|p| p.ping_ms).max().unwrap_or(0);
+        let when = Utc::now() + Duration::milliseconds(max_ping.max(MIN_COMMAND_DELAY_MS));
+        self.resume_playing = false;
+        self.position_time = when;
+        self.set_state(hub, GroupState::Playing, "Unpause");
+        self.send_command(hub, "Unpause", when, self.position_ticks);
+    }
+
+    fn pause(&mut self, hub: &WebSocketHub, reason: &str) {
+        let now = Utc::now();
+        self.position_ticks = self.current_position(now);
+        self.position_time = now;
+        self.resume_playing = false;
+        self.set_state(hub, GroupState::Paused, reason);
+        self.send_command(hub, "Pause", now, self.position_ticks);
+    }
+
+    fn stop(&mut self, hub: &WebSocketHub) {
+        let now = Utc::now();
+        self.position_ticks = 0;
+        self.position_time = now;
+        self.resume_playing = false;
+        self.set_state(hub, GroupState::Idle, "Stop");
+        self.send_command(hub, "Stop", now, 0);
+    }
+
+    fn seek(&mut self, hub: &WebSocketHub, position_ticks: i64) {
+        let now = Utc::now();
+        self.resume_playing = self.state == GroupState::Playing || self.resume_playing;
+        self.position_ticks = position_ticks.max(0);
+        self.position_time = now;
+        for p in &mut self.participants {
+            p.buffering = true;
+        }
+        self.set_state(hub, GroupState::Waiting, "Seek");
+        self.send_command(hub, "Seek", now, self.position_ticks);
+    }
+
+    fn buffering(&mut self, hub: &WebSocketHub, device_id: &str, req: &BufferRequestDto) {
+        if self.current_item().is_some_and(|i| i.playlist_item_id != req.playlist_item_id) {
+            return;
+        }
+        if let Some(p) = self.participant_mut(device_id) {
+            p.buffering = true;
+        }
+        if self.state == GroupState::Playing {
+            // Everyone waits for the slowest participant.
+            let now = Utc::now();
+            self.position_ticks = self.current_position(now);
+            self.position_time = now;
+            self.resume_playing = true;
+            self.set_state(hub, GroupState::Waiting, "Buffer");
+            self.send_command(hub, "Pause", now, self.position_ticks);
+        }
+    }
+
+    fn ready(&mut self, hub: &WebSocketHub, device_id: &str, req: &BufferRequestDto) {
+        if self.current_item().is_some_and(|i| i.playlist_item_id != req.playlist_item_id) {
+            return;
+        }
+        if let Some(p) = self.participant_mut(device_id) {
+            p.buffering = false;
+        }
+        self.check_ready(hub);
+    }
+
+    /// Leave the Waiting state once all participants are ready.
+    fn check_ready(&mut self, hub: &WebSocketHub) {
+        if self.state != GroupState::Waiting || !self.all_ready(hub) {
+            return;
+        }
+        if self.resume_playing {
+            self.unpause(hub);
+        } else {
+            self.pause(hub, "Ready");
+        }
+    }
+
+    /// Switch to another item in the queue. Everyone has to load it first.
+    fn set_playing_index(&mut self, hub: &WebSocketHub, index: i32, start_ticks: i64, reason: &str) {
+        self.playing_index = index;
+        self.position_ticks = start_ticks.max(0);
+        self.position_time = Utc::now();
+        self.resume_playing = true;
+        for p in &mut self.participants {
+            p.buffering = true;
+        }
+        self.set_state(hub, GroupState::Waiting, reason);
+        self.queue_updated(hub, reason);
+    }
+
+    fn queue_updated(&mut self, hub: &WebSocketHub, reason: &str) {
+        self.last_updated = Utc::now();
+        self.send_update(hub, "PlayQueue", self.play_queue(reason));
+    }
+
+    fn set_new_queue(&mut self, hub: &WebSocketHub, req: &PlayRequestDto) {
+        self.queue = req.playing_queue.iter().map(|id| QueueItem::new(id)).collect();
+        self.unshuffled_queue = None;
+        if self.queue.is_empty() {
+            self.playing_index = -1;
+            self.stop(hub);
+            self.queue_updated(hub, "NewPlaylist");
+            return;
+        }
+        let index = req.playing_item_position.clamp(0, self.queue.len() as i32 - 1);
+        self.set_playing_index(hub, index, req.start_position_ticks, "NewPlaylist");
+    }
+
+    fn set_playlist_item(&mut self, hub: &WebSocketHub, playlist_item_id: &str) {
+        if let Some(index) = self.queue.iter().position(|i| i.playlist_item_id == playlist_item_id) {
+            self.set_playing_index(hub, index as i32, 0, "SetCurrentItem");
+        }
+    }
+
+    fn next_item(&mut self, hub: &WebSocketHub, playlist_item_id: &str) {
+        let Some(current) = self.current_item() else { return };
+        if current.playlist_item_id != playlist_item_id {
+            return;
+        }
+        let next = match self.repeat_mode.as_str() {
+            "RepeatOne" => self.playing_index,
+            _ if self.playing_index + 1 < self.queue.len() as i32 => self.playing_index + 1,
+            "RepeatAll" => 0,
+            _ => return,
+        };
+        self.set_playing_index(hub, next, 0, "NextItem");
+    }
+
+    fn previous_item(&mut self, hub: &WebSocketHub, playlist_item_id: &str) {
+        let Some(current) = self.current_item() else { return };
+        if current.playlist_item_id != playlist_item_id {
+            return;
+        }
+        let previous = match self.repeat_mode.as_str() {
+            "RepeatOne" => self.playing_index,
+            _ if self.playing_index > 0 => self.playing_index - 1,
+            "RepeatAll" => self.queue.len() as i32 - 1,
+            _ => return,
+        };
+        self.set_playing_index(hub, previous, 0, "PreviousItem");
+    }
+
+    fn enqueue(&mut self, hub: &WebSocketHub, req: &QueueRequestDto) {
+        let items: Vec<QueueItem> = req.item_ids.iter().map(|id| QueueItem::new(id)).collect();
+        if let Some(unshuffled) = self.unshuffled_queue.as_mut() {
+            unshuffled.extend(items.iter().cloned());
+        }
+        let reason = if req.mode == "QueueNext" && self.current_item().is_some() {
+            let at = self.playing_index as usize + 1;
+            self.queue.splice(at..at, items);
+            "QueueNext"
+        } else {
+            self.queue.extend(items);
+            "Queue"
+        };
+        if self.playing_index < 0 && !self.queue.is_empty() {
+            self.set_playing_index(hub, 0, 0, reason);
+        } else {
+            self.queue_updated(hub, reason);
+        }
+    }
+
+    fn remove_from_playlist(&mut self, hub: &WebSocketHub, req: &RemoveFromPlaylistRequestDto) {
+        let current = self.current_item().map(|i| i.playlist_item_id.clone());
+        let keep_current = !req.clear_playing_item;
+
+        let remove = |item: &QueueItem| {
+            let is_current = current.as_deref() == Some(item.playlist_item_id.as_str());
+            if is_current && keep_current {
+                return false;
+            }
+            req.clear_playlist || req.playlist_item_ids.contains(&item.playlist_item_id)
+        };
+        self.queue.retain(|i| !remove(i));
+        if let Some(unshuffled) = self.unshuffled_queue.as_mut() {
+            unshuffled.retain(|i| !remove(i));
+        }
+
+        match current.and_then(|id| self.queue.iter().position(|i| i.playlist_item_id == id)) {
+            Some(index) => {
+                self.playing_index = index as i32;
+                self.queue_updated(hub, "RemoveItems");
+            }
+            None if self.queue.is_empty() => {
+                self.playing_index = -1;
+                self.stop(hub);
+                self.queue_updated(hub, "RemoveItems");
+            }
+            None => {
+                let index = self.playing_index.clamp(0, self.queue.len() as i32 - 1);
+                self.set_playing_index(hub, index, 0, "RemoveItems");
+            }
+        }
+    }
+
+    fn move_playlist_item(&mut self, hub: &WebSocketHub, req: &MovePlaylistItemRequestDto) {
+        let Some(from) = self.queue.iter().position(|i| i.playlist_item_id == req.playlist_item_id) else {
+            return;
+        };
+        let current = self.current_item().map(|i| i.playlist_item_id.clone());
+        let item = self.queue.remove(from);
+        let to = (req.new_index.max(0) as usize).min(self.queue.len());
+        self.queue.insert(to, item);
+        if let Some(id) = current {
+            self.playing_index = self
+                .queue
+                .iter()
+                .position(|i| i.playlist_item_id == id)
+                .map(|i| i as i32)
+                .unwrap_or(-1);
+        }
+        self.queue_updated(hub, "MoveItem");
+    }
+
+    fn set_repeat_mode(&mut self, hub: &WebSocketHub, mode: &str) {
+        self.repeat_mode = match mode {
+            "RepeatOne" | "RepeatAll" => mode.to_string(),
+            _ => "RepeatNone".to_string(),
+        };
+        self.queue_updated(hub, "RepeatMode");
+    }
+
+    fn set_shuffle_mode(&mut self, hub: &WebSocketHub, mode: &str) {
+        let current = self.current_item().cloned();
+        if mode == "Shuffle" {
+            if self.unshuffled_queue.is_none() {
+                self.unshuffled_queue = Some(self.queue.clone());
+            }
+            // The playing item stays first, the rest is shuffled.
+            let mut rest: Vec<QueueItem> = self
+                .queue
+                .iter()
+                .filter(|i| current.as_ref().map(|c| &c.playlist_item_id) != Some(&i.playlist_item_id))
+                .cloned()
+                .collect();
+            rest.shuffle(&mut rand::thread_rng());
+            self.queue = current.iter().cloned().chain(rest).collect();
+            if current.is_some() {
+                self.playing_index = 0;
+            }
+        } else if let Some(unshuffled) = self.unshuffled_queue.take() {
+            self.queue = unshuffled;
+            if let Some(current) = current {
+                self.playing_index = self
+                    .queue
+                    .iter()
+                    .position(|i| i.playlist_item_id == current.playlist_item_id)
+                    .map(|i| i as i32)
+                    .unwrap_or(-1);
+            }
+        }
+        self.queue_updated(hub, "ShuffleMode");
+    }
+
+    fn join(&mut self, hub: &WebSocketHub, participant: Participant) {
+        let device_id = participant.device_id.clone();
+        let username = participant.username.clone();
+        self.participants.retain(|p| p.device_id != device_id);
+        self.participants.push(participant);
+        self.last_updated = Utc::now();
+
+        self.send_update_to(hub, &device_id, "GroupJoined", self.info());
+        for p in self.participants.iter().filter(|p| p.device_id != device_id) {
+            self.send_update_to(hub, &p.device_id, "UserJoined", json!(username));
+        }
+
+        if self.current_item().is_some() {
+            // The new participant has to load the current item. If the group
+            // is playing, everyone waits for it.
+            let now = Utc::now();
+            self.position_ticks = self.current_position(now);
+            self.position_time = now;
+            if self.state == GroupState::Playing {
+                self.resume_playing = true;
+                self.set_state(hub, GroupState::Waiting, "Join");
+                self.send_command(hub, "Pause", now, self.position_ticks);
+            }
+            if let Some(p) = self.participant_mut(&device_id) {
+                p.buffering = true;
+            }
+            self.send_update_to(hub, &device_id, "PlayQueue", self.play_queue("NewPlaylist"));
+        }
+    }
+
+    fn leave(&mut self, hub: &WebSocketHub, device_id: &str) {
+        let Some(pos) = self.participants.iter().position(|p| p.device_id == device_id) else {
+            return;
+        };
+        let participant = self.participants.remove(pos);
+        self.last_updated = Utc::now();
+
+        self.send_update_to(hub, device_id, "GroupLeft", json!(self.id));
+        self.send_update(hub, "UserLeft", json!(participant.username));
+
+        // The participant that left might have been the one everyone waited for.
+        self.check_ready(hub);
+    }
+}
+
+/// SyncPlayManager holds the SyncPlay groups. A session (device) can be in
+/// one group at a time.
+#[derive(Default)]
+pub struct SyncPlayManager {
+    groups: Mutex<HashMap<String, Group>>,
+}
+
+impl SyncPlayManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn leave_all(groups: &mut HashMap<String, Group>, hub: &WebSocketHub, device_id: &str) {
+        for group in groups.values_mut() {
+            group.leave(hub, device_id);
+        }
+        groups.retain(|_, g| {
+            if g.participants.is_empty() {
+                info!("SyncPlay: removing empty group {}", g.name);
+            }
+            !g.participants.is_empty()
+        });
+    }
+
+    /// Remove a device from its group once its last WebSocket connection is
+    /// gone, so a dead participant cannot keep the group waiting.
+    pub fn device_disconnected(&self, hub: &WebSocketHub, device_id: &str) {
+        if hub.is_connected(device_id) {
+            return;
+        }
+        let mut groups = self.groups.lock().unwrap();
+        Self::leave_all(&mut groups, hub, device_id);
+    }
+
+    /// Run `f` on the group the device is in. Tells the client it is not in a
+    /// group if there is none.
+    fn with_group(&self, hub: &WebSocketHub, device_id: &str, f: impl FnOnce(&mut Group)) {
+        let mut groups = self.groups.lock().unwrap();
+        match groups
+            .values_mut()
+            .find(|g| g.participants.iter().any(|p| p.device_id == device_id))
+        {
+            Some(group) => f(group),
+            None => {
+                hub.send_to_device(
+                    device_id,
+                    "SyncPlayGroupUpdate",
+                    json!({ "GroupId": "", "Type": "NotInGroup", "Data": "" }),
+                );
+            }
+        }
+    }
+}
+
+async fn make_participant(state: &JellyfinState, token: &AccessToken) -> Result<Participant, StatusCode> {
+    let user = state
+        .repo
+        .get_user_by_id(&token.user_id)
+        .await
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    Ok(Participant {
+        device_id: token.device_id.clone(),
+        username: user.username,
+        ping_ms: 0,
+        buffering: false,
+        ignore_wait: false,
+    })
+}
+
+/// GET /SyncPlay/List - List SyncPlay groups
+pub async fn sync_play_list(State(state): State<JellyfinState>) -> Json<Vec<Value>> {
+    let groups = state.syncplay.groups.lock().unwrap();
+    Json(groups.values().map(|g| g.info()).collect())
+}
+
+/// GET /SyncPlay/{id} - Get a SyncPlay group
+pub async fn sync_play_get(State(state): State<JellyfinState>, Path(group_id): Path<String>) -> impl IntoResponse {
+    let groups = state.syncplay.groups.lock().unwrap();
+    match groups.get(&group_id) {
+        Some(g) => Json(g.info()).into_response(),
+        None => apierror(StatusCode::NOT_FOUND, "Group not found").into_response(),
+    }
+}
+
+/// POST /SyncPlay/New - Create a SyncPlay group and join it
+pub async fn sync_play_new(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<NewGroupRequestDto>,
+) -> Result<StatusCode, StatusCode> {
+    let participant = make_participant(&state, &token).await?;
+    let hub = &state.websocket;
+
+    let mut groups = state.syncplay.groups.lock().unwrap();
+    SyncPlayManager::leave_all(&mut groups, hub, &token.device_id);
+
+    let name = if req.group_name.is_empty() {
+        format!("{}'s group", participant.username)
+    } else {
+        req.group_name
+    };
+    let mut group = Group::new(&name);
+    info!("SyncPlay: {} created group {}", participant.username, name);
+    group.join(hub, participant);
+    groups.insert(group.id.clone(), group);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /SyncPlay/Join - Join a SyncPlay group
+pub async fn sync_play_join(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<JoinGroupRequestDto>,
+) -> Result<StatusCode, StatusCode> {
+    let participant = make_participant(&state, &token).await?;
+    let hub = &state.websocket;
+
+    let mut groups = state.syncplay.groups.lock().unwrap();
+    if !groups.contains_key(&req.group_id) {
+        hub.send_to_device(
+            &token.device_id,
+            "SyncPlayGroupUpdate",
+            json!({ "GroupId": req.group_id, "Type": "GroupDoesNotExist", "Data": "" }),
+        );
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    SyncPlayManager::leave_all(&mut groups, hub, &token.device_id);
+    if let Some(group) = groups.get_mut(&req.group_id) {
+        info!("SyncPlay: {} joined group {}", participant.username, group.name);
+        group.join(hub, participant);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /SyncPlay/Leave - Leave the current SyncPlay group
+pub async fn sync_play_leave(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> StatusCode {
+    let mut groups = state.syncplay.groups.lock().unwrap();
+    SyncPlayManager::leave_all(&mut groups, &state.websocket, &token.device_id);
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/SetNewQueue - Replace the play queue
+pub async fn sync_play_set_new_queue(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<PlayRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.set_new_queue(hub, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/SetPlaylistItem - Change the playing item
+pub async fn sync_play_set_playlist_item(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<PlaylistItemRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.set_playlist_item(hub, &req.playlist_item_id));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/RemoveFromPlaylist - Remove items from the play queue
+pub async fn sync_play_remove_from_playlist(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<RemoveFromPlaylistRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.remove_from_playlist(hub, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/MovePlaylistItem - Move an item in the play queue
+pub async fn sync_play_move_playlist_item(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<MovePlaylistItemRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.move_playlist_item(hub, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Queue - Add items to the play queue
+pub async fn sync_play_queue(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<QueueRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.enqueue(hub, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Unpause - Start or resume playback
+pub async fn sync_play_unpause(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state.syncplay.with_group(hub, &token.device_id, |g| g.unpause(hub));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Pause - Pause playback
+pub async fn sync_play_pause(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state.syncplay.with_group(hub, &token.device_id, |g| g.pause(hub, "Pause"));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Stop - Stop playback
+pub async fn sync_play_stop(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state.syncplay.with_group(hub, &token.device_id, |g| g.stop(hub));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Seek - Seek to a position
+pub async fn sync_play_seek(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<SeekRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.seek(hub, req.position_ticks));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Buffering - Client is buffering
+pub async fn sync_play_buffering(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<BufferRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.buffering(hub, &token.device_id, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Ready - Client is ready to play
+pub async fn sync_play_ready(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<BufferRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.ready(hub, &token.device_id, &req));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/SetIgnoreWait - Do not make the group wait for this client
+pub async fn sync_play_set_ignore_wait(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<IgnoreWaitRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state.syncplay.with_group(hub, &token.device_id, |g| {
+        if let Some(p) = g.participant_mut(&token.device_id) {
+            p.ignore_wait = req.ignore_wait;
+        }
+        g.check_ready(hub);
+    });
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/NextItem - Play the next item in the queue
+pub async fn sync_play_next_item(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<PlaylistItemRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.next_item(hub, &req.playlist_item_id));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/PreviousItem - Play the previous item in the queue
+pub async fn sync_play_previous_item(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<PlaylistItemRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.previous_item(hub, &req.playlist_item_id));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/SetRepeatMode
+pub async fn sync_play_set_repeat_mode(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<ModeRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.set_repeat_mode(hub, &req.mode));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/SetShuffleMode
+pub async fn sync_play_set_shuffle_mode(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<ModeRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state
+        .syncplay
+        .with_group(hub, &token.device_id, |g| g.set_shuffle_mode(hub, &req.mode));
+    StatusCode::NO_CONTENT
+}
+
+/// POST /SyncPlay/Ping - Client reports its round trip time
+pub async fn sync_play_ping(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Json(req): Json<PingRequestDto>,
+) -> StatusCode {
+    let hub = &state.websocket;
+    state.syncplay.with_group(hub, &token.device_id, |g| {
+        if let Some(p) = g.participant_mut(&token.device_id) {
+            p.ping_ms = req.ping.max(0);
+        }
+    });
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participant(device_id: &str) -> Participant {
+        Participant {
+            device_id: device_id.to_string(),
+            username: device_id.to_string(),
+            ping_ms: 0,
+            buffering: false,
+            ignore_wait: false,
+        }
+    }
+
+    fn buffer_request(group: &Group) -> BufferRequestDto {
+        BufferRequestDto {
+            playlist_item_id: group.current_item().unwrap().playlist_item_id.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Two connected participants with a queue loaded and everyone ready.
+    fn playing_group(hub: &WebSocketHub) -> Group {
+        let mut group = Group::new("test");
+        group.join(hub, participant("a"));
+        group.join(hub, participant("b"));
+        group.set_new_queue(
+            hub,
+            &PlayRequestDto {
+                playing_queue: vec!["item1".to_string(), "item2".to_string()],
+                ..Default::default()
+            },
+        );
+        let req = buffer_request(&group);
+        group.ready(hub, "a", &req);
+        group.ready(hub, "b", &req);
+        group
+    }
+
+    #[test]
+    fn test_new_queue_waits_for_ready() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+        let _b = hub.register("user", "b");
+
+        let mut group = Group::new("test");
+        group.join(&hub, participant("a"));
+        group.join(&hub, participant("b"));
+        assert_eq!(group.state, GroupState::Idle);
+
+        group.set_new_queue(
+            &hub,
+            &PlayRequestDto {
+                playing_queue: vec!["item1".to_string()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(group.state, GroupState::Waiting);
+        assert!(group.participants.iter().all(|p| p.buffering));
+
+        let req = buffer_request(&group);
+        group.ready(&hub, "a", &req);
+        assert_eq!(group.state, GroupState::Waiting);
+        group.ready(&hub, "b", &req);
+        assert_eq!(group.state, GroupState::Playing);
+        assert!(!group.resume_playing);
+    }
+
+    #[test]
+    fn test_ready_for_other_item_ignored() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+
+        let mut group = Group::new("test");
+        group.join(&hub, participant("a"));
+        group.set_new_queue(
+            &hub,
+            &PlayRequestDto {
+                playing_queue: vec!["item1".to_string()],
+                ..Default::default()
+            },
+        );
+        let req = BufferRequestDto {
+            playlist_item_id: "stale".to_string(),
+            ..Default::default()
+        };
+        group.ready(&hub, "a", &req);
+        assert_eq!(group.state, GroupState::Waiting);
+    }
+
+    #[test]
+    fn test_buffering_pauses_group() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+        let _b = hub.register("user", "b");
+
+        let mut group = playing_group(&hub);
+        assert_eq!(group.state, GroupState::Playing);
+
+        let req = buffer_request(&group);
+        group.buffering(&hub, "b", &req);
+        assert_eq!(group.state, GroupState::Waiting);
+        assert!(group.resume_playing);
+
+        group.ready(&hub, "b", &req);
+        assert_eq!(group.state, GroupState::Playing);
+    }
+
+    #[test]
+    fn test_join_while_playing() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+        let _b = hub.register("user", "b");
+        let _c = hub.register("user", "c");
+
+        let mut group = playing_group(&hub);
+        group.join(&hub, participant("c"));
+        assert_eq!(group.state, GroupState::Waiting);
+        assert_eq!(group.participants.len(), 3);
+        assert!(group.participant_mut("c").unwrap().buffering);
+
+        // Joining again replaces the participant instead of adding it twice.
+        group.join(&hub, participant("c"));
+        assert_eq!(group.participants.len(), 3);
+
+        let req = buffer_request(&group);
+        group.ready(&hub, "c", &req);
+        assert_eq!(group.state, GroupState::Playing);
+    }
+
+    #[test]
+    fn test_leave_unblocks_waiting_group() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+        let _b = hub.register("user", "b");
+
+        let mut group = playing_group(&hub);
+        let req = buffer_request(&group);
+        group.buffering(&hub, "b", &req);
+        assert_eq!(group.state, GroupState::Waiting);
+
+        group.leave(&hub, "b");
+        assert_eq!(group.participants.len(), 1);
+        assert_eq!(group.state, GroupState::Playing);
+    }
+
+    #[test]
+    fn test_ignore_disconnected_participant() {
+        let hub = WebSocketHub::new();
+        let _a = hub.register("user", "a");
+
+        let mut group = Group::new("test");
+        group.join(&hub, participant("a"));
+        group.join(&hub, participant("b"));
+        group.set_new_queue(
+            &hub,
+            &PlayRequestDto {
+                playing_queue: vec!["item1".to_string()],
+                ..Default::default()
+            },
+        );
+        let req = buffer_request(&group);
+        group.ready(&hub, "a", &req);
+        assert_eq!(group.state, GroupState::Playing);
+    }
+
+    #[test]
+    fn test_device_disconnected() {
+        let hub = WebSocketHub::new();
+        let manager = SyncPlayManager::new();
+        let (a, _rx_a) = hub.register("user", "a");
+        let (b1, _rx_b1) = hub.register("user", "b");
+        let (b2, _rx_b2) = hub.register("user", "b");
+
+        let group = playing_group(&hub);
+        let group_id = group.id.clone();
+        manager.groups.lock().unwrap().insert(group_id.clone(), group);
+
+        // The device still has another connection.
+        hub.unregister(b1);
+        manager.device_disconnected(&hub, "b");
+        assert_eq!(manager.groups.lock().unwrap()[&group_id].participants.len(), 2);
+
+        hub.unregister(b2);
+        manager.device_disconnected(&hub, "b");
+        assert_eq!(manager.groups.lock().unwrap()[&group_id].participants.len(), 1);
+
+        // The group is removed once the last participant is gone.
+        hub.unregister(a);
+        manager.device_disconnected(&hub, "a");
+        assert!(manager.groups.lock().unwrap().is_empty());
+    }
+}
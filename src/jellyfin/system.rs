@@ -1,11 +1,16 @@
+use super::auth::is_admin;
+use super::error::apierror;
 use super::jellyfin::JellyfinState;
 use super::types::*;
+use crate::database::model::AccessToken;
+use crate::tasks::{Task, TaskError, TaskTrigger};
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Json, Response},
+    Extension,
 };
-use chrono::Utc;
+use chrono::{SecondsFormat, Utc, Weekday};
 
 /// GET /System/Info - Get system information
 pub async fn system_info(State(state): State<JellyfinState>) -> Json<SystemInfo> {
@@ -46,11 +51,12 @@ pub async fn system_info_public(headers: HeaderMap, State(state): State<Jellyfin
 }
 
 /// GET /GetUtcTime
+/// Used by SyncPlay clients to estimate their clock offset to the server.
 pub async fn get_utc_time() -> Json<GetUtcTimeResponse> {
-    let now = Utc::now().to_rfc3339();
+    let request_reception_time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
     Json(GetUtcTimeResponse {
-        request_reception_time: now.clone(),
-        response_transmission_time: now,
+        request_reception_time,
+        response_transmission_time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
     })
 }
 
@@ -78,22 +84,176 @@ pub async fn system_shutdown() -> StatusCode {
 }
 
 /// GET /ScheduledTasks
-pub async fn scheduled_tasks() -> Json<Vec<ScheduledTaskInfo>> {
-    Json(vec![ScheduledTaskInfo {
-        id: "RefreshLibrary".to_string(),
-        name: "Scan Media Library".to_string(),
-        state: "Idle".to_string(),
-        category: "Library".to_string(),
-        description: "Scans all libraries and refreshes metadata.".to_string(),
-        triggers: vec![ScheduledTaskTrigger {
-            trigger_type: "IntervalTrigger".to_string(),
-            interval_ticks: Some(720_000_000_000), // 2 hours in 100ns ticks
-            time_of_day_ticks: None,
-            day_of_week: None,
-            max_runtime_ticks: None,
-        }],
-        last_execution_result: None,
-    }])
+pub async fn scheduled_tasks(State(state): State<JellyfinState>) -> Json<Vec<ScheduledTaskInfo>> {
+    let tasks = state.tasks.tasks().iter().map(make_task_info).collect();
+    Json(tasks)
+}
+
+/// GET /ScheduledTasks/{id}
+pub async fn scheduled_task(
+    State(state): State<JellyfinState>,
+    Path(id): Path<String>,
+) -> Result<Json<ScheduledTaskInfo>, StatusCode> {
+    // Paths are lowercased, task IDs are not.
+    let task = state.tasks.task(&id).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(make_task_info(&task)))
+}
+
+/// POST /ScheduledTasks/Running/{id}
+/// Starts a task. A task that is already running is not started again.
+pub async fn scheduled_task_start(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(task) = state.tasks.task(&id) else {
+        return apierror(StatusCode::NOT_FOUND, "Task not found").into_response();
+    };
+    match state.tasks.run(&task.info.id) {
+        Ok(()) | Err(TaskError::Running) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => apierror(StatusCode::BAD_REQUEST, &e.to_string()).into_response(),
+    }
+}
+
+/// DELETE /ScheduledTasks/Running/{id}
+/// Cancels a running task.
+pub async fn scheduled_task_stop(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(task) = state.tasks.task(&id) else {
+        return apierror(StatusCode::NOT_FOUND, "Task not found").into_response();
+    };
+    let _ = state.tasks.cancel(&task.info.id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// POST /ScheduledTasks/{id}/Triggers
+/// Replaces the triggers of a task. The triggers are stored, so they survive a restart.
+pub async fn scheduled_task_triggers(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(id): Path<String>,
+    Json(dtos): Json<Vec<ScheduledTaskTrigger>>,
+) -> impl IntoResponse {
+    if !is_admin(&state.repo, &token.user_id).await {
+        return apierror(StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    let Some(task) = state.tasks.task(&id) else {
+        return apierror(StatusCode::NOT_FOUND, "Task not found").into_response();
+    };
+    let Some(triggers) = dtos.iter().map(trigger_from_dto).collect::<Option<Vec<_>>>() else {
+        return apierror(StatusCode::BAD_REQUEST, "Invalid trigger").into_response();
+    };
+
+    if state
+        .repo
+        .upsert_task_triggers(&task.info.id, &triggers)
+        .await
+        .is_err()
+    {
+        return apierror(StatusCode::INTERNAL_SERVER_ERROR, "Error saving triggers").into_response();
+    }
+    let _ = state.tasks.set_triggers(&task.info.id, triggers);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+fn make_task_info(task: &Task) -> ScheduledTaskInfo {
+    let format = |t: chrono::DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    ScheduledTaskInfo {
+        id: task.info.id.clone(),
+        name: task.info.name.clone(),
+        state: task.state.as_str().to_string(),
+        current_progress_percentage: task.progress,
+        category: task.info.category.clone(),
+        description: task.info.description.clone(),
+        key: task.info.id.clone(),
+        is_hidden: false,
+        triggers: task.triggers.iter().map(trigger_to_dto).collect(),
+        last_execution_result: task
+            .last_result
+            .as_ref()
+            .map(|r| ScheduledTaskLastExecutionResult {
+                start_time_utc: format(r.start),
+                end_time_utc: format(r.end),
+                status: r.status.as_str().to_string(),
+                name: task.info.name.clone(),
+                key: task.info.id.clone(),
+                id: task.info.id.clone(),
+                error_message: r.error_message.clone(),
+            }),
+    }
+}
+
+/// Jellyfin expresses durations and times of day in ticks of 100ns.
+const TICKS_PER_SECOND: i64 = 10_000_000;
+
+fn trigger_to_dto(trigger: &TaskTrigger) -> ScheduledTaskTrigger {
+    let ticks = |secs: u64| Some(secs as i64 * TICKS_PER_SECOND);
+    let mut dto = ScheduledTaskTrigger {
+        trigger_type: String::new(),
+        interval_ticks: None,
+        time_of_day_ticks: None,
+        day_of_week: None,
+        max_runtime_ticks: None,
+    };
+    match trigger {
+        TaskTrigger::Startup => dto.trigger_type = "StartupTrigger".to_string(),
+        TaskTrigger::Interval { seconds } => {
+            dto.trigger_type = "IntervalTrigger".to_string();
+            dto.interval_ticks = ticks(*seconds);
+        }
+        TaskTrigger::Daily { time_of_day } => {
+            dto.trigger_type = "DailyTrigger".to_string();
+            dto.time_of_day_ticks = ticks(*time_of_day);
+        }
+        TaskTrigger::Weekly { day, time_of_day } => {
+            dto.trigger_type = "WeeklyTrigger".to_string();
+            dto.time_of_day_ticks = ticks(*time_of_day);
+            dto.day_of_week = Some(weekday_name(*day).to_string());
+        }
+    }
+    dto
+}
+
+/// Convert a trigger from the API, None if it is invalid or not supported.
+fn trigger_from_dto(dto: &ScheduledTaskTrigger) -> Option<TaskTrigger> {
+    let seconds = |ticks: Option<i64>| u64::try_from(ticks? / TICKS_PER_SECOND).ok();
+    match dto.trigger_type.as_str() {
+        "StartupTrigger" => Some(TaskTrigger::Startup),
+        "IntervalTrigger" => Some(TaskTrigger::Interval {
+            seconds: seconds(dto.interval_ticks).filter(|s| *s > 0)?,
+        }),
+        "DailyTrigger" => Some(TaskTrigger::Daily {
+            time_of_day: seconds(dto.time_of_day_ticks)?,
+        }),
+        "WeeklyTrigger" => Some(TaskTrigger::Weekly {
+            day: dto.day_of_week.as_deref()?.parse().ok()?,
+            time_of_day: seconds(dto.time_of_day_ticks)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Day of the week as .NET's DayOfWeek.
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
 }
 
 /// GET /System/Ping - Ping endpoint
@@ -111,11 +271,6 @@ pub async fn plugins() -> Json<Vec<serde_json::Value>> {
     Json(Vec::new())
 }
 
-/// GET /socket - WebSocket endpoint (not implemented)
-pub async fn socket_handler() -> axum::http::StatusCode {
-    axum::http::StatusCode::NOT_FOUND
-}
-
 /// GET / - Root handler
 pub async fn root_handler() -> impl axum::response::IntoResponse {
     use axum::response::Html;
@@ -120,9 +120,11 @@ pub struct UserPolicy {
     #[serde(rename = "EnableUserPreferenceAccess")]
     pub enable_user_preference_access: bool,
     #[serde(rename = "AccessSchedules")]
-    pub access_schedules: Vec<serde_json::Value>,
+    pub access_schedules: Vec<AccessSchedule>,
     #[serde(rename = "BlockUnratedItems")]
     pub block_unrated_items: Vec<String>,
+    #[serde(rename = "MaxParentalRating", default)]
+    pub max_parental_rating: Option<i32>,
     #[serde(rename = "EnableRemoteControlOfOtherUsers")]
     pub enable_remote_control_of_other_users: bool,
     #[serde(rename = "EnableSharedDeviceControl")]
@@ -169,6 +171,22 @@ pub struct UserPolicy {
     pub sync_play_access: String,
 }
 
+/// AccessSchedule is a weekly window in which a user may use the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessSchedule {
+    #[serde(rename = "Id", default)]
+    pub id: i32,
+    #[serde(rename = "UserId", default)]
+    pub user_id: String,
+    /// "Sunday" to "Saturday", "Everyday", "Weekday" or "Weekend".
+    #[serde(rename = "DayOfWeek")]
+    pub day_of_week: String,
+    #[serde(rename = "StartHour")]
+    pub start_hour: f64,
+    #[serde(rename = "EndHour")]
+    pub end_hour: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
     #[serde(rename = "PlayState")]
@@ -213,6 +231,8 @@ pub struct SessionInfo {
     pub now_playing_queue: Vec<serde_json::Value>,
     #[serde(rename = "NowPlayingQueueFullItems")]
     pub now_playing_queue_full_items: Vec<serde_json::Value>,
+    #[serde(rename = "NowPlayingItem", skip_serializing_if = "Option::is_none")]
+    pub now_playing_item: Option<BaseItemDto>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -227,6 +247,40 @@ pub struct PlayState {
     pub repeat_mode: String,
     #[serde(rename = "PlaybackOrder")]
     pub playback_order: String,
+    #[serde(rename = "PositionTicks", skip_serializing_if = "Option::is_none")]
+    pub position_ticks: Option<i64>,
+    #[serde(rename = "MediaSourceId", skip_serializing_if = "Option::is_none")]
+    pub media_source_id: Option<String>,
+    #[serde(rename = "PlayMethod", skip_serializing_if = "Option::is_none")]
+    pub play_method: Option<String>,
+}
+
+/// Body of POST /Sessions/Capabilities/Full
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ClientCapabilitiesDto {
+    pub playable_media_types: Vec<String>,
+    pub supported_commands: Vec<String>,
+    pub supports_media_control: bool,
+}
+
+/// Body of POST /Sessions/{id}/Command
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct GeneralCommand {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub controlling_user_id: Option<String>,
+    pub arguments: HashMap<String, String>,
+}
+
+/// Body of POST /Sessions/{id}/Message
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct MessageCommand {
+    pub header: Option<String>,
+    pub text: String,
+    pub timeout_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -324,6 +378,85 @@ pub struct ItemCountResponse {
     pub item_count: i32,
 }
 
+/// Body of POST /Items/{item}/PlaybackInfo
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct PlaybackInfoDto {
+    pub user_id: Option<String>,
+    pub max_streaming_bitrate: Option<i64>,
+    pub start_time_ticks: Option<i64>,
+    pub audio_stream_index: Option<i32>,
+    pub subtitle_stream_index: Option<i32>,
+    pub max_audio_channels: Option<i32>,
+    pub media_source_id: Option<String>,
+    pub device_profile: Option<DeviceProfile>,
+    pub enable_direct_play: Option<bool>,
+    pub enable_direct_stream: Option<bool>,
+    pub enable_transcoding: Option<bool>,
+}
+
+/// DeviceProfile describes what a client can play natively and what it
+/// wants to receive when transcoding.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct DeviceProfile {
+    pub name: Option<String>,
+    pub max_streaming_bitrate: Option<i64>,
+    pub direct_play_profiles: Vec<DirectPlayProfile>,
+    pub transcoding_profiles: Vec<TranscodingProfile>,
+    pub codec_profiles: Vec<CodecProfile>,
+    pub subtitle_profiles: Vec<SubtitleProfile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct DirectPlayProfile {
+    pub container: Option<String>,
+    pub audio_codec: Option<String>,
+    pub video_codec: Option<String>,
+    pub r#type: String,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct TranscodingProfile {
+    pub container: String,
+    pub r#type: String,
+    pub video_codec: String,
+    pub audio_codec: String,
+    pub protocol: String,
+    pub context: Option<String>,
+    pub max_audio_channels: Option<String>,
+    pub break_on_non_key_frames: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct CodecProfile {
+    pub r#type: String,
+    pub codec: Option<String>,
+    pub container: Option<String>,
+    pub conditions: Vec<ProfileCondition>,
+    pub apply_conditions: Vec<ProfileCondition>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ProfileCondition {
+    pub condition: String,
+    pub property: String,
+    pub value: Option<String>,
+    pub is_required: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct SubtitleProfile {
+    pub format: String,
+    pub method: String,
+    pub container: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct PlaybackInfoResponse {
@@ -573,6 +706,8 @@ pub struct MediaSourceInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transcoding_container: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcode_reasons: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub analyze_duration_ms: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default_audio_stream_index: Option<i32>,
@@ -755,6 +890,8 @@ pub struct UpdatePlayStateRequest {
     pub is_paused: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub event_name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub now_playing_queue: Vec<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -807,6 +944,7 @@ pub struct TypeOption {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "PascalCase")]
 pub struct LibraryOptions {
+    #[serde(default)]
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable_photos: Option<bool>,
@@ -893,6 +1031,25 @@ pub struct MediaLibrary {
     pub refresh_status: Option<String>,
 }
 
+/// Body of POST /Library/VirtualFolders.
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct AddVirtualFolderDto {
+    #[serde(default)]
+    pub library_options: Option<LibraryOptions>,
+}
+
+/// Body of POST /Library/VirtualFolders/Paths.
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MediaPathDto {
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub path_info: Option<PathInfo>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct BrandingConfiguration {
@@ -1029,6 +1186,7 @@ impl Default for UserPolicy {
             enable_user_preference_access: false,
             access_schedules: Vec::new(),
             block_unrated_items: Vec::new(),
+            max_parental_rating: None,
             enable_remote_control_of_other_users: false,
             enable_shared_device_control: false,
             enable_live_tv_management: false,
@@ -1063,6 +1221,9 @@ impl Default for PlayState {
             is_muted: false,
             repeat_mode: "RepeatNone".to_string(),
             playback_order: "Default".to_string(),
+            position_ticks: None,
+            media_source_id: None,
+            play_method: None,
         }
     }
 }
@@ -1123,6 +1284,18 @@ pub struct DeviceItem {
     pub date_last_activity: DateTime<Utc>,
 }
 
+/// AuthenticationInfo describes an API key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthenticationInfo {
+    pub id: i64,
+    pub access_token: String,
+    pub app_name: String,
+    pub is_active: bool,
+    pub date_created: DateTime<Utc>,
+    pub date_last_activity: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Country {
@@ -1138,6 +1311,99 @@ pub struct GetUtcTimeResponse {
     pub response_transmission_time: String,
 }
 
+/// Body of POST /SyncPlay/New
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct NewGroupRequestDto {
+    pub group_name: String,
+}
+
+/// Body of POST /SyncPlay/Join
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct JoinGroupRequestDto {
+    pub group_id: String,
+}
+
+/// Body of POST /SyncPlay/SetNewQueue
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct PlayRequestDto {
+    pub playing_queue: Vec<String>,
+    pub playing_item_position: i32,
+    pub start_position_ticks: i64,
+}
+
+/// Body of POST /SyncPlay/SetPlaylistItem, /SyncPlay/NextItem and /SyncPlay/PreviousItem
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct PlaylistItemRequestDto {
+    pub playlist_item_id: String,
+}
+
+/// Body of POST /SyncPlay/RemoveFromPlaylist
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct RemoveFromPlaylistRequestDto {
+    pub playlist_item_ids: Vec<String>,
+    pub clear_playlist: bool,
+    pub clear_playing_item: bool,
+}
+
+/// Body of POST /SyncPlay/MovePlaylistItem
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct MovePlaylistItemRequestDto {
+    pub playlist_item_id: String,
+    pub new_index: i32,
+}
+
+/// Body of POST /SyncPlay/Queue
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct QueueRequestDto {
+    pub item_ids: Vec<String>,
+    pub mode: String,
+}
+
+/// Body of POST /SyncPlay/Seek
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct SeekRequestDto {
+    pub position_ticks: i64,
+}
+
+/// Body of POST /SyncPlay/Buffering and /SyncPlay/Ready
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct BufferRequestDto {
+    pub when: Option<DateTime<Utc>>,
+    pub position_ticks: i64,
+    pub is_playing: bool,
+    pub playlist_item_id: String,
+}
+
+/// Body of POST /SyncPlay/SetIgnoreWait
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct IgnoreWaitRequestDto {
+    pub ignore_wait: bool,
+}
+
+/// Body of POST /SyncPlay/SetRepeatMode and /SyncPlay/SetShuffleMode
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ModeRequestDto {
+    pub mode: String,
+}
+
+/// Body of POST /SyncPlay/Ping
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct PingRequestDto {
+    pub ping: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct SystemEndpointResponse {
@@ -1151,7 +1417,11 @@ pub struct ScheduledTaskLastExecutionResult {
     pub start_time_utc: String,
     pub end_time_utc: String,
     pub status: String,
+    pub name: String,
     pub key: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1171,8 +1441,12 @@ pub struct ScheduledTaskInfo {
     pub id: String,
     pub name: String,
     pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_progress_percentage: Option<f64>,
     pub category: String,
     pub description: String,
+    pub key: String,
+    pub is_hidden: bool,
     pub triggers: Vec<ScheduledTaskTrigger>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_execution_result: Option<ScheduledTaskLastExecutionResult>,
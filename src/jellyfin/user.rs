@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use super::auth::{generate_random_token, parse_auth_header, AuthSchemeValues, JellyfinAuthState};
+use super::auth::{
+    access_schedule_allows, generate_random_token, parse_auth_header, AuthSchemeValues, JellyfinAuthState,
+};
 use super::jellyfin::JellyfinState;
 use super::types::*;
 use super::util::generate_identicon;
@@ -43,6 +45,12 @@ pub async fn authenticate_by_name(
 
     let username = request.username.to_lowercase();
 
+    // Refuse logins from addresses with too many failed attempts
+    let remote_address = state.lockout.remote_address(&headers);
+    if state.lockout.is_blocked(&state.repo, remote_address.as_deref()).await {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
     // Try to get user from database
     let mut user = state.repo.get_user(&username).await.ok();
 
@@ -50,6 +58,12 @@ pub async fn authenticate_by_name(
     if let Some(db_user) = user.take() {
         // Verify password
         if !verify(&request.pw, &db_user.password).unwrap_or(false) {
+            state
+                .lockout
+                .login_failed(&state.repo, remote_address.as_deref(), Some(db_user))
+                .await;
+            return Err(StatusCode::UNAUTHORIZED);
+        } else if db_user.properties.disabled || !access_schedule_allows(&db_user) {
             return Err(StatusCode::UNAUTHORIZED);
         } else {
             user = Some(db_user);
@@ -61,10 +75,18 @@ pub async fn authenticate_by_name(
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     } else {
+        state
+            .lockout
+            .login_failed(&state.repo, remote_address.as_deref(), None)
+            .await;
         return Err(StatusCode::UNAUTHORIZED);
     }
 
     let mut user = user.unwrap();
+    state
+        .lockout
+        .login_succeeded(&state.repo, remote_address.as_deref(), &mut user)
+        .await;
 
     // Update last login
     user.last_login = chrono::Utc::now();
@@ -81,8 +103,10 @@ pub async fn authenticate_by_name(
     // Reuse existing token for the same device if one exists
     let access_token = if !device_id.is_empty() {
         if let Ok(mut existing) = state.repo.get_access_token_by_device_id(device_id).await {
-            // Update last_used and details, reuse the token string
+            // Update last_used and details, reuse the token string.
+            // A new login starts a new token lifetime.
             existing.user_id = user.id.clone();
+            existing.created = chrono::Utc::now();
             existing.last_used = chrono::Utc::now();
             if let Some(ref h) = emby_header {
                 existing.device_name = h.device.clone();
@@ -143,6 +167,9 @@ pub async fn authenticate_with_quick_connect(
         Ok(u) => u,
         Err(_) => return StatusCode::UNAUTHORIZED.into_response(),
     };
+    if user.properties.disabled || !access_schedule_allows(&user) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
 
     let emby_header = parse_auth_header(&headers);
     let access_token = match create_new_token(&state.repo, &user.id, emby_header.as_ref()).await {
@@ -451,13 +478,29 @@ pub async fn users_policy_post(
         Err(_) => return StatusCode::NOT_FOUND,
     };
     user.properties.admin = body.is_administrator;
+    // Enabling a locked out account resets its failed logins.
+    if user.properties.disabled && !body.is_disabled {
+        user.properties.invalid_login_attempts = 0;
+    }
     user.properties.disabled = body.is_disabled;
+    user.properties.login_attempts_before_lockout = body.login_attempts_before_lockout;
     user.properties.is_hidden = body.is_hidden;
     user.properties.enable_downloads = body.enable_content_downloading;
     user.properties.enable_all_folders = body.enable_all_folders;
     user.properties.enabled_folders = body.enabled_folders;
     user.properties.allow_tags = body.allowed_tags;
     user.properties.block_tags = body.blocked_tags;
+    user.properties.max_parental_rating = body.max_parental_rating;
+    user.properties.block_unrated_items = body.block_unrated_items;
+    user.properties.access_schedules = body
+        .access_schedules
+        .into_iter()
+        .map(|s| model::AccessSchedule {
+            day_of_week: s.day_of_week,
+            start_hour: s.start_hour,
+            end_hour: s.end_hour,
+        })
+        .collect();
     match state.repo.upsert_user(&user).await {
         Ok(_) => StatusCode::NO_CONTENT,
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -501,8 +544,20 @@ fn make_user(user: &model::User, server_id: &str) -> User {
             enable_subtitle_management: p.admin,
             enable_lyric_management: false,
             enable_user_preference_access: true,
-            access_schedules: vec![],
-            block_unrated_items: vec![],
+            access_schedules: p
+                .access_schedules
+                .iter()
+                .enumerate()
+                .map(|(i, s)| AccessSchedule {
+                    id: i as i32 + 1,
+                    user_id: user.id.clone(),
+                    day_of_week: s.day_of_week.clone(),
+                    start_hour: s.start_hour,
+                    end_hour: s.end_hour,
+                })
+                .collect(),
+            block_unrated_items: p.block_unrated_items.clone(),
+            max_parental_rating: p.max_parental_rating,
             enable_remote_control_of_other_users: p.admin,
             enable_shared_device_control: false,
             enable_live_tv_management: false,
@@ -515,8 +570,8 @@ fn make_user(user: &model::User, server_id: &str) -> User {
             enabled_devices: vec![],
             enabled_channels: vec![],
             enable_all_channels: false,
-            invalid_login_attempt_count: 0,
-            login_attempts_before_lockout: 0,
+            invalid_login_attempt_count: p.invalid_login_attempts,
+            login_attempts_before_lockout: p.login_attempts_before_lockout,
             max_active_sessions: 0,
             enable_public_sharing: false,
             blocked_media_folders: vec![],
@@ -606,7 +661,7 @@ fn make_session_info(token: &model::AccessToken, username: &str, server_id: &str
         capabilities: SessionResponseCapabilities::default(),
         remote_end_point: token.remote_address.clone(),
         playable_media_types: vec!["Video".to_string(), "Audio".to_string()],
-        id: token.token.clone(),
+        id: super::sessions::session_id(&token.device_id),
         user_id: token.user_id.clone(),
         user_name: username.to_string(),
         client: token.application_name.clone(),
@@ -622,5 +677,6 @@ fn make_session_info(token: &model::AccessToken, username: &str, server_id: &str
         has_custom_device_name: false,
         now_playing_queue: Vec::new(),
         now_playing_queue_full_items: Vec::new(),
+        now_playing_item: None,
     }
 }
@@ -8,6 +8,7 @@ use chrono::Utc;
 
 use anyhow::anyhow;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
@@ -25,7 +26,8 @@ pub async fn item_details(
     Path(path): Path<Vec<String>>,
 ) -> Result<Json<BaseItemDto>, StatusCode> {
     let item_id = path.last().ok_or(StatusCode::BAD_REQUEST)?;
-    let response = make_jfitem_by_id(&state, &token.user_id, &item_id)
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let response = make_jfitem_by_id(&state, &access, &token.user_id, &item_id)
         .await
         .map_err(|_| StatusCode::NOT_FOUND)?;
 
@@ -37,7 +39,8 @@ pub async fn items_root(
     Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
 ) -> Result<Json<BaseItemDto>, StatusCode> {
-    let item = make_jfitem_root(&state, &token.user_id)
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let item = make_jfitem_root(&state, &access, &token.user_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(item))
@@ -50,18 +53,19 @@ pub async fn items_latest(
     axum::extract::Query(query_params): axum::extract::Query<std::collections::HashMap<String, String>>,
 ) -> Result<Json<Vec<BaseItemDto>>, StatusCode> {
     let parent_id = query_params.get("parentId").cloned();
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
 
     let mut qitems = if let Some(ref pid) = parent_id {
-        get_items_by_collection(&state, pid, false).map_err(|_| StatusCode::NOT_FOUND)?
+        get_items_by_collection(&access, pid, false).map_err(|_| StatusCode::NOT_FOUND)?
     } else {
-        get_items_all(&state)
+        get_items_all(&access)
     };
 
     if needs_user_data(&query_params) {
         load_user_data(&mut qitems, &state, &token.user_id).await;
     }
 
-    let qitems = apply_query_items_filter(qitems, &query_params);
+    let qitems = apply_query_items_filter(qitems, &state.collections, &query_params);
 
     // Sort by premiere date descending
     let mut qitems = qitems;
@@ -133,6 +137,7 @@ pub async fn user_favorite_items_post(
         .await
         .is_ok()
     {
+        state.websocket.user_data_changed(&token.user_id, item_id, &playstate);
         Ok(Json(make_jf_userdata(&token.user_id, item_id, Some(&playstate))))
     } else {
         Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -166,6 +171,7 @@ pub async fn user_favorite_items_post_simple(
         .await
         .is_ok()
     {
+        state.websocket.user_data_changed(&token.user_id, &item_id, &playstate);
         Ok(Json(make_jf_userdata(&token.user_id, &item_id, Some(&playstate))))
     } else {
         Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -202,6 +208,7 @@ pub async fn user_favorite_items_delete(
         .await
         .is_ok()
     {
+        state.websocket.user_data_changed(&token.user_id, item_id, &playstate);
         Ok(Json(make_jf_userdata(&token.user_id, item_id, Some(&playstate))))
     } else {
         Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -235,6 +242,7 @@ pub async fn user_favorite_items_delete_simple(
         .await
         .is_ok()
     {
+        state.websocket.user_data_changed(&token.user_id, &item_id, &playstate);
         Ok(Json(make_jf_userdata(&token.user_id, &item_id, Some(&playstate))))
     } else {
         Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -244,6 +252,7 @@ pub async fn user_favorite_items_delete_simple(
 // make_jfitem_by_id creates a BaseItemDto based on the provided item_id.
 async fn make_jfitem_by_id(
     state: &JellyfinState,
+    access: &AccessFilter,
     user_id: &str,
     item_id: &str,
 ) -> anyhow::Result<BaseItemDto> {
@@ -251,7 +260,7 @@ async fn make_jfitem_by_id(
 
     // Handle special items first
     if is_jf_root_id(item_id) {
-        return make_jfitem_root(state, user_id).await;
+        return make_jfitem_root(state, access, user_id).await;
     }
 
     // Try special collection items — construct native Item, then convert
@@ -288,8 +297,7 @@ async fn make_jfitem_by_id(
         return make_jfitem(state, user_id, &item).await;
     }
     if is_jf_collection_id(item_id) {
-        let c = state
-            .collections
+        let c = access
             .get_collection(item_id)
             .ok_or_else(|| anyhow!("collection not found"))?;
         let item = Item::CollectionFolder(CollectionFolder {
@@ -312,8 +320,7 @@ async fn make_jfitem_by_id(
     }
 
     // Try to fetch individual item: movie, show, season, episode
-    let (_, item) = state
-        .collections
+    let (_, item) = access
         .get_item_by_id(item_id)
         .ok_or_else(|| anyhow!("item not found"))?;
     make_jfitem(state, user_id, &item).await
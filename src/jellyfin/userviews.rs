@@ -1,3 +1,4 @@
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::jfitem::*;
 use super::types::*;
@@ -21,7 +22,8 @@ pub async fn user_views(
     State(state): State<JellyfinState>,
     AxumPath(_user_id): AxumPath<String>,
 ) -> Result<Json<QueryResult<BaseItemDto>>, StatusCode> {
-    let qitems = get_root_overview_items(&state, &token.user_id).await;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let qitems = get_root_overview_items(&state, &access, &token.user_id).await;
     let items = convert_items_to_dtos(&qitems, &state, &token.user_id).await;
 
     Ok(Json(QueryResult {
@@ -37,7 +39,8 @@ pub async fn user_views_query(
     State(state): State<JellyfinState>,
     Query(_query): Query<UserViewsQuery>,
 ) -> Result<Json<QueryResult<BaseItemDto>>, StatusCode> {
-    let qitems = get_root_overview_items(&state, &token.user_id).await;
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let qitems = get_root_overview_items(&state, &access, &token.user_id).await;
     let items = convert_items_to_dtos(&qitems, &state, &token.user_id).await;
 
     Ok(Json(QueryResult {
@@ -49,12 +52,13 @@ pub async fn user_views_query(
 
 /// GET /Users/{id}/GroupingOptions - Get grouping options
 pub async fn user_grouping_options(
-    Extension(_token): Extension<model::AccessToken>,
+    Extension(token): Extension<model::AccessToken>,
     State(state): State<JellyfinState>,
     AxumPath(_user_id): AxumPath<String>,
 ) -> Result<Json<Vec<NameGuidPair>>, StatusCode> {
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
     let mut options = Vec::new();
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         options.push(NameGuidPair {
             name: c.name.clone(),
             id: c.id.clone(),
@@ -0,0 +1,613 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::jellyfin::JellyfinState;
+use crate::collection::{
+    parental_rating_score, Collection, CollectionDetails, CollectionRepo, Episode, Item, LibraryChange,
+    Metadata, Movie, Season, Show,
+};
+use crate::database::UserProperties;
+
+/// Similar items fetched per item returned for users with restricted access.
+const SIMILAR_OVERFETCH: usize = 3;
+
+/// AccessFilter sits between the collection repository and the Jellyfin
+/// handlers and applies the library access policy of a user: the folders
+/// (collections) they may see, the tags that allow or block items and the
+/// maximum parental rating.
+///
+/// Items the user may not access behave as if they do not exist. Seasons and
+/// episodes inherit the outcome of their show.
+#[derive(Clone)]
+pub struct AccessFilter {
+    collections: Arc<CollectionRepo>,
+    /// Collection IDs the user may access, None if all folders are enabled.
+    enabled_folders: Option<HashSet<String>>,
+    /// Lowercased tags of which an item needs at least one, if not empty.
+    allow_tags: Vec<String>,
+    /// Lowercased tags that hide an item.
+    block_tags: Vec<String>,
+    /// Highest parental rating score the user may see.
+    max_parental_rating: Option<i32>,
+    /// Item types ("Movie", "Series") that are hidden when unrated.
+    block_unrated_items: Vec<String>,
+}
+
+impl AccessFilter {
+    /// Create an access filter from the properties of a user.
+    pub fn new(collections: Arc<CollectionRepo>, properties: &UserProperties) -> Self {
+        let enabled_folders = if properties.enable_all_folders {
+            None
+        } else {
+            Some(properties.enabled_folders.iter().cloned().collect())
+        };
+        Self {
+            collections,
+            enabled_folders,
+            allow_tags: lowercase_tags(&properties.allow_tags),
+            block_tags: lowercase_tags(&properties.block_tags),
+            max_parental_rating: properties.max_parental_rating,
+            block_unrated_items: properties.block_unrated_items.clone(),
+        }
+    }
+
+    /// Access filter that hides all collections and items.
+    pub fn deny_all(collections: Arc<CollectionRepo>) -> Self {
+        Self {
+            collections,
+            enabled_folders: Some(HashSet::new()),
+            allow_tags: Vec::new(),
+            block_tags: Vec::new(),
+            max_parental_rating: None,
+            block_unrated_items: Vec::new(),
+        }
+    }
+
+    /// Load the access policy of a user. Unknown users cannot access anything.
+    pub async fn for_user(state: &JellyfinState, user_id: &str) -> Self {
+        match state.repo.get_user_by_id(user_id).await {
+            Ok(user) => Self::new(state.collections.clone(), &user.properties),
+            Err(_) => Self::deny_all(state.collections.clone()),
+        }
+    }
+
+    /// Returns true if the policy does not restrict anything.
+    pub fn is_unrestricted(&self) -> bool {
+        self.enabled_folders.is_none() && !self.has_item_policy()
+    }
+
+    /// Returns true if the policy looks at the metadata of items.
+    fn has_item_policy(&self) -> bool {
+        !self.allow_tags.is_empty()
+            || !self.block_tags.is_empty()
+            || self.max_parental_rating.is_some()
+            || !self.block_unrated_items.is_empty()
+    }
+
+    /// Check if the user may access a collection.
+    pub fn collection_allowed(&self, collection_id: &str) -> bool {
+        match &self.enabled_folders {
+            Some(folders) => folders.contains(collection_id),
+            None => true,
+        }
+    }
+
+    /// Check the tags and the parental rating of a movie or show.
+    /// `unrated_type` is the type used to block unrated items.
+    fn metadata_allowed(&self, metadata: &Metadata, unrated_type: &str) -> bool {
+        if !self.has_item_policy() {
+            return true;
+        }
+        self.rating_allowed(metadata.official_rating.as_deref(), unrated_type)
+            && self.tags_allowed(&metadata.tags)
+    }
+
+    fn rating_allowed(&self, rating: Option<&str>, unrated_type: &str) -> bool {
+        match rating.and_then(parental_rating_score) {
+            Some(score) => self.max_parental_rating.is_none_or(|max| score <= max),
+            None => !self
+                .block_unrated_items
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(unrated_type)),
+        }
+    }
+
+    fn tags_allowed(&self, tags: &[String]) -> bool {
+        if self.allow_tags.is_empty() && self.block_tags.is_empty() {
+            return true;
+        }
+        let tags = lowercase_tags(tags);
+        if tags.iter().any(|t| self.block_tags.contains(t)) {
+            return false;
+        }
+        self.allow_tags.is_empty() || tags.iter().any(|t| self.allow_tags.contains(t))
+    }
+
+    fn movie_allowed(&self, movie: &Movie) -> bool {
+        self.metadata_allowed(&movie.metadata, "Movie")
+    }
+
+    fn show_allowed(&self, show: &Show) -> bool {
+        self.metadata_allowed(&show.metadata, "Series")
+    }
+
+    /// Check if the user may access an item.
+    pub fn item_allowed(&self, item: &Item) -> bool {
+        match item {
+            Item::CollectionFolder(cf) => self.collection_allowed(&cf.id),
+            Item::UserView(_) | Item::Playlist(_) => true,
+            Item::Movie(m) => self.collection_allowed(&m.collection_id) && self.movie_allowed(m),
+            Item::Show(s) => self.collection_allowed(&s.collection_id) && self.show_allowed(s),
+            Item::Season(s) => self.season_allowed(s),
+            Item::Episode(e) => self.episode_allowed(e),
+        }
+    }
+
+    fn season_allowed(&self, season: &Season) -> bool {
+        if !self.collection_allowed(&season.collection_id) {
+            return false;
+        }
+        !self.has_item_policy()
+            || self
+                .collections
+                .get_season_by_id(&season.id)
+                .is_some_and(|(_, show, _)| self.show_allowed(&show))
+    }
+
+    fn episode_allowed(&self, episode: &Episode) -> bool {
+        if !self.collection_allowed(&episode.collection_id) {
+            return false;
+        }
+        !self.has_item_policy()
+            || matches!(
+                self.collections.get_item_by_id(&episode.show_id),
+                Some((_, Item::Show(show))) if self.show_allowed(&show)
+            )
+    }
+
+    /// Remove the items the user may not access.
+    pub fn filter_items(&self, items: Vec<Item>) -> Vec<Item> {
+        if self.is_unrestricted() {
+            return items;
+        }
+        items.into_iter().filter(|item| self.item_allowed(item)).collect()
+    }
+
+    /// Remove the item IDs the user may not access.
+    fn filter_ids(&self, ids: Vec<String>) -> Vec<String> {
+        if self.is_unrestricted() {
+            return ids;
+        }
+        let visible = self.visible_ids();
+        ids.into_iter().filter(|id| visible.contains(id)).collect()
+    }
+
+    /// All IDs of movies, shows, seasons and episodes the user may access.
+    fn visible_ids(&self) -> HashSet<String> {
+        let mut ids = HashSet::new();
+        for c in self.get_collections() {
+            for item in &c.items {
+                ids.insert(item.id());
+                if let Item::Show(show) = item {
+                    for season in &show.seasons {
+                        ids.insert(season.id.clone());
+                        ids.extend(season.episodes.iter().map(|e| e.id.clone()));
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// Collection ID, search document type and name of the people, studios
+    /// and genres of the movies and shows the user may access. None if the
+    /// policy does not look at items, so all names of the allowed
+    /// collections are visible.
+    pub fn visible_names(&self) -> Option<HashSet<(String, String, String)>> {
+        if !self.has_item_policy() {
+            return None;
+        }
+        let mut names = HashSet::new();
+        for c in self.get_collections() {
+            for item in &c.items {
+                let metadata = match item {
+                    Item::Movie(m) => &m.metadata,
+                    Item::Show(s) => &s.metadata,
+                    _ => continue,
+                };
+                let mut add = |item_type: &str, name: &str| {
+                    names.insert((c.id.clone(), item_type.to_string(), name.to_string()));
+                };
+                metadata.credits().for_each(|credit| add("person", credit.name));
+                metadata.studios.iter().for_each(|studio| add("studio", studio));
+                metadata.genres.iter().for_each(|genre| add("genre", genre));
+            }
+        }
+        Some(names)
+    }
+
+    /// Restrict a collection to the items the user may access.
+    fn restrict(&self, mut collection: Collection) -> Option<Collection> {
+        if !self.collection_allowed(&collection.id) {
+            return None;
+        }
+        if self.has_item_policy() {
+            collection.items.retain(|item| match item {
+                Item::Movie(m) => self.movie_allowed(m),
+                Item::Show(s) => self.show_allowed(s),
+                _ => true,
+            });
+        }
+        Some(collection)
+    }
+
+    /// Get all collections the user may access.
+    pub fn get_collections(&self) -> Vec<Collection> {
+        self.collections
+            .get_collections()
+            .into_iter()
+            .filter_map(|c| self.restrict(c))
+            .collect()
+    }
+
+    /// Get a collection by ID.
+    pub fn get_collection(&self, collection_id: &str) -> Option<Collection> {
+        self.restrict(self.collections.get_collection(collection_id)?)
+    }
+
+    /// Get an item by ID across all collections.
+    pub fn get_item_by_id(&self, item_id: &str) -> Option<(Collection, Item)> {
+        let (collection, item) = self.collections.get_item_by_id(item_id)?;
+        let collection = self.restrict(collection)?;
+        if self.has_item_policy() && !self.item_allowed(&item) {
+            return None;
+        }
+        Some((collection, item))
+    }
+
+    /// Get a season by ID across all collections.
+    pub fn get_season_by_id(&self, season_id: &str) -> Option<(Collection, Show, Season)> {
+        let (collection, show, season) = self.collections.get_season_by_id(season_id)?;
+        let collection = self.restrict(collection)?;
+        self.show_allowed(&show).then_some((collection, show, season))
+    }
+
+    /// Get an episode by ID across all collections.
+    pub fn get_episode_by_id(&self, episode_id: &str) -> Option<(Collection, Show, Season, Episode)> {
+        let (collection, show, season, episode) = self.collections.get_episode_by_id(episode_id)?;
+        let collection = self.restrict(collection)?;
+        self.show_allowed(&show)
+            .then_some((collection, show, season, episode))
+    }
+
+    /// NextUp returns the next up episodes the user may access.
+    pub fn next_up(&self, watched_episode_ids: &[String]) -> Vec<String> {
+        self.filter_ids(self.collections.next_up(watched_episode_ids))
+    }
+
+    /// Similar returns items similar to an item that the user may access.
+    pub fn similar(&self, item_id: &str, limit: usize) -> Vec<String> {
+        if self.get_item_by_id(item_id).is_none() {
+            return Vec::new();
+        }
+        // Fetch extra items to make up for the ones the user may not access.
+        let fetch = if self.is_unrestricted() {
+            limit
+        } else {
+            limit.saturating_mul(SIMILAR_OVERFETCH)
+        };
+        let mut ids = self.filter_ids(self.collections.similar(item_id, fetch));
+        ids.truncate(limit);
+        ids
+    }
+
+    /// Restrict a library change to what the user may see. Removed items
+    /// no longer exist, so they are reported if the user may access their
+    /// collection. Returns None if nothing is left.
+    pub fn library_change(&self, change: &LibraryChange) -> Option<LibraryChange> {
+        if !self.collection_allowed(&change.collection_id) {
+            return None;
+        }
+        let items_added: Vec<String> = if self.has_item_policy() {
+            change
+                .items_added
+                .iter()
+                .filter(|id| self.get_item_by_id(id).is_some())
+                .cloned()
+                .collect()
+        } else {
+            change.items_added.clone()
+        };
+        if items_added.is_empty() && change.items_removed.is_empty() {
+            return None;
+        }
+        Some(LibraryChange {
+            collection_id: change.collection_id.clone(),
+            items_added,
+            items_removed: change.items_removed.clone(),
+        })
+    }
+
+    /// Details returns the details of all collections the user may access.
+    pub fn details(&self) -> CollectionDetails {
+        CollectionDetails::combine(self.get_collections().iter().map(|c| c.details()))
+    }
+}
+
+fn lowercase_tags(tags: &[String]) -> Vec<String> {
+    tags.iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn write_movie(dir: &Path, name: &str, nfo: &str) {
+        let dir = dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("movie.mkv"), b"m").unwrap();
+        fs::write(dir.join("movie.nfo"), nfo).unwrap();
+    }
+
+    /// A movies collection with "Alien" (R, horror) and "Up" (PG, family),
+    /// and a shows collection with one episode of "Firefly" (TV-14, scifi).
+    fn collections(name: &str) -> (Arc<CollectionRepo>, std::path::PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let movies = root.join("movies");
+        write_movie(
+            &movies,
+            "Alien (1979)",
+            "<movie><title>Alien</title><mpaa>R</mpaa><tag>Horror</tag><genre>Horror</genre>\
+             <actor><name>Sigourney Weaver</name></actor></movie>",
+        );
+        write_movie(
+            &movies,
+            "Up (2009)",
+            "<movie><title>Up</title><mpaa>PG</mpaa><tag>Family</tag><genre>Animation</genre>\
+             <studio>Pixar</studio></movie>",
+        );
+        let season = root.join("shows/Firefly/Season 1");
+        fs::create_dir_all(&season).unwrap();
+        fs::write(season.join("Firefly S01E01.mkv"), b"e").unwrap();
+        fs::write(
+            root.join("shows/Firefly/tvshow.nfo"),
+            "<tvshow><title>Firefly</title><mpaa>TV-14</mpaa><tag>SciFi</tag></tvshow>",
+        )
+        .unwrap();
+
+        let repo = CollectionRepo::new();
+        for kind in ["movies", "shows"] {
+            repo.add_collection(
+                kind.to_string(),
+                Some(kind.to_string()),
+                kind,
+                vec![root.join(kind).to_string_lossy().to_string()],
+                "".to_string(),
+            )
+            .unwrap();
+        }
+        repo.init();
+        (Arc::new(repo), root)
+    }
+
+    fn collection_id(repo: &CollectionRepo, name: &str) -> String {
+        let collections = repo.get_collections();
+        collections.iter().find(|c| c.name == name).unwrap().id.clone()
+    }
+
+    fn item_id(repo: &CollectionRepo, collection: &str, name: &str) -> String {
+        let collection = repo.get_collection(&collection_id(repo, collection)).unwrap();
+        let item = collection.items.iter().find(|i| i.name() == name).unwrap();
+        item.id()
+    }
+
+    fn episode_id(repo: &CollectionRepo) -> String {
+        let collection = repo.get_collection(&collection_id(repo, "shows")).unwrap();
+        match &collection.items[0] {
+            Item::Show(show) => show.seasons[0].episodes[0].id.clone(),
+            _ => panic!("not a show"),
+        }
+    }
+
+    fn item(repo: &CollectionRepo, id: &str) -> Item {
+        repo.get_item_by_id(id).unwrap().1
+    }
+
+    #[test]
+    fn test_collection_allowed() {
+        let (repo, root) = collections("test_access_collection_allowed");
+        let movies = collection_id(&repo, "movies");
+        let shows = collection_id(&repo, "shows");
+
+        let all = AccessFilter::new(repo.clone(), &UserProperties::default());
+        assert!(all.is_unrestricted());
+        assert!(all.collection_allowed(&movies));
+        assert!(all.collection_allowed(&shows));
+
+        let properties = UserProperties {
+            enable_all_folders: false,
+            enabled_folders: vec![collection_id(&repo, "movies")],
+            ..Default::default()
+        };
+        let movies_only = AccessFilter::new(repo.clone(), &properties);
+        assert!(movies_only.collection_allowed(&movies));
+        assert!(!movies_only.collection_allowed(&shows));
+        let ids: Vec<String> = movies_only
+            .get_collections()
+            .iter()
+            .map(|c| c.id.clone())
+            .collect();
+        assert_eq!(ids, vec![movies.clone()]);
+
+        let episode = item(&repo, &episode_id(&repo));
+        assert!(all.item_allowed(&episode));
+        assert!(!movies_only.item_allowed(&episode));
+
+        let none = AccessFilter::deny_all(repo.clone());
+        assert!(!none.collection_allowed(&movies));
+        assert!(none.get_collections().is_empty());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_item_allowed() {
+        let (repo, root) = collections("test_access_item_allowed");
+        let alien = item(&repo, &item_id(&repo, "movies", "Alien (1979)"));
+        let up = item(&repo, &item_id(&repo, "movies", "Up (2009)"));
+        let firefly_id = item_id(&repo, "shows", "Firefly");
+        let firefly = item(&repo, &firefly_id);
+        let episode = item(&repo, &episode_id(&repo));
+
+        let check = |properties: UserProperties, expected: [bool; 4]| {
+            let access = AccessFilter::new(repo.clone(), &properties);
+            let allowed = [&alien, &up, &firefly, &episode].map(|i| access.item_allowed(i));
+            assert_eq!(allowed, expected, "{:?}", properties);
+        };
+
+        check(UserProperties::default(), [true, true, true, true]);
+        // Tags match case-insensitively, episodes inherit from their show.
+        check(
+            UserProperties {
+                block_tags: vec!["horror".to_string(), "SCIFI".to_string()],
+                ..Default::default()
+            },
+            [false, true, false, false],
+        );
+        check(
+            UserProperties {
+                allow_tags: vec!["family".to_string()],
+                ..Default::default()
+            },
+            [false, true, false, false],
+        );
+        check(
+            UserProperties {
+                max_parental_rating: Some(14),
+                ..Default::default()
+            },
+            [false, true, true, true],
+        );
+        check(
+            UserProperties {
+                max_parental_rating: Some(10),
+                ..Default::default()
+            },
+            [false, true, false, false],
+        );
+
+        let access = AccessFilter::new(
+            repo.clone(),
+            &UserProperties {
+                block_tags: vec!["scifi".to_string()],
+                ..Default::default()
+            },
+        );
+        assert!(access.get_item_by_id(&firefly_id).is_none());
+        assert!(access.get_episode_by_id(&episode_id(&repo)).is_none());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_filter_ids() {
+        let (repo, root) = collections("test_access_filter_ids");
+        let alien = item_id(&repo, "movies", "Alien (1979)");
+        let up = item_id(&repo, "movies", "Up (2009)");
+        let firefly = item_id(&repo, "shows", "Firefly");
+        let episode = episode_id(&repo);
+        let ids = vec![
+            alien.clone(),
+            up.clone(),
+            firefly.clone(),
+            episode.clone(),
+            "unknown".to_string(),
+        ];
+
+        let all = AccessFilter::new(repo.clone(), &UserProperties::default());
+        assert_eq!(all.filter_ids(ids.clone()), ids);
+
+        let properties = UserProperties {
+            max_parental_rating: Some(14),
+            block_tags: vec!["family".to_string()],
+            ..Default::default()
+        };
+        let access = AccessFilter::new(repo.clone(), &properties);
+        assert_eq!(access.filter_ids(ids.clone()), vec![firefly, episode]);
+
+        let properties = UserProperties {
+            enable_all_folders: false,
+            enabled_folders: vec![collection_id(&repo, "movies")],
+            ..Default::default()
+        };
+        let access = AccessFilter::new(repo.clone(), &properties);
+        assert_eq!(access.filter_ids(ids), vec![alien, up]);
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_visible_names() {
+        let (repo, root) = collections("test_access_visible_names");
+        let movies = collection_id(&repo, "movies");
+        let name = |item_type: &str, name: &str| (movies.clone(), item_type.to_string(), name.to_string());
+
+        let all = AccessFilter::new(repo.clone(), &UserProperties::default());
+        assert!(all.visible_names().is_none());
+
+        let properties = UserProperties {
+            max_parental_rating: Some(10),
+            ..Default::default()
+        };
+        let names = AccessFilter::new(repo.clone(), &properties)
+            .visible_names()
+            .unwrap();
+        assert!(names.contains(&name("genre", "Animation")));
+        assert!(names.contains(&name("studio", "Pixar")));
+        assert!(!names.contains(&name("genre", "Horror")));
+        assert!(!names.contains(&name("person", "Sigourney Weaver")));
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_library_change() {
+        let (repo, root) = collections("test_access_library_change");
+        let alien = item_id(&repo, "movies", "Alien (1979)");
+        let up = item_id(&repo, "movies", "Up (2009)");
+        let change = LibraryChange {
+            collection_id: collection_id(&repo, "movies"),
+            items_added: vec![alien.clone(), up.clone()],
+            items_removed: vec!["gone".to_string()],
+        };
+
+        let all = AccessFilter::new(repo.clone(), &UserProperties::default());
+        let unfiltered = all.library_change(&change).unwrap();
+        assert_eq!(unfiltered.items_added, vec![alien, up.clone()]);
+
+        let properties = UserProperties {
+            block_tags: vec!["horror".to_string()],
+            ..Default::default()
+        };
+        let access = AccessFilter::new(repo.clone(), &properties);
+        let filtered = access.library_change(&change).unwrap();
+        assert_eq!(filtered.items_added, vec![up]);
+        assert_eq!(filtered.items_removed, vec!["gone".to_string()]);
+
+        let properties = UserProperties {
+            enable_all_folders: false,
+            enabled_folders: vec![collection_id(&repo, "shows")],
+            ..Default::default()
+        };
+        let access = AccessFilter::new(repo.clone(), &properties);
+        assert!(access.library_change(&change).is_none());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
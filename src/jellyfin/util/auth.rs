@@ -1,14 +1,17 @@
 use axum::{
     extract::State,
-    http::{header, HeaderMap, Request, StatusCode},
+    http::{header, HeaderMap, Request, StatusCode, Uri},
     middleware::Next,
     response::Response,
 };
 use regex::Regex;
 use std::collections::HashMap;
 use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use crate::database::Repository;
+use super::lockout::LoginLockout;
+use super::sessions::SessionRegistry;
+use crate::database::{AccessToken, ApiKey, Repository, User};
 
 static AUTH_HEADER_REGEX: OnceLock<Regex> = OnceLock::new();
 static AUTH_HEADER_REGEX_UNQUOTED: OnceLock<Regex> = OnceLock::new();
@@ -28,6 +31,25 @@ pub struct JellyfinAuthState {
     pub server_id: String,
     pub auto_register: bool,
     pub quick_connect: bool,
+    pub websocket: Arc<crate::jellyfin::WebSocketHub>,
+    pub sessions: Arc<SessionRegistry>,
+    pub syncplay: Arc<crate::jellyfin::SyncPlayManager>,
+    pub lockout: LoginLockout,
+    /// Access tokens unused for this long are no longer valid.
+    pub token_idle_timeout: Option<Duration>,
+    /// Access tokens older than this are no longer valid.
+    pub token_lifetime: Option<Duration>,
+}
+
+impl JellyfinAuthState {
+    /// Check if an access token has been idle or alive for too long.
+    fn token_expired(&self, token: &AccessToken) -> bool {
+        let now = chrono::Utc::now();
+        let older_than = |time: chrono::DateTime<chrono::Utc>, limit: Option<Duration>| {
+            limit.is_some_and(|limit| (now - time).to_std().is_ok_and(|age| age > limit))
+        };
+        older_than(token.last_used, self.token_idle_timeout) || older_than(token.created, self.token_lifetime)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -111,7 +133,7 @@ pub async fn auth_middleware(
     next: Next,
 ) -> Result<Response, StatusCode> {
     // Try to extract token from various sources
-    let token = extract_token(&headers, &request);
+    let token = extract_token(&headers, request.uri());
 
     if token.is_none() {
         return Err(StatusCode::UNAUTHORIZED);
@@ -119,12 +141,52 @@ pub async fn auth_middleware(
 
     let token = token.unwrap();
 
-    // Validate token
-    let access_token = state
+    // Validate token, it is either a device access token or an API key
+    let now = chrono::Utc::now();
+    let (access_token, is_api_key) = match state.repo.get_access_token(&token).await {
+        Ok(mut access_token) => {
+            if state.token_expired(&access_token) {
+                let _ = state.repo.delete_access_token(&access_token.token).await;
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+            if now - access_token.last_used > chrono::Duration::seconds(LAST_USED_RESOLUTION) {
+                access_token.last_used = now;
+                let _ = state.repo.upsert_access_token(&access_token).await;
+            }
+            (access_token, false)
+        }
+        Err(_) => {
+            let mut key = state
+                .repo
+                .get_api_key(&token)
+                .await
+                .map_err(|_| StatusCode::UNAUTHORIZED)?;
+            if now - key.last_used > chrono::Duration::seconds(LAST_USED_RESOLUTION) {
+                key.last_used = now;
+                let _ = state.repo.upsert_api_key(&key).await;
+            }
+            (api_key_access_token(key), true)
+        }
+    };
+
+    // Disabled accounts are locked out. Accounts outside their access
+    // schedule can still browse, but cannot log in or play anything.
+    let user = state
         .repo
-        .get_access_token(&token)
+        .get_user_by_id(&access_token.user_id)
         .await
         .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    if user.properties.disabled {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if is_playback_path(request.uri().path()) && !access_schedule_allows(&user) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // API keys are not a device, so they have no session.
+    if !is_api_key {
+        state.sessions.touch(&access_token);
+    }
 
     // Store access token in request extensions
     request.extensions_mut().insert(access_token);
@@ -132,8 +194,44 @@ pub async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// Seconds between updates of the last used time of a token.
+const LAST_USED_RESOLUTION: i64 = 60;
+
+/// Requests made with an API key act on behalf of the admin that created it.
+fn api_key_access_token(key: ApiKey) -> AccessToken {
+    AccessToken {
+        user_id: key.user_id,
+        token: key.token,
+        device_id: String::new(),
+        device_name: String::new(),
+        application_name: key.name,
+        application_version: String::new(),
+        remote_address: String::new(),
+        created: key.created,
+        last_used: key.last_used,
+    }
+}
+
+/// Check if a (normalized, lowercase) path starts or continues playback.
+fn is_playback_path(path: &str) -> bool {
+    path.starts_with("/videos/") || path.starts_with("/sessions/playing") || path.ends_with("/playbackinfo")
+}
+
+/// Check if the access schedules of a user allow access right now.
+pub(crate) fn access_schedule_allows(user: &User) -> bool {
+    user.properties.access_allowed_at(chrono::Local::now().naive_local())
+}
+
+/// Check if a user is an admin.
+pub(crate) async fn is_admin(repo: &Arc<dyn Repository>, user_id: &str) -> bool {
+    match repo.get_user_by_id(user_id).await {
+        Ok(user) => user.properties.admin,
+        Err(_) => false,
+    }
+}
+
 /// Extract token from headers or query parameters
-fn extract_token(headers: &HeaderMap, request: &Request<axum::body::Body>) -> Option<String> {
+pub(crate) fn extract_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
     // Try auth header first
     if let Some(emby_header) = parse_auth_header(headers) {
         if !emby_header.token.is_empty() {
@@ -156,7 +254,7 @@ fn extract_token(headers: &HeaderMap, request: &Request<axum::body::Body>) -> Op
     }
 
     // Try query parameter ApiKey
-    if let Some(query) = request.uri().query() {
+    if let Some(query) = uri.query() {
         let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
             .into_owned()
             .collect();
@@ -173,3 +271,72 @@ fn extract_token(headers: &HeaderMap, request: &Request<axum::body::Body>) -> Op
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_playback_path() {
+        assert!(is_playback_path("/videos/abc/stream.mkv"));
+        assert!(is_playback_path("/videos/abc/hls1/main/3.ts"));
+        assert!(is_playback_path("/items/abc/playbackinfo"));
+        assert!(is_playback_path("/sessions/playing/progress"));
+        assert!(!is_playback_path("/users/abc/items"));
+        assert!(!is_playback_path("/sessions"));
+    }
+
+    #[tokio::test]
+    async fn test_token_expired() {
+        let jf = crate::jellyfin::JellyfinState::for_test(
+            "test_token_expired",
+            Arc::new(crate::collection::CollectionRepo::new()),
+        )
+        .await;
+        let state = JellyfinAuthState {
+            repo: jf.repo.clone(),
+            server_id: jf.server_id.clone(),
+            auto_register: false,
+            quick_connect: false,
+            websocket: jf.websocket.clone(),
+            sessions: jf.sessions.clone(),
+            syncplay: jf.syncplay.clone(),
+            lockout: LoginLockout::new(&jf.config),
+            token_idle_timeout: None,
+            token_lifetime: None,
+        };
+        let days = |n: i64| chrono::Utc::now() - chrono::Duration::days(n);
+        let token = |created, last_used| AccessToken {
+            user_id: "alice".to_string(),
+            token: "t".to_string(),
+            device_id: "d".to_string(),
+            device_name: "Phone".to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created,
+            last_used,
+        };
+        let old = token(days(400), days(40));
+
+        // Tokens do not expire by default.
+        assert!(!state.token_expired(&old));
+
+        let idle = JellyfinAuthState {
+            token_idle_timeout: Some(Duration::from_secs(30 * 86400)),
+            ..state.clone()
+        };
+        assert!(idle.token_expired(&old));
+        assert!(!idle.token_expired(&token(days(400), days(1))));
+
+        let lifetime = JellyfinAuthState {
+            token_lifetime: Some(Duration::from_secs(365 * 86400)),
+            ..state.clone()
+        };
+        assert!(lifetime.token_expired(&token(days(400), days(1))));
+        assert!(!lifetime.token_expired(&token(days(300), days(40))));
+
+        // Timestamps in the future do not expire a token.
+        assert!(!idle.token_expired(&token(days(-1), days(-1))));
+    }
+}
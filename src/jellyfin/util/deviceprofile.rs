@@ -0,0 +1,515 @@
+use super::types::*;
+
+/// PlaybackDecision is the outcome of evaluating a client's DeviceProfile
+/// against a media source.
+#[derive(Debug, Default)]
+pub struct PlaybackDecision {
+    pub direct_play: bool,
+    /// Reasons why the media source cannot be played directly.
+    pub transcode_reasons: Vec<String>,
+    /// Profile to use when transcoding, if the client has a usable one.
+    pub transcoding_profile: Option<TranscodingProfile>,
+}
+
+/// evaluate_device_profile decides whether the client described by `profile`
+/// can play the media source directly. `max_bitrate` is the streaming bitrate
+/// limit of this request, which overrides the profile's limit.
+pub fn evaluate_device_profile(
+    profile: &DeviceProfile,
+    max_bitrate: Option<i64>,
+    source: &MediaSourceInfo,
+    audio_stream_index: Option<i32>,
+    subtitle_stream_index: Option<i32>,
+) -> PlaybackDecision {
+    let container = source.container.to_lowercase();
+    let video = source.media_streams.iter().find(|s| s.stream_type == "Video");
+    let audio = select_stream(
+        source,
+        "Audio",
+        audio_stream_index.or(source.default_audio_stream_index),
+    );
+    let subtitle = select_stream(source, "Subtitle", subtitle_stream_index);
+
+    let mut reasons: Vec<&str> = Vec::new();
+
+    // Container and codecs must match one of the direct play profiles.
+    let video_profiles: Vec<&DirectPlayProfile> = profile
+        .direct_play_profiles
+        .iter()
+        .filter(|p| p.r#type.eq_ignore_ascii_case("Video"))
+        .filter(|p| list_contains(p.container.as_deref(), &container))
+        .collect();
+    if video_profiles.is_empty() {
+        reasons.push("ContainerNotSupported");
+    } else {
+        let video_codec = video.map(|s| s.codec.to_lowercase()).unwrap_or_default();
+        let audio_codec = audio.map(|s| s.codec.to_lowercase()).unwrap_or_default();
+        // Codecs we know nothing about are assumed to be playable.
+        if !matches!(video_codec.as_str(), "" | "unknown")
+            && !video_profiles
+                .iter()
+                .any(|p| list_contains(p.video_codec.as_deref(), &video_codec))
+        {
+            reasons.push("VideoCodecNotSupported");
+        }
+        if !matches!(audio_codec.as_str(), "" | "unknown")
+            && !video_profiles
+                .iter()
+                .any(|p| list_contains(p.audio_codec.as_deref(), &audio_codec))
+        {
+            reasons.push("AudioCodecNotSupported");
+        }
+    }
+
+    // Bitrate limit of the request or the profile.
+    if let (Some(limit), Some(bitrate)) = (max_bitrate.or(profile.max_streaming_bitrate), source.bitrate) {
+        if limit > 0 && bitrate as i64 > limit {
+            reasons.push("ContainerBitrateExceedsLimit");
+        }
+    }
+
+    // Codec profiles restrict properties of the video and audio streams.
+    for codec_profile in &profile.codec_profiles {
+        let stream = match codec_profile.r#type.as_str() {
+            "Video" => video,
+            "VideoAudio" | "Audio" => audio,
+            _ => continue,
+        };
+        let Some(stream) = stream else { continue };
+        if !list_contains(codec_profile.codec.as_deref(), &stream.codec.to_lowercase())
+            || !list_contains(codec_profile.container.as_deref(), &container)
+        {
+            continue;
+        }
+        if !codec_profile
+            .apply_conditions
+            .iter()
+            .all(|c| condition_satisfied(c, stream))
+        {
+            continue;
+        }
+        for condition in &codec_profile.conditions {
+            if !condition_satisfied(condition, stream) {
+                reasons.push(condition_reason(&condition.property));
+            }
+        }
+    }
+
+    // The selected subtitle stream must be deliverable without burning it in.
+    if let Some(subtitle) = subtitle {
+        let format = subtitle.codec.to_lowercase();
+        let deliverable = profile
+            .subtitle_profiles
+            .iter()
+            .any(|p| p.format.eq_ignore_ascii_case(&format) && !p.method.eq_ignore_ascii_case("Encode"));
+        if !deliverable {
+            reasons.push("SubtitleCodecNotSupported");
+        }
+    }
+
+    let mut transcode_reasons: Vec<String> = Vec::new();
+    for reason in reasons {
+        if !transcode_reasons.iter().any(|r| r == reason) {
+            transcode_reasons.push(reason.to_string());
+        }
+    }
+
+    // We can only produce HLS, prefer a profile that asks for it.
+    let transcoding_profile = profile
+        .transcoding_profiles
+        .iter()
+        .find(|p| p.r#type.eq_ignore_ascii_case("Video") && p.protocol.eq_ignore_ascii_case("hls"))
+        .cloned();
+
+    PlaybackDecision {
+        direct_play: transcode_reasons.is_empty(),
+        transcode_reasons,
+        transcoding_profile,
+    }
+}
+
+/// select_stream returns the stream of `stream_type` with `index`, or the
+/// first one of that type if no index was requested.
+fn select_stream<'a>(
+    source: &'a MediaSourceInfo,
+    stream_type: &str,
+    index: Option<i32>,
+) -> Option<&'a MediaStream> {
+    let mut streams = source
+        .media_streams
+        .iter()
+        .filter(|s| s.stream_type == stream_type);
+    match index {
+        Some(index) if index >= 0 => streams.find(|s| s.index == index),
+        Some(_) => None,
+        None if stream_type == "Subtitle" => None,
+        None => streams.next(),
+    }
+}
+
+/// list_contains checks if a comma separated profile list contains `value`.
+/// An empty or missing list matches everything.
+fn list_contains(list: Option<&str>, value: &str) -> bool {
+    match list {
+        None => true,
+        Some(list) if list.trim().is_empty() => true,
+        Some(list) => list
+            .split(',')
+            .map(|v| v.trim())
+            .any(|v| v.eq_ignore_ascii_case(value) || (v.eq_ignore_ascii_case("mp4") && value == "m4v")),
+    }
+}
+
+/// condition_satisfied evaluates a profile condition against a stream.
+/// Properties we know nothing about pass unless the condition is required.
+fn condition_satisfied(condition: &ProfileCondition, stream: &MediaStream) -> bool {
+    let Some(actual) = stream_property(stream, &condition.property) else {
+        return !condition.is_required;
+    };
+    let expected = condition.value.as_deref().unwrap_or_default();
+
+    match condition.condition.as_str() {
+        "Equals" => property_eq(&actual, expected),
+        "NotEquals" => !property_eq(&actual, expected),
+        "EqualsAny" => expected.split('|').any(|v| property_eq(&actual, v)),
+        "LessThanEqual" => compare(&actual, expected).is_none_or(|o| o.is_le()),
+        "GreaterThanEqual" => compare(&actual, expected).is_none_or(|o| o.is_ge()),
+        _ => true,
+    }
+}
+
+fn stream_property(stream: &MediaStream, property: &str) -> Option<String> {
+    match property {
+        "Width" => stream.width.map(|v| v.to_string()),
+        "Height" => stream.height.map(|v| v.to_string()),
+        "VideoBitrate" | "AudioBitrate" => stream.bit_rate.map(|v| v.to_string()),
+        "VideoFramerate" => stream
+            .real_frame_rate
+            .or(stream.average_frame_rate)
+            .map(|v| v.to_string()),
+        "VideoLevel" => stream.level.map(|v| v.to_string()),
+        "VideoProfile" | "AudioProfile" => stream.profile.clone(),
+        "VideoBitDepth" | "AudioBitDepth" => stream.bit_depth.map(|v| v.to_string()),
+        "VideoRangeType" => stream.video_range_type.clone(),
+        "AudioChannels" => stream.channels.map(|v| v.to_string()),
+        "AudioSampleRate" => stream.sample_rate.map(|v| v.to_string()),
+        "RefFrames" => stream.ref_frames.map(|v| v.to_string()),
+        "IsInterlaced" => Some(stream.is_interlaced.to_string()),
+        "IsAnamorphic" => stream.is_anamorphic.map(|v| v.to_string()),
+        _ => None,
+    }
+}
+
+fn property_eq(actual: &str, expected: &str) -> bool {
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => actual.eq_ignore_ascii_case(expected.trim()),
+    }
+}
+
+fn compare(actual: &str, expected: &str) -> Option<std::cmp::Ordering> {
+    let a = actual.parse::<f64>().ok()?;
+    let b = expected.trim().parse::<f64>().ok()?;
+    a.partial_cmp(&b)
+}
+
+fn condition_reason(property: &str) -> &'static str {
+    match property {
+        "Width" | "Height" => "VideoResolutionNotSupported",
+        "VideoBitrate" => "VideoBitrateNotSupported",
+        "VideoFramerate" => "VideoFramerateNotSupported",
+        "VideoLevel" => "VideoLevelNotSupported",
+        "VideoProfile" => "VideoProfileNotSupported",
+        "VideoBitDepth" => "VideoBitDepthNotSupported",
+        "VideoRangeType" => "VideoRangeTypeNotSupported",
+        "RefFrames" => "RefFramesNotSupported",
+        "IsInterlaced" => "InterlacedVideoNotSupported",
+        "IsAnamorphic" => "AnamorphicVideoNotSupported",
+        "AudioChannels" => "AudioChannelsNotSupported",
+        "AudioBitrate" => "AudioBitrateNotSupported",
+        "AudioProfile" => "AudioProfileNotSupported",
+        "AudioSampleRate" => "AudioSampleRateNotSupported",
+        "AudioBitDepth" => "AudioBitDepthNotSupported",
+        _ => "UnknownVideoStreamInfo",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile(value: serde_json::Value) -> DeviceProfile {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn stream(stream_type: &str, index: i32, codec: &str) -> MediaStream {
+        MediaStream {
+            stream_type: stream_type.to_string(),
+            index,
+            codec: codec.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn source(container: &str, streams: Vec<MediaStream>) -> MediaSourceInfo {
+        MediaSourceInfo {
+            container: container.to_string(),
+            media_streams: streams,
+            ..Default::default()
+        }
+    }
+
+    fn reasons(profile: &DeviceProfile, source: &MediaSourceInfo) -> Vec<String> {
+        evaluate_device_profile(profile, None, source, None, None).transcode_reasons
+    }
+
+    #[test]
+    fn test_direct_play_profiles() {
+        let profile = profile(json!({
+            "DirectPlayProfiles": [
+                { "Container": "mp4,mkv", "VideoCodec": "h264,hevc", "AudioCodec": "aac,ac3", "Type": "Video" },
+                { "Container": "webm", "VideoCodec": "vp9", "AudioCodec": "opus", "Type": "Video" },
+                { "Container": "avi", "Type": "Audio" },
+            ],
+        }));
+
+        let cases: &[(&str, &str, &str, &[&str])] = &[
+            ("mkv", "h264", "aac", &[]),
+            ("MKV", "HEVC", "AC3", &[]),
+            // m4v is played by profiles that list mp4.
+            ("m4v", "h264", "aac", &[]),
+            ("webm", "vp9", "opus", &[]),
+            // Unknown codecs are assumed to be playable.
+            ("mkv", "unknown", "", &[]),
+            ("avi", "h264", "aac", &["ContainerNotSupported"]),
+            ("mkv", "av1", "aac", &["VideoCodecNotSupported"]),
+            ("mkv", "h264", "dts", &["AudioCodecNotSupported"]),
+            ("webm", "vp9", "aac", &["AudioCodecNotSupported"]),
+            (
+                "mp4",
+                "vp9",
+                "opus",
+                &["VideoCodecNotSupported", "AudioCodecNotSupported"],
+            ),
+        ];
+        for (container, video, audio, expected) in cases {
+            let source = source(
+                container,
+                vec![stream("Video", 0, video), stream("Audio", 1, audio)],
+            );
+            let decision = evaluate_device_profile(&profile, None, &source, None, None);
+            assert_eq!(
+                decision.transcode_reasons, *expected,
+                "{} {} {}",
+                container, video, audio
+            );
+            assert_eq!(decision.direct_play, expected.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_selected_audio_stream() {
+        let profile = profile(json!({
+            "DirectPlayProfiles": [{ "Container": "mkv", "AudioCodec": "aac", "Type": "Video" }],
+        }));
+        let mut source = source(
+            "mkv",
+            vec![
+                stream("Video", 0, "h264"),
+                stream("Audio", 1, "aac"),
+                stream("Audio", 2, "dts"),
+            ],
+        );
+
+        assert!(reasons(&profile, &source).is_empty());
+        let decision = evaluate_device_profile(&profile, None, &source, Some(2), None);
+        assert_eq!(decision.transcode_reasons, vec!["AudioCodecNotSupported"]);
+
+        source.default_audio_stream_index = Some(2);
+        assert_eq!(reasons(&profile, &source), vec!["AudioCodecNotSupported"]);
+    }
+
+    #[test]
+    fn test_codec_profile_conditions() {
+        let profile = profile(json!({
+            "DirectPlayProfiles": [{ "Container": "mkv", "Type": "Video" }],
+            "CodecProfiles": [
+                {
+                    "Type": "Video",
+                    "Codec": "h264",
+                    "Conditions": [
+                        { "Condition": "LessThanEqual", "Property": "Width", "Value": "1920" },
+                        { "Condition": "LessThanEqual", "Property": "VideoLevel", "Value": "41" },
+                        { "Condition": "EqualsAny", "Property": "VideoProfile", "Value": "high|main" },
+                        { "Condition": "NotEquals", "Property": "IsInterlaced", "Value": "true" },
+                        { "Condition": "LessThanEqual", "Property": "RefFrames", "Value": "4", "IsRequired": true },
+                    ],
+                },
+                {
+                    // Only 4K HEVC is limited to 8 bit.
+                    "Type": "Video",
+                    "Codec": "hevc",
+                    "ApplyConditions": [
+                        { "Condition": "GreaterThanEqual", "Property": "Width", "Value": "3840" },
+                    ],
+                    "Conditions": [
+                        { "Condition": "Equals", "Property": "VideoBitDepth", "Value": "8" },
+                    ],
+                },
+                {
+                    "Type": "VideoAudio",
+                    "Codec": "aac,ac3",
+                    "Conditions": [
+                        { "Condition": "LessThanEqual", "Property": "AudioChannels", "Value": "6" },
+                    ],
+                },
+                {
+                    // Other containers are not affected.
+                    "Type": "Video",
+                    "Container": "mp4",
+                    "Conditions": [
+                        { "Condition": "LessThanEqual", "Property": "Width", "Value": "640" },
+                    ],
+                },
+            ],
+        }));
+
+        let h264 =
+            |width: i32, level: f32, profile: &str, interlaced: bool, ref_frames: Option<i32>| MediaStream {
+                width: Some(width),
+                level: Some(level),
+                profile: Some(profile.to_string()),
+                is_interlaced: interlaced,
+                ref_frames,
+                ..stream("Video", 0, "h264")
+            };
+        let hevc = |width: i32, bit_depth: i32| MediaStream {
+            width: Some(width),
+            bit_depth: Some(bit_depth),
+            ..stream("Video", 0, "hevc")
+        };
+        let aac = |channels: i32| MediaStream {
+            channels: Some(channels),
+            ..stream("Audio", 1, "aac")
+        };
+
+        let cases: Vec<(MediaStream, MediaStream, &[&str])> = vec![
+            (h264(1920, 41.0, "High", false, Some(4)), aac(2), &[]),
+            (
+                h264(3840, 41.0, "high", false, Some(4)),
+                aac(2),
+                &["VideoResolutionNotSupported"],
+            ),
+            (
+                h264(1920, 51.0, "high", false, Some(4)),
+                aac(2),
+                &["VideoLevelNotSupported"],
+            ),
+            (
+                h264(1920, 41.0, "high 10", false, Some(4)),
+                aac(2),
+                &["VideoProfileNotSupported"],
+            ),
+            (
+                h264(1920, 41.0, "main", true, Some(4)),
+                aac(2),
+                &["InterlacedVideoNotSupported"],
+            ),
+            // A required condition fails when the property is unknown.
+            (
+                h264(1920, 41.0, "main", false, None),
+                aac(2),
+                &["RefFramesNotSupported"],
+            ),
+            (
+                h264(1920, 41.0, "main", false, Some(4)),
+                aac(8),
+                &["AudioChannelsNotSupported"],
+            ),
+            (hevc(1920, 10), aac(2), &[]),
+            (hevc(3840, 8), aac(2), &[]),
+            (hevc(3840, 10), aac(2), &["VideoBitDepthNotSupported"]),
+        ];
+        for (i, (video, audio, expected)) in cases.into_iter().enumerate() {
+            let source = source("mkv", vec![video, audio]);
+            assert_eq!(reasons(&profile, &source), *expected, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn test_bitrate_limit() {
+        let profile = profile(json!({
+            "MaxStreamingBitrate": 8_000_000,
+            "DirectPlayProfiles": [{ "Type": "Video" }],
+        }));
+        let mut source = source("mkv", vec![stream("Video", 0, "h264")]);
+        source.bitrate = Some(10_000_000);
+
+        assert_eq!(reasons(&profile, &source), vec!["ContainerBitrateExceedsLimit"]);
+        // The limit of the request overrides the one of the profile.
+        let decision = evaluate_device_profile(&profile, Some(20_000_000), &source, None, None);
+        assert!(decision.direct_play);
+    }
+
+    #[test]
+    fn test_subtitle_profiles() {
+        let profile = profile(json!({
+            "DirectPlayProfiles": [{ "Type": "Video" }],
+            "SubtitleProfiles": [
+                { "Format": "srt", "Method": "External" },
+                { "Format": "ass", "Method": "Encode" },
+            ],
+        }));
+        let source = source(
+            "mkv",
+            vec![
+                stream("Video", 0, "h264"),
+                stream("Subtitle", 1, "srt"),
+                stream("Subtitle", 2, "ass"),
+                stream("Subtitle", 3, "pgssub"),
+            ],
+        );
+
+        let cases: &[(Option<i32>, &[&str])] = &[
+            (None, &[]),
+            (Some(-1), &[]),
+            (Some(1), &[]),
+            (Some(2), &["SubtitleCodecNotSupported"]),
+            (Some(3), &["SubtitleCodecNotSupported"]),
+        ];
+        for (index, expected) in cases {
+            let decision = evaluate_device_profile(&profile, None, &source, None, *index);
+            assert_eq!(decision.transcode_reasons, *expected, "subtitle {:?}", index);
+        }
+    }
+
+    #[test]
+    fn test_transcoding_profiles() {
+        let cases = [
+            (
+                json!([
+                    { "Container": "mp3", "Type": "Audio", "Protocol": "hls" },
+                    { "Container": "mkv", "Type": "Video", "Protocol": "http" },
+                    { "Container": "ts", "Type": "Video", "Protocol": "HLS", "VideoCodec": "h264" },
+                ]),
+                Some("ts"),
+            ),
+            (
+                json!([{ "Container": "mkv", "Type": "Video", "Protocol": "http" }]),
+                None,
+            ),
+            (json!([]), None),
+        ];
+        for (transcoding_profiles, expected) in cases {
+            let profile = profile(json!({ "TranscodingProfiles": transcoding_profiles }));
+            let source = source("mkv", vec![stream("Video", 0, "h264")]);
+            let decision = evaluate_device_profile(&profile, None, &source, None, None);
+            assert!(!decision.direct_play);
+            assert_eq!(
+                decision.transcoding_profile.map(|p| p.container).as_deref(),
+                expected
+            );
+        }
+    }
+}
@@ -3,21 +3,50 @@ use rand::prelude::*;
 use std::collections::HashMap;
 use tracing::warn;
 
-use crate::collection::Item;
+use crate::collection::{CollectionRepo, Item};
 use crate::idhash::*;
 
 // ---------------------------------------------------------------------------
 // Item-based filtering (operates on native types, not BaseItemDto)
 // ---------------------------------------------------------------------------
 
+/// Number of documents fetched from the search index for `searchTerm`.
+const SEARCH_MAX_RESULTS: usize = 1000;
+
 pub(crate) fn apply_query_items_filter(
     items: Vec<Item>,
+    collections: &CollectionRepo,
     query_params: &HashMap<String, String>,
 ) -> Vec<Item> {
-    items
+    // searchTerm goes through the search index. Matching items are ranked by
+    // relevance, sorting on sortBy happens later.
+    let ranking: Option<HashMap<String, usize>> = query_params.get("searchTerm").map(|term| {
+        collections
+            .search(term, SEARCH_MAX_RESULTS)
+            .into_iter()
+            .enumerate()
+            .map(|(rank, doc)| (doc.id, rank))
+            .collect()
+    });
+    let rank = |item: &Item| match &ranking {
+        Some(ranking) if is_media_item(item) => ranking.get(&item.id()).copied(),
+        _ => Some(usize::MAX),
+    };
+
+    let mut items: Vec<Item> = items
         .into_iter()
-        .filter(|item| apply_query_item_filter(item, query_params))
-        .collect()
+        .filter(|item| rank(item).is_some() && apply_query_item_filter(item, query_params))
+        .collect();
+    if ranking.is_some() {
+        items.sort_by_cached_key(|item| rank(item));
+    }
+    items
+}
+
+/// Movies, shows, seasons and episodes are searched, other items are not.
+fn is_media_item(item: &Item) -> bool {
+    let id = item.id();
+    is_jf_movie_id(&id) || is_jf_show_id(&id) || is_jf_season_id(&id) || is_jf_episode_id(&id)
 }
 
 fn apply_query_item_filter(item: &Item, qp: &HashMap<String, String>) -> bool {
@@ -110,6 +139,25 @@ fn apply_query_item_filter(item: &Item, qp: &HashMap<String, String>) -> bool {
         }
     }
 
+    // personIds (pipe-separated), optionally restricted to personTypes
+    if let Some(person_ids) = qp.get("personIds") {
+        let person_types: Option<Vec<String>> = qp
+            .get("personTypes")
+            .map(|t| t.split(',').map(|t| t.to_lowercase()).collect());
+        let mut item_person_ids = item
+            .metadata()
+            .credits()
+            .filter(|c| {
+                person_types
+                    .as_ref()
+                    .is_none_or(|types| types.contains(&c.kind.as_str().to_lowercase()))
+            })
+            .map(|c| id_hash_prefix(ITEM_PREFIX_PERSON, c.name));
+        if !item_person_ids.any(|ip| person_ids.split('|').any(|pid| pid == ip)) {
+            return false;
+        }
+    }
+
     // seriesId
     if let Some(series_id) = qp.get("seriesId") {
         if item.series_id() != Some(series_id.as_str()) {
@@ -289,16 +337,6 @@ fn apply_query_item_filter(item: &Item, qp: &HashMap<String, String>) -> bool {
         }
     }
 
-    // searchTerm
-    if let Some(term) = qp.get("searchTerm") {
-        let id = item.id();
-        let media =
-            is_jf_movie_id(&id) || is_jf_show_id(&id) || is_jf_season_id(&id) || is_jf_episode_id(&id);
-        if media && !item.name().to_lowercase().contains(&term.to_lowercase()) {
-            return false;
-        }
-    }
-
     true
 }
 
@@ -380,10 +418,10 @@ pub(crate) fn apply_query_item_sorting(items: &mut Vec<Item>, query_params: &Has
 // Item-based pagination
 // ---------------------------------------------------------------------------
 
-pub(crate) fn apply_query_item_pagination(
-    items: Vec<Item>,
+pub(crate) fn apply_query_item_pagination<T>(
+    items: Vec<T>,
     query_params: &HashMap<String, String>,
-) -> (Vec<Item>, i32) {
+) -> (Vec<T>, i32) {
     let start_index = query_params
         .get("startIndex")
         .and_then(|v| v.parse::<usize>().ok())
@@ -401,7 +439,7 @@ pub(crate) fn apply_query_item_pagination(
         total
     };
 
-    let paged: Vec<Item> = items
+    let paged: Vec<T> = items
         .into_iter()
         .skip(start_index)
         .take(end - start_index)
@@ -444,3 +482,84 @@ pub fn parse_iso8601_date(input: &str) -> Option<DateTime<Utc>> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// "Alien" with Sigourney Weaver and John Hurt, directed by Ridley Scott,
+    /// and "Aliens" with Sigourney Weaver, directed by James Cameron.
+    fn movies(name: &str) -> (Vec<Item>, std::path::PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        for (dir, nfo) in [
+            (
+                "Alien (1979)",
+                "<movie><actor><name>Sigourney Weaver</name></actor>\
+                 <actor><name>John Hurt</name></actor><director>Ridley Scott</director></movie>",
+            ),
+            (
+                "Aliens (1986)",
+                "<movie><actor><name>Sigourney Weaver</name></actor>\
+                 <director>James Cameron</director></movie>",
+            ),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("movie.mkv"), b"m").unwrap();
+            fs::write(root.join(dir).join("movie.nfo"), nfo).unwrap();
+        }
+        let repo = CollectionRepo::new();
+        repo.add_collection(
+            "Movies".to_string(),
+            Some("movies".to_string()),
+            "movies",
+            vec![root.to_string_lossy().to_string()],
+            "".to_string(),
+        )
+        .unwrap();
+        repo.init();
+        let mut items = repo.get_collections().remove(0).items;
+        items.sort_by_key(|item| item.name());
+        (items, root)
+    }
+
+    #[test]
+    fn test_person_ids() {
+        let (items, root) = movies("test_item_person_ids");
+        let person = |name: &str| id_hash_prefix(ITEM_PREFIX_PERSON, name);
+        let matching = |params: &[(&str, String)]| -> Vec<String> {
+            let qp: HashMap<String, String> =
+                params.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+            items
+                .iter()
+                .filter(|item| apply_query_item_filter(item, &qp))
+                .map(|item| item.name())
+                .collect()
+        };
+
+        let weaver = person("Sigourney Weaver");
+        assert_eq!(
+            matching(&[("personIds", weaver.clone())]),
+            vec!["Alien (1979)", "Aliens (1986)"]
+        );
+        let cameron = person("James Cameron");
+        assert_eq!(matching(&[("personIds", cameron.clone())]), vec!["Aliens (1986)"]);
+        let either = format!("{}|{}", person("John Hurt"), cameron);
+        assert_eq!(
+            matching(&[("personIds", either)]),
+            vec!["Alien (1979)", "Aliens (1986)"]
+        );
+        assert!(matching(&[("personIds", person("Bill Paxton"))]).is_empty());
+
+        // personTypes restricts the credits that match, in any case.
+        for types in ["Director", "director", "Actor,DIRECTOR"] {
+            let params = [("personIds", cameron.clone()), ("personTypes", types.to_string())];
+            assert_eq!(matching(&params), vec!["Aliens (1986)"], "{}", types);
+        }
+        let params = [("personIds", cameron), ("personTypes", "actor".to_string())];
+        assert!(matching(&params).is_empty());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
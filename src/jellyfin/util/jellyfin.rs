@@ -10,4 +10,43 @@ pub struct JellyfinState {
     pub server_name: String,
     pub image_resizer: Arc<crate::imageresize::ImageResizer>,
     pub config: Arc<crate::server::Config>,
+    pub websocket: Arc<crate::jellyfin::WebSocketHub>,
+    pub sessions: Arc<super::sessions::SessionRegistry>,
+    pub syncplay: Arc<crate::jellyfin::SyncPlayManager>,
+    pub transcoder: Arc<crate::transcode::Transcoder>,
+    pub tasks: Arc<crate::tasks::TaskManager>,
+}
+
+#[cfg(test)]
+impl JellyfinState {
+    /// State for handler tests with an in-memory database and the caches in
+    /// a temporary directory named `name`.
+    pub(crate) async fn for_test(name: &str, collections: Arc<CollectionRepo>) -> Self {
+        let cache_dir = std::env::temp_dir().join(name);
+        let config: crate::server::Config = serde_yaml::from_str(&format!(
+            "listen: {{}}\ncachedir: {}\ndbdir: {}\n",
+            cache_dir.display(),
+            cache_dir.display()
+        ))
+        .unwrap();
+        Self {
+            repo: Arc::new(
+                crate::database::SqliteRepository::new("sqlite::memory:")
+                    .await
+                    .unwrap(),
+            ),
+            collections,
+            server_id: "test".to_string(),
+            server_name: "Test".to_string(),
+            image_resizer: Arc::new(crate::imageresize::ImageResizer::new(cache_dir.join("images")).unwrap()),
+            config: Arc::new(config),
+            websocket: Arc::new(crate::jellyfin::WebSocketHub::new()),
+            sessions: Arc::new(super::sessions::SessionRegistry::new()),
+            syncplay: Arc::new(crate::jellyfin::SyncPlayManager::new()),
+            transcoder: Arc::new(
+                crate::transcode::Transcoder::new("ffmpeg".into(), cache_dir.join("transcode"), 6).unwrap(),
+            ),
+            tasks: Arc::new(crate::tasks::TaskManager::new()),
+        }
+    }
 }
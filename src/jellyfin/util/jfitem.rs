@@ -4,10 +4,11 @@ use anyhow::{anyhow, Result};
 use chrono::Utc;
 use tracing::warn;
 
+use super::access::AccessFilter;
 use super::jellyfin::JellyfinState;
 use super::types::*;
 use crate::collection::item::{CollectionFolder, Episode, Movie, PlaylistItem, Season, Show, UserView};
-use crate::collection::{CollectionType, Item};
+use crate::collection::{AudioTrack, CollectionType, Credit, Item, Metadata, SubtitleTrack};
 use crate::database::UserData as DbUserData;
 use crate::idhash::*;
 
@@ -65,10 +66,10 @@ pub async fn load_user_data(items: &mut [Item], state: &JellyfinState, user_id:
 }
 
 /// Look up items by a list of IDs across all collections.
-pub fn get_items_by_ids(state: &JellyfinState, ids: Vec<&str>) -> Result<Vec<Item>> {
+pub fn get_items_by_ids(access: &AccessFilter, ids: Vec<&str>) -> Result<Vec<Item>> {
     let mut items = Vec::new();
     for id in ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(id) {
+        if let Some((_, item)) = access.get_item_by_id(id) {
             items.push(item);
         }
     }
@@ -78,12 +79,11 @@ pub fn get_items_by_ids(state: &JellyfinState, ids: Vec<&str>) -> Result<Vec<Ite
 /// Collect all items from a specific collection.
 /// When `recursive` is true, Shows are flattened to include their Seasons and Episodes.
 pub fn get_items_by_collection(
-    state: &JellyfinState,
+    access: &AccessFilter,
     collection_id: &str,
     recursive: bool,
 ) -> Result<Vec<Item>> {
-    let c = state
-        .collections
+    let c = access
         .get_collection(collection_id)
         .ok_or_else(|| anyhow!("could not find collection"))?;
     if !recursive {
@@ -108,19 +108,23 @@ pub fn get_items_by_collection(
 }
 
 /// Collect all items across all collections.
-pub fn get_items_all(state: &JellyfinState) -> Vec<Item> {
+pub fn get_items_all(access: &AccessFilter) -> Vec<Item> {
     let mut items = Vec::new();
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         items.extend(c.items);
     }
     items
 }
 
 /// Get root overview items (collections + favorites + playlists) as native Items.
-pub async fn get_root_overview_items(state: &JellyfinState, user_id: &str) -> Vec<Item> {
+pub async fn get_root_overview_items(
+    state: &JellyfinState,
+    access: &AccessFilter,
+    user_id: &str,
+) -> Vec<Item> {
     let mut items = Vec::new();
 
-    for c in state.collections.get_collections() {
+    for c in access.get_collections() {
         items.push(Item::CollectionFolder(CollectionFolder {
             id: c.id.clone(),
             name: c.name.clone(),
@@ -164,14 +168,14 @@ pub async fn get_root_overview_items(state: &JellyfinState, user_id: &str) -> Ve
 }
 
 /// Get favorite items as native Items.
-pub async fn get_favorites_items(state: &JellyfinState, user_id: &str) -> Vec<Item> {
+pub async fn get_favorites_items(state: &JellyfinState, access: &AccessFilter, user_id: &str) -> Vec<Item> {
     let favorite_ids = match state.repo.get_favorites(user_id).await {
         Ok(ids) => ids,
         Err(_) => return Vec::new(),
     };
     let mut items = Vec::new();
     for item_id in &favorite_ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(item_id) {
+        if let Some((_, item)) = access.get_item_by_id(item_id) {
             match &item {
                 Item::Movie(_) | Item::Show(_) => items.push(item),
                 _ => {}
@@ -203,13 +207,14 @@ pub async fn get_playlist_overview_items(state: &JellyfinState, user_id: &str) -
 /// Get items in a specific playlist as native Items.
 pub async fn get_playlist_items_native(
     state: &JellyfinState,
+    access: &AccessFilter,
     user_id: &str,
     playlist_id: &str,
 ) -> Result<Vec<Item>> {
     let playlist = state.repo.get_playlist(user_id, playlist_id).await?;
     let mut items = Vec::new();
     for item_id in &playlist.item_ids {
-        if let Some((_, item)) = state.collections.get_item_by_id(item_id) {
+        if let Some((_, item)) = access.get_item_by_id(item_id) {
             items.push(item);
         }
     }
@@ -217,16 +222,16 @@ pub async fn get_playlist_items_native(
 }
 
 /// Get seasons of a show as native Items.
-pub fn get_seasons_items(state: &JellyfinState, show_id: &str) -> Result<Vec<Item>> {
-    match state.collections.get_item_by_id(show_id) {
+pub fn get_seasons_items(access: &AccessFilter, show_id: &str) -> Result<Vec<Item>> {
+    match access.get_item_by_id(show_id) {
         Some((_, Item::Show(show))) => Ok(show.seasons.iter().map(|s| Item::Season(s.clone())).collect()),
         _ => Err(anyhow!("show not found")),
     }
 }
 
 /// Get episodes of a season as native Items.
-pub fn get_episodes_items(state: &JellyfinState, season_id: &str) -> Result<Vec<Item>> {
-    match state.collections.get_season_by_id(season_id) {
+pub fn get_episodes_items(access: &AccessFilter, season_id: &str) -> Result<Vec<Item>> {
+    match access.get_season_by_id(season_id) {
         Some((_collection, _show, season)) => {
             Ok(season.episodes.iter().map(|e| Item::Episode(e.clone())).collect())
         }
@@ -235,8 +240,8 @@ pub fn get_episodes_items(state: &JellyfinState, season_id: &str) -> Result<Vec<
 }
 
 /// Get all episodes across all seasons of a show as native Items.
-pub fn get_show_all_episodes(state: &JellyfinState, show_id: &str) -> Result<Vec<Item>> {
-    match state.collections.get_item_by_id(show_id) {
+pub fn get_show_all_episodes(access: &AccessFilter, show_id: &str) -> Result<Vec<Item>> {
+    match access.get_item_by_id(show_id) {
         Some((_, Item::Show(show))) => {
             let mut items = Vec::new();
             for season in &show.seasons {
@@ -280,10 +285,14 @@ pub async fn make_jfitem(state: &JellyfinState, user_id: &str, item: &Item) -> R
 // ---------------------------------------------------------------------------
 
 /// make_jfitem_root creates the root folder item.
-pub async fn make_jfitem_root(state: &JellyfinState, user_id: &str) -> Result<BaseItemDto> {
-    let child_count = Some(get_root_overview_items(state, user_id).await.len() as i32);
+pub async fn make_jfitem_root(
+    state: &JellyfinState,
+    access: &AccessFilter,
+    user_id: &str,
+) -> Result<BaseItemDto> {
+    let child_count = Some(get_root_overview_items(state, access, user_id).await.len() as i32);
 
-    let genres = state.collections.details().genres;
+    let genres = access.details().genres;
 
     #[rustfmt::skip]
     let item = BaseItemDto {
@@ -456,12 +465,13 @@ async fn make_jfitem_movie(state: &JellyfinState, user_id: &str, movie: &Movie)
         server_id:                   state.server_id.clone(),
         item_type:                   ITEM_TYPE_MOVIE.to_string(),
         parent_id:                   Some(movie.collection_id.clone()),
-        original_title:              Some(movie.name.clone()),
+        original_title:              movie.metadata.original_title.clone().or_else(|| Some(movie.name.clone())),
         sort_name:                   Some(movie.sort_name.clone()),
         forced_sort_name:            Some(movie.sort_name.clone()),
         genres,
         genre_items,
         studios:                     make_jf_studio_pairs(&movie.metadata.studios),
+        people:                      make_jf_people(&movie.path, &movie.metadata),
         is_hd:                       item_is_hd(&movie.metadata),
         is_4k:                       item_is_4k(&movie.metadata),
         run_time_ticks:              make_runtime_ticks_from_metadata(&movie.metadata),
@@ -486,7 +496,9 @@ async fn make_jfitem_movie(state: &JellyfinState, user_id: &str, movie: &Movie)
         community_rating:            movie.metadata.rating,
         production_year:             movie.metadata.year,
         taglines:                    movie.metadata.taglines.clone(),
-        has_subtitles:               !movie.srt_subs.is_empty() || !movie.vtt_subs.is_empty(),
+        tags:                        movie.metadata.tags.clone(),
+        has_subtitles:               !movie.srt_subs.is_empty() || !movie.vtt_subs.is_empty() || !movie.metadata.subtitle_tracks.is_empty(),
+        chapters:                    make_jf_chapters(&movie.metadata),
         media_sources,
         media_streams,
         user_data,
@@ -578,12 +590,13 @@ async fn make_jfitem_show(state: &JellyfinState, user_id: &str, show: &Show) ->
         server_id:                   state.server_id.clone(),
         item_type:                   ITEM_TYPE_SHOW.to_string(),
         parent_id:                   Some(show.collection_id.clone()),
-        original_title:              Some(show.name.clone()),
+        original_title:              show.metadata.original_title.clone().or_else(|| Some(show.name.clone())),
         sort_name:                   Some(show.sort_name.clone()),
         forced_sort_name:            Some(show.sort_name.clone()),
         genres,
         genre_items,
         studios:                     make_jf_studio_pairs(&show.metadata.studios),
+        people:                      make_jf_people(&show.path, &show.metadata),
         is_folder:                   true,
         etag:                        Some(id_hash(&show.id)),
         date_created:                Some(show.first_video),
@@ -599,6 +612,7 @@ async fn make_jfitem_show(state: &JellyfinState, user_id: &str, show: &Show) ->
         community_rating:            show.metadata.rating,
         production_year:             show.metadata.year,
         taglines:                    show.metadata.taglines.clone(),
+        tags:                        show.metadata.tags.clone(),
         child_count:                 Some(child_count),
         recursive_item_count:        Some(recursive_item_count),
         user_data,
@@ -781,7 +795,7 @@ pub async fn make_jfitem_episode(
         container:              Some("mov,mp4,m4a".to_string()),
         date_created:           Some(episode.created),
         premiere_date,
-        has_subtitles:          !episode.srt_subs.is_empty() || !episode.vtt_subs.is_empty(),
+        has_subtitles:          !episode.srt_subs.is_empty() || !episode.vtt_subs.is_empty() || !episode.metadata.subtitle_tracks.is_empty(),
         can_delete:             Some(false),
         can_download:           Some(true),
         play_access:            Some("Full".to_string()),
@@ -793,6 +807,7 @@ pub async fn make_jfitem_episode(
         genre_items,
         studios:                make_jf_studio_pairs(studios),
         image_tags,
+        chapters:               make_jf_chapters(&episode.metadata),
         media_sources,
         media_streams,
         user_data:              Some(user_data),
@@ -833,6 +848,32 @@ fn make_jf_studio_pairs(studios: &[String]) -> Vec<NameGuidPair> {
         .collect()
 }
 
+/// make_jf_people converts the cast and crew of a movie or show into
+/// BaseItemPersons.
+fn make_jf_people(item_path: &str, metadata: &Metadata) -> Vec<BaseItemPerson> {
+    metadata
+        .credits()
+        .map(|c| BaseItemPerson {
+            name: c.name.to_string(),
+            id: id_hash_prefix(ITEM_PREFIX_PERSON, c.name),
+            role: c.role.map(|r| r.to_string()),
+            r#type: c.kind.as_str().to_string(),
+            primary_image_tag: make_person_image_tag(item_path, &c),
+        })
+        .collect()
+}
+
+/// make_person_image_tag returns the image tag of a credited person. Images
+/// on the web are redirected to, files are looked up by person ID.
+pub(crate) fn make_person_image_tag(item_path: &str, credit: &Credit) -> Option<String> {
+    if let Some(url) = credit.thumb_url() {
+        return Some(format!("redirect_{}", url));
+    }
+    credit
+        .thumb_file()
+        .map(|file| id_hash(&format!("{}/{}", item_path, file)))
+}
+
 /// make_jf_userdata creates a UserItemDataDto, populating from DbUserData if provided.
 pub fn make_jf_userdata(user_id: &str, item_id: &str, data: Option<&DbUserData>) -> UserItemDataDto {
     let mut ud = UserItemDataDto {
@@ -876,6 +917,11 @@ pub(crate) fn make_media_source(
     let runtime_ticks = metadata.runtime_ticks();
     let bitrate = calc_bitrate(file_size, metadata);
     let media_streams = make_jf_media_streams(metadata, bitrate);
+    // Forced subtitles are shown by default.
+    let default_subtitle_stream_index = media_streams
+        .iter()
+        .find(|s| s.stream_type == "Subtitle" && (s.is_forced || s.is_default))
+        .map(|s| s.index);
 
     vec![MediaSourceInfo {
         id: item_id.to_string(),
@@ -888,7 +934,7 @@ pub(crate) fn make_media_source(
         video_type: Some("VideoFile".to_string()),
         size: file_size,
         is_remote: false,
-        supports_transcoding: false,
+        supports_transcoding: true,
         supports_direct_stream: true,
         supports_direct_play: true,
         supports_external_stream: true,
@@ -901,7 +947,8 @@ pub(crate) fn make_media_source(
         run_time_ticks: runtime_ticks,
         bitrate,
         media_streams,
-        default_audio_stream_index: Some(1),
+        default_audio_stream_index: Some(1 + default_audio_track(metadata) as i32),
+        default_subtitle_stream_index,
         formats: Vec::new(),
         ..Default::default()
     }]
@@ -919,10 +966,15 @@ fn make_jf_media_streams(metadata: &crate::collection::Metadata, bitrate: Option
         "avc" | "x264" | "h264" => ("h264", Some("avc1")),
         "x265" | "h265" | "hevc" => ("hevc", Some("hvc1")),
         "vc1" => ("vc1", Some("wvc1")),
+        "av1" => ("av1", Some("av01")),
+        "vp9" => ("vp9", Some("vp09")),
+        "mpeg4" | "mpeg2video" => (video_codec.as_str(), None),
         _ => ("unknown", Some("unknown")),
     };
+    let video_range_type = metadata.video_range_type.as_deref().unwrap_or("SDR");
+    let video_range = if video_range_type == "SDR" { "SDR" } else { "HDR" };
     let video_title = codec.to_uppercase();
-    let video_display_title = format!("{} - SDR", video_title);
+    let video_display_title = format!("{} - {}", video_title, video_range_type);
 
     let video_stream = MediaStream {
         index: 0,
@@ -938,11 +990,11 @@ fn make_jf_media_streams(metadata: &crate::collection::Metadata, bitrate: Option
         codec: codec.to_string(),
         codec_tag: codec_tag.map(|s| s.to_string()),
         aspect_ratio: Some("2.35:1".to_string()),
-        video_range: Some("SDR".to_string()),
-        video_range_type: Some("SDR".to_string()),
-        profile: Some("High".to_string()),
+        video_range: Some(video_range.to_string()),
+        video_range_type: Some(video_range_type.to_string()),
+        profile: Some(metadata.video_profile.clone().unwrap_or_else(|| "High".to_string())),
         is_anamorphic: Some(false),
-        bit_depth: Some(8),
+        bit_depth: Some(metadata.video_bit_depth.unwrap_or(8)),
         bit_rate: bitrate,
         audio_spatial_format: Some("None".to_string()),
         title: Some(video_title),
@@ -955,8 +1007,46 @@ fn make_jf_media_streams(metadata: &crate::collection::Metadata, bitrate: Option
         ..Default::default()
     };
 
-    // Audio stream
-    let audio_channels = metadata.audio_channels.unwrap_or(2);
+    let mut streams = vec![video_stream];
+
+    // Audio streams follow the video stream, subtitle streams follow the audio streams.
+    let default_audio = default_audio_track(metadata);
+    for (i, track) in audio_tracks(metadata).iter().enumerate() {
+        let index = streams.len() as i32;
+        streams.push(make_jf_audio_stream(index, track, i == default_audio));
+    }
+    for track in &metadata.subtitle_tracks {
+        let index = streams.len() as i32;
+        streams.push(make_jf_subtitle_stream(index, track));
+    }
+
+    streams
+}
+
+/// audio_tracks returns the audio tracks of an item. Metadata without a
+/// track list gets a single track from the plain audio fields.
+fn audio_tracks(metadata: &crate::collection::Metadata) -> Vec<AudioTrack> {
+    if !metadata.audio_tracks.is_empty() {
+        return metadata.audio_tracks.clone();
+    }
+    vec![AudioTrack {
+        codec: metadata.audio_codec.clone(),
+        language: metadata.audio_language.clone(),
+        channels: metadata.audio_channels,
+        bitrate: metadata.audio_bitrate,
+        default: true,
+        ..Default::default()
+    }]
+}
+
+/// default_audio_track returns the position of the default audio track in `audio_tracks`.
+fn default_audio_track(metadata: &crate::collection::Metadata) -> usize {
+    metadata.audio_tracks.iter().position(|a| a.default).unwrap_or(0)
+}
+
+/// make_jf_audio_stream creates the media stream of an audio track.
+fn make_jf_audio_stream(index: i32, track: &AudioTrack, is_default: bool) -> MediaStream {
+    let audio_channels = track.channels.unwrap_or(2);
     let (audio_title, channel_layout) = match audio_channels {
         1 => ("Mono", "mono"),
         2 => ("Stereo", "stereo"),
@@ -968,26 +1058,34 @@ fn make_jf_media_streams(metadata: &crate::collection::Metadata, bitrate: Option
         _ => ("Unknown", "unknown"),
     };
 
-    let audio_codec_str = metadata
-        .audio_codec
-        .as_deref()
-        .unwrap_or("unknown")
-        .to_lowercase();
+    let audio_codec_str = track.codec.as_deref().unwrap_or("unknown").to_lowercase();
     let (a_codec, a_codec_tag) = match audio_codec_str.as_str() {
         "ac3" => ("ac3", Some("ac-3")),
+        "eac3" => ("eac3", Some("ec-3")),
         "aac" => ("aac", Some("mp4a")),
         "wma" => ("wmapro", None),
+        "dts" | "truehd" | "flac" | "opus" | "mp3" | "vorbis" => (audio_codec_str.as_str(), None),
         _ => ("unknown", None),
     };
-    let audio_display_title = format!("{} - {}", audio_title, a_codec.to_uppercase());
 
-    let audio_stream = MediaStream {
-        index: 1,
+    // E.g. "Japanese - AAC - Stereo - Default"
+    let mut display = Vec::new();
+    if let Some(name) = track.title.as_ref().or(track.language.as_ref()) {
+        display.push(name.clone());
+    }
+    display.push(a_codec.to_uppercase());
+    display.push(audio_title.to_string());
+    if is_default {
+        display.push("Default".to_string());
+    }
+
+    MediaStream {
+        index,
         stream_type: "Audio".to_string(),
-        is_default: true,
-        language: metadata.audio_language.clone(),
+        is_default,
+        language: track.language.clone(),
         time_base: Some("1/48000".to_string()),
-        sample_rate: Some(48000),
+        sample_rate: Some(track.sample_rate.unwrap_or(48000)),
         audio_spatial_format: Some("None".to_string()),
         localized_default: Some("Default".to_string()),
         localized_external: Some("External".to_string()),
@@ -995,22 +1093,81 @@ fn make_jf_media_streams(metadata: &crate::collection::Metadata, bitrate: Option
         is_avc: Some(false),
         video_range: Some("Unknown".to_string()),
         video_range_type: Some("Unknown".to_string()),
-        profile: Some("LC".to_string()),
-        bit_rate: metadata.audio_bitrate,
+        profile: Some(track.profile.clone().unwrap_or_else(|| "LC".to_string())),
+        bit_rate: track.bitrate,
         channels: Some(audio_channels),
         channel_layout: Some(channel_layout.to_string()),
         codec: a_codec.to_string(),
         codec_tag: a_codec_tag.map(|s| s.to_string()),
-        title: Some(audio_title.to_string()),
-        display_title: Some(audio_display_title),
+        title: Some(track.title.clone().unwrap_or_else(|| audio_title.to_string())),
+        display_title: Some(display.join(" - ")),
         is_forced: false,
         is_external: false,
         is_text_subtitle_stream: false,
         supports_external_stream: false,
         ..Default::default()
+    }
+}
+
+/// make_jf_subtitle_stream creates the media stream of an embedded subtitle track.
+fn make_jf_subtitle_stream(index: i32, track: &SubtitleTrack) -> MediaStream {
+    let codec = match track.codec.as_deref().map(|c| c.to_lowercase()) {
+        Some(c) if c == "srt" => "subrip".to_string(),
+        Some(c) if c == "pgs" || c == "hdmv_pgs_subtitle" => "pgssub".to_string(),
+        Some(c) if c == "vobsub" || c == "dvd_subtitle" => "dvdsub".to_string(),
+        Some(c) => c,
+        None => "unknown".to_string(),
     };
+    let is_text = matches!(
+        codec.as_str(),
+        "subrip" | "ass" | "ssa" | "webvtt" | "mov_text" | "text"
+    );
 
-    vec![video_stream, audio_stream]
+    // E.g. "English - SUBRIP - Forced"
+    let mut display = Vec::new();
+    if let Some(name) = track.title.as_ref().or(track.language.as_ref()) {
+        display.push(name.clone());
+    }
+    display.push(codec.to_uppercase());
+    if track.forced {
+        display.push("Forced".to_string());
+    }
+    if track.default {
+        display.push("Default".to_string());
+    }
+
+    MediaStream {
+        index,
+        stream_type: "Subtitle".to_string(),
+        codec,
+        language: track.language.clone(),
+        title: track.title.clone(),
+        display_title: Some(display.join(" - ")),
+        is_default: track.default,
+        is_forced: track.forced,
+        is_hearing_impaired: Some(track.hearing_impaired),
+        is_external: false,
+        is_interlaced: false,
+        is_text_subtitle_stream: is_text,
+        // Embedded tracks cannot be fetched separately, only sidecar files.
+        supports_external_stream: false,
+        localized_default: Some("Default".to_string()),
+        localized_external: Some("External".to_string()),
+        ..Default::default()
+    }
+}
+
+/// make_jf_chapters converts the chapters of a video file.
+fn make_jf_chapters(metadata: &crate::collection::Metadata) -> Vec<ChapterInfo> {
+    metadata
+        .chapters
+        .iter()
+        .map(|c| ChapterInfo {
+            start_position_ticks: c.start.as_micros() as i64 * 10,
+            name: c.name.clone(),
+            ..Default::default()
+        })
+        .collect()
 }
 
 /// make_runtime_ticks_from_metadata converts metadata duration to Jellyfin runtime ticks.
@@ -1036,3 +1193,80 @@ fn item_is_hd(metadata: &crate::collection::Metadata) -> bool {
 fn item_is_4k(metadata: &crate::collection::Metadata) -> bool {
     metadata.video_height.map(|h| h >= 1500).unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(codec: &str, default: bool) -> AudioTrack {
+        AudioTrack {
+            codec: Some(codec.to_string()),
+            default,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_media_stream_indexes() {
+        let metadata = Metadata {
+            video_codec: Some("h264".to_string()),
+            audio_tracks: vec![track("aac", false), track("ac3", true)],
+            subtitle_tracks: vec![
+                SubtitleTrack {
+                    codec: Some("srt".to_string()),
+                    forced: true,
+                    ..Default::default()
+                },
+                SubtitleTrack {
+                    codec: Some("hdmv_pgs_subtitle".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let source = &make_media_source("id", "movie.mkv", 0, &metadata)[0];
+
+        // Video first, then the audio streams, then the subtitle streams.
+        let streams: Vec<(i32, &str, &str)> = source
+            .media_streams
+            .iter()
+            .map(|s| (s.index, s.stream_type.as_str(), s.codec.as_str()))
+            .collect();
+        assert_eq!(
+            streams,
+            vec![
+                (0, "Video", "h264"),
+                (1, "Audio", "aac"),
+                (2, "Audio", "ac3"),
+                (3, "Subtitle", "subrip"),
+                (4, "Subtitle", "pgssub"),
+            ]
+        );
+        assert_eq!(source.default_audio_stream_index, Some(2));
+        assert_eq!(source.default_subtitle_stream_index, Some(3));
+
+        let subs = &source.media_streams[3..];
+        assert!(subs[0].is_text_subtitle_stream);
+        assert!(!subs[1].is_text_subtitle_stream);
+        assert!(subs.iter().all(|s| !s.supports_external_stream && !s.is_external));
+    }
+
+    #[test]
+    fn test_media_stream_indexes_without_tracks() {
+        let metadata = Metadata {
+            audio_codec: Some("aac".to_string()),
+            ..Default::default()
+        };
+        let source = &make_media_source("id", "movie.mp4", 0, &metadata)[0];
+
+        let types: Vec<&str> = source
+            .media_streams
+            .iter()
+            .map(|s| s.stream_type.as_str())
+            .collect();
+        assert_eq!(types, vec!["Video", "Audio"]);
+        assert_eq!(source.media_streams[1].index, 1);
+        assert_eq!(source.default_audio_stream_index, Some(1));
+        assert_eq!(source.default_subtitle_stream_index, None);
+    }
+}
@@ -0,0 +1,249 @@
+use axum::http::HeaderMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::database::{LoginAttempts, Repository, User};
+use crate::server::{client_ip, Config};
+
+/// LoginLockout protects the login endpoints against password guessing.
+///
+/// Failed logins are counted per user and per remote address. A user that
+/// reaches its limit is disabled until an admin enables it again, a remote
+/// address is blocked until it has not failed a login for a while. Admins
+/// are only disabled if their own policy sets a limit, so that anyone who
+/// knows their name cannot lock them out.
+#[derive(Clone)]
+pub struct LoginLockout {
+    /// Default number of failed logins before an account is disabled, 0 for never.
+    user_attempts: u32,
+    /// Number of failed logins before a remote address is blocked, 0 for never.
+    ip_attempts: u32,
+    /// How long a remote address stays blocked after its last failed login.
+    ip_lockout: Duration,
+    /// Number of reverse proxies that append to X-Forwarded-For.
+    trusted_proxy_hops: usize,
+}
+
+impl LoginLockout {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            user_attempts: config.login_attempts_before_lockout(),
+            ip_attempts: config.ip_login_attempts(),
+            ip_lockout: config.ip_lockout(),
+            trusted_proxy_hops: config.trusted_proxy_hops(),
+        }
+    }
+
+    /// Remote address of the client as reported by the reverse proxy.
+    pub fn remote_address(&self, headers: &HeaderMap) -> Option<String> {
+        client_ip(headers, self.trusted_proxy_hops).map(|ip| ip.to_string())
+    }
+
+    /// Check if a remote address is blocked because of too many failed logins.
+    pub async fn is_blocked(&self, repo: &Arc<dyn Repository>, remote_address: Option<&str>) -> bool {
+        let Some(remote_address) = remote_address else {
+            return false;
+        };
+        if self.ip_attempts == 0 {
+            return false;
+        }
+        match repo.get_login_attempts(remote_address).await {
+            Ok(attempts) => attempts.failed >= self.ip_attempts as i32 && !self.expired(&attempts),
+            Err(_) => false,
+        }
+    }
+
+    /// Record a failed login. If the user is known its counter goes up and
+    /// the account is disabled once it reaches the limit.
+    pub async fn login_failed(
+        &self,
+        repo: &Arc<dyn Repository>,
+        remote_address: Option<&str>,
+        user: Option<User>,
+    ) {
+        if let Some(remote_address) = remote_address {
+            let mut attempts = match repo.get_login_attempts(remote_address).await {
+                Ok(attempts) if !self.expired(&attempts) => attempts,
+                _ => LoginAttempts {
+                    remote_address: remote_address.to_string(),
+                    failed: 0,
+                    last_failed: chrono::Utc::now(),
+                },
+            };
+            attempts.failed += 1;
+            attempts.last_failed = chrono::Utc::now();
+            if attempts.failed == self.ip_attempts as i32 {
+                warn!(
+                    "Blocking logins from {} after {} failed attempts",
+                    remote_address, attempts.failed
+                );
+            }
+            let _ = repo.upsert_login_attempts(&attempts).await;
+        }
+
+        if let Some(mut user) = user {
+            user.properties.invalid_login_attempts += 1;
+            if let Some(limit) = self.user_limit(&user) {
+                if user.properties.invalid_login_attempts >= limit && !user.properties.disabled {
+                    warn!(
+                        "Disabling user {} after {} failed logins",
+                        user.username, user.properties.invalid_login_attempts
+                    );
+                    user.properties.disabled = true;
+                }
+            }
+            let _ = repo.upsert_user(&user).await;
+        }
+    }
+
+    /// Forget the failed logins after a successful login. The caller stores the user.
+    pub async fn login_succeeded(
+        &self,
+        repo: &Arc<dyn Repository>,
+        remote_address: Option<&str>,
+        user: &mut User,
+    ) {
+        user.properties.invalid_login_attempts = 0;
+        if let Some(remote_address) = remote_address {
+            let _ = repo.delete_login_attempts(remote_address).await;
+        }
+    }
+
+    /// Number of failed logins after which a user is disabled, None if never.
+    fn user_limit(&self, user: &User) -> Option<i32> {
+        match user.properties.login_attempts_before_lockout {
+            0 if self.user_attempts > 0 && !user.properties.admin => Some(self.user_attempts as i32),
+            limit if limit > 0 => Some(limit),
+            _ => None,
+        }
+    }
+
+    fn expired(&self, attempts: &LoginAttempts) -> bool {
+        let age = chrono::Utc::now() - attempts.last_failed;
+        age.to_std().map(|age| age > self.ip_lockout).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::SqliteRepository;
+
+    fn lockout() -> LoginLockout {
+        LoginLockout {
+            user_attempts: 3,
+            ip_attempts: 2,
+            ip_lockout: Duration::from_secs(15 * 60),
+            trusted_proxy_hops: 1,
+        }
+    }
+
+    fn user(name: &str, admin: bool) -> User {
+        let mut user = User {
+            id: name.to_string(),
+            username: name.to_string(),
+            password: String::new(),
+            created: chrono::Utc::now(),
+            last_login: chrono::Utc::now(),
+            last_used: chrono::Utc::now(),
+            properties: Default::default(),
+        };
+        user.properties.admin = admin;
+        user
+    }
+
+    async fn repo() -> Arc<dyn Repository> {
+        Arc::new(SqliteRepository::new("sqlite::memory:").await.unwrap())
+    }
+
+    #[test]
+    fn test_user_limit() {
+        let lockout = lockout();
+        let mut u = user("alice", false);
+        assert_eq!(lockout.user_limit(&u), Some(3));
+        u.properties.login_attempts_before_lockout = 5;
+        assert_eq!(lockout.user_limit(&u), Some(5));
+        u.properties.login_attempts_before_lockout = -1;
+        assert_eq!(lockout.user_limit(&u), None);
+
+        // Admins only have the limit their policy sets.
+        let mut admin = user("admin", true);
+        assert_eq!(lockout.user_limit(&admin), None);
+        admin.properties.login_attempts_before_lockout = 10;
+        assert_eq!(lockout.user_limit(&admin), Some(10));
+
+        let disabled = LoginLockout {
+            user_attempts: 0,
+            ..lockout
+        };
+        assert_eq!(disabled.user_limit(&user("bob", false)), None);
+    }
+
+    #[test]
+    fn test_expired() {
+        let lockout = lockout();
+        let mut attempts = LoginAttempts {
+            remote_address: "10.0.0.1".to_string(),
+            failed: 5,
+            last_failed: chrono::Utc::now(),
+        };
+        assert!(!lockout.expired(&attempts));
+        attempts.last_failed = chrono::Utc::now() - chrono::Duration::minutes(16);
+        assert!(lockout.expired(&attempts));
+    }
+
+    #[test]
+    fn test_remote_address() {
+        let lockout = lockout();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "10.0.0.9".parse().unwrap());
+        assert_eq!(lockout.remote_address(&headers).as_deref(), Some("10.0.0.9"));
+
+        // The client controls everything left of what the proxy appended.
+        headers.insert("x-forwarded-for", "1.2.3.4, 10.0.0.1".parse().unwrap());
+        assert_eq!(lockout.remote_address(&headers).as_deref(), Some("10.0.0.1"));
+
+        let two_hops = LoginLockout {
+            trusted_proxy_hops: 2,
+            ..lockout
+        };
+        assert_eq!(two_hops.remote_address(&headers).as_deref(), Some("1.2.3.4"));
+    }
+
+    #[tokio::test]
+    async fn test_login_failed() {
+        let lockout = lockout();
+        let repo = repo().await;
+        let addr = Some("10.0.0.1");
+
+        repo.upsert_user(&user("alice", false)).await.unwrap();
+        for _ in 0..3 {
+            let u = repo.get_user("alice").await.unwrap();
+            assert!(!u.properties.disabled);
+            lockout.login_failed(&repo, None, Some(u)).await;
+        }
+        let u = repo.get_user("alice").await.unwrap();
+        assert_eq!(u.properties.invalid_login_attempts, 3);
+        assert!(u.properties.disabled);
+
+        repo.upsert_user(&user("admin", true)).await.unwrap();
+        for _ in 0..10 {
+            let u = repo.get_user("admin").await.unwrap();
+            lockout.login_failed(&repo, None, Some(u)).await;
+        }
+        assert!(!repo.get_user("admin").await.unwrap().properties.disabled);
+
+        assert!(!lockout.is_blocked(&repo, addr).await);
+        lockout.login_failed(&repo, addr, None).await;
+        assert!(!lockout.is_blocked(&repo, addr).await);
+        lockout.login_failed(&repo, addr, None).await;
+        assert!(lockout.is_blocked(&repo, addr).await);
+        assert!(!lockout.is_blocked(&repo, Some("10.0.0.2")).await);
+
+        let mut u = repo.get_user("admin").await.unwrap();
+        lockout.login_succeeded(&repo, addr, &mut u).await;
+        assert_eq!(u.properties.invalid_login_attempts, 0);
+        assert!(repo.get_login_attempts("10.0.0.1").await.is_err());
+    }
+}
@@ -1,5 +1,9 @@
+pub mod access;
+pub use access::*;
 pub mod auth;
 pub use auth::*;
+pub mod deviceprofile;
+pub use deviceprofile::*;
 pub mod error;
 pub use error::*;
 pub mod jellyfin;
@@ -10,6 +14,10 @@ pub mod identicon;
 pub use identicon::*;
 pub mod item;
 pub use item::*;
+pub mod lockout;
+pub use lockout::*;
+pub mod sessions;
+pub use sessions::*;
 
 // Re-export parent's types module so moved files' `super::types::*` keeps working
 pub use super::types;
@@ -0,0 +1,314 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::types::{ClientCapabilitiesDto, UpdatePlayStateRequest};
+use crate::database::AccessToken;
+use crate::idhash::hash_bytes;
+
+/// Playback without a progress report for this long is considered stopped.
+const PLAYBACK_IDLE_TIMEOUT_MINUTES: i64 = 5;
+
+/// Session is the state of a client device, as seen by the server.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Session ID, derived from the device ID so it is stable across restarts.
+    pub id: String,
+    pub user_id: String,
+    pub device_id: String,
+    pub device_name: String,
+    pub client: String,
+    pub application_version: String,
+    pub remote_address: String,
+    pub last_activity: DateTime<Utc>,
+    /// Capabilities as reported by the client.
+    pub playable_media_types: Vec<String>,
+    pub supported_commands: Vec<String>,
+    pub supports_media_control: bool,
+    /// Item currently being played, if any.
+    pub now_playing: Option<NowPlaying>,
+}
+
+/// NowPlaying holds the playback state reported by a client.
+#[derive(Debug, Clone)]
+pub struct NowPlaying {
+    pub item_id: String,
+    pub media_source_id: Option<String>,
+    pub play_session_id: Option<String>,
+    pub position_ticks: i64,
+    pub can_seek: bool,
+    pub is_paused: bool,
+    pub is_muted: bool,
+    pub play_method: String,
+    pub repeat_mode: String,
+    pub now_playing_queue: Vec<serde_json::Value>,
+    pub last_update: DateTime<Utc>,
+}
+
+/// SessionRegistry keeps track of active client sessions, keyed by device ID.
+/// It is fed by the auth middleware and the playstate handlers and lives in memory only.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record activity of an authenticated client, creating its session if needed.
+    pub fn touch(&self, token: &AccessToken) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .entry(token.device_id.clone())
+            .or_insert_with(|| Session {
+                id: session_id(&token.device_id),
+                user_id: token.user_id.clone(),
+                device_id: token.device_id.clone(),
+                device_name: String::new(),
+                client: String::new(),
+                application_version: String::new(),
+                remote_address: String::new(),
+                last_activity: Utc::now(),
+                playable_media_types: Vec::new(),
+                supported_commands: Vec::new(),
+                supports_media_control: false,
+                now_playing: None,
+            });
+
+        // A different user logged in on this device.
+        if session.user_id != token.user_id {
+            session.user_id = token.user_id.clone();
+            session.now_playing = None;
+        }
+        session.device_name = token.device_name.clone();
+        session.client = token.application_name.clone();
+        session.application_version = token.application_version.clone();
+        session.remote_address = token.remote_address.clone();
+        session.last_activity = Utc::now();
+    }
+
+    /// Store the capabilities a client reported.
+    pub fn set_capabilities(&self, token: &AccessToken, capabilities: &ClientCapabilitiesDto) {
+        self.touch(token);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&token.device_id) {
+            session.playable_media_types = capabilities.playable_media_types.clone();
+            session.supported_commands = capabilities.supported_commands.clone();
+            session.supports_media_control = capabilities.supports_media_control;
+        }
+    }
+
+    /// Update the playback state of a device from a playing or progress report.
+    pub fn update_playing(&self, token: &AccessToken, req: &UpdatePlayStateRequest) {
+        if req.item_id.is_empty() {
+            return;
+        }
+        self.touch(token);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&token.device_id) else {
+            return;
+        };
+        session.now_playing = Some(NowPlaying {
+            item_id: req.item_id.clone(),
+            media_source_id: req.media_source_id.clone(),
+            play_session_id: req.play_session_id.clone(),
+            position_ticks: req.position_ticks,
+            can_seek: req.can_seek,
+            is_paused: req.is_paused,
+            is_muted: req.is_muted,
+            play_method: req.play_method.clone().unwrap_or_else(|| "DirectPlay".to_string()),
+            repeat_mode: if req.repeat_mode.is_empty() {
+                "RepeatNone".to_string()
+            } else {
+                req.repeat_mode.clone()
+            },
+            now_playing_queue: req.now_playing_queue.clone(),
+            last_update: Utc::now(),
+        });
+    }
+
+    /// Keep paused playback alive, clients ping while not sending progress.
+    pub fn ping(&self, token: &AccessToken) {
+        self.touch(token);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(np) = sessions
+            .get_mut(&token.device_id)
+            .and_then(|s| s.now_playing.as_mut())
+        {
+            np.last_update = Utc::now();
+        }
+    }
+
+    /// Clear the playback state of a device, returning what was playing.
+    pub fn stop_playing(&self, token: &AccessToken) -> Option<NowPlaying> {
+        self.touch(token);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.get_mut(&token.device_id)?.now_playing.take()
+    }
+
+    /// Get session by device ID.
+    pub fn get_by_device_id(&self, device_id: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(device_id).map(expire_playback)
+    }
+
+    /// Get session by session ID.
+    pub fn get_by_id(&self, session_id: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.values().find(|s| s.id == session_id).map(expire_playback)
+    }
+
+    /// Get all sessions, most recently active first.
+    pub fn list(&self) -> Vec<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        let mut list: Vec<Session> = sessions.values().map(expire_playback).collect();
+        list.sort_by_key(|s| std::cmp::Reverse(s.last_activity));
+        list
+    }
+}
+
+/// Return a copy of the session, without playback state if the client
+/// stopped reporting progress (e.g. it crashed or lost its connection).
+fn expire_playback(session: &Session) -> Session {
+    let mut session = session.clone();
+    let idle_limit = Utc::now() - Duration::minutes(PLAYBACK_IDLE_TIMEOUT_MINUTES);
+    if session
+        .now_playing
+        .as_ref()
+        .is_some_and(|np| np.last_update < idle_limit)
+    {
+        session.now_playing = None;
+    }
+    session
+}
+
+/// Session ID for a device.
+pub(crate) fn session_id(device_id: &str) -> String {
+    hash_bytes(device_id.as_bytes())[..32].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(user_id: &str, device_id: &str) -> AccessToken {
+        AccessToken {
+            user_id: user_id.to_string(),
+            token: format!("{}-token", device_id),
+            device_id: device_id.to_string(),
+            device_name: "Phone".to_string(),
+            application_name: "App".to_string(),
+            application_version: "1.0".to_string(),
+            remote_address: "10.0.0.1".to_string(),
+            created: Utc::now(),
+            last_used: Utc::now(),
+        }
+    }
+
+    fn playing(item_id: &str, position_ticks: i64) -> UpdatePlayStateRequest {
+        UpdatePlayStateRequest {
+            item_id: item_id.to_string(),
+            position_ticks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_touch() {
+        let registry = SessionRegistry::new();
+        registry.touch(&token("alice", "d1"));
+        registry.touch(&token("bob", "d2"));
+
+        let session = registry.get_by_device_id("d1").unwrap();
+        assert_eq!(session.id, session_id("d1"));
+        assert_eq!(session.user_id, "alice");
+        assert_eq!(session.client, "App");
+        assert_eq!(registry.get_by_id(&session.id).unwrap().device_id, "d1");
+        assert!(registry.get_by_device_id("d3").is_none());
+
+        // Most recently active first, one session per device.
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        registry.touch(&token("alice", "d1"));
+        let devices: Vec<String> = registry.list().into_iter().map(|s| s.device_id).collect();
+        assert_eq!(devices, vec!["d1", "d2"]);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let registry = SessionRegistry::new();
+        let capabilities = ClientCapabilitiesDto {
+            playable_media_types: vec!["Video".to_string()],
+            supported_commands: vec!["DisplayMessage".to_string()],
+            supports_media_control: true,
+            ..Default::default()
+        };
+        registry.set_capabilities(&token("alice", "d1"), &capabilities);
+
+        let session = registry.get_by_device_id("d1").unwrap();
+        assert_eq!(session.playable_media_types, vec!["Video"]);
+        assert_eq!(session.supported_commands, vec!["DisplayMessage"]);
+        assert!(session.supports_media_control);
+    }
+
+    #[test]
+    fn test_now_playing() {
+        let registry = SessionRegistry::new();
+        let alice = token("alice", "d1");
+
+        // Reports without an item are ignored.
+        registry.update_playing(&alice, &playing("", 0));
+        assert!(registry.get_by_device_id("d1").is_none());
+
+        registry.update_playing(&alice, &playing("m1", 10));
+        let np = registry.get_by_device_id("d1").unwrap().now_playing.unwrap();
+        assert_eq!(np.item_id, "m1");
+        assert_eq!(np.play_method, "DirectPlay");
+        assert_eq!(np.repeat_mode, "RepeatNone");
+
+        registry.update_playing(&alice, &playing("m1", 20));
+        let np = registry.get_by_device_id("d1").unwrap().now_playing.unwrap();
+        assert_eq!(np.position_ticks, 20);
+
+        // Another user on the same device does not inherit the playback.
+        registry.touch(&token("bob", "d1"));
+        let session = registry.get_by_device_id("d1").unwrap();
+        assert_eq!(session.user_id, "bob");
+        assert!(session.now_playing.is_none());
+
+        let bob = token("bob", "d1");
+        registry.update_playing(&bob, &playing("m2", 0));
+        assert_eq!(registry.stop_playing(&bob).unwrap().item_id, "m2");
+        assert!(registry.stop_playing(&bob).is_none());
+    }
+
+    #[test]
+    fn test_playback_expiry() {
+        let registry = SessionRegistry::new();
+        let alice = token("alice", "d1");
+        registry.update_playing(&alice, &playing("m1", 10));
+
+        let idle = Utc::now() - Duration::minutes(PLAYBACK_IDLE_TIMEOUT_MINUTES + 1);
+        let set_last_update = |time| {
+            let mut sessions = registry.sessions.lock().unwrap();
+            let session = sessions.get_mut("d1").unwrap();
+            session.now_playing.as_mut().unwrap().last_update = time;
+        };
+
+        // A ping keeps paused playback alive.
+        set_last_update(idle);
+        registry.ping(&alice);
+        assert!(registry.get_by_device_id("d1").unwrap().now_playing.is_some());
+
+        set_last_update(idle);
+        assert!(registry.get_by_device_id("d1").unwrap().now_playing.is_none());
+        assert!(registry.list()[0].now_playing.is_none());
+        // The session itself stays.
+        assert_eq!(registry.list().len(), 1);
+    }
+}
@@ -1,17 +1,27 @@
 use axum::{
-    extract::{Path, Request, State},
-    http::StatusCode,
+    extract::{Path, Query, RawQuery, Request, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
+    Extension,
 };
+use serde::Deserialize;
 use std::path::PathBuf;
 use tower::ServiceExt;
 use tower_http::services::ServeFile;
 use tracing::warn;
 
-use crate::jellyfin::JellyfinState;
+use crate::collection::Item;
+use crate::database::model::AccessToken;
+use crate::jellyfin::{AccessFilter, JellyfinState};
+use crate::transcode::TranscodeOptions;
+
+/// Default bandwidth advertised in the master playlist if the client
+/// did not ask for a specific bitrate.
+const DEFAULT_BANDWIDTH: u32 = 8_000_000;
 
 /// Handlers for /Videos/{item}/stream and related routes
 pub async fn video_stream_handler(
+    Extension(token): Extension<AccessToken>,
     State(state): State<JellyfinState>,
     Path(params): Path<std::collections::HashMap<String, String>>,
     req: Request,
@@ -21,49 +31,195 @@ pub async fn video_stream_handler(
         None => return StatusCode::BAD_REQUEST.into_response(),
     };
 
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let (full_path, _) = match video_file(&access, item_id) {
+        Ok(res) => res,
+        Err(status) => return status.into_response(),
+    };
+
+    // Serve file using tower-http ServeFile which handles Range requests etc.
+    match ServeFile::new(full_path).oneshot(req).await {
+        Ok(res) => res.into_response(),
+        Err(e) => {
+            warn!("Failed to serve video file: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// video_file returns the path of the video file of an item the user may access.
+fn video_file(access: &AccessFilter, item_id: &str) -> Result<(PathBuf, Item), StatusCode> {
     // Remove any prefix from item_id if present (compatibility with Go behavior)
     let clean_id = item_id.trim_start_matches("item_"); // Example prefix, adjust if needed based on Go's trimPrefix
 
     // Look up item
-    let (collection, item) = match state.collections.get_item_by_id(clean_id) {
-        Some(res) => res,
-        None => return StatusCode::NOT_FOUND.into_response(),
-    };
-
-    let filename = match &item {
-        crate::collection::Item::Movie(m) => &m.file_name,
-        crate::collection::Item::Show(s) => &s.file_name,
-        crate::collection::Item::Episode(e) => &e.file_name,
-        _ => return StatusCode::NOT_FOUND.into_response(),
-    };
+    let (collection, item) = access.get_item_by_id(clean_id).ok_or(StatusCode::NOT_FOUND)?;
 
-    let path_str = match &item {
-        crate::collection::Item::Movie(m) => &m.path,
-        crate::collection::Item::Show(s) => &s.path,
-        crate::collection::Item::Episode(e) => &e.path,
-        _ => return StatusCode::NOT_FOUND.into_response(),
+    let (path_str, filename) = match &item {
+        Item::Movie(m) => (&m.path, &m.file_name),
+        Item::Show(s) => (&s.path, &s.file_name),
+        Item::Episode(e) => (&e.path, &e.file_name),
+        _ => return Err(StatusCode::NOT_FOUND),
     };
 
     if filename.is_empty() {
-        return StatusCode::NOT_FOUND.into_response();
+        return Err(StatusCode::NOT_FOUND);
     }
 
-    // Construct full path
-    let mut full_path = PathBuf::from(&collection.directory);
-    full_path.push(path_str);
-    full_path.push(filename);
+    // Construct full path, the file can be in any of the collection directories
+    let full_path = collection.resolve(PathBuf::from(path_str).join(filename));
 
     if !full_path.exists() {
         warn!("Video file not found: {:?}", full_path);
-        return StatusCode::NOT_FOUND.into_response();
+        return Err(StatusCode::NOT_FOUND);
     }
 
-    // Serve file using tower-http ServeFile which handles Range requests etc.
-    match ServeFile::new(full_path).oneshot(req).await {
-        Ok(res) => res.into_response(),
+    Ok((full_path, item))
+}
+
+/// Query parameters of the HLS transcoding endpoints.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HlsQuery {
+    #[serde(rename = "playSessionId")]
+    pub play_session_id: Option<String>,
+    #[serde(rename = "videoBitrate")]
+    pub video_bitrate: Option<u32>,
+    #[serde(rename = "audioBitrate")]
+    pub audio_bitrate: Option<u32>,
+    #[serde(rename = "maxWidth")]
+    pub max_width: Option<u32>,
+    #[serde(rename = "maxHeight")]
+    pub max_height: Option<u32>,
+    #[serde(rename = "transcodingMaxAudioChannels")]
+    pub transcoding_max_audio_channels: Option<u32>,
+    #[serde(rename = "maxAudioChannels")]
+    pub max_audio_channels: Option<u32>,
+    #[serde(rename = "audioStreamIndex")]
+    pub audio_stream_index: Option<usize>,
+}
+
+impl HlsQuery {
+    fn options(&self) -> TranscodeOptions {
+        TranscodeOptions {
+            video_bitrate: self.video_bitrate,
+            audio_bitrate: self.audio_bitrate,
+            max_width: self.max_width,
+            max_height: self.max_height,
+            audio_channels: self.transcoding_max_audio_channels.or(self.max_audio_channels),
+            // Stream 0 is the video stream, audio streams follow.
+            audio_track: self.audio_stream_index.map(|i| i.saturating_sub(1)),
+        }
+    }
+}
+
+fn playlist_response(playlist: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")], playlist).into_response()
+}
+
+/// GET /Videos/{item}/master.m3u8 - HLS master playlist with a single variant
+pub async fn video_master_playlist(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(item_id): Path<String>,
+    Query(query): Query<HlsQuery>,
+    RawQuery(raw_query): RawQuery,
+) -> Response {
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    if let Err(status) = video_file(&access, &item_id) {
+        return status.into_response();
+    }
+
+    let bandwidth = query.video_bitrate.unwrap_or(DEFAULT_BANDWIDTH) + query.audio_bitrate.unwrap_or(0);
+    let mut playlist = String::from("#EXTM3U\n");
+    playlist.push_str(&format!("#EXT-X-STREAM-INF:BANDWIDTH={}\n", bandwidth));
+    match raw_query {
+        Some(q) if !q.is_empty() => playlist.push_str(&format!("main.m3u8?{}\n", q)),
+        _ => playlist.push_str("main.m3u8\n"),
+    }
+    playlist_response(playlist)
+}
+
+/// GET /Videos/{item}/main.m3u8 - HLS media playlist listing all segments
+pub async fn video_main_playlist(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path(item_id): Path<String>,
+    RawQuery(raw_query): RawQuery,
+) -> Response {
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let (_, item) = match video_file(&access, &item_id) {
+        Ok(res) => res,
+        Err(status) => return status.into_response(),
+    };
+    let Some(duration) = item.duration() else {
+        warn!("Cannot transcode item {} without duration", item_id);
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let playlist = state
+        .transcoder
+        .media_playlist(duration, raw_query.as_deref().unwrap_or_default());
+    playlist_response(playlist)
+}
+
+/// GET /Videos/{item}/hls1/{playlist}/{segment} - Transcoded HLS segment
+pub async fn video_hls_segment(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Path((item_id, _playlist, segment)): Path<(String, String, String)>,
+    Query(query): Query<HlsQuery>,
+) -> Response {
+    let Some(segment) = segment
+        .strip_suffix(".ts")
+        .and_then(|n| n.parse::<u32>().ok())
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let access = AccessFilter::for_user(&state, &token.user_id).await;
+    let (input, _) = match video_file(&access, &item_id) {
+        Ok(res) => res,
+        Err(status) => return status.into_response(),
+    };
+
+    // Without a play session, a device gets one job at a time.
+    let job_id = query
+        .play_session_id
+        .clone()
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| token.device_id.clone());
+
+    let path = match state
+        .transcoder
+        .segment(&job_id, &token.device_id, &item_id, &input, &query.options(), segment)
+        .await
+    {
+        Ok(path) => path,
         Err(e) => {
-            warn!("Failed to serve video file: {}", e);
+            warn!("Failed to transcode segment {} of {}: {}", segment, item_id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(data) => ([(header::CONTENT_TYPE, "video/mp2t")], data).into_response(),
+        Err(e) => {
+            warn!("Failed to read segment {:?}: {}", path, e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
+
+/// DELETE /Videos/ActiveEncodings - Stop transcoding jobs of a device
+pub async fn video_active_encodings_delete(
+    Extension(token): Extension<AccessToken>,
+    State(state): State<JellyfinState>,
+    Query(query): Query<HlsQuery>,
+) -> StatusCode {
+    match query.play_session_id.filter(|id| !id.is_empty()) {
+        Some(play_session_id) => state.transcoder.stop(&play_session_id).await,
+        None => state.transcoder.stop_device(&token.device_id).await,
+    }
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,306 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::Response,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, info, warn};
+
+use super::access::AccessFilter;
+use super::auth::JellyfinAuthState;
+use super::jfitem::make_jf_userdata;
+use super::syncplay::SyncPlayManager;
+use crate::collection::{CollectionRepo, LibraryChange};
+use crate::database::{Repository, UserData as DbUserData};
+
+/// Seconds between KeepAlive messages we ask clients for in ForceKeepAlive.
+/// Clients that stay silent for twice this long are disconnected.
+const KEEPALIVE_INTERVAL: u64 = 60;
+
+struct Connection {
+    user_id: String,
+    device_id: String,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+/// WebSocketHub keeps track of connected WebSocket clients and pushes
+/// server messages to them.
+#[derive(Default)]
+pub struct WebSocketHub {
+    connections: Mutex<HashMap<u64, Connection>>,
+    next_id: AtomicU64,
+}
+
+impl WebSocketHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn register(&self, user_id: &str, device_id: &str) -> (u64, mpsc::UnboundedReceiver<String>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.connections.lock().unwrap().insert(
+            id,
+            Connection {
+                user_id: user_id.to_string(),
+                device_id: device_id.to_string(),
+                tx,
+            },
+        );
+        (id, rx)
+    }
+
+    pub(super) fn unregister(&self, id: u64) {
+        self.connections.lock().unwrap().remove(&id);
+    }
+
+    /// Send a message to all connections of a user.
+    pub fn send_to_user(&self, user_id: &str, message_type: &str, data: Value) {
+        let message = make_message(message_type, Some(data));
+        for conn in self.connections.lock().unwrap().values() {
+            if conn.user_id == user_id {
+                let _ = conn.tx.send(message.clone());
+            }
+        }
+    }
+
+    /// Send a message to all connected clients.
+    pub fn broadcast(&self, message_type: &str, data: Value) {
+        let message = make_message(message_type, Some(data));
+        for conn in self.connections.lock().unwrap().values() {
+            let _ = conn.tx.send(message.clone());
+        }
+    }
+
+    /// Send a message to all connections of a device.
+    /// Returns false if the device has no WebSocket connection.
+    pub fn send_to_device(&self, device_id: &str, message_type: &str, data: Value) -> bool {
+        let message = make_message(message_type, Some(data));
+        let mut sent = false;
+        for conn in self.connections.lock().unwrap().values() {
+            if conn.device_id == device_id && conn.tx.send(message.clone()).is_ok() {
+                sent = true;
+            }
+        }
+        sent
+    }
+
+    /// Returns true if the device has a WebSocket connection.
+    pub fn is_connected(&self, device_id: &str) -> bool {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .any(|conn| conn.device_id == device_id)
+    }
+
+    /// Push UserDataChanged to all clients of the user after an item's
+    /// played state, position or favorite flag changed.
+    pub fn user_data_changed(&self, user_id: &str, item_id: &str, data: &DbUserData) {
+        let mut user_data = make_jf_userdata(user_id, item_id, Some(data));
+        user_data.item_id = item_id.to_string();
+        self.send_to_user(
+            user_id,
+            "UserDataChanged",
+            json!({
+                "UserId": user_id,
+                "UserDataList": [user_data],
+            }),
+        );
+    }
+
+    /// Push LibraryChanged to the clients of each connected user after a
+    /// rescan added or removed items, limited to what that user may access.
+    pub async fn library_changed(
+        &self,
+        repo: &dyn Repository,
+        collections: &Arc<CollectionRepo>,
+        change: &LibraryChange,
+    ) {
+        let user_ids: HashSet<String> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|conn| conn.user_id.clone())
+            .collect();
+        for user_id in user_ids {
+            let access = match repo.get_user_by_id(&user_id).await {
+                Ok(user) => AccessFilter::new(collections.clone(), &user.properties),
+                Err(_) => continue,
+            };
+            if let Some(change) = access.library_change(change) {
+                self.send_to_user(&user_id, "LibraryChanged", library_changed_data(&change));
+            }
+        }
+    }
+
+    /// Forward library changes from collection rescans to connected clients.
+    pub fn forward_library_changes(
+        self: &Arc<Self>,
+        repo: Arc<dyn Repository>,
+        collections: Arc<CollectionRepo>,
+    ) {
+        let hub = Arc::clone(self);
+        let mut changes = collections.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => hub.library_changed(repo.as_ref(), &collections, &change).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("WebSocket: dropped {} library change notifications", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+fn library_changed_data(change: &LibraryChange) -> Value {
+    let folders_added_to: Vec<&str> = if change.items_added.is_empty() {
+        Vec::new()
+    } else {
+        vec![change.collection_id.as_str()]
+    };
+    let folders_removed_from: Vec<&str> = if change.items_removed.is_empty() {
+        Vec::new()
+    } else {
+        vec![change.collection_id.as_str()]
+    };
+    json!({
+        "FoldersAddedTo": folders_added_to,
+        "FoldersRemovedFrom": folders_removed_from,
+        "ItemsAdded": change.items_added,
+        "ItemsRemoved": change.items_removed,
+        "ItemsUpdated": [],
+        "CollectionFolders": [change.collection_id],
+        "IsEmpty": false,
+    })
+}
+
+fn make_message(message_type: &str, data: Option<Value>) -> String {
+    let mut message = json!({
+        "MessageType": message_type,
+        "MessageId": uuid::Uuid::new_v4().simple().to_string(),
+    });
+    if let Some(data) = data {
+        message["Data"] = data;
+    }
+    message.to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SocketQuery {
+    #[serde(default, alias = "apiKey", alias = "ApiKey")]
+    pub api_key: Option<String>,
+    #[serde(default, rename = "deviceId", alias = "DeviceId")]
+    pub device_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InboundMessage {
+    message_type: String,
+}
+
+/// GET /socket - Jellyfin WebSocket for server to client notifications
+pub async fn socket_handler(
+    State(state): State<JellyfinAuthState>,
+    Query(query): Query<SocketQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let token = query
+        .api_key
+        .filter(|t| !t.is_empty())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let access_token = state
+        .repo
+        .get_access_token(&token)
+        .await
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    state.sessions.touch(&access_token);
+
+    let device_id = query
+        .device_id
+        .filter(|d| !d.is_empty())
+        .unwrap_or(access_token.device_id);
+    let user_id = access_token.user_id;
+    let hub = state.websocket.clone();
+    let syncplay = state.syncplay.clone();
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, hub, syncplay, user_id, device_id)))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    hub: Arc<WebSocketHub>,
+    syncplay: Arc<SyncPlayManager>,
+    user_id: String,
+    device_id: String,
+) {
+    let (id, mut outbound) = hub.register(&user_id, &device_id);
+    let (mut sender, mut receiver) = socket.split();
+    info!("WebSocket connected: user {}, device {}", user_id, device_id);
+
+    // Tell the client how often it has to send KeepAlive.
+    let force_keepalive = make_message("ForceKeepAlive", Some(json!(KEEPALIVE_INTERVAL)));
+    if sender.send(Message::Text(force_keepalive.into())).await.is_ok() {
+        let timeout = Duration::from_secs(2 * KEEPALIVE_INTERVAL);
+        let mut last_seen = Instant::now();
+        let mut check = tokio::time::interval(Duration::from_secs(10));
+
+        loop {
+            tokio::select! {
+                msg = receiver.next() => {
+                    let text = match msg {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {
+                            last_seen = Instant::now();
+                            continue;
+                        }
+                    };
+                    last_seen = Instant::now();
+                    match serde_json::from_str::<InboundMessage>(&text) {
+                        Ok(msg) if msg.message_type == "KeepAlive" => {
+                            let reply = make_message("KeepAlive", None);
+                            if sender.send(Message::Text(reply.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(msg) => debug!("WebSocket: ignoring message {}", msg.message_type),
+                        Err(e) => debug!("WebSocket: invalid message: {}", e),
+                    }
+                }
+                message = outbound.recv() => {
+                    let Some(message) = message else { break };
+                    if sender.send(Message::Text(message.into())).await.is_err() {
+                        break;
+                    }
+                }
+                _ = check.tick() => {
+                    if last_seen.elapsed() > timeout {
+                        debug!("WebSocket: keepalive timeout for device {}", device_id);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    hub.unregister(id);
+    syncplay.device_disconnected(&hub, &device_id);
+    info!("WebSocket disconnected: user {}, device {}", user_id, device_id);
+}
@@ -3,7 +3,10 @@ pub mod database;
 pub mod idhash;
 pub mod imageresize;
 pub mod jellyfin;
+pub mod mediaprobe;
 pub mod notflix;
 pub mod server;
+pub mod tasks;
+pub mod transcode;
 
 pub use server::run;
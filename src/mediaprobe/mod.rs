@@ -0,0 +1,627 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+use crate::collection::{AudioTrack, Chapter, Metadata, SubtitleTrack};
+use crate::database::{MediaProbe, Repository};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProbeError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("ffprobe failed: {0}")]
+    Ffprobe(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ProbeError>;
+
+/// MediaInfo holds the technical details of a video file as reported by ffprobe.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaInfo {
+    pub duration: Option<Duration>,
+    pub bitrate: Option<i32>,
+    pub video_codec: Option<String>,
+    pub video_profile: Option<String>,
+    pub video_bitrate: Option<i32>,
+    pub video_width: Option<i32>,
+    pub video_height: Option<i32>,
+    pub video_frame_rate: Option<f64>,
+    pub video_bit_depth: Option<i32>,
+    pub video_range_type: Option<String>,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub subtitle_tracks: Vec<SubtitleTrack>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl MediaInfo {
+    /// Apply the probed details to metadata. Probe results are the real
+    /// thing, so they take precedence over NFO streamdetails.
+    pub fn apply(&self, m: &mut Metadata) {
+        if self.duration.is_some() {
+            m.duration = self.duration;
+        }
+        if self.video_codec.is_some() {
+            m.video_codec = self.video_codec.clone();
+            m.video_profile = self.video_profile.clone();
+            m.video_bitrate = self.video_bitrate.or(m.video_bitrate);
+            m.video_width = self.video_width;
+            m.video_height = self.video_height;
+            m.video_frame_rate = self.video_frame_rate;
+            m.video_bit_depth = self.video_bit_depth;
+            m.video_range_type = self.video_range_type.clone();
+        }
+
+        // The default (or first) audio track fills in the single-stream fields.
+        let audio = self
+            .audio_tracks
+            .iter()
+            .find(|a| a.default)
+            .or_else(|| self.audio_tracks.first());
+        if let Some(audio) = audio {
+            m.audio_codec = audio.codec.clone();
+            m.audio_language = audio.language.clone();
+            m.audio_channels = audio.channels;
+            m.audio_bitrate = audio.bitrate.or(m.audio_bitrate);
+        }
+        if !self.audio_tracks.is_empty() {
+            m.audio_tracks = self.audio_tracks.clone();
+        }
+        if !self.subtitle_tracks.is_empty() {
+            m.subtitle_tracks = self.subtitle_tracks.clone();
+        }
+        if !self.chapters.is_empty() {
+            m.chapters = self.chapters.clone();
+        }
+    }
+}
+
+/// Size and modification time of a file. A cached probe result is only
+/// used if these are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    size: i64,
+    mtime: i64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        let mtime = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Some(Self {
+            size: meta.len() as i64,
+            mtime,
+        })
+    }
+}
+
+/// A cached probe result: the stamp it was taken at, and None if ffprobe failed.
+type CachedProbe = (FileStamp, Option<Arc<MediaInfo>>);
+
+/// MediaProber runs ffprobe on video files and caches the results in the
+/// database, keyed by path, size and modification time. All cached results
+/// are kept in memory so collection scans can apply them without waiting.
+/// Files ffprobe cannot read are cached as None, so they are not probed
+/// again until they change.
+pub struct MediaProber {
+    ffprobe: PathBuf,
+    repo: Arc<dyn Repository>,
+    cache: Mutex<HashMap<PathBuf, CachedProbe>>,
+    /// Files that are currently being probed.
+    pending: Mutex<HashSet<PathBuf>>,
+    /// Set once ffprobe turned out to be missing, to avoid a warning per file.
+    unavailable: AtomicBool,
+}
+
+impl MediaProber {
+    pub fn new(ffprobe: PathBuf, repo: Arc<dyn Repository>) -> Self {
+        Self {
+            ffprobe,
+            repo,
+            cache: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashSet::new()),
+            unavailable: AtomicBool::new(false),
+        }
+    }
+
+    /// Load the cached probe results from the database.
+    pub async fn load(&self) {
+        let probes = match self.repo.get_media_probes().await {
+            Ok(probes) => probes,
+            Err(e) => {
+                warn!("Failed to load media probe cache: {}", e);
+                return;
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        for probe in probes {
+            match serde_json::from_str::<Option<MediaInfo>>(&probe.data) {
+                Ok(info) => {
+                    let stamp = FileStamp {
+                        size: probe.size,
+                        mtime: probe.mtime,
+                    };
+                    cache.insert(PathBuf::from(probe.path), (stamp, info.map(Arc::new)));
+                }
+                Err(e) => debug!("Ignoring cached probe of {}: {}", probe.path, e),
+            }
+        }
+        info!("Loaded {} media probe results", cache.len());
+    }
+
+    /// Return the cached probe result of a file if the file is unchanged,
+    /// Some(None) if ffprobe could not read it.
+    pub fn cached(&self, path: &Path) -> Option<Option<Arc<MediaInfo>>> {
+        let stamp = FileStamp::of(path)?;
+        let cache = self.cache.lock().unwrap();
+        match cache.get(path) {
+            Some((cached, info)) if *cached == stamp => Some(info.clone()),
+            _ => None,
+        }
+    }
+
+    /// Probe files that have no valid cached result. Files that are already
+    /// being probed are skipped. Returns the number of files probed.
+    pub async fn probe_files(&self, paths: Vec<PathBuf>) -> usize {
+        let mut probed = 0;
+        for path in paths {
+            if self.unavailable.load(Ordering::Relaxed) {
+                break;
+            }
+            if self.cached(&path).is_some() || !self.pending.lock().unwrap().insert(path.clone()) {
+                continue;
+            }
+            match self.probe(&path).await {
+                Ok(_) => probed += 1,
+                Err(e) => warn!("Failed to probe {}: {}", path.display(), e),
+            }
+            self.pending.lock().unwrap().remove(&path);
+        }
+        probed
+    }
+
+    /// Forget the probe results of files that were removed from a collection.
+    pub async fn remove(&self, paths: &[PathBuf]) {
+        for path in paths {
+            self.cache.lock().unwrap().remove(path);
+            if let Err(e) = self.repo.delete_media_probe(&path.to_string_lossy()).await {
+                warn!("Failed to delete probe of {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Run ffprobe on a file and store the result. A file ffprobe cannot
+    /// read is stored as a failure.
+    pub async fn probe(&self, path: &Path) -> Result<Arc<MediaInfo>> {
+        let stamp = FileStamp::of(path).ok_or_else(|| ProbeError::Ffprobe("file not found".to_string()))?;
+
+        debug!("Probing {}", path.display());
+        let output = Command::new(&self.ffprobe)
+            .args([
+                "-v",
+                "error",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+                "-show_chapters",
+            ])
+            .arg(path)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound && !self.unavailable.swap(true, Ordering::Relaxed) {
+                    warn!("{} not found, media probing disabled", self.ffprobe.display());
+                }
+                ProbeError::Io(e)
+            })?;
+        let result = if output.status.success() {
+            parse_ffprobe_output(&output.stdout).map(Arc::new)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(ProbeError::Ffprobe(stderr.trim().to_string()))
+        };
+        self.store(path, stamp, result.as_ref().ok().cloned()).await?;
+        result
+    }
+
+    /// Store a probe result in the database and the cache, None if ffprobe
+    /// could not read the file.
+    async fn store(&self, path: &Path, stamp: FileStamp, info: Option<Arc<MediaInfo>>) -> Result<()> {
+        let probe = MediaProbe {
+            path: path.to_string_lossy().into_owned(),
+            size: stamp.size,
+            mtime: stamp.mtime,
+            data: serde_json::to_string(&info.as_deref())?,
+        };
+        if let Err(e) = self.repo.upsert_media_probe(&probe).await {
+            warn!("Failed to store probe of {}: {}", path.display(), e);
+        }
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (stamp, info));
+        Ok(())
+    }
+}
+
+// --- ffprobe JSON output ---
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeOutput {
+    streams: Vec<FfprobeStream>,
+    chapters: Vec<FfprobeChapter>,
+    format: FfprobeFormat,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeFormat {
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeStream {
+    codec_type: String,
+    codec_name: Option<String>,
+    profile: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+    bit_rate: Option<String>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    pix_fmt: Option<String>,
+    color_transfer: Option<String>,
+    channels: Option<i32>,
+    sample_rate: Option<String>,
+    tags: HashMap<String, String>,
+    disposition: HashMap<String, i32>,
+    side_data_list: Vec<HashMap<String, serde_json::Value>>,
+}
+
+impl FfprobeStream {
+    fn tag(&self, name: &str) -> Option<String> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn disposition(&self, name: &str) -> bool {
+        self.disposition.get(name).copied().unwrap_or(0) != 0
+    }
+
+    fn bitrate(&self) -> Option<i32> {
+        self.bit_rate
+            .as_deref()
+            .and_then(|b| b.parse().ok())
+            // Matroska files carry the bitrate in a BPS tag instead.
+            .or_else(|| self.tag("BPS").and_then(|b| b.parse().ok()))
+    }
+
+    fn bit_depth(&self) -> Option<i32> {
+        if let Some(bits) = self.bits_per_raw_sample.as_deref().and_then(|b| b.parse().ok()) {
+            return Some(bits);
+        }
+        let pix_fmt = self.pix_fmt.as_deref()?;
+        if pix_fmt.contains("12le") || pix_fmt.contains("12be") {
+            Some(12)
+        } else if pix_fmt.contains("10le") || pix_fmt.contains("10be") {
+            Some(10)
+        } else {
+            Some(8)
+        }
+    }
+
+    fn range_type(&self) -> String {
+        let dovi = self.side_data_list.iter().any(|sd| {
+            sd.get("side_data_type")
+                .and_then(|t| t.as_str())
+                .is_some_and(|t| t.starts_with("DOVI"))
+        });
+        let transfer = self.color_transfer.as_deref().unwrap_or_default();
+        match (dovi, transfer) {
+            (true, "smpte2084") => "DOVIWithHDR10",
+            (true, _) => "DOVI",
+            (false, "smpte2084") => "HDR10",
+            (false, "arib-std-b67") => "HLG",
+            _ => "SDR",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeChapter {
+    start_time: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+/// Parse a frame rate like "24000/1001".
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let num: f64 = num.parse().ok()?;
+    let den: f64 = den.parse().ok()?;
+    if num == 0.0 || den == 0.0 {
+        return None;
+    }
+    Some((num / den * 1000.0).round() / 1000.0)
+}
+
+fn parse_seconds(secs: &str) -> Option<Duration> {
+    secs.parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// Convert the JSON output of ffprobe into MediaInfo.
+pub fn parse_ffprobe_output(json: &[u8]) -> Result<MediaInfo> {
+    let output: FfprobeOutput = serde_json::from_slice(json)?;
+
+    let mut info = MediaInfo {
+        duration: output.format.duration.as_deref().and_then(parse_seconds),
+        bitrate: output.format.bit_rate.as_deref().and_then(|b| b.parse().ok()),
+        ..Default::default()
+    };
+
+    for stream in &output.streams {
+        match stream.codec_type.as_str() {
+            // Cover art is reported as a video stream.
+            "video" if info.video_codec.is_none() && !stream.disposition("attached_pic") => {
+                info.video_codec = stream.codec_name.clone();
+                info.video_profile = stream.profile.clone();
+                info.video_bitrate = stream.bitrate();
+                info.video_width = stream.width;
+                info.video_height = stream.height;
+                info.video_frame_rate = stream
+                    .avg_frame_rate
+                    .as_deref()
+                    .and_then(parse_frame_rate)
+                    .or_else(|| stream.r_frame_rate.as_deref().and_then(parse_frame_rate));
+                info.video_bit_depth = stream.bit_depth();
+                info.video_range_type = Some(stream.range_type());
+            }
+            "audio" => info.audio_tracks.push(AudioTrack {
+                codec: stream.codec_name.clone(),
+                language: stream.tag("language"),
+                title: stream.tag("title"),
+                channels: stream.channels,
+                bitrate: stream.bitrate(),
+                sample_rate: stream.sample_rate.as_deref().and_then(|s| s.parse().ok()),
+                profile: stream.profile.clone(),
+                default: stream.disposition("default"),
+            }),
+            "subtitle" => info.subtitle_tracks.push(SubtitleTrack {
+                codec: stream.codec_name.clone(),
+                language: stream.tag("language"),
+                title: stream.tag("title"),
+                default: stream.disposition("default"),
+                forced: stream.disposition("forced"),
+                hearing_impaired: stream.disposition("hearing_impaired"),
+            }),
+            _ => {}
+        }
+    }
+
+    for chapter in &output.chapters {
+        let Some(start) = chapter.start_time.as_deref().and_then(parse_seconds) else {
+            continue;
+        };
+        info.chapters.push(Chapter {
+            start,
+            name: chapter.tags.get("title").cloned(),
+        });
+    }
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFPROBE_OUTPUT: &str = r#"{
+        "streams": [
+            {
+                "index": 0, "codec_name": "hevc", "profile": "Main 10", "codec_type": "video",
+                "width": 3840, "height": 2160, "pix_fmt": "yuv420p10le", "color_transfer": "smpte2084",
+                "avg_frame_rate": "24000/1001", "r_frame_rate": "24000/1001",
+                "side_data_list": [{"side_data_type": "DOVI configuration record", "dv_profile": 8}],
+                "disposition": {"default": 1, "attached_pic": 0}
+            },
+            {
+                "index": 1, "codec_name": "aac", "profile": "LC", "codec_type": "audio",
+                "sample_rate": "48000", "channels": 2, "bit_rate": "192000",
+                "disposition": {"default": 0}, "tags": {"language": "jpn", "title": "Japanese"}
+            },
+            {
+                "index": 2, "codec_name": "eac3", "codec_type": "audio", "channels": 6,
+                "disposition": {"default": 1}, "tags": {"language": "eng", "BPS": "640000"}
+            },
+            {
+                "index": 3, "codec_name": "subrip", "codec_type": "subtitle",
+                "disposition": {"default": 0, "forced": 1}, "tags": {"language": "eng"}
+            },
+            {
+                "index": 4, "codec_name": "mjpeg", "codec_type": "video", "width": 600, "height": 900,
+                "disposition": {"attached_pic": 1}
+            }
+        ],
+        "chapters": [
+            {"id": 0, "start_time": "0.000000", "end_time": "300.0", "tags": {"title": "Opening"}},
+            {"id": 1, "start_time": "300.500000", "end_time": "1420.0", "tags": {}}
+        ],
+        "format": {"format_name": "matroska,webm", "duration": "1420.123000", "bit_rate": "8000000"}
+    }"#;
+
+    #[test]
+    fn test_parse_ffprobe_output() {
+        let info = parse_ffprobe_output(FFPROBE_OUTPUT.as_bytes()).unwrap();
+
+        assert_eq!(info.duration, Some(Duration::from_secs_f64(1420.123)));
+        assert_eq!(info.bitrate, Some(8_000_000));
+        assert_eq!(info.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(info.video_width, Some(3840));
+        assert_eq!(info.video_bit_depth, Some(10));
+        assert_eq!(info.video_frame_rate, Some(23.976));
+        assert_eq!(info.video_range_type.as_deref(), Some("DOVIWithHDR10"));
+
+        assert_eq!(info.audio_tracks.len(), 2);
+        assert_eq!(info.audio_tracks[0].language.as_deref(), Some("jpn"));
+        assert_eq!(info.audio_tracks[0].bitrate, Some(192_000));
+        assert_eq!(info.audio_tracks[1].bitrate, Some(640_000));
+        assert!(info.audio_tracks[1].default);
+
+        assert_eq!(info.subtitle_tracks.len(), 1);
+        assert!(info.subtitle_tracks[0].forced);
+
+        assert_eq!(info.chapters.len(), 2);
+        assert_eq!(info.chapters[0].name.as_deref(), Some("Opening"));
+        assert_eq!(info.chapters[1].start, Duration::from_millis(300_500));
+    }
+
+    #[test]
+    fn test_apply_media_info() {
+        let info = parse_ffprobe_output(FFPROBE_OUTPUT.as_bytes()).unwrap();
+        let mut m = Metadata {
+            video_codec: Some("x264".to_string()),
+            audio_bitrate: Some(128_000),
+            ..Default::default()
+        };
+        info.apply(&mut m);
+
+        assert_eq!(m.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(m.video_height, Some(2160));
+        assert_eq!(m.runtime_ticks(), Some(14_201_230_000));
+        // The default audio track wins.
+        assert_eq!(m.audio_codec.as_deref(), Some("eac3"));
+        assert_eq!(m.audio_channels, Some(6));
+        assert_eq!(m.audio_tracks.len(), 2);
+        assert_eq!(m.chapters.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_frame_rate() {
+        assert_eq!(parse_frame_rate("25/1"), Some(25.0));
+        assert_eq!(parse_frame_rate("30000/1001"), Some(29.97));
+        assert_eq!(parse_frame_rate("0/0"), None);
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let dir = std::env::temp_dir().join("test_mediaprobe_remove");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let repo: Arc<dyn Repository> = Arc::new(
+            crate::database::SqliteRepository::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let paths = [dir.join("a.mkv"), dir.join("b.mkv")];
+        for path in &paths {
+            std::fs::write(path, b"video").unwrap();
+            let stamp = FileStamp::of(path).unwrap();
+            let probe = MediaProbe {
+                path: path.to_string_lossy().into_owned(),
+                size: stamp.size,
+                mtime: stamp.mtime,
+                data: serde_json::to_string(&MediaInfo::default()).unwrap(),
+            };
+            repo.upsert_media_probe(&probe).await.unwrap();
+        }
+
+        let prober = MediaProber::new(PathBuf::from("ffprobe"), repo.clone());
+        prober.load().await;
+        assert!(prober.cached(&paths[0]).is_some());
+
+        prober.remove(&paths[..1]).await;
+        assert!(prober.cached(&paths[0]).is_none());
+        assert!(prober.cached(&paths[1]).is_some());
+        let probes = repo.get_media_probes().await.unwrap();
+        let stored: Vec<&str> = probes.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(stored, vec![paths[1].to_str().unwrap()]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_probe_failure_cached() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("test_mediaprobe_failure");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // An ffprobe that fails and counts its runs.
+        let ffprobe = dir.join("ffprobe");
+        let calls = dir.join("calls");
+        std::fs::write(
+            &ffprobe,
+            format!(
+                "#!/bin/sh\necho run >> {}\necho broken >&2\nexit 1\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffprobe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let runs = || {
+            std::fs::read_to_string(&calls)
+                .unwrap_or_default()
+                .lines()
+                .count()
+        };
+
+        let repo: Arc<dyn Repository> = Arc::new(
+            crate::database::SqliteRepository::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let video = dir.join("broken.mkv");
+        std::fs::write(&video, b"video").unwrap();
+
+        let prober = MediaProber::new(ffprobe.clone(), repo.clone());
+        assert_eq!(prober.probe_files(vec![video.clone()]).await, 0);
+        assert_eq!(prober.probe_files(vec![video.clone()]).await, 0);
+        assert_eq!(runs(), 1);
+        assert_eq!(prober.cached(&video), Some(None));
+
+        // The failure is remembered across restarts.
+        let prober = MediaProber::new(ffprobe, repo);
+        prober.load().await;
+        assert_eq!(prober.cached(&video), Some(None));
+        prober.probe_files(vec![video.clone()]).await;
+        assert_eq!(runs(), 1);
+
+        // A changed file is probed again.
+        std::fs::write(&video, b"fixed video").unwrap();
+        assert_eq!(prober.cached(&video), None);
+        prober.probe_files(vec![video.clone()]).await;
+        assert_eq!(runs(), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -156,6 +156,85 @@ pub async fn genres_handler(
     Ok(Json(genre_count))
 }
 
+/// Number of results /api/search returns if no limit is given.
+const SEARCH_DEFAULT_LIMIT: usize = 50;
+
+/// Number of documents fetched from the search index. Seasons and episodes
+/// are dropped from them.
+const SEARCH_MAX_RESULTS: usize = 1000;
+
+/// GET /api/search?q={query} - Search movies and shows in all collections
+pub async fn search_handler(
+    State(state): State<NotflixState>,
+    Query(params): Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Vec<SearchResult>>, StatusCode> {
+    let query = params
+        .get("q")
+        .filter(|q| !q.trim().is_empty())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let limit = params
+        .get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(SEARCH_DEFAULT_LIMIT);
+
+    let results = state
+        .collections
+        .search(query, SEARCH_MAX_RESULTS)
+        .into_iter()
+        .filter(|doc| doc.item_type == "movie" || doc.item_type == "show")
+        .filter_map(|doc| {
+            let item = state.collections.get_item(&doc.collection_id, &doc.id)?;
+            Some(SearchResult {
+                item: copy_item(&item, &doc.collection_id),
+                collection_id: doc.collection_id,
+            })
+        })
+        .take(limit)
+        .collect();
+
+    Ok(Json(results))
+}
+
+/// Number of results /api/collection/{coll}/item/{item}/similar returns if
+/// no limit is given.
+const SIMILAR_DEFAULT_LIMIT: usize = 20;
+
+/// Most similar items returned, whatever limit the client asks for.
+const SIMILAR_MAX_LIMIT: usize = 100;
+
+/// GET /api/collection/{coll}/item/{item}/similar - Movies or shows similar
+/// to an item, in all collections
+pub async fn similar_handler(
+    State(state): State<NotflixState>,
+    AxumPath((collection_id, item_id)): AxumPath<(String, String)>,
+    Query(params): Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Vec<SearchResult>>, StatusCode> {
+    state
+        .collections
+        .get_item(&collection_id, &item_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let limit = params
+        .get("limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(SIMILAR_DEFAULT_LIMIT)
+        .min(SIMILAR_MAX_LIMIT);
+
+    let results = state
+        .collections
+        .similar(&item_id, limit)
+        .into_iter()
+        .filter_map(|id| {
+            let (collection, item) = state.collections.get_item_by_id(&id)?;
+            Some(SearchResult {
+                item: copy_item(&item, &collection.id),
+                collection_id: collection.id,
+            })
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
 /// GET /data/{source}/{path} - Get media data
 pub async fn data_handler(
     State(state): State<NotflixState>,
@@ -188,8 +267,7 @@ pub async fn data_handler(
         None => return StatusCode::NOT_FOUND.into_response(),
     };
 
-    let base_path = std::path::Path::new(&collection.directory);
-    let file_path = base_path.join(path_string.trim_start_matches('/'));
+    let file_path = collection.resolve(path_string.trim_start_matches('/'));
     let file_path_str = file_path.to_str().unwrap_or_default();
 
     tracing::debug!(
@@ -57,6 +57,16 @@ pub struct Item {
     pub seasons: Option<Vec<Season>>,
 }
 
+/// SearchResult is a movie or show found by /api/search or
+/// /api/collection/{coll}/item/{item}/similar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    #[serde(rename = "collectionId")]
+    pub collection_id: String,
+    #[serde(flatten)]
+    pub item: Item,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ItemNfo {
     pub id: String,
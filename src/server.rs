@@ -1,8 +1,11 @@
 #![cfg_attr(rustfmt, rustfmt_skip)]
 mod config;
 mod middleware;
+mod redact;
 
 pub use config::Config;
+pub(crate) use middleware::client_ip;
+use redact::Redactor;
 
 use axum::{
     response::IntoResponse,
@@ -10,17 +13,24 @@ use axum::{
     Router,
 };
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::{compression::CompressionLayer, trace::TraceLayer};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::collection::CollectionRepo;
+use crate::collection::people::import_people;
+use crate::collection::{CollectionRepo, Search};
 use crate::database::sqlite::SqliteRepository;
-use crate::database::Repository;
+use crate::database::{LibraryRepo, Repository, TaskTriggerRepo};
 use crate::imageresize::ImageResizer;
-use crate::jellyfin::{JellyfinAuthState, JellyfinState};
+use crate::mediaprobe::MediaProber;
+use crate::jellyfin::{
+    JellyfinAuthState, JellyfinState, LoginLockout, SessionRegistry, SyncPlayManager, WebSocketHub,
+};
 use crate::notflix::NotflixState;
+use crate::tasks::{self, TaskInfo, TaskManager, TaskTrigger};
+use crate::transcode::Transcoder;
 
 /// Application state shared across all handlers
 #[derive(Clone)]
@@ -29,6 +39,9 @@ pub struct AppState {
     pub collections: Arc<CollectionRepo>,
     pub repo: Arc<SqliteRepository>,
     pub image_resizer: Arc<ImageResizer>,
+    pub transcoder: Arc<Transcoder>,
+    pub redactor: Arc<Redactor>,
+    pub tasks: Arc<TaskManager>,
     pub debug: bool,
 }
 
@@ -58,18 +71,41 @@ pub async fn run(config_path: String, debug: bool) -> Result<(), Box<dyn std::er
     let db_path_str = db_path.to_str().ok_or("Invalid database path")?.to_string();
 
     let repo = Arc::new(SqliteRepository::new(&db_path_str).await?);
-    repo.start_background_jobs();
     info!("Database initialized at {}", db_path_str);
 
     // Initialize collection repository
     let collections = Arc::new(CollectionRepo::new());
     info!("Collection repository initialized");
 
+    // Probe video files with ffprobe, results are cached in the database
+    if config.probe.enabled {
+        let prober = Arc::new(MediaProber::new(PathBuf::from(&config.probe.ffprobe), repo.clone()));
+        prober.load().await;
+        collections.set_prober(prober);
+        info!("Media prober initialized");
+    }
+
     // Initialize image resizer
     let cache_dir = PathBuf::from(config.cachedir.clone());
-    let image_resizer = Arc::new(ImageResizer::new(cache_dir)?);
+    let image_resizer = Arc::new(ImageResizer::new(cache_dir.clone())?);
     info!("Image resizer initialized");
 
+    // Search index, kept up to date by the collection scans
+    let search = Search::new_on_disk(&cache_dir.join("search")).or_else(|e| {
+        warn!("Failed to open search index, using one in memory: {}", e);
+        Search::new_in_memory()
+    })?;
+    collections.set_search(Arc::new(search));
+
+    // Initialize transcoder
+    let transcoder = Arc::new(Transcoder::new(
+        PathBuf::from(&config.transcoding.ffmpeg),
+        cache_dir.join("transcode"),
+        config.transcoding.segment_length,
+    )?);
+    transcoder.background();
+    info!("Transcoder initialized");
+
     // Initialize collections from config
     for collection_config in &config.collections {
         collections
@@ -77,24 +113,78 @@ pub async fn run(config_path: String, debug: bool) -> Result<(), Box<dyn std::er
                 collection_config.name.clone(),
                 Some(collection_config.id.clone()),
                 &collection_config.collection_type,
-                collection_config.directory.clone(),
+                collection_config.directories(),
                 collection_config.hls_server.clone().unwrap_or_default(),
             )
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
     }
 
+    // Apply the collections added, changed or removed through the library API
+    match repo.get_libraries().await {
+        Ok(libraries) => {
+            for library in libraries {
+                if library.removed {
+                    collections.remove_collection(&library.id);
+                } else if collections.get_collection(&library.id).is_some() {
+                    collections.rename_collection(&library.id, &library.name);
+                    collections.set_collection_directories(&library.id, &library.directories);
+                } else if let Err(e) = collections.add_collection(
+                    library.name.clone(),
+                    Some(library.id.clone()),
+                    &library.collection_type,
+                    library.directories.clone(),
+                    library.hls_server.clone(),
+                ) {
+                    warn!("Failed to add library {}: {}", library.name, e);
+                }
+            }
+        }
+        Err(e) => warn!("Failed to load libraries: {}", e),
+    }
+
     // Scan collections
     collections.init();
 
-    // Start background background scan
-    collections.background();
+    // Import person metadata, this is repeated on every library scan
+    if let Some(dir) = config.people_dir.clone() {
+        let repo = repo.clone();
+        tokio::spawn(async move {
+            if let Err(e) = import_people(Path::new(&dir), repo.as_ref()).await {
+                warn!("Failed to import people: {}", e);
+            }
+        });
+    }
+
+    // Watch collection directories for changes
+    if config.watch {
+        if let Err(e) = collections.watch(config.watch_delay()) {
+            warn!("Failed to watch collections: {}", e);
+        }
+    }
+
+    // Scheduled tasks, with the triggers changed through the API
+    let tasks = Arc::new(TaskManager::new());
+    register_tasks(&tasks, &config, &collections, repo.clone(), &image_resizer);
+    match repo.get_task_triggers().await {
+        Ok(triggers) => {
+            for (id, triggers) in triggers {
+                let _ = tasks.set_triggers(&id, triggers);
+            }
+        }
+        Err(e) => warn!("Failed to load task triggers: {}", e),
+    }
+    tasks.background();
 
     // Create application state
+    let redactor = Arc::new(Redactor::new(&config.logging));
     let state = AppState {
         config: Arc::new(config),
         collections,
         repo,
         image_resizer,
+        transcoder,
+        redactor,
+        tasks,
         debug,
     };
 
@@ -134,6 +224,128 @@ pub async fn run(config_path: String, debug: bool) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+/// Cached images are removed after this long, they are created again when needed.
+const IMAGE_CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 86400);
+
+/// Quick connect codes are valid for this long.
+const QUICK_CONNECT_LIFETIME: Duration = Duration::from_secs(600);
+
+/// Register the scheduled tasks with their default triggers.
+fn register_tasks(
+    tasks: &TaskManager,
+    config: &Config,
+    collections: &Arc<CollectionRepo>,
+    repo: Arc<dyn Repository>,
+    image_resizer: &Arc<ImageResizer>,
+) {
+    let info = |id: &str, name: &str, description: &str, category: &str| TaskInfo {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        category: category.to_string(),
+    };
+    let hours = |h: u64| TaskTrigger::Interval { seconds: h * 3600 };
+
+    let scan_interval = config.scan_interval();
+    let triggers = match scan_interval.is_zero() {
+        true => Vec::new(),
+        false => vec![TaskTrigger::Interval { seconds: scan_interval.as_secs() }],
+    };
+    let c = collections.clone();
+    let r = repo.clone();
+    let people_dir = config.people_dir.clone().map(PathBuf::from);
+    tasks.register(
+        info(tasks::TASK_REFRESH_LIBRARY, "Scan Media Library",
+            "Scans all libraries for new, changed and removed items.", "Library"),
+        triggers,
+        Some(tasks::job(move |ctx| {
+            let collections = c.clone();
+            let repo = r.clone();
+            let people_dir = people_dir.clone();
+            async move {
+                collections.refresh(scan_interval, ctx.progress_fn(), ctx.cancelled_fn()).await?;
+                if let Some(dir) = people_dir {
+                    import_people(&dir, repo.as_ref()).await?;
+                }
+                Ok(())
+            }
+        })),
+    );
+
+    // Started by POST /Items/{id}/Refresh only.
+    tasks.register(
+        info(tasks::TASK_REFRESH_ITEMS, "Refresh Items",
+            "Reads single items again from disk, including metadata and images.", "Library"),
+        Vec::new(),
+        None,
+    );
+
+    let resizer = image_resizer.clone();
+    tasks.register(
+        info(tasks::TASK_CLEAN_IMAGE_CACHE, "Clean Image Cache",
+            "Removes resized images that were not created recently.", "Maintenance"),
+        vec![hours(24)],
+        Some(tasks::job(move |_| {
+            let resizer = resizer.clone();
+            async move {
+                let removed = tokio::task::spawn_blocking(move || resizer.clean_cache(IMAGE_CACHE_MAX_AGE))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .map_err(|e| e.to_string())?;
+                info!("Removed {} cached images", removed);
+                Ok(())
+            }
+        })),
+    );
+
+    let r = repo.clone();
+    tasks.register(
+        info(tasks::TASK_CLEAN_QUICK_CONNECT, "Clean Quick Connect Codes",
+            "Removes expired quick connect codes.", "Maintenance"),
+        vec![hours(1)],
+        Some(tasks::job(move |_| {
+            let repo = r.clone();
+            async move {
+                let before = chrono::Utc::now() - QUICK_CONNECT_LIFETIME;
+                repo.delete_expired_quick_connects(before).await.map_err(|e| e.to_string())
+            }
+        })),
+    );
+
+    let r = repo.clone();
+    let (idle_timeout, lifetime) = (config.token_idle_timeout(), config.token_lifetime());
+    tasks.register(
+        info(tasks::TASK_CLEAN_ACCESS_TOKENS, "Clean Access Tokens",
+            "Removes access tokens that were idle too long or are too old.", "Maintenance"),
+        vec![hours(1)],
+        Some(tasks::job(move |_| {
+            let repo = r.clone();
+            async move {
+                let now = chrono::Utc::now();
+                let before = |d: Option<Duration>| d.map(|d| now - d);
+                let removed = repo
+                    .delete_expired_access_tokens(before(idle_timeout), before(lifetime))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if removed > 0 {
+                    info!("Removed {} expired access tokens", removed);
+                }
+                Ok(())
+            }
+        })),
+    );
+
+    tasks.register(
+        info(tasks::TASK_VACUUM_DATABASE, "Optimize Database",
+            "Compacts the database file.", "Maintenance"),
+        vec![TaskTrigger::Daily { time_of_day: 3 * 3600 }],
+        Some(tasks::job(move |_| {
+            let repo = repo.clone();
+            async move { repo.vacuum().await.map_err(|e| e.to_string()) }
+        })),
+    );
+}
+
 /// Build the axum router with all routes and middleware
 fn build_router(state: AppState) -> Router {
     use axum::middleware as mw;
@@ -145,6 +357,14 @@ fn build_router(state: AppState) -> Router {
         app_dir: state.config.app_dir().unwrap_or_else(|| "./app".to_string()),
     };
 
+    // WebSocket clients get notified of library changes
+    let websocket = Arc::new(WebSocketHub::new());
+    websocket.forward_library_changes(state.repo.clone(), state.collections.clone());
+
+    // Sessions of connected clients
+    let sessions = Arc::new(SessionRegistry::new());
+    let syncplay = Arc::new(SyncPlayManager::new());
+
     // Create Jellyfin auth state
     let server_id = state
         .config
@@ -155,6 +375,12 @@ fn build_router(state: AppState) -> Router {
         server_id: server_id.clone(),
         auto_register: state.config.auto_register().unwrap_or(true),
         quick_connect: state.config.quick_connect().unwrap_or(false),
+        websocket: websocket.clone(),
+        sessions: sessions.clone(),
+        syncplay: syncplay.clone(),
+        lockout: LoginLockout::new(&state.config),
+        token_idle_timeout: state.config.token_idle_timeout(),
+        token_lifetime: state.config.token_lifetime(),
     };
 
     // Create Jellyfin API state
@@ -165,6 +391,11 @@ fn build_router(state: AppState) -> Router {
         server_name: state.config.server_name().unwrap_or_else(|| "Jellofin-rs".to_string()),
         image_resizer: state.image_resizer.clone(),
         config: state.config.clone(),
+        websocket: websocket.clone(),
+        sessions: sessions.clone(),
+        syncplay,
+        transcoder: state.transcoder.clone(),
+        tasks: state.tasks.clone(),
     };
 
     // Notflix API routes (no auth required)
@@ -173,7 +404,9 @@ fn build_router(state: AppState) -> Router {
         .route("/api/collection/{id}", get(crate::notflix::collection_handler))
         .route("/api/collection/{coll}/items", get(crate::notflix::items_handler))
         .route("/api/collection/{coll}/item/{item}", get(crate::notflix::item_handler))
+        .route("/api/collection/{coll}/item/{item}/similar", get(crate::notflix::similar_handler))
         .route("/api/collection/{id}/genres", get(crate::notflix::genres_handler))
+        .route("/api/search", get(crate::notflix::search_handler))
         .route("/data/{source}/{*path}", get(crate::notflix::data_handler))
         .route("/v/{*path}", get(crate::notflix::index_handler))
         .with_state(notflix_state);
@@ -212,6 +445,9 @@ fn build_router(state: AppState) -> Router {
         // Protected routes
         .merge(
             Router::new()
+                // API keys
+                .route("/auth/keys", get(crate::jellyfin::api_keys_get).post(crate::jellyfin::api_keys_post))
+                .route("/auth/keys/{key}", delete(crate::jellyfin::api_keys_delete))
                 // Devices
                 .route("/devices", get(crate::jellyfin::devices_get).delete(crate::jellyfin::devices_delete))
                 .route("/devices/info", get(crate::jellyfin::devices_info))
@@ -242,7 +478,9 @@ fn build_router(state: AppState) -> Router {
                 .route("/items/{item}/similar", get(crate::jellyfin::items_similar))
                 .route("/items/{item}/specialfeatures", get(crate::jellyfin::items_special_features))
                 // Library routes
-                .route("/library/virtualfolders", get(crate::jellyfin::library_virtual_folders))
+                .route("/library/virtualfolders", get(crate::jellyfin::library_virtual_folders).post(crate::jellyfin::library_virtual_folders_add).delete(crate::jellyfin::library_virtual_folders_delete))
+                .route("/library/virtualfolders/name", post(crate::jellyfin::library_virtual_folders_rename))
+                .route("/library/virtualfolders/paths", post(crate::jellyfin::library_virtual_folders_add_path).delete(crate::jellyfin::library_virtual_folders_delete_path))
                 .route("/library/mediafolders", get(crate::jellyfin::library_media_folders))
                 .route("/library/refresh", post(crate::jellyfin::library_refresh))
                 // Localization routes
@@ -278,6 +516,12 @@ fn build_router(state: AppState) -> Router {
                 .route("/sessions/playing", post(crate::jellyfin::sessions_playing))
                 .route("/sessions/playing/progress", post(crate::jellyfin::sessions_playing_progress))
                 .route("/sessions/playing/stopped", post(crate::jellyfin::sessions_playing_stopped))
+                .route("/sessions/playing/ping", post(crate::jellyfin::sessions_playing_ping))
+                .route("/sessions/{id}/command", post(crate::jellyfin::sessions_general_command))
+                .route("/sessions/{id}/command/{command}", post(crate::jellyfin::sessions_general_command_named))
+                .route("/sessions/{id}/message", post(crate::jellyfin::sessions_message))
+                .route("/sessions/{id}/playing", post(crate::jellyfin::sessions_play))
+                .route("/sessions/{id}/playing/{command}", post(crate::jellyfin::sessions_playstate_command))
                 // Playing items
                 // TODO .route("/playingitems/{item}", delete(super::userdata::delete_playing_item))
                 // Show routes.
@@ -294,10 +538,34 @@ fn build_router(state: AppState) -> Router {
                 .route("/system/restart", post(crate::jellyfin::system_restart))
                 .route("/system/shutdown", post(crate::jellyfin::system_shutdown))
                 .route("/scheduledtasks", get(crate::jellyfin::scheduled_tasks))
+                .route("/scheduledtasks/{id}", get(crate::jellyfin::scheduled_task))
+                .route("/scheduledtasks/{id}/triggers", post(crate::jellyfin::scheduled_task_triggers))
+                .route("/scheduledtasks/running/{id}", post(crate::jellyfin::scheduled_task_start))
+                .route("/scheduledtasks/running/{id}", delete(crate::jellyfin::scheduled_task_stop))
                 .route("/playback/bitratetest", get(crate::jellyfin::playback_bitrate_test))
-                // SyncPlay stubs
+                // SyncPlay
                 .route("/syncplay/list", get(crate::jellyfin::sync_play_list))
                 .route("/syncplay/new", post(crate::jellyfin::sync_play_new))
+                .route("/syncplay/join", post(crate::jellyfin::sync_play_join))
+                .route("/syncplay/leave", post(crate::jellyfin::sync_play_leave))
+                .route("/syncplay/setnewqueue", post(crate::jellyfin::sync_play_set_new_queue))
+                .route("/syncplay/setplaylistitem", post(crate::jellyfin::sync_play_set_playlist_item))
+                .route("/syncplay/removefromplaylist", post(crate::jellyfin::sync_play_remove_from_playlist))
+                .route("/syncplay/moveplaylistitem", post(crate::jellyfin::sync_play_move_playlist_item))
+                .route("/syncplay/queue", post(crate::jellyfin::sync_play_queue))
+                .route("/syncplay/unpause", post(crate::jellyfin::sync_play_unpause))
+                .route("/syncplay/pause", post(crate::jellyfin::sync_play_pause))
+                .route("/syncplay/stop", post(crate::jellyfin::sync_play_stop))
+                .route("/syncplay/seek", post(crate::jellyfin::sync_play_seek))
+                .route("/syncplay/buffering", post(crate::jellyfin::sync_play_buffering))
+                .route("/syncplay/ready", post(crate::jellyfin::sync_play_ready))
+                .route("/syncplay/setignorewait", post(crate::jellyfin::sync_play_set_ignore_wait))
+                .route("/syncplay/nextitem", post(crate::jellyfin::sync_play_next_item))
+                .route("/syncplay/previousitem", post(crate::jellyfin::sync_play_previous_item))
+                .route("/syncplay/setrepeatmode", post(crate::jellyfin::sync_play_set_repeat_mode))
+                .route("/syncplay/setshufflemode", post(crate::jellyfin::sync_play_set_shuffle_mode))
+                .route("/syncplay/ping", post(crate::jellyfin::sync_play_ping))
+                .route("/syncplay/{id}", get(crate::jellyfin::sync_play_get))
                 // Users.
                 .route("/users", get(crate::jellyfin::users_all).post(crate::jellyfin::users_update))
                 .route("/users/me", get(crate::jellyfin::users_me))
@@ -334,6 +602,10 @@ fn build_router(state: AppState) -> Router {
                 // TODO .route("/videos/{id}/subtitles/{index}/stream", get(super::video::stream_subtitle))
                 .route("/videos/{item}/stream", get(crate::jellyfin::video_stream_handler))
                 .route("/videos/{item}/stream.{container}", get(crate::jellyfin::video_stream_handler))
+                .route("/videos/{item}/master.m3u8", get(crate::jellyfin::video_master_playlist))
+                .route("/videos/{item}/main.m3u8", get(crate::jellyfin::video_main_playlist))
+                .route("/videos/{item}/hls1/{playlist}/{segment}", get(crate::jellyfin::video_hls_segment))
+                .route("/videos/activeencodings", delete(crate::jellyfin::video_active_encodings_delete))
                 // Legacy/Alias Routes
                 .route("/userviews", get(crate::jellyfin::user_views_query))
                 .route("/userviews/groupingoptions", get(crate::jellyfin::user_grouping_options))
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -11,10 +13,28 @@ pub struct Config {
     pub dbdir: String,
     #[serde(default)]
     pub logfile: Option<String>,
+    /// Interval in seconds between background collection rescans, 0 disables them.
+    #[serde(default = "default_scan_interval", rename = "scaninterval")]
+    pub scan_interval: u64,
+    /// Watch collection directories for changes and rescan them right away.
+    #[serde(default)]
+    pub watch: bool,
+    /// Seconds a changed directory must be quiet before it is rescanned.
+    #[serde(default = "default_watch_delay", rename = "watchdelay")]
+    pub watch_delay: u64,
+    /// Directory with Kodi-style person NFOs and images, imported during scans.
+    #[serde(default, rename = "peopledir")]
+    pub people_dir: Option<String>,
     #[serde(default)]
     pub collections: Vec<CollectionConfig>,
     #[serde(default)]
     pub jellyfin: JellyfinConfig,
+    #[serde(default)]
+    pub transcoding: TranscodingConfig,
+    #[serde(default)]
+    pub probe: ProbeConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
 }
 
 impl Config {
@@ -46,6 +66,46 @@ impl Config {
     pub fn ip_allowlist(&self) -> &[String] {
         &self.jellyfin.ip_allowlist
     }
+
+    pub fn trusted_proxy_hops(&self) -> usize {
+        self.jellyfin.trusted_proxy_hops.unwrap_or(1).max(1)
+    }
+
+    pub fn login_attempts_before_lockout(&self) -> u32 {
+        self.jellyfin.login_attempts_before_lockout.unwrap_or(3)
+    }
+
+    pub fn ip_login_attempts(&self) -> u32 {
+        self.jellyfin.ip_login_attempts.unwrap_or(10)
+    }
+
+    pub fn ip_lockout(&self) -> Duration {
+        Duration::from_secs(self.jellyfin.ip_lockout_minutes.unwrap_or(15) * 60)
+    }
+
+    /// Idle timeout of access tokens, None if they do not expire when idle.
+    pub fn token_idle_timeout(&self) -> Option<Duration> {
+        match self.jellyfin.token_idle_days.unwrap_or(0) {
+            0 => None,
+            days => Some(Duration::from_secs(days * 86400)),
+        }
+    }
+
+    /// Absolute lifetime of access tokens, None if unlimited.
+    pub fn token_lifetime(&self) -> Option<Duration> {
+        match self.jellyfin.token_lifetime_days.unwrap_or(0) {
+            0 => None,
+            days => Some(Duration::from_secs(days * 86400)),
+        }
+    }
+
+    pub fn scan_interval(&self) -> Duration {
+        Duration::from_secs(self.scan_interval)
+    }
+
+    pub fn watch_delay(&self) -> Duration {
+        Duration::from_secs(self.watch_delay)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,13 +126,28 @@ pub struct CollectionConfig {
     pub name: String,
     #[serde(rename = "type")]
     pub collection_type: String,
+    #[serde(default)]
     pub directory: String,
+    /// Additional directories, for collections that span multiple disks.
+    #[serde(default)]
+    pub directories: Vec<String>,
     #[serde(default, rename = "baseurl")]
     pub base_url: Option<String>,
     #[serde(default, rename = "hlsserver")]
     pub hls_server: Option<String>,
 }
 
+impl CollectionConfig {
+    /// All directories of the collection, `directory` first.
+    pub fn directories(&self) -> Vec<String> {
+        std::iter::once(&self.directory)
+            .chain(self.directories.iter())
+            .filter(|d| !d.is_empty())
+            .cloned()
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct JellyfinConfig {
     #[serde(default, rename = "serverid")]
@@ -88,6 +163,123 @@ pub struct JellyfinConfig {
     /// Optional IP allowlist (CIDR or exact IPs). Empty = allow all.
     #[serde(default, rename = "ipallowlist")]
     pub ip_allowlist: Vec<String>,
+    /// Number of reverse proxies that append to X-Forwarded-For. The client
+    /// address is the entry this many places from the right. Default 1.
+    #[serde(default, rename = "trustedproxyhops")]
+    pub trusted_proxy_hops: Option<usize>,
+    /// Failed logins before an account is disabled, unless the user policy
+    /// sets its own limit. Default 3, 0 disables account lockout.
+    #[serde(default, rename = "loginattemptsbeforelockout")]
+    pub login_attempts_before_lockout: Option<u32>,
+    /// Failed logins from one remote address before it is blocked.
+    /// Default 10, 0 disables address blocking.
+    #[serde(default, rename = "iploginattempts")]
+    pub ip_login_attempts: Option<u32>,
+    /// Minutes a remote address stays blocked after its last failed login. Default 15.
+    #[serde(default, rename = "iplockoutminutes")]
+    pub ip_lockout_minutes: Option<u64>,
+    /// Days an access token stays valid without being used. Default 0, forever.
+    #[serde(default, rename = "tokenidledays")]
+    pub token_idle_days: Option<u64>,
+    /// Days an access token stays valid after login, however often it is used.
+    /// Default 0, forever.
+    #[serde(default, rename = "tokenlifetimedays")]
+    pub token_lifetime_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscodingConfig {
+    /// Path of the ffmpeg binary.
+    #[serde(default = "default_ffmpeg")]
+    pub ffmpeg: String,
+    /// Length of HLS segments in seconds.
+    #[serde(default = "default_segment_length", rename = "segmentlength")]
+    pub segment_length: u32,
+}
+
+impl Default for TranscodingConfig {
+    fn default() -> Self {
+        Self {
+            ffmpeg: default_ffmpeg(),
+            segment_length: default_segment_length(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeConfig {
+    /// Probe video files with ffprobe for codecs, tracks and chapters.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Path of the ffprobe binary.
+    #[serde(default = "default_ffprobe")]
+    pub ffprobe: String,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ffprobe: default_ffprobe(),
+        }
+    }
+}
+
+/// Request logging. Credentials are always redacted from logged URLs,
+/// headers and bodies, the lists below add to the built-in ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// JSON keys whose values are redacted, e.g. "Pw" or "AccessToken".
+    #[serde(default, rename = "redactkeys")]
+    pub redact_keys: Vec<String>,
+    /// Headers whose values are redacted, e.g. "Authorization".
+    #[serde(default, rename = "redactheaders")]
+    pub redact_headers: Vec<String>,
+    /// Query parameters whose values are redacted, e.g. "api_key".
+    #[serde(default, rename = "redactqueryparams")]
+    pub redact_query_params: Vec<String>,
+    /// Logged request and response bodies are truncated to this many bytes.
+    #[serde(default = "default_max_body_size", rename = "maxbodysize")]
+    pub max_body_size: usize,
+    /// Log level per route, keyed by path prefix: "off", "debug" or "info".
+    /// The longest matching prefix wins, other routes log at info.
+    #[serde(default)]
+    pub routes: HashMap<String, String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            redact_keys: Vec::new(),
+            redact_headers: Vec::new(),
+            redact_query_params: Vec::new(),
+            max_body_size: default_max_body_size(),
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Log level of a (normalized, lowercase) request path.
+    pub fn route_level(&self, path: &str) -> LogLevel {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.to_lowercase().as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| match level.to_lowercase().as_str() {
+                "off" | "none" => LogLevel::Off,
+                "debug" => LogLevel::Debug,
+                _ => LogLevel::Info,
+            })
+            .unwrap_or(LogLevel::Info)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Debug,
+    Info,
 }
 
 fn default_address() -> String {
@@ -102,6 +294,34 @@ fn default_image_quality() -> u32 {
     90
 }
 
+fn default_scan_interval() -> u64 {
+    300
+}
+
+fn default_watch_delay() -> u64 {
+    10
+}
+
+fn default_ffmpeg() -> String {
+    "ffmpeg".to_string()
+}
+
+fn default_segment_length() -> u32 {
+    6
+}
+
+fn default_ffprobe() -> String {
+    "ffprobe".to_string()
+}
+
+fn default_max_body_size() -> usize {
+    4096
+}
+
+fn default_true() -> bool {
+    true
+}
+
 impl Config {
     /// Load configuration from YAML file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
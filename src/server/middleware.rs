@@ -1,13 +1,17 @@
 use crate::server::AppState;
 use axum::{
     extract::{Request, State},
+    http::HeaderMap,
     middleware::Next,
     response::Response,
 };
+use futures_util::StreamExt;
 use std::net::IpAddr;
 use std::task::{Context, Poll};
 use tower::Service;
-use tracing::info;
+use tracing::{debug, info};
+
+use super::config::LogLevel;
 
 /// Tower service that normalizes request URIs before passing to inner service.
 /// Generic over body type so it works with both `axum::serve` (Body) and `axum_server` (Incoming).
@@ -105,64 +109,133 @@ fn normalize_uri<B>(req: &mut axum::http::Request<B>) {
     }
 }
 
+/// Log at the level configured for a route.
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            LogLevel::Off => {}
+            LogLevel::Debug => debug!($($arg)+),
+            LogLevel::Info => info!($($arg)+),
+        }
+    };
+}
+
+fn is_text_content_type(content_type: &str) -> bool {
+    content_type.contains("json")
+        || content_type.contains("text")
+        || content_type.contains("xml")
+        || content_type.contains("application/x-www-form-urlencoded")
+}
+
+fn content_type(headers: &HeaderMap) -> String {
+    headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Cut a body to at most `max` bytes for logging, on a character boundary.
+fn truncate_body(bytes: &[u8], max: usize) -> (String, bool) {
+    if bytes.len() <= max {
+        return (String::from_utf8_lossy(bytes).into_owned(), false);
+    }
+    let mut end = max;
+    while end > 0 && end < bytes.len() && (bytes[end] & 0xC0) == 0x80 {
+        end -= 1;
+    }
+    (String::from_utf8_lossy(&bytes[..end]).into_owned(), true)
+}
+
+/// Read the first `limit` bytes of a body without buffering all of it.
+/// Returns those bytes, whether they are the whole body, and a body that
+/// still yields the complete content.
+async fn peek_body(body: axum::body::Body, limit: usize) -> (Vec<u8>, bool, axum::body::Body) {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut head = Vec::new();
+    let complete = loop {
+        if head.len() > limit {
+            break false;
+        }
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                head.extend_from_slice(&chunk);
+                chunks.push(Ok(chunk));
+            }
+            Some(Err(e)) => {
+                chunks.push(Err(e));
+                break false;
+            }
+            None => break true,
+        }
+    };
+
+    if complete {
+        let body = axum::body::Body::from(head.clone());
+        return (head, true, body);
+    }
+    let rest = futures_util::stream::iter(chunks).chain(stream);
+    (head, false, axum::body::Body::from_stream(rest))
+}
+
 pub async fn log_request_middleware(State(state): State<AppState>, req: Request, next: Next) -> Response {
     let debug_logs = state.debug;
+    let logging = &state.config.logging;
+    let redactor = &state.redactor;
     let method = req.method().clone();
-    let uri = req.uri().clone();
-    let path = uri.path();
+    let path = req.uri().path().to_string();
 
     // Skip logging for ignored paths
-    if path == "/socket" {
+    let level = logging.route_level(&path);
+    if path == "/socket" || (level == LogLevel::Off && !debug_logs) {
         return next.run(req).await;
     }
-
-    // Log POST request body for debugging
-    let (parts, body) = req.into_parts();
-    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let url = redactor.uri(req.uri());
 
     if debug_logs {
-        info!("Request: {} {}", method, uri);
-        for (name, value) in &parts.headers {
-            info!("Req Header: {}: {:?}", name, value);
+        info!("Request: {} {}", method, url);
+        for (name, value) in req.headers() {
+            info!("Req Header: {}: {}", name, redactor.header(name.as_str(), value));
         }
     }
 
-    if method == axum::http::Method::POST && !bytes.is_empty() {
-        if let Ok(body_str) = std::str::from_utf8(&bytes) {
-            info!(
+    // Log POST request body for debugging. Only text bodies are buffered,
+    // uploads are passed on untouched.
+    let req_content_type = content_type(req.headers());
+    let req = if method == axum::http::Method::POST && is_text_content_type(&req_content_type) {
+        let (parts, body) = req.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+        if !bytes.is_empty() {
+            let (body_str, truncated) = truncate_body(&bytes, logging.max_body_size);
+            log_at!(
+                level,
                 method = %method,
-                url = %uri,
-                body = %body_str,
+                url = %url,
+                body = %redactor.body(&req_content_type, &body_str),
+                truncated = truncated,
                 "POST request"
             );
         }
-    }
+        Request::from_parts(parts, axum::body::Body::from(bytes))
+    } else {
+        req
+    };
 
-    // Reconstruct request with body
-    let req = Request::from_parts(parts, axum::body::Body::from(bytes));
     let response = next.run(req).await;
 
     let status = response.status().as_u16();
 
     if debug_logs {
-        info!("Response: {} {} Status: {}", method, uri, status);
+        info!("Response: {} {} Status: {}", method, url, status);
         for (name, value) in response.headers() {
-            info!("Res Header: {}: {:?}", name, value);
+            info!("Res Header: {}: {}", name, redactor.header(name.as_str(), value));
         }
     }
 
-    // Check Content-Type to decide whether to buffer body
-    let content_type = response
-        .headers()
-        .get(axum::http::header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .map(|ct| ct.to_lowercase())
-        .unwrap_or_default();
-
-    let is_text = content_type.contains("json")
-        || content_type.contains("text")
-        || content_type.contains("xml")
-        || content_type.contains("application/x-www-form-urlencoded");
+    // Check Content-Type to decide whether to log the body
+    let content_type = content_type(response.headers());
+    let is_text = is_text_content_type(&content_type);
 
     if debug_logs {
         info!(
@@ -172,46 +245,44 @@ pub async fn log_request_middleware(State(state): State<AppState>, req: Request,
     }
 
     if is_text {
-        // Buffer text/json responses for debugging logging
+        // Log the start of text/json responses, without buffering large ones whole
         let (parts, body) = response.into_parts();
-        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
-        let length = bytes.len();
-
-        let body_str_res = std::str::from_utf8(&bytes);
+        let (head, complete, body) = peek_body(body, logging.max_body_size).await;
+        let (body_str, truncated) = truncate_body(&head, logging.max_body_size);
+        let body_str = redactor.body(&content_type, &body_str);
+        let length = if complete {
+            head.len().to_string()
+        } else {
+            parts
+                .headers
+                .get(axum::http::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("unknown")
+                .to_string()
+        };
 
         if debug_logs {
-            match body_str_res {
-                Ok(body_str) => info!("Res Body: {}", body_str),
-                Err(e) => info!("Res Body skipped: Invalid UTF-8 sequence: {}", e),
-            }
+            info!("Res Body: {}", body_str);
         }
 
-        // Standard logging
-        if let Ok(body_str) = body_str_res {
-            info!(
-                method = %method,
-                url = %uri,
-                status = status,
-                length = length,
-                res_body = %body_str,
-                "HTTP response (Logged)"
-            );
-        } else {
-            info!(
-                method = %method,
-                url = %uri,
-                status = status,
-                length = length,
-                "HTTP response"
-            );
-        }
+        log_at!(
+            level,
+            method = %method,
+            url = %url,
+            status = status,
+            length = %length,
+            res_body = %body_str,
+            truncated = truncated || !complete,
+            "HTTP response (Logged)"
+        );
 
-        Response::from_parts(parts, axum::body::Body::from(bytes))
+        Response::from_parts(parts, body)
     } else {
         // Do NOT buffer video/binary streams - pass through directly
-        info!(
+        log_at!(
+            level,
             method = %method,
-            url = %uri,
+            url = %url,
             status = status,
             type = %content_type,
             "HTTP response (Streamed)"
@@ -321,7 +392,7 @@ pub async fn ip_acl_middleware(State(state): State<AppState>, req: Request, next
         return next.run(req).await;
     }
 
-    let client_ip = extract_client_ip(&req);
+    let client_ip = extract_client_ip(&req, state.config.trusted_proxy_hops());
 
     let allowed = client_ip
         .map(|ip| allowlist.iter().any(|entry| ip_matches(ip, entry)))
@@ -337,9 +408,23 @@ pub async fn ip_acl_middleware(State(state): State<AppState>, req: Request, next
     next.run(req).await
 }
 
-/// Extract client IP from X-Real-IP, X-Forwarded-For, or nothing.
-fn extract_client_ip(req: &Request) -> Option<IpAddr> {
-    let headers = req.headers();
+fn extract_client_ip(req: &Request, trusted_hops: usize) -> Option<IpAddr> {
+    client_ip(req.headers(), trusted_hops)
+}
+
+/// Extract client IP from X-Forwarded-For, X-Real-IP, or nothing.
+///
+/// Every proxy appends the address it got the request from to
+/// X-Forwarded-For, so only the last `trusted_hops` entries are written by
+/// our own proxies; anything left of them comes from the client. X-Real-IP
+/// is only used without X-Forwarded-For, as a proxy that sets it overwrites
+/// whatever the client sent.
+pub(crate) fn client_ip(headers: &HeaderMap, trusted_hops: usize) -> Option<IpAddr> {
+    if let Some(v) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+        let entries: Vec<&str> = v.split(',').map(str::trim).collect();
+        let index = entries.len().saturating_sub(trusted_hops.max(1));
+        return entries[index].parse::<IpAddr>().ok();
+    }
 
     // X-Real-IP (set by nginx)
     if let Some(v) = headers.get("x-real-ip").and_then(|v| v.to_str().ok()) {
@@ -348,15 +433,6 @@ fn extract_client_ip(req: &Request) -> Option<IpAddr> {
         }
     }
 
-    // X-Forwarded-For: take the first (leftmost) address
-    if let Some(v) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
-        if let Some(first) = v.split(',').next() {
-            if let Ok(ip) = first.trim().parse::<IpAddr>() {
-                return Some(ip);
-            }
-        }
-    }
-
     None
 }
 
@@ -422,3 +498,77 @@ fn etags_match(client_etag: &str, server_etag: &str) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::config::LoggingConfig;
+
+    #[test]
+    fn test_truncate_body() {
+        assert_eq!(truncate_body(b"hello", 10), ("hello".to_string(), false));
+        assert_eq!(truncate_body(b"hello", 5), ("hello".to_string(), false));
+        assert_eq!(truncate_body(b"hello world", 5), ("hello".to_string(), true));
+        // Multi-byte characters are not cut in half.
+        let body = "héllo".as_bytes();
+        assert_eq!(truncate_body(body, 2), ("h".to_string(), true));
+        assert_eq!(truncate_body(body, 3), ("hé".to_string(), true));
+        assert_eq!(truncate_body(b"", 0), (String::new(), false));
+    }
+
+    fn chunked(chunks: &[&'static str]) -> axum::body::Body {
+        let chunks = chunks
+            .iter()
+            .map(|c| Ok::<_, std::io::Error>(axum::body::Bytes::from_static(c.as_bytes())));
+        axum::body::Body::from_stream(futures_util::stream::iter(chunks.collect::<Vec<_>>()))
+    }
+
+    async fn read(body: axum::body::Body) -> String {
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_peek_body() {
+        let (head, complete, body) = peek_body(chunked(&["abc", "def"]), 10).await;
+        assert_eq!(head, b"abcdef");
+        assert!(complete);
+        assert_eq!(read(body).await, "abcdef");
+
+        // A body larger than the limit is read only a little past it, and
+        // passed on whole.
+        let (head, complete, body) = peek_body(chunked(&["abcd", "efgh", "ijkl", "mnop"]), 5).await;
+        assert_eq!(head, b"abcdefgh");
+        assert!(!complete);
+        assert_eq!(read(body).await, "abcdefghijklmnop");
+
+        let (head, complete, body) = peek_body(axum::body::Body::empty(), 5).await;
+        assert!(head.is_empty());
+        assert!(complete);
+        assert_eq!(read(body).await, "");
+    }
+
+    #[test]
+    fn test_route_level() {
+        let logging = LoggingConfig {
+            routes: [
+                ("/Users/AuthenticateByName", "off"),
+                ("/users", "debug"),
+                ("/videos", "none"),
+            ]
+            .iter()
+            .map(|(route, level)| (route.to_string(), level.to_string()))
+            .collect(),
+            ..Default::default()
+        };
+        // Paths are normalized to lowercase, the longest prefix wins.
+        assert_eq!(logging.route_level("/users/authenticatebyname"), LogLevel::Off);
+        assert_eq!(logging.route_level("/users/abc/items"), LogLevel::Debug);
+        assert_eq!(logging.route_level("/videos/abc/stream.mkv"), LogLevel::Off);
+        assert_eq!(logging.route_level("/items"), LogLevel::Info);
+        assert_eq!(
+            LoggingConfig::default().route_level("/users/authenticatebyname"),
+            LogLevel::Info
+        );
+    }
+}
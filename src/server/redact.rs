@@ -0,0 +1,174 @@
+use axum::http::{HeaderValue, Uri};
+use regex::Regex;
+use std::collections::HashSet;
+
+use super::config::LoggingConfig;
+
+/// JSON keys that always hold credentials.
+const REDACT_KEYS: &[&str] = &[
+    "Pw",
+    "NewPw",
+    "CurrentPw",
+    "Password",
+    "AccessToken",
+    "Token",
+    "Secret",
+    "ApiKey",
+];
+
+/// Headers that always hold credentials.
+const REDACT_HEADERS: &[&str] = &[
+    "authorization",
+    "x-emby-authorization",
+    "x-emby-token",
+    "x-mediabrowser-token",
+    "cookie",
+];
+
+/// Query parameters that always hold credentials.
+const REDACT_QUERY_PARAMS: &[&str] = &["api_key", "apikey", "token"];
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Redactor removes credentials from request and response details before
+/// they are logged. Keys, headers and query parameters match case-insensitively.
+pub struct Redactor {
+    /// Matches `"key": value` pairs of sensitive JSON keys. The value may be
+    /// cut off, bodies are redacted after truncation.
+    json_keys: Regex,
+    headers: HashSet<String>,
+    query_params: HashSet<String>,
+    /// Matches the token in a MediaBrowser authorization header.
+    auth_token: Regex,
+}
+
+impl Redactor {
+    pub fn new(config: &LoggingConfig) -> Self {
+        let lowercase = |builtin: &[&str], extra: &[String]| -> HashSet<String> {
+            builtin
+                .iter()
+                .map(|s| s.to_string())
+                .chain(extra.iter().cloned())
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+
+        let keys = lowercase(REDACT_KEYS, &config.redact_keys);
+        let mut keys: Vec<String> = keys.iter().map(|k| regex::escape(k)).collect();
+        keys.sort();
+        let json_keys = Regex::new(&format!(
+            r#"(?i)"({})"\s*:\s*("(?:[^"\\]|\\.)*"?|[^,}}\]\s]+)"#,
+            keys.join("|")
+        ))
+        .unwrap();
+
+        Self {
+            json_keys,
+            headers: lowercase(REDACT_HEADERS, &config.redact_headers),
+            query_params: lowercase(REDACT_QUERY_PARAMS, &config.redact_query_params),
+            auth_token: Regex::new(r#"(?i)\b(Token=)("[^"]*"?|[^,\s]*)"#).unwrap(),
+        }
+    }
+
+    /// Redact the values of sensitive keys in a (possibly truncated) JSON body.
+    pub fn json(&self, body: &str) -> String {
+        self.json_keys
+            .replace_all(body, format!(r#""$1":"{}""#, REDACTED).as_str())
+            .into_owned()
+    }
+
+    /// Redact sensitive parameters in a query string or form body.
+    pub fn query(&self, query: &str) -> String {
+        query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if self.query_params.contains(&key.to_lowercase()) => {
+                    format!("{}={}", key, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Redact sensitive query parameters of a URI.
+    pub fn uri(&self, uri: &Uri) -> String {
+        match uri.query() {
+            Some(query) => format!("{}?{}", uri.path(), self.query(query)),
+            None => uri.path().to_string(),
+        }
+    }
+
+    /// Redact a header value. Of MediaBrowser authorization headers only the
+    /// token is redacted, so the client and device stay visible.
+    pub fn header(&self, name: &str, value: &HeaderValue) -> String {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        if !self.headers.contains(&name.to_lowercase()) {
+            return value.into_owned();
+        }
+        if value.starts_with("MediaBrowser ") || value.starts_with("Emby ") {
+            let replacement = format!(r#"${{1}}"{}""#, REDACTED);
+            return self
+                .auth_token
+                .replace_all(&value, replacement.as_str())
+                .into_owned();
+        }
+        REDACTED.to_string()
+    }
+
+    /// Redact a request or response body based on its content type.
+    pub fn body(&self, content_type: &str, body: &str) -> String {
+        if content_type.contains("x-www-form-urlencoded") {
+            self.query(body)
+        } else {
+            self.json(body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::new(&LoggingConfig {
+            redact_keys: vec!["Pin".to_string()],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_redact_json() {
+        let r = redactor();
+        assert_eq!(
+            r.json(r#"{"Username":"joe","Pw":"hunter2"}"#),
+            r#"{"Username":"joe","Pw":"[REDACTED]"}"#
+        );
+        assert_eq!(
+            r.json(r#"{"CurrentPw": "a\"b", "newPw":"x", "Pin": 1234}"#),
+            r#"{"CurrentPw":"[REDACTED]", "newPw":"[REDACTED]", "Pin":"[REDACTED]"}"#
+        );
+        // Truncated bodies are redacted as well.
+        assert_eq!(
+            r.json(r#"{"User":{"Name":"joe"},"AccessToken":"0123456"#),
+            r#"{"User":{"Name":"joe"},"AccessToken":"[REDACTED]""#
+        );
+    }
+
+    #[test]
+    fn test_redact_uri_and_headers() {
+        let r = redactor();
+        let uri: Uri = "/videos/1/stream?static=true&api_key=secret".parse().unwrap();
+        assert_eq!(r.uri(&uri), "/videos/1/stream?static=true&api_key=[REDACTED]");
+
+        let auth = HeaderValue::from_static(r#"MediaBrowser Client="web", Token="abc", DeviceId="d1""#);
+        assert_eq!(
+            r.header("X-Emby-Authorization", &auth),
+            r#"MediaBrowser Client="web", Token="[REDACTED]", DeviceId="d1""#
+        );
+        let token = HeaderValue::from_static("abc");
+        assert_eq!(r.header("x-emby-token", &token), "[REDACTED]");
+        assert_eq!(r.header("user-agent", &token), "abc");
+    }
+}
@@ -0,0 +1,636 @@
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, TimeZone, Utc, Weekday};
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// Task ID of the library scan.
+pub const TASK_REFRESH_LIBRARY: &str = "RefreshLibrary";
+
+/// Task ID of refreshing single items.
+pub const TASK_REFRESH_ITEMS: &str = "RefreshItems";
+
+/// Task ID of removing old resized images from the cache.
+pub const TASK_CLEAN_IMAGE_CACHE: &str = "CleanImageCache";
+
+/// Task ID of removing expired quick connect codes.
+pub const TASK_CLEAN_QUICK_CONNECT: &str = "CleanQuickConnect";
+
+/// Task ID of removing expired access tokens.
+pub const TASK_CLEAN_ACCESS_TOKENS: &str = "CleanAccessTokens";
+
+/// Task ID of compacting the database.
+pub const TASK_VACUUM_DATABASE: &str = "VacuumDatabase";
+
+/// The scheduler checks for due tasks at least this often.
+const SCHEDULER_MAX_SLEEP: Duration = Duration::from_secs(3600);
+
+/// TaskInfo describes a task as shown in the dashboard.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Idle,
+    Running,
+    Cancelling,
+}
+
+impl TaskState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskState::Idle => "Idle",
+            TaskState::Running => "Running",
+            TaskState::Cancelling => "Cancelling",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Completed,
+    Failed,
+    Cancelled,
+    Aborted,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Completed => "Completed",
+            TaskStatus::Failed => "Failed",
+            TaskStatus::Cancelled => "Cancelled",
+            TaskStatus::Aborted => "Aborted",
+        }
+    }
+}
+
+/// TaskResult is the outcome of the last run of a task.
+#[derive(Debug, Clone)]
+pub struct TaskResult {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub status: TaskStatus,
+    pub error_message: Option<String>,
+}
+
+impl TaskResult {
+    pub fn duration(&self) -> chrono::Duration {
+        self.end - self.start
+    }
+}
+
+/// TaskTrigger decides when a task runs. Times of day are in seconds after
+/// midnight, local time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TaskTrigger {
+    /// Run once when the server starts.
+    Startup,
+    /// Run every `seconds` after the previous run ended.
+    Interval { seconds: u64 },
+    /// Run every day.
+    Daily { time_of_day: u64 },
+    /// Run every week on `day`.
+    Weekly { day: Weekday, time_of_day: u64 },
+}
+
+impl TaskTrigger {
+    /// Next time the trigger fires after `now`, None if it does not.
+    /// `last_end` is the end of the previous run.
+    pub fn next_run(&self, now: DateTime<Utc>, last_end: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        match self {
+            TaskTrigger::Startup => None,
+            TaskTrigger::Interval { seconds } => {
+                let next = last_end.unwrap_or(now) + chrono::Duration::seconds(*seconds as i64);
+                Some(next.max(now))
+            }
+            TaskTrigger::Daily { time_of_day } => {
+                next_time_of_day(now.with_timezone(&Local), None, *time_of_day)
+            }
+            TaskTrigger::Weekly { day, time_of_day } => {
+                next_time_of_day(now.with_timezone(&Local), Some(*day), *time_of_day)
+            }
+        }
+    }
+}
+
+/// Next time after `now` that is `time_of_day` seconds after midnight in the
+/// timezone of `now`, optionally on a given day of the week.
+fn next_time_of_day<Tz: TimeZone>(
+    now: DateTime<Tz>,
+    day: Option<Weekday>,
+    time_of_day: u64,
+) -> Option<DateTime<Utc>> {
+    let time = NaiveTime::from_num_seconds_from_midnight_opt((time_of_day % 86400) as u32, 0)?;
+    let timezone = now.timezone();
+    (0..=7)
+        .filter_map(|days| now.date_naive().checked_add_days(Days::new(days)))
+        .filter(|date| day.is_none_or(|d| date.weekday() == d))
+        // Times that do not exist because of a DST change are skipped.
+        .filter_map(|date| timezone.from_local_datetime(&date.and_time(time)).earliest())
+        .find(|t| *t > now)
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// TaskContext is handed to a running task to report its progress.
+#[derive(Clone)]
+pub struct TaskContext {
+    manager: Arc<TaskManager>,
+    id: String,
+    cancel: Arc<Cancel>,
+}
+
+/// Cancellation state of a task run.
+#[derive(Default)]
+struct Cancel {
+    requested: AtomicBool,
+    /// The job checks `requested` itself, so it is not aborted.
+    cooperative: AtomicBool,
+}
+
+impl TaskContext {
+    /// Report the progress in percent.
+    pub fn progress(&self, percentage: f64) {
+        self.manager.set_progress(&self.id, percentage);
+    }
+
+    /// A progress callback that can be moved to another thread.
+    pub fn progress_fn(&self) -> impl Fn(f64) + Send + Sync + 'static {
+        let ctx = self.clone();
+        move |percentage| ctx.progress(percentage)
+    }
+
+    /// A callback that tells if the task was cancelled, for jobs that do
+    /// work another future cannot abort, like blocking threads. A job that
+    /// asks for it is not aborted when cancelled but has to stop by itself,
+    /// and the task stays Cancelling until it does.
+    pub fn cancelled_fn(&self) -> impl Fn() -> bool + Send + Sync + 'static {
+        self.cancel.cooperative.store(true, Ordering::SeqCst);
+        let cancel = Arc::clone(&self.cancel);
+        move || cancel.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// TaskJob runs a task.
+pub type TaskJob = Arc<dyn Fn(TaskContext) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Make a TaskJob from an async closure.
+pub fn job<F, Fut>(f: F) -> TaskJob
+where
+    F: Fn(TaskContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+{
+    Arc::new(move |ctx| f(ctx).boxed())
+}
+
+/// Task is the current state of a registered task.
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub info: TaskInfo,
+    pub state: TaskState,
+    /// Progress of the running task in percent.
+    pub progress: Option<f64>,
+    pub last_result: Option<TaskResult>,
+    pub triggers: Vec<TaskTrigger>,
+    /// Next time a trigger fires.
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+struct Entry {
+    task: Task,
+    job: Option<TaskJob>,
+    abort: Option<AbortHandle>,
+    cancel: Option<Arc<Cancel>>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum TaskError {
+    #[error("task not found")]
+    NotFound,
+    #[error("task is already running")]
+    Running,
+    #[error("task cannot be started on its own")]
+    NotRunnable,
+}
+
+/// TaskManager runs tasks in the background, either by their triggers or on
+/// request, and keeps track of their progress and outcome so they can be
+/// shown through /ScheduledTasks. A task does not run twice at the same time.
+#[derive(Default)]
+pub struct TaskManager {
+    tasks: Mutex<Vec<Entry>>,
+    /// Wakes up the scheduler when triggers change or a task ends.
+    wakeup: Notify,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a task. Tasks without a job are run by their callers through
+    /// `spawn`, e.g. because they need arguments.
+    pub fn register(&self, info: TaskInfo, triggers: Vec<TaskTrigger>, job: Option<TaskJob>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let next_run = next_run(&triggers, Utc::now(), None);
+        let entry = Entry {
+            task: Task {
+                info,
+                state: TaskState::Idle,
+                progress: None,
+                last_result: None,
+                triggers,
+                next_run,
+            },
+            job,
+            abort: None,
+            cancel: None,
+        };
+        match tasks.iter_mut().find(|e| e.task.info.id == entry.task.info.id) {
+            Some(existing) => *existing = entry,
+            None => tasks.push(entry),
+        }
+        self.wakeup.notify_one();
+    }
+
+    /// All registered tasks.
+    pub fn tasks(&self) -> Vec<Task> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| e.task.clone())
+            .collect()
+    }
+
+    /// Get a task by ID, ignoring case.
+    pub fn task(&self, id: &str) -> Option<Task> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|e| e.task.info.id.eq_ignore_ascii_case(id))
+            .map(|e| e.task.clone())
+    }
+
+    /// Replace the triggers of a task.
+    pub fn set_triggers(&self, id: &str, triggers: Vec<TaskTrigger>) -> Result<(), TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks
+            .iter_mut()
+            .find(|e| e.task.info.id == id)
+            .ok_or(TaskError::NotFound)?;
+        let last_end = entry.task.last_result.as_ref().map(|r| r.end);
+        entry.task.next_run = next_run(&triggers, Utc::now(), last_end);
+        entry.task.triggers = triggers;
+        self.wakeup.notify_one();
+        Ok(())
+    }
+
+    /// Run the job of a task now.
+    pub fn run(self: &Arc<Self>, id: &str) -> Result<(), TaskError> {
+        let job = {
+            let tasks = self.tasks.lock().unwrap();
+            let entry = tasks
+                .iter()
+                .find(|e| e.task.info.id == id)
+                .ok_or(TaskError::NotFound)?;
+            entry.job.clone().ok_or(TaskError::NotRunnable)?
+        };
+        self.spawn(id, move |ctx| job(ctx))
+    }
+
+    /// Run `f` as the task `id` in the background.
+    pub fn spawn<F, Fut>(self: &Arc<Self>, id: &str, f: F) -> Result<(), TaskError>
+    where
+        F: FnOnce(TaskContext) -> Fut,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks
+            .iter_mut()
+            .find(|e| e.task.info.id == id)
+            .ok_or(TaskError::NotFound)?;
+        if entry.task.state != TaskState::Idle {
+            return Err(TaskError::Running);
+        }
+        entry.task.state = TaskState::Running;
+        entry.task.progress = Some(0.0);
+        entry.task.next_run = None;
+        info!("Task {} started", entry.task.info.name);
+
+        let run = TaskRun {
+            manager: Arc::clone(self),
+            id: id.to_string(),
+            start: Utc::now(),
+            finished: false,
+        };
+        let cancel = Arc::new(Cancel::default());
+        let job = f(TaskContext {
+            manager: Arc::clone(self),
+            id: id.to_string(),
+            cancel: Arc::clone(&cancel),
+        });
+        let handle = tokio::spawn(async move {
+            let result = job.await;
+            run.finish(result);
+        });
+        entry.abort = Some(handle.abort_handle());
+        entry.cancel = Some(cancel);
+        Ok(())
+    }
+
+    /// Cancel a running task.
+    pub fn cancel(&self, id: &str) -> Result<(), TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks
+            .iter_mut()
+            .find(|e| e.task.info.id == id)
+            .ok_or(TaskError::NotFound)?;
+        if entry.task.state == TaskState::Running {
+            info!("Cancelling task {}", entry.task.info.name);
+            entry.task.state = TaskState::Cancelling;
+            let cooperative = match &entry.cancel {
+                Some(cancel) => {
+                    cancel.requested.store(true, Ordering::SeqCst);
+                    cancel.cooperative.load(Ordering::SeqCst)
+                }
+                None => false,
+            };
+            if !cooperative {
+                if let Some(abort) = entry.abort.take() {
+                    abort.abort();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run tasks when their triggers fire. Tasks with a startup trigger run
+    /// right away.
+    pub fn background(self: &Arc<Self>) {
+        let now = Utc::now();
+        for entry in self.tasks.lock().unwrap().iter_mut() {
+            if entry.task.triggers.contains(&TaskTrigger::Startup) {
+                entry.task.next_run = Some(now);
+            }
+        }
+
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                let now = Utc::now();
+                for id in manager.due(now) {
+                    if let Err(e) = manager.run(&id) {
+                        warn!("Failed to start task {}: {}", id, e);
+                    }
+                }
+
+                let sleep = manager
+                    .tasks()
+                    .iter()
+                    .filter(|t| t.state == TaskState::Idle)
+                    .filter_map(|t| t.next_run)
+                    .min()
+                    .map(|next| (next - Utc::now()).to_std().unwrap_or_default())
+                    .unwrap_or(SCHEDULER_MAX_SLEEP)
+                    .min(SCHEDULER_MAX_SLEEP);
+                tokio::select! {
+                    _ = tokio::time::sleep(sleep) => {}
+                    _ = manager.wakeup.notified() => {}
+                }
+            }
+        });
+    }
+
+    /// IDs of idle tasks whose next run is due.
+    fn due(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.job.is_some() && e.task.state == TaskState::Idle)
+            .filter(|e| e.task.next_run.is_some_and(|t| t <= now))
+            .map(|e| e.task.info.id.clone())
+            .collect()
+    }
+
+    fn set_progress(&self, id: &str, percentage: f64) {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(entry) = tasks.iter_mut().find(|e| e.task.info.id == id) {
+            if entry.task.state == TaskState::Running {
+                entry.task.progress = Some(percentage.clamp(0.0, 100.0));
+            }
+        }
+    }
+
+    fn finish(&self, id: &str, mut result: TaskResult) {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(entry) = tasks.iter_mut().find(|e| e.task.info.id == id) {
+            let task = &mut entry.task;
+            if task.state == TaskState::Cancelling {
+                result.status = TaskStatus::Cancelled;
+            }
+            let secs = result.duration().num_milliseconds() as f64 / 1000.0;
+            match &result.error_message {
+                Some(e) => warn!(
+                    "Task {} finished with status {} in {:.1}s: {}",
+                    task.info.name,
+                    result.status.as_str(),
+                    secs,
+                    e
+                ),
+                None => info!(
+                    "Task {} finished with status {} in {:.1}s",
+                    task.info.name,
+                    result.status.as_str(),
+                    secs
+                ),
+            }
+            task.state = TaskState::Idle;
+            task.progress = None;
+            task.next_run = next_run(&task.triggers, result.end, Some(result.end));
+            task.last_result = Some(result);
+            entry.abort = None;
+            entry.cancel = None;
+        }
+        self.wakeup.notify_one();
+    }
+}
+
+/// Earliest next run of a list of triggers.
+fn next_run(
+    triggers: &[TaskTrigger],
+    now: DateTime<Utc>,
+    last_end: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    triggers.iter().filter_map(|t| t.next_run(now, last_end)).min()
+}
+
+/// TaskRun records the outcome of a run when it ends. Dropping it without
+/// calling `finish`, e.g. because the task was cancelled, records the run as
+/// aborted.
+struct TaskRun {
+    manager: Arc<TaskManager>,
+    id: String,
+    start: DateTime<Utc>,
+    finished: bool,
+}
+
+impl TaskRun {
+    fn finish(mut self, result: Result<(), String>) {
+        self.end(match result {
+            Ok(()) => (TaskStatus::Completed, None),
+            Err(e) => (TaskStatus::Failed, Some(e)),
+        });
+    }
+
+    fn end(&mut self, (status, error_message): (TaskStatus, Option<String>)) {
+        self.finished = true;
+        self.manager.finish(
+            &self.id,
+            TaskResult {
+                start: self.start,
+                end: Utc::now(),
+                status,
+                error_message,
+            },
+        );
+    }
+}
+
+impl Drop for TaskRun {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.end((TaskStatus::Aborted, None));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(job: Option<TaskJob>) -> Arc<TaskManager> {
+        let manager = Arc::new(TaskManager::new());
+        manager.register(
+            TaskInfo {
+                id: "t1".to_string(),
+                name: "Test".to_string(),
+                description: String::new(),
+                category: "Library".to_string(),
+            },
+            vec![TaskTrigger::Interval { seconds: 3600 }],
+            job,
+        );
+        manager
+    }
+
+    async fn wait_idle(manager: &TaskManager) -> Task {
+        for _ in 0..100 {
+            let task = manager.task("t1").unwrap();
+            if task.state == TaskState::Idle {
+                return task;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("task did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_task_run() {
+        let manager = manager(Some(job(|ctx| async move {
+            ctx.progress(42.0);
+            Err("disk gone".to_string())
+        })));
+        assert!(manager.task("t1").unwrap().next_run.is_some());
+        assert_eq!(manager.run("unknown"), Err(TaskError::NotFound));
+
+        manager.run("t1").unwrap();
+        let task = wait_idle(&manager).await;
+        assert_eq!(task.progress, None);
+        let result = task.last_result.unwrap();
+        assert_eq!(result.status, TaskStatus::Failed);
+        assert_eq!(result.error_message.as_deref(), Some("disk gone"));
+        // The interval starts over after the run.
+        assert!(task.next_run.unwrap() > result.end + chrono::Duration::seconds(3500));
+    }
+
+    #[tokio::test]
+    async fn test_task_cancel() {
+        let manager = manager(None);
+        assert_eq!(manager.run("t1"), Err(TaskError::NotRunnable));
+
+        manager
+            .spawn("t1", |_| async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(manager.spawn("t1", |_| async { Ok(()) }), Err(TaskError::Running));
+
+        manager.cancel("t1").unwrap();
+        let task = wait_idle(&manager).await;
+        assert_eq!(task.last_result.unwrap().status, TaskStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_task_cancel_cooperative() {
+        let manager = manager(None);
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        manager
+            .spawn("t1", |ctx| async move {
+                let cancelled = ctx.cancelled_fn();
+                tokio::task::spawn_blocking(move || {
+                    while !cancelled() {
+                        std::thread::sleep(Duration::from_millis(5));
+                    }
+                    // Blocks until the test lets the thread end.
+                    rx.recv().unwrap();
+                })
+                .await
+                .map_err(|e| e.to_string())
+            })
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // The task is not done before the blocking work is.
+        manager.cancel("t1").unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(manager.task("t1").unwrap().state, TaskState::Cancelling);
+        assert_eq!(manager.spawn("t1", |_| async { Ok(()) }), Err(TaskError::Running));
+
+        tx.send(()).unwrap();
+        let task = wait_idle(&manager).await;
+        assert_eq!(task.last_result.unwrap().status, TaskStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_next_time_of_day() {
+        // Wednesday 2024-01-10 12:00 UTC.
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let at = |d, h| Utc.with_ymd_and_hms(2024, 1, d, h, 0, 0).unwrap();
+
+        assert_eq!(next_time_of_day(now, None, 3 * 3600), Some(at(11, 3)));
+        assert_eq!(next_time_of_day(now, None, 13 * 3600), Some(at(10, 13)));
+        assert_eq!(
+            next_time_of_day(now, Some(Weekday::Sun), 3 * 3600),
+            Some(at(14, 3))
+        );
+        assert_eq!(
+            next_time_of_day(now, Some(Weekday::Wed), 3 * 3600),
+            Some(at(17, 3))
+        );
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::idhash::id_hash;
+
+/// Requests more than this many segments ahead of the transcoder restart
+/// it at the requested position instead of waiting for it to get there.
+const MAX_SEGMENTS_AHEAD: u32 = 5;
+
+/// How long a segment request waits for ffmpeg to produce the segment.
+const SEGMENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Jobs that have not been accessed for this long are stopped.
+const JOB_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Name of the playlist ffmpeg writes. Segments listed in it are complete.
+const FFMPEG_PLAYLIST: &str = "ffmpeg.m3u8";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscodeError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("ffmpeg failed: {0}")]
+    Ffmpeg(String),
+    #[error("timeout waiting for segment {0}")]
+    Timeout(u32),
+}
+
+pub type Result<T> = std::result::Result<T, TranscodeError>;
+
+/// Output parameters requested by the client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscodeOptions {
+    /// Video bitrate in bits per second.
+    pub video_bitrate: Option<u32>,
+    /// Audio bitrate in bits per second.
+    pub audio_bitrate: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub audio_channels: Option<u32>,
+    /// Index of the audio track to use, counting audio tracks only.
+    pub audio_track: Option<usize>,
+}
+
+/// Job is a running ffmpeg process for one playback session.
+struct Job {
+    item_id: String,
+    device_id: String,
+    options: TranscodeOptions,
+    dir: PathBuf,
+    start_segment: u32,
+    child: Child,
+    last_access: Instant,
+}
+
+/// Transcoder converts video files to HLS (H.264/AAC in MPEG-TS segments)
+/// on demand using ffmpeg. Segments are written to a directory per
+/// playback session under the cache directory.
+pub struct Transcoder {
+    ffmpeg: PathBuf,
+    dir: PathBuf,
+    segment_length: u32,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl Transcoder {
+    /// Create a new transcoder. Leftovers of a previous run in `dir` are removed.
+    pub fn new(ffmpeg: PathBuf, dir: PathBuf, segment_length: u32) -> Result<Self> {
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            ffmpeg,
+            dir,
+            segment_length: segment_length.max(1),
+            jobs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Segment length in seconds.
+    pub fn segment_length(&self) -> u32 {
+        self.segment_length
+    }
+
+    /// Build the HLS media playlist for a video of `duration`. All segments
+    /// are listed up front so clients can seek; they are transcoded on request.
+    /// `query` is appended to every segment URL.
+    pub fn media_playlist(&self, duration: Duration, query: &str) -> String {
+        let seg_len = self.segment_length as f64;
+        let total = duration.as_secs_f64();
+        let count = (total / seg_len).ceil().max(1.0) as u32;
+
+        let mut playlist = String::new();
+        playlist.push_str("#EXTM3U\n");
+        playlist.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n");
+        playlist.push_str("#EXT-X-VERSION:3\n");
+        playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", self.segment_length));
+        playlist.push_str("#EXT-X-MEDIA-SEQUENCE:0\n");
+        for n in 0..count {
+            let length = (total - n as f64 * seg_len).min(seg_len).max(0.0);
+            playlist.push_str(&format!("#EXTINF:{:.6}, nodesc\n", length));
+            if query.is_empty() {
+                playlist.push_str(&format!("hls1/main/{}.ts\n", n));
+            } else {
+                playlist.push_str(&format!("hls1/main/{}.ts?{}\n", n, query));
+            }
+        }
+        playlist.push_str("#EXT-X-ENDLIST\n");
+        playlist
+    }
+
+    /// Return the path of a transcoded segment, starting or restarting
+    /// ffmpeg if needed, and waiting until the segment is complete.
+    pub async fn segment(
+        &self,
+        job_id: &str,
+        device_id: &str,
+        item_id: &str,
+        input: &Path,
+        options: &TranscodeOptions,
+        segment: u32,
+    ) -> Result<PathBuf> {
+        let dir = {
+            let mut jobs = self.jobs.lock().await;
+
+            // Segments on disk can only be reused if they are of the same
+            // item, transcoded with the same options.
+            let same_source = matches!(
+                jobs.get(job_id),
+                Some(job) if job.item_id == item_id && job.options == *options
+            );
+            let restart = match jobs.get(job_id) {
+                None => true,
+                Some(job) => {
+                    !same_source
+                        || segment < job.start_segment
+                        || segment > last_segment(&job.dir).unwrap_or(job.start_segment) + MAX_SEGMENTS_AHEAD
+                }
+            };
+            if restart && !(same_source && segment_complete(&self.job_dir(job_id), segment)) {
+                // Dropping the old job kills its ffmpeg process.
+                jobs.remove(job_id);
+                let job = self.start_job(job_id, device_id, item_id, input, options, segment)?;
+                jobs.insert(job_id.to_string(), job);
+            }
+
+            let Some(job) = jobs.get_mut(job_id) else {
+                return Err(TranscodeError::Ffmpeg("job was stopped".to_string()));
+            };
+            job.last_access = Instant::now();
+            job.dir.clone()
+        };
+
+        let started = Instant::now();
+        loop {
+            if segment_complete(&dir, segment) {
+                return Ok(dir.join(segment_file_name(segment)));
+            }
+
+            {
+                let mut jobs = self.jobs.lock().await;
+                let Some(job) = jobs.get_mut(job_id) else {
+                    return Err(TranscodeError::Ffmpeg("job was stopped".to_string()));
+                };
+                if let Some(status) = job.child.try_wait()? {
+                    // ffmpeg may have finished writing the segment just before exiting.
+                    if segment_complete(&dir, segment) {
+                        continue;
+                    }
+                    return Err(TranscodeError::Ffmpeg(format!("exited with {}", status)));
+                }
+            }
+
+            if started.elapsed() > SEGMENT_TIMEOUT {
+                return Err(TranscodeError::Timeout(segment));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Stop a job and remove its segments.
+    pub async fn stop(&self, job_id: &str) {
+        // Keep the lock until the segments are gone, so a concurrent segment
+        // request cannot find them without a job.
+        let mut jobs = self.jobs.lock().await;
+        if jobs.remove(job_id).is_some() {
+            info!("Stopped transcoding job {}", job_id);
+        }
+        let _ = fs::remove_dir_all(self.job_dir(job_id));
+    }
+
+    /// Stop all jobs of a device.
+    pub async fn stop_device(&self, device_id: &str) {
+        let ids: Vec<String> = self
+            .jobs
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, job)| job.device_id == device_id)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids {
+            self.stop(&id).await;
+        }
+    }
+
+    /// Background task that stops jobs of clients that went away without
+    /// telling us.
+    pub fn background(self: &Arc<Self>) {
+        let transcoder = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                let idle: Vec<String> = transcoder
+                    .jobs
+                    .lock()
+                    .await
+                    .iter()
+                    .filter(|(_, job)| job.last_access.elapsed() > JOB_IDLE_TIMEOUT)
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in idle {
+                    transcoder.stop(&id).await;
+                }
+            }
+        });
+    }
+
+    fn job_dir(&self, job_id: &str) -> PathBuf {
+        // Job IDs come from clients, keep them from escaping our directory.
+        // The hash keeps IDs that only differ in stripped characters apart.
+        let name: String = job_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        self.dir.join(format!("{}_{}", name, id_hash(job_id)))
+    }
+
+    fn start_job(
+        &self,
+        job_id: &str,
+        device_id: &str,
+        item_id: &str,
+        input: &Path,
+        options: &TranscodeOptions,
+        start_segment: u32,
+    ) -> Result<Job> {
+        let dir = self.job_dir(job_id);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        let args = self.ffmpeg_args(input, &dir, options, start_segment);
+        info!(
+            "Starting transcode of {} at segment {} for job {}",
+            input.display(),
+            start_segment,
+            job_id
+        );
+        debug!("{} {}", self.ffmpeg.display(), args.join(" "));
+
+        let child = Command::new(&self.ffmpeg)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                warn!("Failed to start {}: {}", self.ffmpeg.display(), e);
+                TranscodeError::Ffmpeg(e.to_string())
+            })?;
+
+        Ok(Job {
+            item_id: item_id.to_string(),
+            device_id: device_id.to_string(),
+            options: options.clone(),
+            dir,
+            start_segment,
+            child,
+            last_access: Instant::now(),
+        })
+    }
+
+    fn ffmpeg_args(&self, input: &Path, dir: &Path, options: &TranscodeOptions, start_segment: u32) -> Vec<String> {
+        let start = start_segment * self.segment_length;
+        let mut args: Vec<String> = vec!["-hide_banner".into(), "-loglevel".into(), "error".into()];
+        if start > 0 {
+            args.extend(["-ss".into(), start.to_string()]);
+        }
+        args.extend(["-i".into(), input.to_string_lossy().into_owned()]);
+
+        let audio_track = options.audio_track.unwrap_or(0);
+        args.extend([
+            "-map".into(),
+            "0:v:0".into(),
+            "-map".into(),
+            format!("0:a:{}?", audio_track),
+            "-sn".into(),
+        ]);
+
+        // Video: H.264 with a keyframe at every segment boundary.
+        args.extend([
+            "-c:v".into(),
+            "libx264".into(),
+            "-preset".into(),
+            "veryfast".into(),
+            "-pix_fmt".into(),
+            "yuv420p".into(),
+            "-sc_threshold".into(),
+            "0".into(),
+            "-force_key_frames".into(),
+            format!("expr:gte(t,n_forced*{})", self.segment_length),
+        ]);
+        if let Some(bitrate) = options.video_bitrate {
+            args.extend([
+                "-b:v".into(),
+                bitrate.to_string(),
+                "-maxrate".into(),
+                bitrate.to_string(),
+                "-bufsize".into(),
+                (bitrate * 2).to_string(),
+            ]);
+        }
+        match (options.max_width, options.max_height) {
+            (Some(w), _) => args.extend(["-vf".into(), format!("scale=min(iw\\,{}):-2", w)]),
+            (None, Some(h)) => args.extend(["-vf".into(), format!("scale=-2:min(ih\\,{})", h)]),
+            (None, None) => {}
+        }
+
+        // Audio: AAC.
+        args.extend(["-c:a".into(), "aac".into()]);
+        args.extend(["-ac".into(), options.audio_channels.unwrap_or(2).to_string()]);
+        if let Some(bitrate) = options.audio_bitrate {
+            args.extend(["-b:a".into(), bitrate.to_string()]);
+        }
+
+        // HLS output. Timestamps continue from the start position so that
+        // segments of restarted jobs line up with earlier ones.
+        args.extend([
+            "-output_ts_offset".into(),
+            start.to_string(),
+            "-f".into(),
+            "hls".into(),
+            "-hls_time".into(),
+            self.segment_length.to_string(),
+            "-hls_list_size".into(),
+            "0".into(),
+            "-hls_segment_type".into(),
+            "mpegts".into(),
+            "-start_number".into(),
+            start_segment.to_string(),
+            "-hls_segment_filename".into(),
+            dir.join("segment_%d.ts").to_string_lossy().into_owned(),
+            dir.join(FFMPEG_PLAYLIST).to_string_lossy().into_owned(),
+        ]);
+        args
+    }
+}
+
+fn segment_file_name(segment: u32) -> String {
+    format!("segment_{}.ts", segment)
+}
+
+/// Segments are complete once ffmpeg lists them in its playlist.
+fn segment_complete(dir: &Path, segment: u32) -> bool {
+    let name = segment_file_name(segment);
+    fs::read_to_string(dir.join(FFMPEG_PLAYLIST))
+        .map(|playlist| playlist.lines().any(|line| line == name))
+        .unwrap_or(false)
+}
+
+/// Number of the last complete segment in a job directory.
+fn last_segment(dir: &Path) -> Option<u32> {
+    let playlist = fs::read_to_string(dir.join(FFMPEG_PLAYLIST)).ok()?;
+    playlist
+        .lines()
+        .filter_map(|line| line.strip_prefix("segment_")?.strip_suffix(".ts")?.parse().ok())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Stub ffmpeg that writes the requested start segment and its playlist.
+    const STUB_FFMPEG: &str = r#"#!/bin/sh
+start=0
+while [ $# -gt 1 ]; do
+    case "$1" in
+        -start_number) start="$2" ;;
+        -hls_segment_filename) pattern="$2" ;;
+    esac
+    shift
+done
+playlist="$1"
+dir=$(dirname "$playlist")
+echo "segment data" > "$dir/segment_$start.ts"
+printf '#EXTM3U\nsegment_%s.ts\n' "$start" > "$playlist"
+"#;
+
+    fn setup(name: &str) -> (PathBuf, Transcoder) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let ffmpeg = dir.join("ffmpeg");
+        fs::write(&ffmpeg, STUB_FFMPEG).unwrap();
+        fs::set_permissions(&ffmpeg, fs::Permissions::from_mode(0o755)).unwrap();
+        let transcoder = Transcoder::new(ffmpeg, dir.join("transcode"), 6).unwrap();
+        (dir, transcoder)
+    }
+
+    #[test]
+    fn test_media_playlist() {
+        let (dir, transcoder) = setup("test_media_playlist");
+        let playlist = transcoder.media_playlist(Duration::from_secs(14), "api_key=abc");
+
+        assert!(playlist.starts_with("#EXTM3U\n"));
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:6\n"));
+        assert!(playlist.contains("hls1/main/0.ts?api_key=abc\n"));
+        assert!(playlist.contains("hls1/main/2.ts?api_key=abc\n"));
+        assert!(!playlist.contains("hls1/main/3.ts"));
+        assert!(playlist.contains("#EXTINF:2.000000, nodesc\n"));
+        assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        let (dir, transcoder) = setup("test_ffmpeg_args");
+        let options = TranscodeOptions {
+            video_bitrate: Some(4_000_000),
+            max_width: Some(1280),
+            audio_track: Some(1),
+            ..Default::default()
+        };
+        let args = transcoder.ffmpeg_args(Path::new("/movies/a.mkv"), Path::new("/tmp/job"), &options, 10);
+        let args = args.join(" ");
+
+        assert!(args.contains("-ss 60 -i /movies/a.mkv"));
+        assert!(args.contains("-map 0:a:1?"));
+        assert!(args.contains("-b:v 4000000"));
+        assert!(args.contains("-vf scale=min(iw\\,1280):-2"));
+        assert!(args.contains("-start_number 10"));
+        assert!(args.ends_with("/tmp/job/ffmpeg.m3u8"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_segment_with_stub_ffmpeg() {
+        let (dir, transcoder) = setup("test_segment_with_stub_ffmpeg");
+        let options = TranscodeOptions::default();
+
+        let path = transcoder
+            .segment("job1", "dev1", "item1", Path::new("/movies/a.mkv"), &options, 3)
+            .await
+            .unwrap();
+        assert_eq!(path.file_name().unwrap(), "segment_3.ts");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "segment data\n");
+
+        transcoder.stop_device("dev1").await;
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_job_dir_unique() {
+        let (dir, transcoder) = setup("test_job_dir_unique");
+        assert_ne!(transcoder.job_dir("a.1"), transcoder.job_dir("a1"));
+        assert!(transcoder.job_dir("../a1").starts_with(dir.join("transcode")));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_segment_not_reused_for_other_item() {
+        let (dir, transcoder) = setup("test_segment_not_reused_for_other_item");
+        let options = TranscodeOptions::default();
+
+        let path = transcoder
+            .segment("job1", "dev1", "item1", Path::new("/movies/a.mkv"), &options, 0)
+            .await
+            .unwrap();
+        fs::write(&path, "stale").unwrap();
+
+        // Same job key, other item: the segment must be transcoded again.
+        let path = transcoder
+            .segment("job1", "dev1", "item2", Path::new("/movies/b.mkv"), &options, 0)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "segment data\n");
+
+        // A segment that is left on disk without a job is not served as-is.
+        transcoder.jobs.lock().await.clear();
+        fs::write(&path, "stale").unwrap();
+        let path = transcoder
+            .segment("job1", "dev1", "item2", Path::new("/movies/b.mkv"), &options, 0)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "segment data\n");
+
+        transcoder.stop("job1").await;
+        let _ = fs::remove_dir_all(dir);
+    }
+}